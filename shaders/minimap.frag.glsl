@@ -0,0 +1,51 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_diffuse;
+layout(set=0, binding=1) uniform sampler s_diffuse;
+
+layout(set=1, binding=0) uniform MinimapUniform {
+    // the part of the canvas in view, as left, top, right, bottom in texture coordinates
+    vec4 visible;
+    // one window pixel in texture coordinates
+    vec2 pixel;
+    vec2 _pad;
+};
+
+// window pixels per side of each square of the checkerboard behind transparent pixels
+const float CHECKER_SIZE = 4.0;
+const vec3 CHECKER_LIGHT = vec3(0.8);
+const vec3 CHECKER_DARK = vec3(0.6);
+const vec3 OUTLINE = vec3(1.0, 0.25, 0.2);
+const vec3 BORDER = vec3(0.1);
+
+// whether a point is inside a rectangle of left, top, right, bottom
+bool inside(vec2 point, vec4 rect) {
+    return point.x >= rect.x && point.y >= rect.y && point.x <= rect.z && point.y <= rect.w;
+}
+
+void main() {
+    vec4 color = textureLod(sampler2D(t_diffuse, s_diffuse), v_tex_coords, 0.0);
+    vec2 cell = floor(v_tex_coords / pixel / CHECKER_SIZE);
+    vec3 checker = mod(cell.x + cell.y, 2.0) < 1.0 ? CHECKER_LIGHT : CHECKER_DARK;
+    vec3 rgb = mix(checker, color.rgb, color.a);
+
+    // darken what's out of view, and outline what's in it two pixels thick
+    vec4 outer = visible + vec4(-pixel, pixel);
+    vec4 inner = visible + vec4(pixel, -pixel);
+    if (!inside(v_tex_coords, outer)) {
+        rgb *= 0.6;
+    } else if (!inside(v_tex_coords, inner)) {
+        rgb = OUTLINE;
+    }
+
+    // and a border around the whole thing so it stands out from the canvas behind it
+    if (!inside(v_tex_coords, vec4(pixel, vec2(1.0) - pixel))) {
+        rgb = BORDER;
+    }
+
+    f_color = vec4(rgb, 1.0);
+}
@@ -7,7 +7,63 @@ layout(location=0) out vec4 f_color;
 layout(set=0, binding=0) uniform texture2D t_diffuse;
 layout(set=0, binding=1) uniform sampler s_diffuse;
 
+layout(set=1, binding=0) uniform Uniform {
+    float scale_x;
+    float scale_y;
+    float xform_x;
+    float xform_y;
+    float zoom_x;
+    float zoom_y;
+    float rotation;
+    float aspect;
+    float minify_samples;
+    float _pad0;
+    float _pad1;
+    float _pad2;
+};
+
+// canvas pixels per side of each square of the checkerboard behind transparent pixels
+const float CHECKER_SIZE = 8.0;
+const vec3 CHECKER_LIGHT = vec3(0.8);
+const vec3 CHECKER_DARK = vec3(0.6);
+
+// the canvas color over the checkerboard, which is in canvas pixels so it moves and zooms with the
+// canvas. the result is opaque, so nothing behind the quad shows through
+vec4 over_checker(vec4 color, vec2 texel) {
+    vec2 cell = floor(texel / CHECKER_SIZE);
+    vec3 checker = mod(cell.x + cell.y, 2.0) < 1.0 ? CHECKER_LIGHT : CHECKER_DARK;
+    return vec4(mix(checker, color.rgb, color.a), 1.0);
+}
+
 void main() {
     //f_color = vec4(0.3, 0.95, 0.12, 1.0);
-    f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
+
+    // how far apart neighboring fragments are on the canvas, in texels
+    vec2 texels = vec2(textureSize(sampler2D(t_diffuse, s_diffuse), 0));
+    vec2 dx = dFdx(v_tex_coords);
+    vec2 dy = dFdy(v_tex_coords);
+    float footprint = max(length(dx * texels), length(dy * texels));
+
+    int samples = int(minify_samples);
+    if (samples <= 1 || footprint <= 1.0) {
+        vec4 color = textureLod(sampler2D(t_diffuse, s_diffuse), v_tex_coords, 0.0);
+        f_color = over_checker(color, v_tex_coords * texels);
+        return;
+    }
+
+    // average a grid of samples spread over the whole fragment, so texels between the fragment
+    // centers count too. premultiplied so transparent texels don't tint their neighbors
+    vec4 sum = vec4(0.0);
+    for (int i = 0; i < samples; i++) {
+        for (int j = 0; j < samples; j++) {
+            vec2 offset = (vec2(float(i), float(j)) + 0.5) / float(samples) - 0.5;
+            vec2 coords = v_tex_coords + offset.x * dx + offset.y * dy;
+            vec4 texel = textureLod(sampler2D(t_diffuse, s_diffuse), coords, 0.0);
+            sum += vec4(texel.rgb * texel.a, texel.a);
+        }
+    }
+
+    sum /= float(samples * samples);
+    vec4 color = sum.a > 0.0 ? vec4(sum.rgb / sum.a, sum.a) : vec4(0.0);
+    f_color = over_checker(color, v_tex_coords * texels);
 }
@@ -10,15 +10,30 @@ layout(set=1, binding=0) uniform Uniform {
     float scale_y;
     float xform_x;
     float xform_y;
-    float zoom;
+    float zoom_x;
+    float zoom_y;
+    float rotation;
+    float aspect;
+    float minify_samples;
+    float _pad0;
+    float _pad1;
+    float _pad2;
 };
 
 void main() {
     v_tex_coords = a_tex_coords;
 
-    vec2 pos = zoom * a_position;
+    vec2 pos = vec2(zoom_x, zoom_y) * a_position;
     pos.x *= scale_x;
     pos.y *= scale_y;
 
+    // rotate with square pixels, not in stretched clip space
+    pos.x *= aspect;
+    pos = mat2(cos(rotation), sin(rotation), -sin(rotation), cos(rotation)) * pos;
+    pos.x /= aspect;
+
+    // panned last, so it's in window pixels no matter the zoom or rotation
+    pos += vec2(xform_x, xform_y);
+
     gl_Position = vec4(pos, 0.0, 1.0);
 }
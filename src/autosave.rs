@@ -0,0 +1,235 @@
+use crate::{
+    composite::{NodeGraph, Port},
+    density::Density,
+    image::Image,
+    io_worker::{IoWorker, Job},
+    Result,
+};
+
+use anyhow::{anyhow, Context};
+
+use serde_json::{json, Value};
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Periodically writes the canvas and node graph to recovery files, so a crash doesn't lose
+/// everything.
+///
+/// Only writes when something has changed since the last save, and at most once per interval.
+/// The canvas goes to the path it's made with, and the graph next to it with a `.json` extension.
+#[derive(Debug)]
+pub struct AutoSave {
+    path: PathBuf,
+    interval: Duration,
+    last_save: Instant,
+    dirty: bool,
+}
+
+impl AutoSave {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(path: impl Into<PathBuf>, interval: Duration, now: Instant) -> AutoSave {
+        AutoSave {
+            path: path.into(),
+            interval,
+            last_save: now,
+            dirty: false,
+        }
+    }
+
+    /// Where the recovery file goes when nothing else is configured.
+    pub fn default_path() -> PathBuf {
+        std::env::temp_dir().join("yocto-canvas-recovery.png")
+    }
+
+    /// Where the canvas is saved.
+    pub fn canvas_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the graph is saved, if there is one.
+    pub fn graph_path(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    /// Whether there's anything left over from a session that didn't exit cleanly.
+    pub fn has_recovery(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Note that the document changed.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether it's time to write the recovery files.
+    pub fn should_save(&self, now: Instant) -> bool {
+        self.dirty && now.duration_since(self.last_save) >= self.interval
+    }
+
+    /// When the next save would happen, if nothing else changes.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.dirty {
+            Some(self.last_save + self.interval)
+        } else {
+            None
+        }
+    }
+
+    /// Queue up a save of the canvas and the graph showing `output` if it's time to, returning
+    /// whether it was.
+    pub fn tick(
+        &mut self,
+        canvas: &Image,
+        density: Option<Density>,
+        graph: Option<(&NodeGraph, &Port)>,
+        now: Instant,
+        io: &mut IoWorker,
+    ) -> bool {
+        if !self.should_save(now) {
+            return false;
+        }
+
+        self.dirty = false;
+        self.last_save = now;
+
+        io.submit(Job::SaveImage(self.path.clone(), canvas.clone(), density));
+        if let Some((graph, output)) = graph {
+            io.submit(Job::SaveText(
+                self.graph_path(),
+                graph_to_json(graph, output),
+            ));
+        }
+        true
+    }
+
+    /// Note that a recovery file was read back in. It's deleted, since what was in it only lives
+    /// in memory now, and everything gets saved again at the next tick.
+    pub fn restored(&mut self, path: &Path) -> Result<()> {
+        if path != self.path && path != self.graph_path() {
+            return Ok(());
+        }
+
+        self.mark_dirty();
+        std::fs::remove_file(path).with_context(|| format!("Couldn't delete {}", path.display()))
+    }
+
+    /// Delete the recovery files, once there's nothing in them worth keeping.
+    pub fn discard(&self) -> Result<()> {
+        for path in [self.path.clone(), self.graph_path()].iter() {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Couldn't delete {}", path.display()))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// {
+//   "graph": { ... as written by NodeGraph::to_value ... },
+//   "output": { "node": "MixRgba", "slot": "OUTPUT" }
+// }
+
+/// Serialize a graph for the recovery file, along with which of its outputs is shown.
+pub fn graph_to_json(graph: &NodeGraph, output: &Port) -> String {
+    json!({
+        "graph": graph.to_value(),
+        "output": { "node": output.node_name, "slot": output.slot_name },
+    })
+    .to_string()
+}
+
+/// Load a graph written by `graph_to_json`, and the output that was shown.
+pub fn graph_from_json(json: &str) -> Result<(NodeGraph, Port)> {
+    let value: Value = serde_json::from_str(json).context("Couldn't parse recovered graph")?;
+    let graph = NodeGraph::from_value(&value["graph"])?;
+
+    let node = value["output"]["node"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Recovered graph has no output node"))?;
+    let slot = value["output"]["slot"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Recovered graph has no output slot"))?;
+    let output = graph.port(node, slot, false)?;
+
+    Ok((graph, output))
+}
+
+#[test]
+fn autosave_only_when_dirty() {
+    use crate::{
+        composite::nodes::{MixRgba, Source},
+        image::Pixel,
+    };
+
+    let path = std::env::temp_dir().join(format!(
+        "yocto-canvas-autosave-test-{}.png",
+        std::process::id()
+    ));
+    let canvas = Image::from_solid(2, 2, Pixel::TRANSPARENT).unwrap();
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(canvas.clone().into_data())));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+    graph.connect(
+        Port {
+            node_name: source,
+            slot_name: Source::OUTPUT,
+        },
+        Port {
+            node_name: mix.clone(),
+            slot_name: MixRgba::INPUT_A,
+        },
+    );
+    let output = Port {
+        node_name: mix,
+        slot_name: MixRgba::OUTPUT_MIX,
+    };
+
+    let start = Instant::now();
+    let mut autosave = AutoSave::new(&path, Duration::from_secs(10), start);
+    let mut io = IoWorker::new(1, || {});
+
+    let mut tick = |autosave: &mut AutoSave, seconds| {
+        let now = start + Duration::from_secs(seconds);
+        autosave.tick(&canvas, None, Some((&graph, &output)), now, &mut io)
+    };
+
+    assert!(!tick(&mut autosave, 30));
+    assert_eq!(None, autosave.deadline());
+
+    autosave.mark_dirty();
+    assert!(!tick(&mut autosave, 5));
+    assert_eq!(Some(start + Duration::from_secs(10)), autosave.deadline());
+    assert!(tick(&mut autosave, 10));
+    assert_eq!(None, autosave.deadline());
+
+    // nothing's changed since
+    assert!(!tick(&mut autosave, 60));
+
+    autosave.mark_dirty();
+    assert!(!tick(&mut autosave, 15));
+    assert!(autosave.should_save(start + Duration::from_secs(20)));
+
+    // dropping the worker finishes the saves
+    drop(io);
+    assert!(autosave.has_recovery());
+    let (recovered, recovered_output) =
+        graph_from_json(&std::fs::read_to_string(autosave.graph_path()).unwrap()).unwrap();
+    assert_eq!(output, recovered_output);
+    assert_eq!(graph.to_json(), recovered.to_json());
+
+    autosave.restored(&autosave.graph_path()).unwrap();
+    assert!(!autosave.graph_path().exists());
+    autosave.discard().unwrap();
+    assert!(!autosave.has_recovery());
+    // already gone
+    autosave.discard().unwrap();
+}
@@ -5,18 +5,21 @@ use wgpu::{
     ColorTargetState, ColorWrite, CommandEncoder, CullMode, Device, FragmentState, FrontFace,
     LoadOp, MultisampleState, Operations, Origin3d, PipelineLayoutDescriptor, PolygonMode,
     PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachmentDescriptor,
-    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStage,
-    SwapChainDescriptor, SwapChainTexture, TextureCopyView, VertexState,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStage, TextureCopyView,
+    TextureFormat, TextureView, VertexState,
 };
 
+use bytemuck::Zeroable;
+
 use super::{Uniform, Vertex, VERTICES};
 
 use crate::{image::Image, texture::MyTexture, Result};
 
+use image_library::DynamicImage;
+
 pub struct CanvasPipeline {
     pub canvas_pipeline: RenderPipeline,
     pub canvas_texture: MyTexture,
-    pub canvas_image: Image,
     pub canvas_uniform_buffer: Buffer,
     pub canvas_uniform_bind_group: BindGroup,
     pub quad_vertex_buffer: Buffer,
@@ -27,7 +30,8 @@ impl CanvasPipeline {
         &self,
         encoder: &mut CommandEncoder,
         queue: &Queue,
-        frame: &SwapChainTexture,
+        target: &TextureView,
+        canvas: &Image,
         width: f32,
         height: f32,
     ) {
@@ -37,7 +41,7 @@ impl CanvasPipeline {
                 mip_level: 0,
                 origin: Origin3d::ZERO,
             },
-            &self.canvas_image.as_raw(),
+            &canvas.as_raw(),
             self.canvas_texture.layout.clone(),
             self.canvas_texture.size.clone(),
         );
@@ -46,7 +50,7 @@ impl CanvasPipeline {
             let mut rp = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("render pass"),
                 color_attachments: &[RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: target,
                     resolve_target: None,
                     ops: Operations {
                         load: LoadOp::Clear(wgpu::Color {
@@ -75,23 +79,28 @@ impl CanvasPipeline {
         }
     }
 
-    pub fn new(device: &Device, queue: &Queue, sc_desc: &SwapChainDescriptor) -> Result<Self> {
-        let (canvas_texture, image) = MyTexture::load(device, queue, "res/4751549.png")?;
-        //let (texture, image) = MyTexture::load(&device, &queue, "happy-tree.bdff8a19.png")?;
-
-        let canvas_image = Image::from(image);
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        canvas: &Image,
+    ) -> Result<Self> {
+        let (canvas_texture, _) = MyTexture::from_image(
+            device,
+            queue,
+            &DynamicImage::ImageRgba8(canvas.to_rgba_image()),
+            "canvas",
+        )?;
 
-        let initial_uniform = Uniform {
-            scale_x: 1.0,
-            scale_y: 1.0,
-            xform_x: 1.0,
-            xform_y: 1.0,
-            zoom: 1.0f32,
-        };
+        debug_assert!(
+            Uniform::validate_layout().is_ok(),
+            "{}",
+            Uniform::validate_layout().unwrap_err()
+        );
 
         let canvas_uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("uniform"),
-            contents: bytemuck::cast_slice(&[initial_uniform]),
+            contents: bytemuck::cast_slice(&[initial_uniform()]),
             usage: BufferUsage::UNIFORM | BufferUsage::COPY_DST,
         });
 
@@ -100,7 +109,8 @@ impl CanvasPipeline {
                 label: Some("uniform bgl"),
                 entries: &[BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: ShaderStage::VERTEX,
+                    // the fragment shader needs the sample count
+                    visibility: ShaderStage::VERTEX | ShaderStage::FRAGMENT,
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -157,8 +167,10 @@ impl CanvasPipeline {
             fragment: Some(FragmentState {
                 module: &fs_module,
                 entry_point: "main",
+                // the shader puts the canvas over a checkerboard itself, so every fragment is
+                // already opaque and there's nothing to blend with
                 targets: &[ColorTargetState {
-                    format: sc_desc.format,
+                    format,
                     alpha_blend: BlendState::REPLACE,
                     color_blend: BlendState::REPLACE,
                     write_mask: ColorWrite::ALL,
@@ -175,10 +187,161 @@ impl CanvasPipeline {
         Ok(Self {
             canvas_pipeline,
             canvas_texture,
-            canvas_image,
             canvas_uniform_buffer,
             canvas_uniform_bind_group,
             quad_vertex_buffer,
         })
     }
 }
+
+// fills the viewport, until the backend sets the real view
+fn initial_uniform() -> Uniform {
+    Uniform {
+        scale_x: 1.0,
+        scale_y: 1.0,
+        xform_x: 0.0,
+        xform_y: 0.0,
+        zoom_x: 1.0f32,
+        zoom_y: 1.0f32,
+        rotation: 0.0,
+        aspect: 1.0,
+        minify_samples: 1.0,
+        ..Zeroable::zeroed()
+    }
+}
+
+// cargo test minify_samples -- --ignored
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn minify_samples_reduce_aliasing() {
+    use crate::image::Pixel;
+    use wgpu::{
+        BackendBit, BufferCopyView, BufferDescriptor, CommandEncoderDescriptor, DeviceDescriptor,
+        Extent3d, Instance, Maintain, MapMode, RequestAdapterOptions, TextureDataLayout,
+        TextureDescriptor, TextureDimension, TextureUsage, TextureViewDescriptor,
+    };
+
+    let instance = Instance::new(BackendBit::PRIMARY);
+    let adapter = futures::executor::block_on(instance.request_adapter(&RequestAdapterOptions {
+        power_preference: Default::default(),
+        compatible_surface: None,
+    }))
+    .expect("no adapter");
+    let (device, queue) = futures::executor::block_on(adapter.request_device(
+        &DeviceDescriptor {
+            label: None,
+            features: Default::default(),
+            limits: Default::default(),
+        },
+        None,
+    ))
+    .unwrap();
+
+    // single pixel checkers, shrunk by a bit over 4, so nearest sampling lands on whatever
+    let mut canvas = Image::from_solid(256, 256, Pixel::TRANSPARENT).unwrap();
+    for y in 0..256 {
+        for x in 0..256 {
+            let value = ((x + y) % 2) as f32;
+            canvas.set_rgba(x, y, value, value, value, 1.0);
+        }
+    }
+
+    let size = 60;
+    let format = TextureFormat::Rgba8Unorm;
+    let pipeline = CanvasPipeline::new(&device, &queue, format, &canvas).unwrap();
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("target"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::COPY_SRC,
+    });
+    let view = target.create_view(&TextureViewDescriptor::default());
+
+    // rows have to be 256 byte aligned, which 60 pixels fits in
+    let bytes_per_row = 256;
+    let readback = device.create_buffer(&BufferDescriptor {
+        label: Some("readback"),
+        size: (bytes_per_row * size) as u64,
+        usage: BufferUsage::MAP_READ | BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // how far each pixel is from the flat gray the checkers average to, from 0 to 1
+    let render = |minify_samples: f32| {
+        let uniform = Uniform {
+            minify_samples,
+            ..initial_uniform()
+        };
+        queue.write_buffer(
+            &pipeline.canvas_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        pipeline.execute(
+            &mut encoder,
+            &queue,
+            &view,
+            &canvas,
+            size as f32,
+            size as f32,
+        );
+        encoder.copy_texture_to_buffer(
+            TextureCopyView {
+                texture: &target,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+            },
+            BufferCopyView {
+                buffer: &readback,
+                layout: TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row,
+                    rows_per_image: size,
+                },
+            },
+            Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let mapped = slice.map_async(MapMode::Read);
+        device.poll(Maintain::Wait);
+        futures::executor::block_on(mapped).unwrap();
+
+        let mut deviation = 0.0;
+        {
+            let bytes = slice.get_mapped_range();
+            for row in bytes.chunks(bytes_per_row as usize) {
+                for pixel in row[..size as usize * 4].chunks(4) {
+                    deviation += (pixel[0] as f32 / 255.0 - 0.5).abs() * 2.0;
+                }
+            }
+        }
+        readback.unmap();
+
+        deviation / (size * size) as f32
+    };
+
+    let nearest = render(1.0);
+    let supersampled = render(4.0);
+    assert!(nearest > 0.9, "nearest deviates {}", nearest);
+    assert!(
+        supersampled < nearest / 2.0,
+        "supersampled deviates {}, nearest {}",
+        supersampled,
+        nearest
+    );
+}
@@ -0,0 +1,157 @@
+use wgpu::{Features, Limits, PowerPreference, RequestAdapterOptions, Surface};
+
+/// Optional GPU paths the backend can be set up with, and which GPU to run them on.
+///
+/// Anything here that needs more than the baseline device features is negotiated with the adapter
+/// when the device is created, and turned off if the adapter can't do it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Run spatial nodes (blur and friends) as compute shaders instead of on the CPU.
+    pub compute_nodes: bool,
+    /// Whether to ask for the integrated or the discrete GPU, on machines that have both.
+    pub power_preference: PowerPreference,
+    /// How many samples per side to average when the canvas is zoomed out far enough that more
+    /// than one texel lands on each screen pixel. 0 and 1 both take a single sample, which is
+    /// fastest but shimmers on fine detail.
+    pub minify_samples: u32,
+    /// Time each frame on the GPU with timestamp queries. See `WgpuBackend::gpu_frame_time`.
+    pub gpu_timing: bool,
+}
+
+impl PipelineConfig {
+    /// What to ask the instance for when picking an adapter.
+    pub fn adapter_options<'a>(
+        &self,
+        compatible_surface: Option<&'a Surface>,
+    ) -> RequestAdapterOptions<'a> {
+        RequestAdapterOptions {
+            power_preference: self.power_preference,
+            compatible_surface,
+        }
+    }
+
+    /// Device features this configuration needs.
+    pub fn required_features(&self) -> Features {
+        let mut features = Features::empty();
+
+        if self.compute_nodes {
+            // node parameters go in push constants, and the intermediate images are read-write
+            // rgba32float storage textures, which aren't allowed without adapter-specific formats
+            features |=
+                Features::PUSH_CONSTANTS | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        }
+
+        if self.gpu_timing {
+            features |= Features::TIMESTAMP_QUERY;
+        }
+
+        features
+    }
+
+    /// Device limits this configuration needs.
+    pub fn required_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+
+        if self.compute_nodes {
+            limits.max_push_constant_size = 128;
+        }
+
+        limits
+    }
+
+    /// Turn off whatever the adapter doesn't have the features for.
+    ///
+    /// Those paths fall back to the CPU, so this never fails.
+    pub fn negotiate(self, available: Features) -> PipelineConfig {
+        let mut config = self;
+
+        if config.compute_nodes {
+            let missing = config.required_features() - available;
+            if !missing.is_empty() {
+                log::warn!(
+                    "compute nodes need {:?} which the adapter doesn't support, using the cpu instead",
+                    missing
+                );
+                config.compute_nodes = false;
+            }
+        }
+
+        if config.gpu_timing && !available.contains(Features::TIMESTAMP_QUERY) {
+            log::warn!("the adapter doesn't support timestamp queries, so frames won't be timed");
+            config.gpu_timing = false;
+        }
+
+        config
+    }
+}
+
+#[test]
+fn compute_requests_features() {
+    assert_eq!(
+        Features::empty(),
+        PipelineConfig::default().required_features()
+    );
+
+    let compute = PipelineConfig {
+        compute_nodes: true,
+        ..PipelineConfig::default()
+    };
+    assert_eq!(
+        Features::PUSH_CONSTANTS | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+        compute.required_features()
+    );
+    assert_eq!(128, compute.required_limits().max_push_constant_size);
+}
+
+#[test]
+fn negotiate_falls_back() {
+    let compute = PipelineConfig {
+        compute_nodes: true,
+        ..PipelineConfig::default()
+    };
+
+    assert_eq!(compute, compute.negotiate(Features::all()));
+    assert_eq!(
+        PipelineConfig::default(),
+        compute.negotiate(Features::PUSH_CONSTANTS)
+    );
+    assert_eq!(
+        PipelineConfig::default(),
+        PipelineConfig::default().negotiate(Features::empty())
+    );
+
+    let timed = PipelineConfig {
+        gpu_timing: true,
+        ..PipelineConfig::default()
+    };
+    assert_eq!(Features::TIMESTAMP_QUERY, timed.required_features());
+    assert_eq!(timed, timed.negotiate(Features::TIMESTAMP_QUERY));
+    assert_eq!(
+        PipelineConfig::default(),
+        timed.negotiate(Features::PUSH_CONSTANTS)
+    );
+}
+
+#[test]
+fn adapter_options_power_preference() {
+    assert_eq!(
+        PowerPreference::default(),
+        PipelineConfig::default()
+            .adapter_options(None)
+            .power_preference
+    );
+
+    let discrete = PipelineConfig {
+        power_preference: PowerPreference::HighPerformance,
+        ..PipelineConfig::default()
+    };
+    let options = discrete.adapter_options(None);
+    assert_eq!(PowerPreference::HighPerformance, options.power_preference);
+    assert!(options.compatible_surface.is_none());
+
+    // negotiating features doesn't lose the preference
+    assert_eq!(
+        PowerPreference::HighPerformance,
+        discrete.negotiate(Features::empty()).power_preference
+    );
+}
@@ -0,0 +1,216 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsage,
+    ColorTargetState, ColorWrite, CommandEncoder, CullMode, Device, FragmentState, FrontFace,
+    LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+    PrimitiveTopology, Queue, RenderPassColorAttachmentDescriptor, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderStage, TextureFormat, TextureView, VertexState,
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use winit::dpi::PhysicalSize;
+
+use super::{Vertex, VERTICES};
+
+use crate::minimap::Minimap;
+
+/// Draws the minimap over the canvas, from the same texture the canvas pipeline uploads to.
+pub struct MinimapPipeline {
+    pipeline: RenderPipeline,
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+    vertex_buffer: Buffer,
+    // whether there's a minimap to draw at all, as of the last update
+    shown: bool,
+}
+
+/// What the minimap fragment shader needs besides the canvas texture.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct MinimapUniform {
+    /// The part of the canvas in view, as left, top, right, bottom in texture coordinates.
+    pub visible: [f32; 4],
+    /// One window pixel in texture coordinates, for the outline and the checkerboard.
+    pub pixel: [f32; 2],
+    pub _pad: [f32; 2],
+}
+
+const _: () = assert!(std::mem::size_of::<MinimapUniform>().is_multiple_of(16));
+
+impl MinimapPipeline {
+    /// `texture_layout` is the layout of the canvas texture's bind group, which is what gets
+    /// sampled.
+    pub fn new(device: &Device, format: TextureFormat, texture_layout: &BindGroupLayout) -> Self {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("minimap uniform"),
+            contents: bytemuck::cast_slice(&[MinimapUniform::zeroed()]),
+            usage: BufferUsage::UNIFORM | BufferUsage::COPY_DST,
+        });
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("minimap uniform bgl"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStage::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("minimap uniform b group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("minimap pipeline layout"),
+            bind_group_layouts: &[texture_layout, &uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../../shaders/minimap.vert.spv"));
+        let fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../../shaders/minimap.frag.spv"));
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("minimap pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc()],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                cull_mode: CullMode::None,
+                polygon_mode: PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                // opaque, like the canvas
+                targets: &[ColorTargetState {
+                    format,
+                    alpha_blend: BlendState::REPLACE,
+                    color_blend: BlendState::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        // rewritten whenever the minimap moves
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("minimap vertex buffer"),
+            contents: bytemuck::cast_slice(&VERTICES),
+            usage: BufferUsage::VERTEX | BufferUsage::COPY_DST,
+        });
+
+        MinimapPipeline {
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            vertex_buffer,
+            shown: false,
+        }
+    }
+
+    /// Move the minimap and its outline, or hide it if there's no minimap.
+    pub fn update(&mut self, queue: &Queue, window: PhysicalSize<u32>, minimap: Option<&Minimap>) {
+        let minimap = match minimap {
+            Some(minimap) => minimap,
+            None => {
+                self.shown = false;
+                return;
+            }
+        };
+
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&quad(minimap, window)),
+        );
+
+        let uniform = MinimapUniform {
+            visible: minimap.visible,
+            pixel: [
+                1.0 / minimap.bounds.size.width as f32,
+                1.0 / minimap.bounds.size.height as f32,
+            ],
+            _pad: [0.0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.shown = true;
+    }
+
+    /// Draw the minimap on top of whatever's in `target` already. `texture` is the canvas
+    /// texture's bind group.
+    pub fn execute(&self, encoder: &mut CommandEncoder, target: &TextureView, texture: &BindGroup) {
+        if !self.shown {
+            return;
+        }
+
+        let mut rp = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("minimap render pass"),
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rp.set_pipeline(&self.pipeline);
+        rp.set_bind_group(0, texture, &[]);
+        rp.set_bind_group(1, &self.uniform_bind_group, &[]);
+        rp.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rp.draw(0..VERTICES.len() as u32, 0..1);
+    }
+}
+
+// the canvas quad, moved and shrunk to where the minimap is in clip space
+fn quad(minimap: &Minimap, window: PhysicalSize<u32>) -> [Vertex; 6] {
+    let bounds = minimap.bounds;
+    let clip_x = |x: i32| 2.0 * x as f32 / window.width.max(1) as f32 - 1.0;
+    let clip_y = |y: i32| 1.0 - 2.0 * y as f32 / window.height.max(1) as f32;
+    let (left, right) = (clip_x(bounds.left()), clip_x(bounds.right()));
+    let (top, bottom) = (clip_y(bounds.top()), clip_y(bounds.bottom()));
+
+    VERTICES.map(|vertex| Vertex {
+        position: [
+            if vertex.position[0] < 0.0 {
+                left
+            } else {
+                right
+            },
+            if vertex.position[1] < 0.0 {
+                bottom
+            } else {
+                top
+            },
+        ],
+        ..vertex
+    })
+}
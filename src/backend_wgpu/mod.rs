@@ -3,18 +3,32 @@ use bytemuck::{Pod, Zeroable};
 use cgmath::Matrix4;
 
 use wgpu::{
-    BackendBit, BufferAddress, CommandEncoderDescriptor, Device, DeviceDescriptor, Features,
-    InputStepMode, Instance, PresentMode, Queue, RequestAdapterOptions, Surface, SwapChain,
-    SwapChainDescriptor, TextureUsage, VertexAttribute, VertexBufferLayout, VertexFormat,
+    AdapterInfo, BackendBit, BufferAddress, CommandEncoderDescriptor, Device, DeviceDescriptor,
+    InputStepMode, Instance, PowerPreference, PresentMode, Queue, Surface, SwapChain,
+    SwapChainDescriptor, TextureFormat, TextureUsage, VertexAttribute, VertexBufferLayout,
+    VertexFormat,
 };
 
 use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::{Context, Result};
+use crate::{
+    image::Image,
+    minimap::Minimap,
+    view::{self, View},
+    Context, Result,
+};
+
+use std::fmt;
 
 pub mod canvas;
+pub mod features;
+pub mod minimap;
+pub mod timing;
 
 use canvas::CanvasPipeline;
+use features::PipelineConfig;
+use minimap::MinimapPipeline;
+use timing::GpuTimer;
 
 pub struct WgpuBackend {
     pub surface: Surface,
@@ -23,29 +37,37 @@ pub struct WgpuBackend {
     pub swapchain: SwapChain,
     pub sc_desc: SwapChainDescriptor,
     pub canvas_pipeline: CanvasPipeline,
+    pub minimap_pipeline: MinimapPipeline,
     pub updated_uniforms: bool,
+    /// Only there if `PipelineConfig::gpu_timing` is on and the adapter can do it.
+    timer: Option<GpuTimer>,
+    adapter_info: AdapterInfo,
+    config: PipelineConfig,
+    // before negotiation, so rebuilding on another adapter can turn things back on
+    requested_config: PipelineConfig,
 }
 
 impl WgpuBackend {
-    pub async fn new(window: &Window) -> Result<Self> {
+    pub async fn new(window: &Window, config: PipelineConfig, canvas: &Image) -> Result<Self> {
         let size = window.inner_size();
         let instance = Instance::new(BackendBit::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
 
         let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: Default::default(),
-                compatible_surface: Some(&surface),
-            })
+            .request_adapter(&config.adapter_options(Some(&surface)))
             .await
-            .unwrap();
+            .context("Couldn't get adapter")?;
+
+        let adapter_info = adapter.get_info();
+        let requested_config = config;
+        let config = config.negotiate(adapter.features());
 
         let (device, queue) = adapter
             .request_device(
                 &DeviceDescriptor {
                     label: Some("device descriptor"),
-                    features: Features::empty(),
-                    limits: Default::default(),
+                    features: config.required_features(),
+                    limits: config.required_limits(),
                 },
                 None,
             )
@@ -62,28 +84,130 @@ impl WgpuBackend {
 
         let swapchain = device.create_swap_chain(&surface, &sc_desc);
 
-        let canvas_pipeline = CanvasPipeline::new(&device, &queue, &sc_desc)?;
+        let canvas_pipeline = CanvasPipeline::new(&device, &queue, sc_desc.format, canvas)?;
+        let minimap_pipeline = MinimapPipeline::new(
+            &device,
+            sc_desc.format,
+            &canvas_pipeline.canvas_texture.group_layout,
+        );
+
+        let timer = if config.gpu_timing {
+            Some(GpuTimer::new(&device, adapter.get_timestamp_period()))
+        } else {
+            None
+        };
 
-        Ok(WgpuBackend {
+        let backend = WgpuBackend {
             surface,
             device,
             queue,
             swapchain,
             sc_desc,
             canvas_pipeline,
+            minimap_pipeline,
             updated_uniforms: false,
-        })
+            timer,
+            adapter_info,
+            config,
+            requested_config,
+        };
+
+        log::info!("{}", backend.diagnostics());
+
+        Ok(backend)
+    }
+
+    /// Switch to the adapter picked with a different power preference.
+    ///
+    /// This throws away the device and everything created with it and starts over, so it's slow.
+    /// Prefer setting the preference in the config passed to `new`.
+    #[allow(dead_code)] // TODO settings menu
+    pub async fn set_power_preference(
+        &mut self,
+        window: &Window,
+        power_preference: PowerPreference,
+        canvas: &Image,
+    ) -> Result<()> {
+        let config = PipelineConfig {
+            power_preference,
+            ..self.requested_config
+        };
+
+        *self = WgpuBackend::new(window, config, canvas).await?;
+        Ok(())
+    }
+
+    /// Make a new canvas texture for a canvas of a different size. The minimap samples the
+    /// canvas texture, so it's rebuilt too.
+    pub fn replace_canvas(&mut self, canvas: &Image) -> Result<()> {
+        self.canvas_pipeline =
+            CanvasPipeline::new(&self.device, &self.queue, self.sc_desc.format, canvas)?;
+        self.minimap_pipeline = MinimapPipeline::new(
+            &self.device,
+            self.sc_desc.format,
+            &self.canvas_pipeline.canvas_texture.group_layout,
+        );
+        self.updated_uniforms = false;
+        Ok(())
+    }
+
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// The pipeline configuration after negotiating features with the adapter.
+    pub fn config(&self) -> PipelineConfig {
+        self.config
+    }
+
+    /// How many milliseconds the GPU spent on the last frame, if GPU timing is on.
+    #[allow(dead_code)] // TODO frame stats overlay
+    pub fn gpu_frame_time(&self) -> Option<f64> {
+        self.timer.as_ref()?.last()
+    }
+
+    pub fn swapchain_format(&self) -> TextureFormat {
+        self.sc_desc.format
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.sc_desc.present_mode
+    }
+
+    /// Collect what we know about the GPU setup, for bug reports.
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            adapter_info: self.adapter_info().clone(),
+            swapchain_format: self.swapchain_format(),
+            present_mode: self.present_mode(),
+            config: self.config(),
+        }
     }
 
     // TODO maybe write a trait eventually?
-    pub fn update(&mut self, size: &PhysicalSize<u32>, zoom: f32) {
+    pub fn update(
+        &mut self,
+        size: &PhysicalSize<u32>,
+        view: &View,
+        canvas: &Image,
+        minimap: Option<&Minimap>,
+    ) {
         if !self.updated_uniforms {
+            let (scale_x, scale_y) = view::fit_scale(canvas.width(), canvas.height(), *size);
+            let (zoom_x, zoom_y) = view.display_zoom(canvas.width(), canvas.height(), *size);
+            let (xform_x, xform_y) = view.pan_clip(*size);
+
             let uniform = Uniform {
-                scale_x: self.canvas_pipeline.canvas_image.width() as f32 / size.width as f32,
-                scale_y: self.canvas_pipeline.canvas_image.height() as f32 / size.height as f32,
-                xform_x: 0.0,
-                xform_y: 0.0,
-                zoom,
+                scale_x,
+                scale_y,
+                xform_x,
+                xform_y,
+                zoom_x,
+                zoom_y,
+                rotation: view.rotation.to_radians(),
+                aspect: view::aspect(*size),
+                minify_samples: self.config.minify_samples as f32,
+                ..Uniform::zeroed()
             };
 
             self.queue.write_buffer(
@@ -91,17 +215,26 @@ impl WgpuBackend {
                 0,
                 bytemuck::cast_slice(&[uniform]),
             );
+            self.minimap_pipeline.update(&self.queue, *size, minimap);
             self.updated_uniforms = true;
         }
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        log::debug!(
+            "recreating swapchain at {}x{}",
+            new_size.width,
+            new_size.height
+        );
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
         self.swapchain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        // the canvas is fit to the new size, even if the uniforms were already written this
+        // frame for the old one
+        self.updated_uniforms = false;
     }
 
-    pub fn render(&mut self, size: &PhysicalSize<u32>) -> Result<()> {
+    pub fn render(&mut self, size: &PhysicalSize<u32>, canvas: &Image) -> Result<()> {
         let frame = self.swapchain.get_current_frame()?.output;
         let mut encoder = self
             .device
@@ -109,21 +242,67 @@ impl WgpuBackend {
                 label: Some("command encoder"),
             });
 
+        if let Some(timer) = &self.timer {
+            timer.begin(&mut encoder);
+        }
+
         self.canvas_pipeline.execute(
             &mut encoder,
             &self.queue,
-            &frame,
+            &frame.view,
+            canvas,
             size.width as f32,
             size.height as f32,
         );
+        self.minimap_pipeline.execute(
+            &mut encoder,
+            &frame.view,
+            &self.canvas_pipeline.canvas_texture.group,
+        );
+
+        if let Some(timer) = &self.timer {
+            timer.end(&mut encoder);
+        }
 
         self.queue.submit(std::iter::once(encoder.finish()));
         self.updated_uniforms = false;
 
+        if let Some(timer) = &mut self.timer {
+            if let Some(ms) = timer.read(&self.device) {
+                log::debug!("gpu frame time: {:.3}ms", ms);
+            }
+        }
+
         Ok(())
     }
 }
 
+/// The adapter and swapchain configuration that was negotiated at startup.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub adapter_info: AdapterInfo,
+    pub swapchain_format: TextureFormat,
+    pub present_mode: PresentMode,
+    pub config: PipelineConfig,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "adapter: {} ({:?}, {:?}, vendor {:#06x}, device {:#06x}), swapchain: {:?}, {:?}, compute nodes: {}",
+            self.adapter_info.name,
+            self.adapter_info.backend,
+            self.adapter_info.device_type,
+            self.adapter_info.vendor,
+            self.adapter_info.device,
+            self.swapchain_format,
+            self.present_mode,
+            if self.config.compute_nodes { "on" } else { "off" },
+        )
+    }
+}
+
 #[rustfmt::skip]
 #[allow(dead_code)]
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
@@ -201,6 +380,11 @@ impl Vertex {
     }
 }
 
+/// Everything the shaders need to place and sample the canvas quad.
+///
+/// Uniform buffer bindings have to be a multiple of 16 bytes, so the `_pad` fields round the
+/// struct back up after `minify_samples`. Any new field should take the place of one of them,
+/// with matching members in both shaders.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct Uniform {
@@ -208,5 +392,146 @@ pub struct Uniform {
     pub scale_y: f32,
     pub xform_x: f32,
     pub xform_y: f32,
-    pub zoom: f32,
+    pub zoom_x: f32,
+    pub zoom_y: f32,
+    pub rotation: f32,
+    pub aspect: f32,
+    /// Samples per side averaged for each fragment when zoomed out, so 4 is 16 samples. 1 or
+    /// less picks the nearest texel.
+    pub minify_samples: f32,
+    pub _pad0: f32,
+    pub _pad1: f32,
+    pub _pad2: f32,
+}
+
+// catch a bad size at compile time rather than as a validation error on some drivers
+const _: () = assert!(std::mem::size_of::<Uniform>().is_multiple_of(16));
+
+impl Uniform {
+    /// The std140 layout of the `Uniform` block in `shaders/shader.vert.glsl`, as the byte offset
+    /// of each member. Keep this in sync with the shader, it's what the struct is checked against.
+    pub const SHADER_LAYOUT: &'static [(&'static str, usize)] = &[
+        ("scale_x", 0),
+        ("scale_y", 4),
+        ("xform_x", 8),
+        ("xform_y", 12),
+        ("zoom_x", 16),
+        ("zoom_y", 20),
+        ("rotation", 24),
+        ("aspect", 28),
+        ("minify_samples", 32),
+        ("_pad0", 36),
+        ("_pad1", 40),
+        ("_pad2", 44),
+    ];
+
+    /// Byte offsets of the fields of the struct, in declaration order.
+    pub fn field_offsets() -> Vec<(&'static str, usize)> {
+        let uniform = Uniform::zeroed();
+        let base = &uniform as *const Uniform as usize;
+        let offset = |field: &f32| field as *const f32 as usize - base;
+
+        vec![
+            ("scale_x", offset(&uniform.scale_x)),
+            ("scale_y", offset(&uniform.scale_y)),
+            ("xform_x", offset(&uniform.xform_x)),
+            ("xform_y", offset(&uniform.xform_y)),
+            ("zoom_x", offset(&uniform.zoom_x)),
+            ("zoom_y", offset(&uniform.zoom_y)),
+            ("rotation", offset(&uniform.rotation)),
+            ("aspect", offset(&uniform.aspect)),
+            ("minify_samples", offset(&uniform.minify_samples)),
+            ("_pad0", offset(&uniform._pad0)),
+            ("_pad1", offset(&uniform._pad1)),
+            ("_pad2", offset(&uniform._pad2)),
+        ]
+    }
+
+    /// Check that the struct can be copied byte for byte into the shader's uniform block.
+    pub fn validate_layout() -> Result<()> {
+        let size = std::mem::size_of::<Uniform>();
+        if !size.is_multiple_of(16) {
+            anyhow::bail!(
+                "Uniform is {} bytes, uniform buffers must be a multiple of 16",
+                size
+            );
+        }
+
+        let offsets = Uniform::field_offsets();
+        if offsets.as_slice() != Uniform::SHADER_LAYOUT {
+            anyhow::bail!(
+                "Uniform fields are laid out as {:?}, but the shader expects {:?}",
+                offsets,
+                Uniform::SHADER_LAYOUT
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn diagnostics_string() {
+    use wgpu::{Backend, DeviceType};
+
+    let diagnostics = Diagnostics {
+        adapter_info: AdapterInfo {
+            name: String::from("Cool GPU 9000"),
+            vendor: 0x10de,
+            device: 0x1b80,
+            device_type: DeviceType::DiscreteGpu,
+            backend: Backend::Vulkan,
+        },
+        swapchain_format: TextureFormat::Bgra8UnormSrgb,
+        present_mode: PresentMode::Fifo,
+        config: PipelineConfig::default(),
+    };
+
+    assert_eq!(
+        "adapter: Cool GPU 9000 (Vulkan, DiscreteGpu, vendor 0x10de, device 0x1b80), swapchain: Bgra8UnormSrgb, Fifo, compute nodes: off",
+        diagnostics.to_string()
+    );
+}
+
+#[test]
+fn uniform_matches_shader_layout() {
+    Uniform::validate_layout().unwrap();
+    assert_eq!(0, std::mem::size_of::<Uniform>() % 16);
+
+    let documented = Uniform::SHADER_LAYOUT
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    // both stages declare the block, and they have to agree
+    for glsl in &[
+        include_str!("../../shaders/shader.vert.glsl"),
+        include_str!("../../shaders/shader.frag.glsl"),
+    ] {
+        let block = &glsl[glsl.find("uniform Uniform {").unwrap()..];
+        let block = &block[..block.find("};").unwrap()];
+        let members = block
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("float "))
+            .map(|member| member.trim_end_matches(';'))
+            .collect::<Vec<_>>();
+        assert_eq!(documented, members);
+    }
+}
+
+#[test]
+fn uniform_is_aligned() {
+    assert_eq!(48, std::mem::size_of::<Uniform>());
+    assert_eq!(0, std::mem::size_of::<Uniform>() % 16);
+
+    // tightly packed floats, in the same order as the shader
+    for (i, ((field, offset), (member, shader_offset))) in Uniform::field_offsets()
+        .into_iter()
+        .zip(Uniform::SHADER_LAYOUT.iter().copied())
+        .enumerate()
+    {
+        assert_eq!(member, field);
+        assert_eq!(i * 4, offset);
+        assert_eq!(shader_offset, offset);
+    }
 }
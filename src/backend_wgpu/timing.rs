@@ -0,0 +1,102 @@
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsage, CommandEncoder, Device, Maintain, MapMode, QuerySet,
+    QuerySetDescriptor, QueryType,
+};
+
+// bytes per resolved timestamp
+const QUERY_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// Measures how long the GPU spends on each frame, with a timestamp before and after.
+///
+/// Needs `Features::TIMESTAMP_QUERY`. Reading the result back waits for the GPU to finish the
+/// frame, so this slows things down a little and should only be on while tuning.
+pub struct GpuTimer {
+    queries: QuerySet,
+    readback: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    last: Option<f64>,
+}
+
+impl GpuTimer {
+    /// `period` is from `Adapter::get_timestamp_period`.
+    pub fn new(device: &Device, period: f32) -> GpuTimer {
+        let queries = device.create_query_set(&QuerySetDescriptor {
+            ty: QueryType::Timestamp,
+            count: 2,
+        });
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("timestamp readback"),
+            size: 2 * QUERY_SIZE,
+            usage: BufferUsage::MAP_READ | BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        GpuTimer {
+            queries,
+            readback,
+            period,
+            last: None,
+        }
+    }
+
+    /// Write the starting timestamp. Everything encoded between this and `end` is timed.
+    pub fn begin(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.queries, 0);
+    }
+
+    /// Write the ending timestamp, and copy both out to be read.
+    pub fn end(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.queries, 1);
+        encoder.resolve_query_set(&self.queries, 0..2, &self.readback, 0);
+    }
+
+    /// Wait for the timed commands to finish, and read how long they took. Call after the
+    /// encoder passed to `begin` and `end` has been submitted.
+    pub fn read(&mut self, device: &Device) -> Option<f64> {
+        let slice = self.readback.slice(..);
+        let mapped = slice.map_async(MapMode::Read);
+        device.poll(Maintain::Wait);
+
+        self.last = match futures::executor::block_on(mapped) {
+            Ok(()) => {
+                let elapsed = {
+                    let bytes = slice.get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&bytes);
+                    timestamps_to_ms(timestamps[0], timestamps[1], self.period)
+                };
+                self.readback.unmap();
+                Some(elapsed)
+            }
+            Err(error) => {
+                log::warn!("couldn't read gpu timestamps: {:?}", error);
+                None
+            }
+        };
+
+        self.last
+    }
+
+    /// Milliseconds the last frame took, if it was read successfully.
+    pub fn last(&self) -> Option<f64> {
+        self.last
+    }
+}
+
+/// Convert a pair of raw timestamps to the milliseconds between them.
+pub fn timestamps_to_ms(start: u64, end: u64, period: f32) -> f64 {
+    // the counter can wrap around, but not more than once in a frame
+    end.wrapping_sub(start) as f64 * period as f64 / 1_000_000.
+}
+
+#[test]
+fn timestamps_convert_with_period() {
+    // one tick per nanosecond
+    assert_eq!(16.0, timestamps_to_ms(1_000, 16_001_000, 1.0));
+    // slower clocks count fewer ticks in the same time
+    assert_eq!(2.5, timestamps_to_ms(0, 31_250, 80.0));
+    assert_eq!(0.0, timestamps_to_ms(500, 500, 52.08));
+    // around the end of the counter
+    assert_eq!(0.001, timestamps_to_ms(u64::MAX - 499, 500, 1.0));
+}
@@ -0,0 +1,216 @@
+use super::{NodeGraph, Port};
+
+use serde_json::Value;
+
+/// One difference between two versions of a graph. See `NodeGraph::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphChange {
+    NodeAdded {
+        name: String,
+        /// The `Node::name` of the new node.
+        node_type: &'static str,
+    },
+    NodeRemoved {
+        name: String,
+    },
+    EdgeAdded {
+        from: Port,
+        to: Port,
+    },
+    EdgeRemoved {
+        from: Port,
+        to: Port,
+    },
+    PropertiesChanged {
+        name: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl NodeGraph {
+    /// What changed to get from this graph to `other`.
+    ///
+    /// Nodes are matched up by name, and a node whose type changed counts as removed and added
+    /// again. The connections of removed and added nodes are listed too, so the changes can be
+    /// applied in order: removed edges, removed nodes, added nodes, added edges, then properties.
+    /// Each group is sorted by node name, so the same two graphs always give the same list.
+    pub fn diff(&self, other: &NodeGraph) -> Vec<GraphChange> {
+        let same_node = |name: &str| {
+            matches!(
+                (self.nodes.get(name), other.nodes.get(name)),
+                (Some(old), Some(new)) if old.name() == new.name()
+            )
+        };
+
+        let old_edges = self.edges();
+        let new_edges = other.edges();
+
+        let mut changes = Vec::new();
+        changes.extend(
+            old_edges
+                .iter()
+                .filter(|edge| !new_edges.contains(edge))
+                .map(|(from, to)| GraphChange::EdgeRemoved {
+                    from: from.clone(),
+                    to: to.clone(),
+                }),
+        );
+        changes.extend(
+            sorted_names(self)
+                .into_iter()
+                .filter(|name| !same_node(name))
+                .map(|name| GraphChange::NodeRemoved {
+                    name: name.to_string(),
+                }),
+        );
+        changes.extend(
+            sorted_names(other)
+                .into_iter()
+                .filter(|name| !same_node(name))
+                .map(|name| GraphChange::NodeAdded {
+                    name: name.to_string(),
+                    node_type: other.nodes[name].name(),
+                }),
+        );
+        changes.extend(
+            new_edges
+                .iter()
+                .filter(|edge| !old_edges.contains(edge))
+                .map(|(from, to)| GraphChange::EdgeAdded {
+                    from: from.clone(),
+                    to: to.clone(),
+                }),
+        );
+
+        for name in sorted_names(self) {
+            if !same_node(name) {
+                continue;
+            }
+
+            let old = self.nodes[name].properties();
+            let new = other.nodes[name].properties();
+            if old != new {
+                changes.push(GraphChange::PropertiesChanged {
+                    name: name.to_string(),
+                    old,
+                    new,
+                });
+            }
+        }
+
+        changes
+    }
+
+    // every connection in the graph, sorted by the input it goes into. inputs only have one source,
+    // so that's enough to order them
+    fn edges(&self) -> Vec<(Port, Port)> {
+        let mut edges = Vec::new();
+        for name in sorted_names(self) {
+            let node = &self.nodes[name];
+            for &slot in node.inputs() {
+                if let Some(source) = node.input_source(slot) {
+                    let to = Port {
+                        node_name: name.to_string(),
+                        slot_name: slot,
+                    };
+                    edges.push((source.clone(), to));
+                }
+            }
+        }
+
+        edges.sort_by(|(_, a), (_, b)| {
+            (&a.node_name, a.slot_name).cmp(&(&b.node_name, b.slot_name))
+        });
+        edges
+    }
+}
+
+fn sorted_names(graph: &NodeGraph) -> Vec<&str> {
+    let mut names = graph.nodes.keys().map(String::as_str).collect::<Vec<_>>();
+    names.sort_unstable();
+    names
+}
+
+#[test]
+fn diff_added_node_and_removed_edge() {
+    use super::{
+        nodes::{MixRgba, MotionBlur, Source},
+        Node,
+    };
+    use crate::image::{Image, Pixel};
+
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(
+        Image::from_solid(2, 2, Pixel::TRANSPARENT)
+            .unwrap()
+            .into_data(),
+    )));
+    let blur = graph.add(Box::new(MotionBlur::new(0.0, 2.0)));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+    graph.connect(
+        port(&source, Source::OUTPUT),
+        port(&blur, MotionBlur::INPUT),
+    );
+    graph.connect(
+        port(&blur, MotionBlur::OUTPUT),
+        port(&mix, MixRgba::INPUT_A),
+    );
+    graph.connect(port(&source, Source::OUTPUT), port(&mix, MixRgba::INPUT_B));
+
+    let mut copy = NodeGraph::from_json(&graph.to_json()).unwrap();
+    assert_eq!(Vec::<GraphChange>::new(), graph.diff(&copy));
+
+    let added = copy.add(Box::new(MixRgba::new(0.25)));
+    copy.disconnect(&port(&mix, MixRgba::INPUT_B));
+
+    assert_eq!(
+        vec![
+            GraphChange::EdgeRemoved {
+                from: port(&source, Source::OUTPUT),
+                to: port(&mix, MixRgba::INPUT_B),
+            },
+            GraphChange::NodeAdded {
+                name: added,
+                node_type: "MixRgba",
+            },
+        ],
+        graph.diff(&copy)
+    );
+
+    // a removed node takes its edges with it, and a node of the same type added under the same name
+    // only changes properties
+    let mut changed = NodeGraph::from_json(&graph.to_json()).unwrap();
+    changed.remove_node(&blur);
+    changed.remove_node(&mix);
+    changed.add(Box::new(MixRgba::new(0.75)));
+
+    assert_eq!(
+        vec![
+            GraphChange::EdgeRemoved {
+                from: port(&blur, MotionBlur::OUTPUT),
+                to: port(&mix, MixRgba::INPUT_A),
+            },
+            GraphChange::EdgeRemoved {
+                from: port(&source, Source::OUTPUT),
+                to: port(&mix, MixRgba::INPUT_B),
+            },
+            GraphChange::EdgeRemoved {
+                from: port(&source, Source::OUTPUT),
+                to: port(&blur, MotionBlur::INPUT),
+            },
+            GraphChange::NodeRemoved { name: blur },
+            GraphChange::PropertiesChanged {
+                name: mix,
+                old: MixRgba::new(0.5).properties(),
+                new: MixRgba::new(0.75).properties(),
+            },
+        ],
+        graph.diff(&changed)
+    );
+}
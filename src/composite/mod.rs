@@ -1,10 +1,21 @@
 #![allow(dead_code)]
 
-use crate::image::ImageData;
+use crate::{
+    geometry::Rect,
+    image::{ImageData, Pixel},
+};
 
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::{self, Debug, Display},
+    hash::{Hash, Hasher},
+};
 
+mod diff;
 pub mod nodes;
+mod serialize;
+mod stats;
+mod tiled;
 
 // generate a new node name
 fn format_name(s: &str, i: usize) -> String {
@@ -29,11 +40,38 @@ pub trait Node: Debug {
 
     /// TODO Execute the node.
     ///
-    /// Meant to only be called by NodeGraph.
+    /// Meant to only be called by NodeGraph. Takes `&mut self` so that stateful nodes can carry
+    /// something over from one evaluation to the next, like `Accumulate` does.
     fn execute(
-        &self,
+        &mut self,
         input: HashMap<&'static str, ImageData>,
-    ) -> Option<HashMap<&'static str, ImageData>>;
+        context: &EvalContext,
+    ) -> Result<HashMap<&'static str, ImageData>, NodeError>;
+
+    /// The node's properties as a JSON object, for serialization.
+    fn properties(&self) -> serde_json::Value;
+
+    /// Forget whatever state the node has built up over previous evaluations. Most nodes don't
+    /// have any.
+    fn reset(&mut self) {}
+
+    /// Whether the node keeps state from one evaluation to the next, so its output can change
+    /// even when its inputs and properties don't. Stateful nodes aren't cached.
+    fn is_stateful(&self) -> bool {
+        false
+    }
+
+    /// Whether the node can be evaluated a piece at a time, and how. `None` if it needs all of
+    /// its inputs at once, which is the safe answer.
+    fn tiling(&self, _context: &EvalContext) -> Option<Tiling> {
+        None
+    }
+
+    /// Names of the node's input slots.
+    fn inputs(&self) -> &'static [&'static str];
+
+    /// Names of the node's output slots.
+    fn outputs(&self) -> &'static [&'static str];
 
     /// Get the node and output slot connected to the input slot.
     fn input_source(&self, input_slot: &'static str) -> Option<&Port>;
@@ -46,6 +84,9 @@ pub trait Node: Debug {
     /// Data flows from `source_port.node_name.output_port_name` to `self.input_slot`.
     fn connect_input(&mut self, input_slot: &'static str, source_port: Port);
 
+    /// Clear the connection to the input slot, if there is one.
+    fn disconnect_input(&mut self, input_slot: &'static str);
+
     /// Connect the output slot to the destination port.
     ///
     /// Data flows from `self.output_slot` to `destination_port.node_name.input_port_name`.
@@ -59,12 +100,221 @@ pub trait Node: Debug {
     /// Check if the node has a connection from `self.output_slot` to `destination_port.node_name.input_port_name`.
     fn has_connection(&self, output_slot: &'static str, destination_port: &Port) -> bool {
         self.output_destinations(output_slot)
-            .map_or(false, |destinations| {
-                destinations.contains(destination_port)
+            .is_some_and(|destinations| destinations.contains(destination_port))
+    }
+}
+
+/// How a node can be evaluated one region at a time. See `NodeGraph::evaluate_tiled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiling {
+    /// The node's output is the same size as its inputs, and each output pixel only depends on
+    /// the input pixels at most `halo` pixels away from it.
+    Local { halo: u32 },
+    /// The node has no inputs and produces an image of this size, only making the part of it in
+    /// `EvalContext::region` if there is one.
+    Generator { width: u32, height: u32 },
+}
+
+/// How good the output of an evaluation needs to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Evaluate at a fraction of the resolution, for fast feedback while the user is editing.
+    Preview,
+    /// Evaluate at the native resolution.
+    Full,
+}
+
+impl Quality {
+    /// How much smaller than native images are evaluated at `Preview` quality.
+    pub const PREVIEW_SCALE: f32 = 0.25;
+}
+
+/// Which color space blending nodes do their math in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BlendSpace {
+    /// Blend the sRGB-encoded values directly, like most image editors do by default. Midtones
+    /// between light and dark colors come out darker than they physically should.
+    Srgb,
+    /// Decode to linear light, blend, and encode again. Physically correct, but doesn't match
+    /// what most other software does.
+    Linear,
+}
+
+impl BlendSpace {
+    /// Convert an sRGB pixel into the space blending happens in.
+    pub fn decode(&self, pixel: Pixel) -> Pixel {
+        match self {
+            BlendSpace::Srgb => pixel,
+            BlendSpace::Linear => pixel.to_linear(),
+        }
+    }
+
+    /// Convert a blended pixel back to sRGB.
+    pub fn encode(&self, pixel: Pixel) -> Pixel {
+        match self {
+            BlendSpace::Srgb => pixel,
+            BlendSpace::Linear => pixel.to_srgb(),
+        }
+    }
+}
+
+/// Settings that apply to the whole graph, for matching the behavior of other software.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompositeSettings {
+    pub blend_space: BlendSpace,
+}
+
+impl Default for CompositeSettings {
+    fn default() -> Self {
+        CompositeSettings {
+            blend_space: BlendSpace::Srgb,
+        }
+    }
+}
+
+/// Settings for a whole evaluation of the graph, passed to each node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalContext {
+    pub quality: Quality,
+    pub composite: CompositeSettings,
+    /// The part of its output a `Tiling::Generator` node needs to make. Everything if `None`.
+    pub region: Option<Rect>,
+    /// How far into the animation the graph is being evaluated, in seconds.
+    pub time: f32,
+}
+
+impl EvalContext {
+    /// How much images are scaled relative to their native size.
+    ///
+    /// Source nodes scale their output by this, and nodes with properties measured in pixels
+    /// should scale those too.
+    pub fn scale(&self) -> f32 {
+        match self.quality {
+            Quality::Preview => Quality::PREVIEW_SCALE,
+            Quality::Full => 1.0,
+        }
+    }
+
+    /// Scale a size in pixels, keeping it at least one pixel.
+    pub fn scale_size(&self, size: u32) -> u32 {
+        ((size as f32 * self.scale()).round() as u32).max(1)
+    }
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        EvalContext {
+            quality: Quality::Full,
+            composite: CompositeSettings::default(),
+            region: None,
+            time: 0.,
+        }
+    }
+}
+
+/// Why a node couldn't produce its output.
+///
+/// `node` is the name of the node type when returned from `Node::execute`, and the name of the node
+/// in the graph when returned from `NodeGraph::evaluate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeError {
+    /// There is no node in the graph with this name.
+    NoSuchNode(String),
+    /// Nothing is connected to an input slot the node needs.
+    MissingInput { node: String, slot: &'static str },
+    /// The node doesn't have the requested output slot, or didn't produce anything for it.
+    MissingOutput { node: String, slot: &'static str },
+    /// Two inputs that need to be the same size aren't.
+    SizeMismatch {
+        node: String,
+        a: (u32, u32),
+        b: (u32, u32),
+    },
+    /// Something the node loads from outside of the graph, like a file, couldn't be loaded.
+    Resource { node: String, message: String },
+    /// The node's properties don't make sense together.
+    InvalidProperty { node: String, message: String },
+}
+
+impl NodeError {
+    /// Remove an input from the map passed to `Node::execute`.
+    pub fn take_input(
+        node: &dyn Node,
+        input: &mut HashMap<&'static str, ImageData>,
+        slot: &'static str,
+    ) -> Result<ImageData, NodeError> {
+        input.remove(slot).ok_or_else(|| NodeError::MissingInput {
+            node: node.name().to_string(),
+            slot,
+        })
+    }
+
+    /// Check that two inputs have the same dimensions.
+    pub fn check_sizes(node: &dyn Node, a: &ImageData, b: &ImageData) -> Result<(), NodeError> {
+        if (a.width, a.height) == (b.width, b.height) {
+            Ok(())
+        } else {
+            Err(NodeError::SizeMismatch {
+                node: node.name().to_string(),
+                a: (a.width, a.height),
+                b: (b.width, b.height),
             })
+        }
+    }
+
+    fn with_node_name(self, name: &str) -> NodeError {
+        match self {
+            NodeError::NoSuchNode(node) => NodeError::NoSuchNode(node),
+            NodeError::MissingInput { slot, .. } => NodeError::MissingInput {
+                node: name.to_string(),
+                slot,
+            },
+            NodeError::MissingOutput { slot, .. } => NodeError::MissingOutput {
+                node: name.to_string(),
+                slot,
+            },
+            NodeError::SizeMismatch { a, b, .. } => NodeError::SizeMismatch {
+                node: name.to_string(),
+                a,
+                b,
+            },
+            NodeError::Resource { message, .. } => NodeError::Resource {
+                node: name.to_string(),
+                message,
+            },
+            NodeError::InvalidProperty { message, .. } => NodeError::InvalidProperty {
+                node: name.to_string(),
+                message,
+            },
+        }
+    }
+}
+
+impl Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::NoSuchNode(node) => write!(f, "no node named {}", node),
+            NodeError::MissingInput { node, slot } => {
+                write!(f, "nothing connected to {}.{}", node, slot)
+            }
+            NodeError::MissingOutput { node, slot } => {
+                write!(f, "{} has no output {}", node, slot)
+            }
+            NodeError::SizeMismatch { node, a, b } => write!(
+                f,
+                "inputs to {} have different sizes: {}x{} and {}x{}",
+                node, a.0, a.1, b.0, b.1
+            ),
+            NodeError::Resource { node, message } => write!(f, "{}: {}", node, message),
+            NodeError::InvalidProperty { node, message } => {
+                write!(f, "invalid properties on {}: {}", node, message)
+            }
+        }
     }
 }
 
+impl std::error::Error for NodeError {}
+
 /// Represents a single end of a node graph connection
 #[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct Port {
@@ -76,6 +326,12 @@ pub struct Port {
 #[derive(Debug)]
 pub struct NodeGraph {
     nodes: HashMap<String, Box<dyn Node>>,
+    /// Where each node sits in the editor. Not every node has to have a position.
+    positions: HashMap<String, (f32, f32)>,
+    pub settings: CompositeSettings,
+    /// Every output of each node from when it was last executed, and the hash of everything that
+    /// went into it. See `evaluate_with`.
+    cache: HashMap<String, (u64, HashMap<&'static str, ImageData>)>,
 }
 
 // TODO check for cycles
@@ -84,9 +340,29 @@ impl NodeGraph {
     pub fn new() -> Self {
         NodeGraph {
             nodes: HashMap::new(),
+            positions: HashMap::new(),
+            settings: CompositeSettings::default(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Forget every cached node output, to free up the memory.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Set where a node sits in the editor.
+    pub fn set_position(&mut self, node_name: &str, position: (f32, f32)) {
+        if self.nodes.contains_key(node_name) {
+            self.positions.insert(node_name.to_string(), position);
         }
     }
 
+    /// Get where a node sits in the editor, if it has been placed.
+    pub fn position(&self, node_name: &str) -> Option<(f32, f32)> {
+        self.positions.get(node_name).copied()
+    }
+
     /// Add a node to the graph. Returns the name of the node.
     ///
     /// Use `connect` to add connections to the node.
@@ -101,20 +377,123 @@ impl NodeGraph {
         name
     }
 
+    /// Remove a node from the graph along with all of its connections.
+    pub fn remove_node(&mut self, node_name: &str) -> Option<Box<dyn Node>> {
+        let node = self.nodes.remove(node_name)?;
+        self.positions.remove(node_name);
+        self.cache.remove(node_name);
+
+        for &slot in node.inputs() {
+            if let Some(source) = node.input_source(slot) {
+                if let Some(source_node) = self.nodes.get_mut(&source.node_name) {
+                    source_node.remove_output(
+                        source.slot_name,
+                        &Port {
+                            node_name: node_name.to_string(),
+                            slot_name: slot,
+                        },
+                    );
+                }
+            }
+        }
+
+        for &slot in node.outputs() {
+            let removed = Port {
+                node_name: node_name.to_string(),
+                slot_name: slot,
+            };
+            for destination in node.output_destinations(slot).unwrap_or_default() {
+                if let Some(destination_node) = self.nodes.get_mut(&destination.node_name) {
+                    // the input might have been connected to something else since
+                    if destination_node.input_source(destination.slot_name) == Some(&removed) {
+                        destination_node.disconnect_input(destination.slot_name);
+                    }
+                }
+            }
+        }
+
+        Some(node)
+    }
+
+    /// Move all of the nodes in `other` into this graph, along with their connections and
+    /// positions.
+    ///
+    /// Nodes from `other` are renamed if their names are taken. Returns a map from the old names to
+    /// the new names.
+    pub fn merge(&mut self, mut other: NodeGraph) -> HashMap<String, String> {
+        let names = other.nodes.keys().cloned().collect::<Vec<_>>();
+
+        let mut renamed = HashMap::new();
+        let mut nodes = Vec::new();
+        for old_name in names {
+            let node = other.nodes.remove(&old_name).unwrap();
+            let mut i: usize = 0;
+            while self.nodes.contains_key(&format_name(&old_name, i))
+                || renamed
+                    .values()
+                    .any(|new| *new == format_name(&old_name, i))
+            {
+                i += 1;
+            }
+
+            renamed.insert(old_name.clone(), format_name(&old_name, i));
+            nodes.push((old_name, node));
+        }
+
+        let mut connections = Vec::new();
+        for (old_name, node) in nodes {
+            for &slot in node.inputs() {
+                if let Some(source) = node.input_source(slot) {
+                    connections.push((
+                        Port {
+                            node_name: renamed[&source.node_name].clone(),
+                            slot_name: source.slot_name,
+                        },
+                        Port {
+                            node_name: renamed[&old_name].clone(),
+                            slot_name: slot,
+                        },
+                    ));
+                }
+            }
+
+            // connections are remade with the new names below
+            let mut node = node;
+            for &slot in node.inputs() {
+                node.disconnect_input(slot);
+            }
+            for &slot in node.outputs() {
+                let destinations = node
+                    .output_destinations(slot)
+                    .map(<[Port]>::to_vec)
+                    .unwrap_or_default();
+                for destination in destinations {
+                    node.remove_output(slot, &destination);
+                }
+            }
+
+            if let Some(position) = other.positions.get(&old_name) {
+                self.positions.insert(renamed[&old_name].clone(), *position);
+            }
+            self.nodes.insert(renamed[&old_name].clone(), node);
+        }
+
+        for (from, to) in connections {
+            self.connect(from, to);
+        }
+
+        renamed
+    }
+
     /// Connect one node to another node.
     ///
     /// The input port on `to` is cleared of its connection, if it exists. The corresponding port on
     /// the output node of the node connected to this node is also removed. The ports are then
     /// connected.
     pub fn connect(&mut self, from: Port, to: Port) {
-        // remove other outputs going to `to` (since an input slot can only have one source)
-        for (_, node) in self.nodes.iter_mut() {
-            // if `node`'s slot called `from.slot_name` has an output destination that is `to`
-            if node.has_connection(from.slot_name, &to) {
-                node.remove_output(from.slot_name, &to);
-                break; // there should only be one
-            }
-        }
+        // remove the output going to `to` from its current source, since an input slot can only
+        // have one source
+        self.disconnect(&to);
 
         // and then connect the output of `from`...
         self.nodes
@@ -128,6 +507,138 @@ impl NodeGraph {
             .unwrap()
             .connect_input(to.slot_name, from.clone());
     }
+
+    /// Disconnect whatever is connected to an input, from both ends. Does nothing if the input
+    /// isn't connected.
+    pub fn disconnect(&mut self, to: &Port) {
+        let from = match self
+            .nodes
+            .get(&to.node_name)
+            .and_then(|node| node.input_source(to.slot_name))
+        {
+            Some(from) => from.clone(),
+            None => return,
+        };
+
+        if let Some(node) = self.nodes.get_mut(&from.node_name) {
+            node.remove_output(from.slot_name, to);
+        }
+        self.nodes
+            .get_mut(&to.node_name)
+            .unwrap()
+            .disconnect_input(to.slot_name);
+    }
+
+    /// Evaluate the graph at full quality to get the data flowing out of `output`.
+    ///
+    /// Every node upstream of `output` is executed. The first error encountered is returned.
+    pub fn evaluate(&mut self, output: &Port) -> Result<ImageData, NodeError> {
+        self.evaluate_with(output, &self.context(Quality::Full))
+    }
+
+    /// The settings to evaluate the graph with at the given quality.
+    pub fn context(&self, quality: Quality) -> EvalContext {
+        EvalContext {
+            quality,
+            composite: self.settings,
+            region: None,
+            time: 0.,
+        }
+    }
+
+    /// Evaluate the graph with the given settings.
+    ///
+    /// The outputs of nodes with inputs are cached. A node is only executed again if its
+    /// properties, the settings, or the contents of its inputs have changed since last time, no
+    /// matter what else in the graph has. Nodes without inputs are cheap enough to always execute.
+    ///
+    /// Stateful nodes aren't cached, and are advanced once for every time they're executed, so a
+    /// stateful node feeding more than one input downstream advances more than once per
+    /// evaluation.
+    pub fn evaluate_with(
+        &mut self,
+        output: &Port,
+        context: &EvalContext,
+    ) -> Result<ImageData, NodeError> {
+        let node = self
+            .nodes
+            .get(&output.node_name)
+            .ok_or_else(|| NodeError::NoSuchNode(output.node_name.clone()))?;
+
+        let sources = node
+            .inputs()
+            .iter()
+            .filter_map(|&slot| Some((slot, node.input_source(slot)?.clone())))
+            .collect::<Vec<_>>();
+
+        let mut input = HashMap::new();
+        for (slot, source) in sources {
+            input.insert(slot, self.evaluate_with(&source, context)?);
+        }
+
+        let node = self.nodes.get_mut(&output.node_name).unwrap();
+        let key = (!input.is_empty() && !node.is_stateful())
+            .then(|| cache_key(node.as_ref(), &input, context));
+
+        // some nodes only make some outputs when something is connected to them, so the output
+        // being asked for might not have been made last time
+        if let Some((cached_key, outputs)) = self.cache.get(&output.node_name) {
+            if key == Some(*cached_key) {
+                if let Some(image) = outputs.get(output.slot_name) {
+                    return Ok(image.clone());
+                }
+            }
+        }
+
+        let mut outputs = node
+            .execute(input, context)
+            .map_err(|error| error.with_node_name(&output.node_name))?;
+        let image = outputs
+            .remove(output.slot_name)
+            .ok_or_else(|| NodeError::MissingOutput {
+                node: output.node_name.clone(),
+                slot: output.slot_name,
+            })?;
+
+        match key {
+            Some(key) => {
+                outputs.insert(output.slot_name, image.clone());
+                self.cache.insert(output.node_name.clone(), (key, outputs));
+            }
+            None => {
+                self.cache.remove(&output.node_name);
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+// everything that goes into the output of a node
+fn cache_key(
+    node: &dyn Node,
+    input: &HashMap<&'static str, ImageData>,
+    context: &EvalContext,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    node.name().hash(&mut hasher);
+    node.properties().to_string().hash(&mut hasher);
+
+    (context.quality as u8).hash(&mut hasher);
+    (context.composite.blend_space as u8).hash(&mut hasher);
+    context.region.hash(&mut hasher);
+    context.time.to_bits().hash(&mut hasher);
+
+    // in a consistent order, unlike the map
+    for &slot in node.inputs() {
+        slot.hash(&mut hasher);
+        input
+            .get(slot)
+            .map(ImageData::content_hash)
+            .hash(&mut hasher);
+    }
+
+    hasher.finish()
 }
 
 #[test]
@@ -185,3 +696,372 @@ fn node_graph_connect() {
 
     panic!("ok");
 }
+
+#[test]
+fn removing_a_node_keeps_newer_connections() {
+    use nodes::MixRgba;
+
+    let mut graph = NodeGraph::new();
+    let a = graph.add(Box::new(MixRgba::new(1.0)));
+    let b = graph.add(Box::new(MixRgba::new(0.6)));
+    let c = graph.add(Box::new(MixRgba::new(0.3)));
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+
+    graph.connect(port(&a, MixRgba::OUTPUT_MIX), port(&c, MixRgba::INPUT_A));
+    graph.connect(port(&b, MixRgba::OUTPUT_MIX), port(&c, MixRgba::INPUT_A));
+    assert_eq!(
+        Some(&[][..]),
+        graph.nodes[&a].output_destinations(MixRgba::OUTPUT_MIX)
+    );
+
+    graph.remove_node(&a);
+    assert_eq!(
+        Some(&port(&b, MixRgba::OUTPUT_MIX)),
+        graph.nodes[&c].input_source(MixRgba::INPUT_A)
+    );
+    assert!(graph.nodes[&b].has_connection(MixRgba::OUTPUT_MIX, &port(&c, MixRgba::INPUT_A)));
+}
+
+#[test]
+fn evaluate_size_mismatch() {
+    use nodes::{MixRgba, Source};
+
+    let mut graph = NodeGraph::new();
+    let small = graph.add(Box::new(Source::new(ImageData {
+        data: vec![0.; 2 * 2 * 4],
+        width: 2,
+        height: 2,
+        origin: None,
+    })));
+    let wide = graph.add(Box::new(Source::new(ImageData {
+        data: vec![0.; 3 * 4],
+        width: 3,
+        height: 1,
+        origin: None,
+    })));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+
+    graph.connect(port(&small, Source::OUTPUT), port(&mix, MixRgba::INPUT_A));
+    assert_eq!(
+        Err(NodeError::MissingInput {
+            node: mix.clone(),
+            slot: MixRgba::INPUT_B,
+        }),
+        graph.evaluate(&port(&mix, MixRgba::OUTPUT_MIX))
+    );
+
+    graph.connect(port(&wide, Source::OUTPUT), port(&mix, MixRgba::INPUT_B));
+    assert_eq!(
+        Err(NodeError::SizeMismatch {
+            node: mix.clone(),
+            a: (2, 2),
+            b: (3, 1),
+        }),
+        graph.evaluate(&port(&mix, MixRgba::OUTPUT_MIX))
+    );
+}
+
+#[test]
+fn evaluate_preview_quality() {
+    use nodes::{MixRgba, Source};
+
+    let image = || ImageData {
+        data: vec![0.5; 8 * 8 * 4],
+        width: 8,
+        height: 8,
+        origin: None,
+    };
+
+    let mut graph = NodeGraph::new();
+    let a = graph.add(Box::new(Source::new(image())));
+    let b = graph.add(Box::new(Source::new(image())));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+    graph.connect(port(&a, Source::OUTPUT), port(&mix, MixRgba::INPUT_A));
+    graph.connect(port(&b, Source::OUTPUT), port(&mix, MixRgba::INPUT_B));
+
+    let full = graph
+        .evaluate_with(
+            &port(&mix, MixRgba::OUTPUT_MIX),
+            &EvalContext {
+                quality: Quality::Full,
+                ..EvalContext::default()
+            },
+        )
+        .unwrap();
+    assert_eq!((8, 8), (full.width, full.height));
+    assert_eq!(8 * 8 * 4, full.data.len());
+
+    let preview = graph
+        .evaluate_with(
+            &port(&mix, MixRgba::OUTPUT_MIX),
+            &EvalContext {
+                quality: Quality::Preview,
+                ..EvalContext::default()
+            },
+        )
+        .unwrap();
+    assert_eq!((2, 2), (preview.width, preview.height));
+    assert_eq!(2 * 2 * 4, preview.data.len());
+    assert!(preview.data.iter().all(|&value| value == 0.5));
+}
+
+#[test]
+fn positions_json_round_trip() {
+    use nodes::{Crop, MixRgba, Source, UnCrop};
+
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(ImageData {
+        data: vec![0.25; 4],
+        width: 1,
+        height: 1,
+        origin: None,
+    })));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+    let unplaced = graph.add(Box::new(MixRgba::new(0.1)));
+    graph.connect(
+        Port {
+            node_name: source.clone(),
+            slot_name: Source::OUTPUT,
+        },
+        Port {
+            node_name: mix.clone(),
+            slot_name: MixRgba::INPUT_A,
+        },
+    );
+    graph.set_position(&source, (10.0, -20.5));
+    graph.set_position(&mix, (200.0, 40.0));
+    graph.settings.blend_space = BlendSpace::Linear;
+
+    // a region cropped out and put back
+    let crop = graph.add(Box::new(Crop::new(3, 1, 2, 2)));
+    let uncrop = graph.add(Box::new(UnCrop::new()));
+    graph.connect(
+        Port {
+            node_name: crop.clone(),
+            slot_name: Crop::OUTPUT,
+        },
+        Port {
+            node_name: uncrop.clone(),
+            slot_name: UnCrop::INPUT,
+        },
+    );
+
+    let json = graph.to_json();
+    let loaded = NodeGraph::from_json(&json).unwrap();
+    assert_eq!(BlendSpace::Linear, loaded.settings.blend_space);
+    assert_eq!(Some((10.0, -20.5)), loaded.position(&source));
+    assert_eq!(Some((200.0, 40.0)), loaded.position(&mix));
+    assert_eq!(None, loaded.position(&unplaced));
+    assert_eq!(
+        Some(&Port {
+            node_name: source.clone(),
+            slot_name: Source::OUTPUT,
+        }),
+        loaded.nodes[&mix].input_source(MixRgba::INPUT_A)
+    );
+    assert_eq!(
+        Some(&Port {
+            node_name: crop.clone(),
+            slot_name: Crop::OUTPUT,
+        }),
+        loaded.nodes[&uncrop].input_source(UnCrop::INPUT)
+    );
+    assert_eq!(json, loaded.to_json());
+
+    let mut loaded = loaded;
+    loaded.remove_node(&source);
+    assert_eq!(None, loaded.position(&source));
+    assert_eq!(None, loaded.nodes[&mix].input_source(MixRgba::INPUT_A));
+    assert_eq!(Some((200.0, 40.0)), loaded.position(&mix));
+}
+
+#[test]
+fn merge_keeps_positions() {
+    use nodes::MixRgba;
+
+    let mut graph = NodeGraph::new();
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+    graph.set_position(&mix, (1.0, 2.0));
+
+    let mut other = NodeGraph::new();
+    let a = other.add(Box::new(MixRgba::new(0.1)));
+    let b = other.add(Box::new(MixRgba::new(0.2)));
+    other.set_position(&a, (3.0, 4.0));
+    other.connect(
+        Port {
+            node_name: a.clone(),
+            slot_name: MixRgba::OUTPUT_MIX,
+        },
+        Port {
+            node_name: b.clone(),
+            slot_name: MixRgba::INPUT_A,
+        },
+    );
+
+    let renamed = graph.merge(other);
+    assert_eq!(3, graph.nodes.len());
+    assert_eq!(Some((1.0, 2.0)), graph.position(&mix));
+    assert_eq!(Some((3.0, 4.0)), graph.position(&renamed[&a]));
+    assert_ne!(mix, renamed[&a]);
+    assert_eq!(
+        Some(&Port {
+            node_name: renamed[&a].clone(),
+            slot_name: MixRgba::OUTPUT_MIX,
+        }),
+        graph.nodes[&renamed[&b]].input_source(MixRgba::INPUT_A)
+    );
+}
+
+/// A node that passes its input through and counts how many times it's been executed.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct CountingNode {
+    pub executions: std::rc::Rc<std::cell::Cell<usize>>,
+    input: Option<Port>,
+    output: Vec<Port>,
+}
+
+#[cfg(test)]
+impl CountingNode {
+    pub const INPUT: &'static str = "INPUT";
+    pub const OUTPUT: &'static str = "OUTPUT";
+}
+
+#[cfg(test)]
+impl Node for CountingNode {
+    fn name(&self) -> &'static str {
+        "CountingNode"
+    }
+
+    fn execute(
+        &mut self,
+        mut input: HashMap<&'static str, ImageData>,
+        _context: &EvalContext,
+    ) -> Result<HashMap<&'static str, ImageData>, NodeError> {
+        self.executions.set(self.executions.get() + 1);
+        let image = NodeError::take_input(self, &mut input, Self::INPUT)?;
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image);
+        Ok(output)
+    }
+
+    fn properties(&self) -> serde_json::Value {
+        serde_json::json!({})
+    }
+
+    fn inputs(&self) -> &'static [&'static str] {
+        &[Self::INPUT]
+    }
+
+    fn outputs(&self) -> &'static [&'static str] {
+        &[Self::OUTPUT]
+    }
+
+    fn input_source(&self, input_slot: &'static str) -> Option<&Port> {
+        self.input.as_ref().filter(|_| input_slot == Self::INPUT)
+    }
+
+    fn output_destinations(&self, output_slot: &'static str) -> Option<&[Port]> {
+        (output_slot == Self::OUTPUT).then_some(&self.output[..])
+    }
+
+    fn connect_input(&mut self, _input_slot: &'static str, source_port: Port) {
+        self.input = Some(source_port);
+    }
+
+    fn disconnect_input(&mut self, _input_slot: &'static str) {
+        self.input = None;
+    }
+
+    fn connect_output(&mut self, _output_slot: &'static str, destination_port: Port) {
+        self.output.push(destination_port);
+    }
+
+    fn remove_output(&mut self, _output_slot: &'static str, destination_port: &Port) {
+        self.output.retain(|port| port != destination_port);
+    }
+}
+
+#[test]
+fn cache_survives_unrelated_edits() {
+    use nodes::{MixRgba, Source};
+
+    let solid = |value| ImageData {
+        data: vec![value; 2 * 2 * 4],
+        width: 2,
+        height: 2,
+        origin: None,
+    };
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+
+    // two branches that meet at a mix
+    let mut graph = NodeGraph::new();
+    let a = graph.add(Box::new(Source::new(solid(0.25))));
+    let b = graph.add(Box::new(Source::new(solid(0.5))));
+    let counting_a = CountingNode::default();
+    let counting_b = CountingNode::default();
+    let (executions_a, executions_b) =
+        (counting_a.executions.clone(), counting_b.executions.clone());
+    let counting_a = graph.add(Box::new(counting_a));
+    let counting_b = graph.add(Box::new(counting_b));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+
+    graph.connect(
+        port(&a, Source::OUTPUT),
+        port(&counting_a, CountingNode::INPUT),
+    );
+    graph.connect(
+        port(&b, Source::OUTPUT),
+        port(&counting_b, CountingNode::INPUT),
+    );
+    graph.connect(
+        port(&counting_a, CountingNode::OUTPUT),
+        port(&mix, MixRgba::INPUT_A),
+    );
+    graph.connect(
+        port(&counting_b, CountingNode::OUTPUT),
+        port(&mix, MixRgba::INPUT_B),
+    );
+
+    let output = port(&mix, MixRgba::OUTPUT_MIX);
+    let first = graph.evaluate(&output).unwrap();
+    assert_eq!((1, 1), (executions_a.get(), executions_b.get()));
+
+    // nothing changed
+    assert_eq!(first, graph.evaluate(&output).unwrap());
+    assert_eq!((1, 1), (executions_a.get(), executions_b.get()));
+
+    // swap out the source of one branch, which the other branch doesn't care about
+    graph.remove_node(&b);
+    let b = graph.add(Box::new(Source::new(solid(1.0))));
+    graph.connect(
+        port(&b, Source::OUTPUT),
+        port(&counting_b, CountingNode::INPUT),
+    );
+
+    let second = graph.evaluate(&output).unwrap();
+    assert_ne!(first, second);
+    assert_eq!((1, 2), (executions_a.get(), executions_b.get()));
+
+    // different settings are different outputs
+    graph.settings.blend_space = BlendSpace::Linear;
+    graph.evaluate(&output).unwrap();
+    assert_eq!((2, 3), (executions_a.get(), executions_b.get()));
+}
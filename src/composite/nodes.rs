@@ -1,15 +1,35 @@
-use crate::image::ImageData;
+use crate::{
+    geometry::Point,
+    geometry::{Homography, Rect, Size},
+    image::{self, ColorSpace, EdgeMode, Image, ImageBuilder, ImageData, Pixel},
+    lut::CubeLut,
+    noise::Fractal,
+    text::TextFont,
+};
 
-use super::{Node, Port};
+use super::{EvalContext, Node, NodeError, Port, Quality, Tiling};
 
 use std::collections::HashMap;
 
 macro_rules! impl_node {
-    ($Name:ident; in $($INPUT:ident)*; out $($OUTPUT:ident)*; has $($prop:ident : $type_:ty),*; $exec:expr) => {
+    (
+        $Name:ident;
+        in $($INPUT:ident)*;
+        out $($OUTPUT:ident)*;
+        // state that changes the output from one evaluation to the next, which makes the node
+        // stateful, and things loaded once that only depend on the properties, which doesn't
+        $(keeps $($state:ident : $state_type:ty),*;)?
+        $(caches $($cache:ident : $cache_type:ty),*;)?
+        $(tiles $tiling:expr;)?
+        has $($prop:ident : $type_:ty),*;
+        $exec:expr
+    ) => {
         #[allow(non_snake_case)]
         #[derive(Debug)]
         pub struct $Name {
             $(pub $prop : $type_,)*
+            $($($state: $state_type,)*)?
+            $($($cache: $cache_type,)*)?
             $($INPUT: Option<Port>,)*
             $($OUTPUT: Vec<Port>,)*
         }
@@ -18,13 +38,27 @@ macro_rules! impl_node {
             $(pub const $INPUT: &'static str = stringify!($INPUT);)*
             $(pub const $OUTPUT: &'static str = stringify!($OUTPUT);)*
 
+            #[allow(clippy::too_many_arguments)] // nodes can have lots of properties
             pub fn new($($prop: $type_,)*) -> $Name {
                 $Name {
                     $($prop,)*
+                    $($($state: Default::default(),)*)?
+                    $($($cache: Default::default(),)*)?
                     $($INPUT: None,)*
                     $($OUTPUT: Vec::new(),)*
                 }
             }
+
+            /// Create the node from properties written by `Node::properties`.
+            pub fn from_properties(properties: &serde_json::Value) -> serde_json::Result<$Name> {
+                #[derive(serde::Deserialize)]
+                struct Properties {
+                    $($prop: $type_,)*
+                }
+
+                let Properties { $($prop,)* } = serde::Deserialize::deserialize(properties)?;
+                Ok($Name::new($($prop,)*))
+            }
         }
 
         impl Node for $Name {
@@ -33,10 +67,44 @@ macro_rules! impl_node {
             }
 
             fn execute(
-                &self,
+                &mut self,
                 input: HashMap<&'static str, ImageData>,
-            ) -> Option<HashMap<&'static str, ImageData>> {
-                $exec(self, input)
+                context: &EvalContext,
+            ) -> Result<HashMap<&'static str, ImageData>, NodeError> {
+                $exec(self, input, context)
+            }
+
+            fn properties(&self) -> serde_json::Value {
+                #[allow(unused_mut)]
+                let mut properties = serde_json::Map::new();
+                $(properties.insert(
+                    String::from(stringify!($prop)),
+                    serde_json::to_value(&self.$prop).unwrap(),
+                );)*
+                serde_json::Value::Object(properties)
+            }
+
+            #[allow(unreachable_code, unused_variables)] // for nodes that can't be tiled
+            fn tiling(&self, context: &EvalContext) -> Option<Tiling> {
+                $(return $tiling(self, context);)?
+                None
+            }
+
+            fn reset(&mut self) {
+                $($(self.$state = Default::default();)*)?
+            }
+
+            fn is_stateful(&self) -> bool {
+                let state: &[&str] = &[$($(stringify!($state),)*)?];
+                !state.is_empty()
+            }
+
+            fn inputs(&self) -> &'static [&'static str] {
+                &[$(Self::$INPUT,)*]
+            }
+
+            fn outputs(&self) -> &'static [&'static str] {
+                &[$(Self::$OUTPUT,)*]
             }
 
             fn input_source(&self, input_slot: &'static str) -> Option<&Port> {
@@ -53,6 +121,7 @@ macro_rules! impl_node {
                 }
             }
 
+            #[allow(unused_variables)] // for nodes without inputs
             fn connect_input(&mut self, input_slot: &'static str, source_port: Port) {
                 match input_slot {
                     $(Self::$INPUT => self.$INPUT = Some(source_port),)*
@@ -64,6 +133,17 @@ macro_rules! impl_node {
                 }
             }
 
+            fn disconnect_input(&mut self, input_slot: &'static str) {
+                match input_slot {
+                    $(Self::$INPUT => self.$INPUT = None,)*
+                    _ => panic!(
+                        "cannot disconnect: no input slot on {} named {}",
+                        self.name(),
+                        input_slot
+                    ),
+                }
+            }
+
             fn connect_output(&mut self, output_slot: &'static str, destination_port: Port) {
                 match output_slot {
                     $(Self::$OUTPUT => self.$OUTPUT.push(destination_port),)*
@@ -89,28 +169,2159 @@ macro_rules! impl_node {
     }
 }
 
+/// Construct a node from its type name and properties, as written by `Node::name` and
+/// `Node::properties`. Returns `None` if there's no node with that name.
+pub fn from_properties(
+    name: &str,
+    properties: &serde_json::Value,
+) -> Option<serde_json::Result<Box<dyn Node>>> {
+    macro_rules! registry {
+        ($($Name:ident),*) => {
+            match name {
+                $(stringify!($Name) => Some(
+                    $Name::from_properties(properties).map(|node| Box::new(node) as Box<dyn Node>)
+                ),)*
+                _ => None,
+            }
+        };
+    }
+
+    registry!(
+        Source,
+        MixRgba,
+        AlphaOver,
+        Resize,
+        Crop,
+        UnCrop,
+        GradientMap,
+        Wipe,
+        DistanceField,
+        MotionBlur,
+        Preview,
+        Accumulate,
+        Lut3d,
+        Offset,
+        Bloom,
+        Vignette,
+        Desaturate,
+        ColorSpaceConvert,
+        Text,
+        FractalNoise,
+        FrequencySeparation,
+        FrequencyRecombine,
+        Stroke,
+        PerspectiveWarp,
+        LumaKey,
+        BlackAndWhite,
+        MathBlend
+    )
+}
+
+impl_node!(
+    Source;
+    in;
+    out OUTPUT;
+    tiles |this: &Source, context: &EvalContext| Some(Tiling::Generator {
+        width: context.scale_size(this.image.width),
+        height: context.scale_size(this.image.height),
+    });
+    has image: ImageData;
+
+    |this: &Source, _input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = match (context.quality, context.region) {
+            // without copying the whole thing first
+            (Quality::Full, Some(region)) => this.image.crop(region),
+            (Quality::Full, None) => this.image.clone(),
+            (Quality::Preview, region) => {
+                let image = Image::from(this.image.clone())
+                    .resize(
+                        context.scale_size(this.image.width),
+                        context.scale_size(this.image.height),
+                    )
+                    .into_data();
+                match region {
+                    Some(region) => image.crop(region),
+                    None => image,
+                }
+            }
+        };
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image);
+        Ok(output)
+    }
+);
+
 impl_node!(
     MixRgba;
     in INPUT_A INPUT_B;
     out OUTPUT_MIX;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
     has mix: f32;
 
-    |this: &MixRgba, mut input: HashMap<&'static str, ImageData>| {
-        let a = input.remove(Self::INPUT_A)?;
-        let b = input.remove(Self::INPUT_B)?;
+    |this: &MixRgba, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let a = NodeError::take_input(this, &mut input, Self::INPUT_A)?;
+        let b = NodeError::take_input(this, &mut input, Self::INPUT_B)?;
+        NodeError::check_sizes(this, &a, &b)?;
+
+        let space = context.composite.blend_space;
+        let (width, height, origin) = (a.width, a.height, a.origin);
+        let mut data = Vec::with_capacity(a.data.len());
+        for (a, b) in a.data.chunks_exact(4).zip(b.data.chunks_exact(4)) {
+            let a = space.decode(Pixel::from_slice(a));
+            let b = space.decode(Pixel::from_slice(b));
+            let pixel = space.encode(b.lerp(a, this.mix));
+            data.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
 
         let mut output = HashMap::new();
         output.insert(
             Self::OUTPUT_MIX,
             ImageData {
-                data: a
-                    .into_iter()
-                    .zip(b.into_iter())
-                    .map(|(a, b)| a * this.mix + b * (1. - this.mix))
-                    .collect(),
+                data,
+                width,
+                height,
+                origin,
+            },
+        );
+
+        Ok(output)
+    }
+);
+
+impl_node!(
+    AlphaOver;
+    in INPUT_A INPUT_B;
+    out OUTPUT COVERAGE;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has opacity: f32;
+
+    |this: &AlphaOver, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        // A is composited over B
+        let a = NodeError::take_input(this, &mut input, Self::INPUT_A)?;
+        let b = NodeError::take_input(this, &mut input, Self::INPUT_B)?;
+        NodeError::check_sizes(this, &a, &b)?;
+
+        let space = context.composite.blend_space;
+        let (width, height, origin) = (a.width, a.height, a.origin);
+        let mut data = Vec::with_capacity(a.data.len());
+        let mut coverage = Vec::with_capacity(a.data.len());
+        for (a, b) in a.data.chunks_exact(4).zip(b.data.chunks_exact(4)) {
+            let above = Pixel {
+                a: a[3] * this.opacity,
+                ..space.decode(Pixel::from_slice(a))
+            };
+            let below = space.decode(Pixel::from_slice(b));
+
+            let pixel = space.encode(above.over(below));
+            data.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+            coverage.extend_from_slice(&[pixel.a, pixel.a, pixel.a, 1.]);
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width,
+                height,
+                origin,
+            },
+        );
+        output.insert(
+            Self::COVERAGE,
+            ImageData {
+                data: coverage,
+                width,
+                height,
+                origin,
+            },
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Resize;
+    in INPUT;
+    out OUTPUT;
+    has width: u32, height: u32;
+
+    |this: &Resize, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image
+                .resize(context.scale_size(this.width), context.scale_size(this.height))
+                .into_data(),
+        );
+
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Crop;
+    in INPUT;
+    out OUTPUT;
+    has x: u32, y: u32, width: u32, height: u32;
+
+    |this: &Crop, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+        let rect = Rect::new(
+            (this.x as f32 * context.scale()).round() as i32,
+            (this.y as f32 * context.scale()).round() as i32,
+            context.scale_size(this.width),
+            context.scale_size(this.height),
+        );
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image.crop(rect).into_data());
+        Ok(output)
+    }
+);
+
+impl_node!(
+    UnCrop;
+    in INPUT BACKGROUND;
+    out OUTPUT;
+    has;
+
+    |this: &UnCrop, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        // put the cropped region back where it came from
+        let region = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+        let background = NodeError::take_input(this, &mut input, Self::BACKGROUND)?;
+        let mut background = Image::from(background);
+
+        background.paste(&region, region.origin().unwrap_or_default());
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, background.into_data());
+        Ok(output)
+    }
+);
+
+impl_node!(
+    GradientMap;
+    in INPUT;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has stops: Vec<(f32, Pixel)>;
+
+    |this: &GradientMap, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image.map_pixels(|pixel| {
+                let mapped = image::sample_gradient(&this.stops, pixel.luminance());
+                Pixel { a: pixel.a, ..mapped }
+            }),
+        );
+
+        Ok(output)
+    }
+);
+
+/// The shape of the boundary of a `Wipe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WipeMode {
+    /// B comes in from the left edge.
+    LinearLeft,
+    /// B comes in from the right edge.
+    LinearRight,
+    /// B grows out of the center.
+    Radial,
+    /// B sweeps clockwise from twelve o'clock.
+    Clock,
+}
+
+impl WipeMode {
+    /// How far into the wipe a pixel switches from A to B, from 0 to 1.
+    fn threshold(&self, x: u32, y: u32, width: u32, height: u32) -> f32 {
+        // pixel centers, relative to the center of the image
+        let (w, h) = (width as f32, height as f32);
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let (dx, dy) = (px - w / 2., py - h / 2.);
+
+        match self {
+            WipeMode::LinearLeft => px / w,
+            WipeMode::LinearRight => 1. - px / w,
+            WipeMode::Radial => (dx * dx + dy * dy).sqrt() / (w * w + h * h).sqrt() * 2.,
+            WipeMode::Clock => {
+                // atan2 of (x, -y) is the clockwise angle from straight up
+                let angle = dx.atan2(-dy);
+                angle.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU
+            }
+        }
+    }
+}
+
+impl_node!(
+    Wipe;
+    in INPUT_A INPUT_B;
+    out OUTPUT;
+    has progress: f32, mode: WipeMode;
+
+    |this: &Wipe, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let a = NodeError::take_input(this, &mut input, Self::INPUT_A)?;
+        let b = NodeError::take_input(this, &mut input, Self::INPUT_B)?;
+        NodeError::check_sizes(this, &a, &b)?;
+
+        let (width, height, origin) = (a.width, a.height, a.origin);
+        let mut data = a.data;
+        for y in 0..height {
+            for x in 0..width {
+                if this.mode.threshold(x, y, width, height) < this.progress {
+                    let i = (y as usize * width as usize + x as usize) * 4;
+                    data[i..i + 4].copy_from_slice(&b.data[i..i + 4]);
+                }
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width,
+                height,
+                origin,
+            },
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    DistanceField;
+    in INPUT;
+    out OUTPUT;
+    has max_distance: f32;
+
+    |this: &DistanceField, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        // pixels at least half opaque count as inside the shape
+        let mask = image
+            .data
+            .chunks_exact(4)
+            .map(|pixel| pixel[3] >= 0.5)
+            .collect::<Vec<_>>();
+        let distances =
+            image::distance_transform(&mask, image.width as usize, image.height as usize);
+
+        let max_distance = (this.max_distance * context.scale()).max(f32::EPSILON);
+        let data = distances
+            .into_iter()
+            .flat_map(|distance| {
+                let value = (distance / max_distance).min(1.);
+                vec![value, value, value, 1.]
+            })
+            .collect();
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width: image.width,
+                height: image.height,
+                origin: image.origin,
             },
         );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    MotionBlur;
+    in INPUT;
+    out OUTPUT;
+    tiles |this: &MotionBlur, context: &EvalContext| Some(Tiling::Local {
+        halo: (this.samples(context) / 2) as u32,
+    });
+    has angle: f32, length: f32;
+
+    |this: &MotionBlur, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+
+        // one sample per pixel along a line through the center of the kernel, counterclockwise
+        // from the positive x axis
+        let samples = this.samples(context);
+        let radius = samples / 2;
+        let size = radius * 2 + 1;
+        let (sin, cos) = this.angle.to_radians().sin_cos();
+
+        let mut kernel = vec![0.; size * size];
+        for i in 0..samples {
+            let offset = i as f32 - (samples - 1) as f32 / 2.;
+            let kx = (radius as f32 + offset * cos).round() as usize;
+            let ky = (radius as f32 - offset * sin).round() as usize;
+            kernel[ky * size + kx] += 1.;
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image
+                .convolve(&kernel, size, size, EdgeMode::Clamp)
+                .into_data(),
+        );
+        Ok(output)
+    }
+);
+
+impl MotionBlur {
+    // how many pixels are averaged together
+    fn samples(&self, context: &EvalContext) -> usize {
+        ((self.length * context.scale()).round() as usize).max(1)
+    }
+}
+
+impl_node!(
+    Preview;
+    in INPUT;
+    out OUTPUT THUMBNAIL;
+    has size: u32;
+
+    |this: &Preview, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        // nobody to show the thumbnail to, so don't bother making it
+        let mut output = HashMap::new();
+        if !this.THUMBNAIL.is_empty() {
+            output.insert(
+                Self::THUMBNAIL,
+                Image::from(image.clone()).thumbnail(this.size).into_data(),
+            );
+        }
+
+        output.insert(Self::OUTPUT, image);
+        Ok(output)
+    }
+);
+
+/// How `Accumulate` combines frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AccumulateMode {
+    /// The average of every frame so far.
+    Mean,
+    /// The brightest value of each channel so far.
+    Max,
+    /// The darkest value of each channel so far.
+    Min,
+}
+
+impl_node!(
+    Accumulate;
+    in INPUT;
+    out OUTPUT;
+    keeps accumulated: Option<ImageData>, frames: u32;
+    has mode: AccumulateMode;
+
+    |this: &mut Accumulate, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let frame = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        let accumulated = match this.accumulated.take() {
+            Some(accumulated)
+                if (accumulated.width, accumulated.height) == (frame.width, frame.height) =>
+            {
+                this.frames += 1;
+                let n = this.frames as f32;
+                let data = accumulated
+                    .data
+                    .iter()
+                    .zip(frame.data.iter())
+                    .map(|(&acc, &value)| match this.mode {
+                        // running mean, so nothing grows without bound
+                        AccumulateMode::Mean => acc + (value - acc) / n,
+                        AccumulateMode::Max => acc.max(value),
+                        AccumulateMode::Min => acc.min(value),
+                    })
+                    .collect();
+
+                ImageData {
+                    data,
+                    ..accumulated
+                }
+            }
+
+            // start over if the frames change size, say from switching to preview quality
+            _ => {
+                this.frames = 1;
+                frame
+            }
+        };
+
+        this.accumulated = Some(accumulated.clone());
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, accumulated);
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Lut3d;
+    in INPUT;
+    out OUTPUT;
+    // the table, and the path it was loaded from
+    caches lut: Option<(String, CubeLut)>;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has path: String;
+
+    |this: &mut Lut3d, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        // only hit the disk again if the path changed
+        if this.lut.as_ref().map(|(path, _)| path) != Some(&this.path) {
+            let lut = CubeLut::load(&this.path).map_err(|error| NodeError::Resource {
+                node: this.name().to_string(),
+                message: format!("{:#}", error),
+            })?;
+            this.lut = Some((this.path.clone(), lut));
+        }
+
+        let (_, lut) = this.lut.as_ref().unwrap();
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image.map_pixels(|pixel| lut.apply(pixel)));
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Offset;
+    in INPUT;
+    out OUTPUT;
+    has x: i32, y: i32;
+
+    |this: &Offset, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+        let scale = |offset: i32| (offset as f32 * context.scale()).round() as i32;
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image.offset_wrap(scale(this.x), scale(this.y)).into_data(),
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Bloom;
+    in INPUT;
+    out OUTPUT;
+    tiles |this: &Bloom, context: &EvalContext| Some(Tiling::Local {
+        halo: this.radius(context),
+    });
+    has threshold: f32, intensity: f32, radius: f32;
+
+    |this: &Bloom, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        // everything not bright enough is opaque black, so it darkens the glow around bright
+        // pixels instead of being ignored by the blur
+        let bright = Image::from(image.clone().map_pixels(|pixel| {
+            if pixel.luminance() > this.threshold {
+                Pixel { a: 1., ..pixel }
+            } else {
+                Pixel {
+                    r: 0.,
+                    g: 0.,
+                    b: 0.,
+                    a: 1.,
+                }
+            }
+        }))
+        .blur(this.radius(context));
+
+        let mut glowing = Image::from(image);
+        for (x, y, pixel) in glowing.region_pixels_mut(bright.bounds()) {
+            let glow = bright.pixel_at(x as usize, y as usize);
+            let add = |value: f32, glow: f32| (value + glow * this.intensity).clamp(0., 1.);
+            pixel[0] = add(pixel[0], glow.r);
+            pixel[1] = add(pixel[1], glow.g);
+            pixel[2] = add(pixel[2], glow.b);
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, glowing.into_data());
+        Ok(output)
+    }
+);
+
+impl Bloom {
+    // how far the glow reaches, in pixels
+    fn radius(&self, context: &EvalContext) -> u32 {
+        (self.radius * context.scale()).round().max(0.) as u32
+    }
+}
+
+impl_node!(
+    Vignette;
+    in INPUT;
+    out OUTPUT;
+    has amount: f32, radius: f32, softness: f32;
+
+    |this: &Vignette, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let mut image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+
+        // distances are relative to the distance from the center to a corner, so the vignette
+        // looks the same at any resolution
+        let (w, h) = (image.width() as f32, image.height() as f32);
+        let corner = (w * w + h * h).sqrt() / 2.;
+        for (x, y, pixel) in image.region_pixels_mut(image.bounds()) {
+            let (dx, dy) = (x as f32 + 0.5 - w / 2., y as f32 + 0.5 - h / 2.);
+            let distance = (dx * dx + dy * dy).sqrt() / corner.max(f32::EPSILON);
+
+            let falloff = smoothstep(this.radius, this.radius + this.softness, distance);
+            let factor = 1. - this.amount * falloff;
+            for channel in &mut pixel[..3] {
+                *channel *= factor;
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image.into_data());
+        Ok(output)
+    }
+);
+
+// 0 up to `edge0`, 1 from `edge1` on, and a smooth curve in between. a hard step if the edges
+// are the same
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x < edge0 { 0. } else { 1. };
+    }
+
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+impl_node!(
+    Desaturate;
+    in INPUT;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has amount: f32;
+
+    |this: &Desaturate, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image.map_pixels(|pixel| {
+                let gray = pixel.luminance();
+                let desaturated = Pixel {
+                    r: gray,
+                    g: gray,
+                    b: gray,
+                    a: pixel.a,
+                };
+                pixel.lerp(desaturated, this.amount)
+            }),
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    ColorSpaceConvert;
+    in INPUT;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has from: ColorSpace, to: ColorSpace;
+
+    |this: &ColorSpaceConvert, mut input: HashMap<&'static str, ImageData>, _: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image.map_pixels(|pixel| image::convert_color(pixel, this.from, this.to)),
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    Text;
+    in BACKGROUND;
+    out OUTPUT;
+    // the font, and the path it was loaded from
+    caches font: Option<(Option<String>, TextFont)>;
+    has string: String, font_size: f32, color: Pixel, x: i32, y: i32, width: u32, height: u32,
+        font_path: Option<String>;
+
+    |this: &mut Text, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        // drawn over the background if there is one, or onto a transparent image if not
+        let mut image = match input.remove(Self::BACKGROUND) {
+            Some(background) => Image::from(background),
+            None => Image::from_solid(
+                context.scale_size(this.width),
+                context.scale_size(this.height),
+                Pixel::TRANSPARENT,
+            )
+            .map_err(|error| NodeError::InvalidProperty {
+                node: this.name().to_string(),
+                message: format!("{:#}", error),
+            })?,
+        };
+
+        if this.font.as_ref().map(|(path, _)| path) != Some(&this.font_path) {
+            let font = match &this.font_path {
+                Some(path) => TextFont::load(path).map_err(|error| NodeError::Resource {
+                    node: this.name().to_string(),
+                    message: format!("{:#}", error),
+                })?,
+                None => TextFont::default(),
+            };
+            this.font = Some((this.font_path.clone(), font));
+        }
+
+        let (_, font) = this.font.as_ref().unwrap();
+        let scale = |value: i32| (value as f32 * context.scale()).round() as i32;
+        font.draw(
+            &mut image,
+            &this.string,
+            this.font_size * context.scale(),
+            this.color,
+            Point::new(scale(this.x), scale(this.y)),
+        );
 
-        Some(output)
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image.into_data());
+        Ok(output)
     }
 );
+
+impl_node!(
+    FractalNoise;
+    in;
+    out OUTPUT;
+    tiles |this: &FractalNoise, context: &EvalContext| Some(Tiling::Generator {
+        width: context.scale_size(this.width),
+        height: context.scale_size(this.height),
+    });
+    has seed: u32, octaves: u32, persistence: f32, lacunarity: f32, scale: f32, width: u32,
+        height: u32;
+
+    |this: &FractalNoise, _input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let (width, height) = (context.scale_size(this.width), context.scale_size(this.height));
+        let region = context
+            .region
+            .unwrap_or_else(|| Rect::new(0, 0, width, height))
+            .clamp_to(Size::new(width, height));
+
+        let fractal = Fractal {
+            seed: this.seed,
+            octaves: this.octaves,
+            persistence: this.persistence,
+            lacunarity: this.lacunarity,
+        };
+        // `scale` is how many full size pixels the first octave's features are across, so the
+        // preview looks the same, just blurrier
+        let step = 1. / (context.scale() * this.scale.max(f32::EPSILON));
+
+        let mut data = Vec::with_capacity(region.size.area() * 4);
+        for y in region.top()..region.bottom() {
+            for x in region.left()..region.right() {
+                let value = fractal.sample((x as f32 + 0.5) * step, (y as f32 + 0.5) * step);
+                data.extend_from_slice(&[value, value, value, 1.]);
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width: region.size.width,
+                height: region.size.height,
+                origin: Some(region.origin),
+            },
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    FrequencySeparation;
+    in INPUT;
+    out LOW HIGH;
+    tiles |this: &FrequencySeparation, context: &EvalContext| Some(Tiling::Local {
+        halo: this.radius(context),
+    });
+    has radius: f32;
+
+    |this: &FrequencySeparation, mut input: HashMap<&'static str, ImageData>, context| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+        let low = Image::from(image.clone()).blur(this.radius(context)).into_data();
+
+        // the detail the blur took out, centered on gray so it can be viewed and painted on. it
+        // isn't clamped, so values past black or white survive the trip through FrequencyRecombine
+        let mut high = image;
+        for (pixel, low) in high.data.chunks_exact_mut(4).zip(low.data.chunks_exact(4)) {
+            for channel in 0..3 {
+                pixel[channel] = pixel[channel] - low[channel] + 0.5;
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::LOW, low);
+        output.insert(Self::HIGH, high);
+        Ok(output)
+    }
+);
+
+impl FrequencySeparation {
+    // how far the blur reaches, in pixels
+    fn radius(&self, context: &EvalContext) -> u32 {
+        (self.radius * context.scale()).round().max(0.) as u32
+    }
+}
+
+impl_node!(
+    FrequencyRecombine;
+    in LOW HIGH;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has;
+
+    |this: &FrequencyRecombine, mut input: HashMap<&'static str, ImageData>, _: &EvalContext| {
+        let low = NodeError::take_input(this, &mut input, Self::LOW)?;
+        let mut high = NodeError::take_input(this, &mut input, Self::HIGH)?;
+        NodeError::check_sizes(this, &low, &high)?;
+
+        // alpha comes from the high band, which kept the original's
+        for (pixel, low) in high.data.chunks_exact_mut(4).zip(low.data.chunks_exact(4)) {
+            for channel in 0..3 {
+                pixel[channel] = low[channel] + pixel[channel] - 0.5;
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, high);
+        Ok(output)
+    }
+);
+
+/// Which side of the edge of a shape a `Stroke` goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum StrokePosition {
+    /// Over the edge of the shape, leaving its outline where it was.
+    Inside,
+    /// Around the shape, behind anything partly transparent at its edge.
+    Outside,
+    /// Half inside and half outside.
+    Center,
+}
+
+impl_node!(
+    Stroke;
+    in INPUT;
+    out OUTPUT;
+    tiles |this: &Stroke, context: &EvalContext| Some(Tiling::Local {
+        halo: this.width(context).ceil() as u32 + 1,
+    });
+    has width: f32, color: Pixel, position: StrokePosition;
+
+    |this: &Stroke, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+        let (width, height) = (image.width as usize, image.height as usize);
+
+        // pixels at least half opaque count as inside the shape, like DistanceField
+        let inside = image
+            .data
+            .chunks_exact(4)
+            .map(|pixel| pixel[3] >= 0.5)
+            .collect::<Vec<_>>();
+        let outside = inside.iter().map(|inside| !inside).collect::<Vec<_>>();
+
+        // how far outside pixels are from the shape, and inside pixels from the outside. the
+        // pixels right next to the edge are 1 away
+        let from_inside = image::distance_transform(&inside, width, height);
+        let from_outside = image::distance_transform(&outside, width, height);
+
+        let stroke_width = this.width(context);
+        let (inner, outer) = match this.position {
+            StrokePosition::Inside => (stroke_width, 0.),
+            StrokePosition::Outside => (0., stroke_width),
+            StrokePosition::Center => (stroke_width / 2., stroke_width / 2.),
+        };
+        // antialiased, so diagonal distances get partial coverage
+        let coverage = |distance: f32, reach: f32| (reach + 1. - distance).clamp(0., 1.);
+
+        let mut image = Image::from(image);
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let pixel = image.pixel_at(x, y);
+                let stroked = if inside[i] {
+                    let covered = coverage(from_outside[i], inner);
+                    if covered <= 0. {
+                        continue;
+                    }
+                    Pixel {
+                        a: this.color.a * covered,
+                        ..this.color
+                    }
+                    .over(pixel)
+                } else {
+                    let covered = coverage(from_inside[i], outer);
+                    if covered <= 0. {
+                        continue;
+                    }
+                    pixel.over(Pixel {
+                        a: this.color.a * covered,
+                        ..this.color
+                    })
+                };
+                image.set_pixel(x, y, stroked);
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, image.into_data());
+        Ok(output)
+    }
+);
+
+impl Stroke {
+    // how wide the stroke is, in pixels
+    fn width(&self, context: &EvalContext) -> f32 {
+        (self.width * context.scale()).max(0.)
+    }
+}
+
+impl_node!(
+    PerspectiveWarp;
+    in INPUT;
+    out OUTPUT;
+    has top_left: (f32, f32), top_right: (f32, f32), bottom_right: (f32, f32),
+        bottom_left: (f32, f32);
+
+    |this: &PerspectiveWarp, mut input: HashMap<&'static str, ImageData>, context: &EvalContext| {
+        let image = Image::from(NodeError::take_input(this, &mut input, Self::INPUT)?);
+        let (width, height) = (image.width() as f32, image.height() as f32);
+
+        // the corners are where the corners of the input end up, in pixels of the output, which
+        // is the same size as the input. mapping backwards finds where each output pixel came from
+        let scale = |(x, y): (f32, f32)| (x * context.scale(), y * context.scale());
+        let to = [
+            scale(this.top_left),
+            scale(this.top_right),
+            scale(this.bottom_right),
+            scale(this.bottom_left),
+        ];
+        let from = [(0., 0.), (width, 0.), (width, height), (0., height)];
+        let homography =
+            Homography::from_quads(to, from).ok_or_else(|| NodeError::InvalidProperty {
+                node: this.name().to_string(),
+                message: String::from("three of the corners are in a line"),
+            })?;
+
+        // transparent, and as empty as the input if that's empty
+        let mut warped = ImageBuilder::new()
+            .size(image.width(), image.height())
+            .build();
+        for y in 0..image.height() as usize {
+            for x in 0..image.width() as usize {
+                match homography.apply(x as f32 + 0.5, y as f32 + 0.5) {
+                    Some((u, v)) if (0. ..width).contains(&u) && (0. ..height).contains(&v) => {
+                        warped.set_pixel(x, y, image.sample_bilinear(u, v));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut output = HashMap::new();
+        output.insert(Self::OUTPUT, warped.into_data());
+        Ok(output)
+    }
+);
+
+impl_node!(
+    LumaKey;
+    in INPUT;
+    out OUTPUT MASK;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has low: f32, high: f32, softness: f32, spill: f32;
+
+    |this: &LumaKey, mut input: HashMap<&'static str, ImageData>, _context: &EvalContext| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+        let (width, height, origin) = (image.width, image.height, image.origin);
+        if this.low.is_nan() || this.high.is_nan() || this.low > this.high {
+            return Err(NodeError::InvalidProperty {
+                node: this.name().to_string(),
+                message: format!("low {} is above high {}", this.low, this.high),
+            });
+        }
+
+        // pixels with luminance from low to high are kept, fading out over `softness` past
+        // either end
+        let mut data = Vec::with_capacity(image.data.len());
+        let mut mask = Vec::with_capacity(image.data.len());
+        for pixel in image.data.chunks_exact(4) {
+            let pixel = Pixel::from_slice(pixel);
+            let luminance = pixel.luminance();
+            let kept = smoothstep(this.low - this.softness, this.low, luminance)
+                * (1. - smoothstep(this.high, this.high + this.softness, luminance));
+
+            // edges that are partly background are pulled back into the kept range, so a bright
+            // background doesn't leave a glowing fringe
+            let shift = (luminance.clamp(this.low, this.high) - luminance) * this.spill;
+            data.extend_from_slice(&[
+                (pixel.r + shift).clamp(0., 1.),
+                (pixel.g + shift).clamp(0., 1.),
+                (pixel.b + shift).clamp(0., 1.),
+                pixel.a * kept,
+            ]);
+            mask.extend_from_slice(&[kept, kept, kept, 1.]);
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width,
+                height,
+                origin,
+            },
+        );
+        output.insert(
+            Self::MASK,
+            ImageData {
+                data: mask,
+                width,
+                height,
+                origin,
+            },
+        );
+        Ok(output)
+    }
+);
+
+impl_node!(
+    BlackAndWhite;
+    in INPUT;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has red: f32, yellow: f32, green: f32, cyan: f32, blue: f32, magenta: f32;
+
+    |this: &BlackAndWhite, mut input: HashMap<&'static str, ImageData>, _context| {
+        let image = NodeError::take_input(this, &mut input, Self::INPUT)?;
+
+        // each color is some gray, plus a primary on top of that, plus a secondary between the
+        // primary and the next strongest channel. the gray passes through and the other two are
+        // weighted by whichever colors they are
+        let primaries = [this.red, this.green, this.blue];
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            image.map_pixels(|pixel| {
+                let channels = [pixel.r, pixel.g, pixel.b];
+                let mut order = [0, 1, 2];
+                order.sort_by(|&a, &b| channels[b].total_cmp(&channels[a]));
+                let [max, mid, min] = order.map(|i| channels[i]);
+
+                let secondary = match order[0] + order[1] {
+                    1 => this.yellow,
+                    2 => this.magenta,
+                    _ => this.cyan,
+                };
+                let primary = primaries[order[0]];
+
+                let gray = (min + (max - mid) * primary + (mid - min) * secondary).clamp(0., 1.);
+                Pixel {
+                    r: gray,
+                    g: gray,
+                    b: gray,
+                    a: pixel.a,
+                }
+            }),
+        );
+        Ok(output)
+    }
+);
+
+/// What a `MathBlend` does with each pair of channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MathOperation {
+    Add,
+    /// A minus B.
+    Subtract,
+    Multiply,
+    /// A divided by B. Dividing by zero gives zero.
+    Divide,
+    Min,
+    Max,
+    /// The absolute value of A minus B.
+    Difference,
+}
+
+impl MathOperation {
+    pub fn apply(&self, a: f32, b: f32) -> f32 {
+        match self {
+            MathOperation::Add => a + b,
+            MathOperation::Subtract => a - b,
+            MathOperation::Multiply => a * b,
+            MathOperation::Divide if b == 0. => 0.,
+            MathOperation::Divide => a / b,
+            MathOperation::Min => a.min(b),
+            MathOperation::Max => a.max(b),
+            MathOperation::Difference => (a - b).abs(),
+        }
+    }
+}
+
+impl_node!(
+    MathBlend;
+    in INPUT_A INPUT_B;
+    out OUTPUT;
+    tiles |_, _| Some(Tiling::Local { halo: 0 });
+    has operation: MathOperation, clamp: bool;
+
+    // straight arithmetic on the stored values, ignoring the blend space, so masks come out as
+    // expected. alpha is A's
+    |this: &MathBlend, mut input: HashMap<&'static str, ImageData>, _context| {
+        let a = NodeError::take_input(this, &mut input, Self::INPUT_A)?;
+        let b = NodeError::take_input(this, &mut input, Self::INPUT_B)?;
+        NodeError::check_sizes(this, &a, &b)?;
+
+        let apply = |a, b| {
+            let value = this.operation.apply(a, b);
+            if this.clamp {
+                value.clamp(0., 1.)
+            } else {
+                value
+            }
+        };
+
+        let (width, height, origin) = (a.width, a.height, a.origin);
+        let mut data = Vec::with_capacity(a.data.len());
+        for (a, b) in a.data.chunks_exact(4).zip(b.data.chunks_exact(4)) {
+            let (r, g, b) = (apply(a[0], b[0]), apply(a[1], b[1]), apply(a[2], b[2]));
+            data.extend_from_slice(&[r, g, b, a[3]]);
+        }
+
+        let mut output = HashMap::new();
+        output.insert(
+            Self::OUTPUT,
+            ImageData {
+                data,
+                width,
+                height,
+                origin,
+            },
+        );
+        Ok(output)
+    }
+);
+
+#[test]
+fn gradient_map() {
+    let gray = |v| Pixel {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    let mut input = HashMap::new();
+    input.insert(
+        GradientMap::INPUT,
+        ImageData {
+            data: vec![0.0, 0.0, 0.0, 1.0, 0.5, 0.5, 0.5, 0.25, 1.0, 1.0, 1.0, 1.0],
+            width: 3,
+            height: 1,
+            origin: None,
+        },
+    );
+
+    let grayscale = GradientMap::new(vec![(0.0, gray(0.0)), (1.0, gray(1.0))])
+        .execute(input.clone(), &EvalContext::default())
+        .unwrap();
+    assert_eq!(input[GradientMap::INPUT], grayscale[GradientMap::OUTPUT]);
+
+    let tinted = GradientMap::new(vec![(0.0, gray(0.0)), (1.0, red)])
+        .execute(input.clone(), &EvalContext::default())
+        .unwrap();
+    assert_eq!(
+        vec![0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 0.0, 0.25, 1.0, 0.0, 0.0, 1.0],
+        tinted[GradientMap::OUTPUT].data
+    );
+
+    let constant = GradientMap::new(vec![(0.3, red)])
+        .execute(input, &EvalContext::default())
+        .unwrap();
+    assert_eq!(
+        vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.25, 1.0, 0.0, 0.0, 1.0],
+        constant[GradientMap::OUTPUT].data
+    );
+}
+
+#[test]
+fn wipe() {
+    let mut input = HashMap::new();
+    input.insert(
+        Wipe::INPUT_A,
+        ImageData {
+            data: vec![0.0; 4 * 2 * 4],
+            width: 4,
+            height: 2,
+            origin: None,
+        },
+    );
+    input.insert(
+        Wipe::INPUT_B,
+        ImageData {
+            data: vec![1.0; 4 * 2 * 4],
+            width: 4,
+            height: 2,
+            origin: None,
+        },
+    );
+
+    let wipe = |progress, mode| {
+        Wipe::new(progress, mode)
+            .execute(input.clone(), &EvalContext::default())
+            .unwrap()
+            .remove(Wipe::OUTPUT)
+            .unwrap()
+    };
+    // red channel of the top row
+    let row = |image: &ImageData| (0..4).map(|x| image.data[x * 4]).collect::<Vec<_>>();
+
+    for &mode in &[
+        WipeMode::LinearLeft,
+        WipeMode::LinearRight,
+        WipeMode::Radial,
+        WipeMode::Clock,
+    ] {
+        assert_eq!(input[Wipe::INPUT_A], wipe(0.0, mode));
+        assert_eq!(input[Wipe::INPUT_B], wipe(1.0, mode));
+    }
+
+    assert_eq!(
+        vec![1.0, 1.0, 0.0, 0.0],
+        row(&wipe(0.5, WipeMode::LinearLeft))
+    );
+    assert_eq!(
+        vec![0.0, 0.0, 1.0, 1.0],
+        row(&wipe(0.5, WipeMode::LinearRight))
+    );
+    assert_eq!(vec![0.0, 0.0, 1.0, 1.0], row(&wipe(0.5, WipeMode::Clock)));
+}
+
+#[test]
+fn distance_field() {
+    // opaque 2x2 square in the middle of a transparent 6x6 image
+    let mut data = vec![0.0; 6 * 6 * 4];
+    for &(x, y) in &[(2, 2), (3, 2), (2, 3), (3, 3)] {
+        data[(y * 6 + x) * 4 + 3] = 1.0;
+    }
+
+    let mut input = HashMap::new();
+    input.insert(
+        DistanceField::INPUT,
+        ImageData {
+            data,
+            width: 6,
+            height: 6,
+            origin: None,
+        },
+    );
+
+    let output = DistanceField::new(4.0)
+        .execute(input, &EvalContext::default())
+        .unwrap()
+        .remove(DistanceField::OUTPUT)
+        .unwrap();
+    let at = |x: usize, y: usize| output.data[(y * 6 + x) * 4];
+
+    for &(x, y) in &[(2, 2), (3, 2), (2, 3), (3, 3)] {
+        assert_eq!(0.0, at(x, y));
+    }
+
+    // moving away from the square along a row
+    assert_eq!(0.25, at(4, 2));
+    assert_eq!(0.5, at(5, 2));
+    assert_eq!(0.25, at(1, 3));
+    assert_eq!(0.5, at(0, 3));
+
+    // diagonally away from the corner
+    assert!((at(4, 4) - 2f32.sqrt() / 4.0).abs() < 1e-6);
+    assert!(at(5, 5) > at(4, 4));
+    assert_eq!(1.0, output.data[3]);
+
+    let mut input = HashMap::new();
+    input.insert(
+        DistanceField::INPUT,
+        ImageData {
+            data: Vec::new(),
+            width: 0,
+            height: 3,
+            origin: None,
+        },
+    );
+    let empty = DistanceField::new(4.0)
+        .execute(input, &EvalContext::default())
+        .unwrap()
+        .remove(DistanceField::OUTPUT)
+        .unwrap();
+    assert!(empty.data.is_empty());
+}
+
+#[test]
+fn alpha_over_coverage() {
+    let mut input = HashMap::new();
+    input.insert(
+        AlphaOver::INPUT_A,
+        ImageData {
+            data: vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0],
+            width: 3,
+            height: 1,
+            origin: None,
+        },
+    );
+    input.insert(
+        AlphaOver::INPUT_B,
+        ImageData {
+            data: vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 0.0, 1.0, 1.0],
+            width: 3,
+            height: 1,
+            origin: None,
+        },
+    );
+
+    let mut output = AlphaOver::new(1.0)
+        .execute(input, &EvalContext::default())
+        .unwrap();
+    let composited = output.remove(AlphaOver::OUTPUT).unwrap();
+    let coverage = output.remove(AlphaOver::COVERAGE).unwrap();
+
+    assert_eq!(composited.width, coverage.width);
+    assert_eq!(composited.height, coverage.height);
+    for (pixel, coverage) in composited
+        .data
+        .chunks_exact(4)
+        .zip(coverage.data.chunks_exact(4))
+    {
+        assert_eq!(&[pixel[3], pixel[3], pixel[3], 1.0], coverage);
+    }
+    assert_eq!(
+        vec![1.0, 0.75, 1.0],
+        coverage.data.iter().step_by(4).copied().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn crop_process_uncrop() {
+    use crate::geometry::Point;
+
+    let gray = |v| Pixel {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    };
+    let canvas = ImageBuilder::new()
+        .size(6, 4)
+        .fill(gray(0.25))
+        .build()
+        .into_data();
+
+    let mut input = HashMap::new();
+    input.insert(Crop::INPUT, canvas.clone());
+    let region = Crop::new(3, 1, 2, 2)
+        .execute(input, &EvalContext::default())
+        .unwrap()
+        .remove(Crop::OUTPUT)
+        .unwrap();
+    assert_eq!((2, 2), (region.width, region.height));
+    assert_eq!(Some(Point::new(3, 1)), region.origin);
+
+    // invert just the region, which keeps track of where it came from
+    let mut input = HashMap::new();
+    input.insert(GradientMap::INPUT, region);
+    let processed = GradientMap::new(vec![(0.0, gray(1.0)), (1.0, gray(0.0))])
+        .execute(input, &EvalContext::default())
+        .unwrap()
+        .remove(GradientMap::OUTPUT)
+        .unwrap();
+    assert_eq!(Some(Point::new(3, 1)), processed.origin);
+
+    let mut input = HashMap::new();
+    input.insert(UnCrop::INPUT, processed);
+    input.insert(UnCrop::BACKGROUND, canvas);
+    let output = Image::from(
+        UnCrop::new()
+            .execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(UnCrop::OUTPUT)
+            .unwrap(),
+    );
+
+    assert_eq!((6, 4), (output.width(), output.height()));
+    for y in 0..4 {
+        for x in 0..6 {
+            let inside = (3..5).contains(&x) && (1..3).contains(&y);
+            let expected = if inside { gray(0.75) } else { gray(0.25) };
+            assert_eq!(expected, output.pixel_at(x, y), "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn motion_blur_streak() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    let mut image = Image::from_solid(9, 5, black).unwrap();
+    image.set_pixel(4, 2, white);
+
+    let mut input = HashMap::new();
+    input.insert(MotionBlur::INPUT, image.into_data());
+    let output = Image::from(
+        MotionBlur::new(0.0, 5.0)
+            .execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(MotionBlur::OUTPUT)
+            .unwrap(),
+    );
+
+    for x in 0..9 {
+        let expected = if (2..7).contains(&x) { 0.2 } else { 0.0 };
+        assert!(
+            (output.pixel_at(x, 2).r - expected).abs() < 1e-6,
+            "at {}",
+            x
+        );
+        assert_eq!(0.0, output.pixel_at(x, 1).r);
+        assert_eq!(0.0, output.pixel_at(x, 3).r);
+    }
+}
+
+#[test]
+fn blend_space_midtone() {
+    use super::{BlendSpace, CompositeSettings};
+
+    let mut input = HashMap::new();
+    input.insert(
+        MixRgba::INPUT_A,
+        Image::from_solid(
+            1,
+            1,
+            Pixel {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+            },
+        )
+        .unwrap()
+        .into_data(),
+    );
+    input.insert(
+        MixRgba::INPUT_B,
+        Image::from_solid(
+            1,
+            1,
+            Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+        )
+        .unwrap()
+        .into_data(),
+    );
+
+    let mix = |blend_space| {
+        let context = EvalContext {
+            composite: CompositeSettings { blend_space },
+            ..EvalContext::default()
+        };
+        MixRgba::new(0.5)
+            .execute(input.clone(), &context)
+            .unwrap()
+            .remove(MixRgba::OUTPUT_MIX)
+            .unwrap()
+            .data
+    };
+
+    // half of white in sRGB is 0.5, but half of the light is encoded as about 0.735
+    let srgb = mix(BlendSpace::Srgb);
+    assert_eq!(vec![0.5, 0.5, 0.5, 1.0], srgb);
+    let linear = mix(BlendSpace::Linear);
+    assert!((linear[0] - 0.7354).abs() < 1e-3);
+    assert!(linear[0] > srgb[0]);
+    assert_eq!(1.0, linear[3]);
+}
+
+#[test]
+fn preview_thumbnail() {
+    use super::Port;
+
+    let image = ImageBuilder::new()
+        .size(8, 4)
+        .checker(
+            1,
+            Pixel {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+            },
+            Pixel::TRANSPARENT,
+        )
+        .build()
+        .into_data();
+    let mut input = HashMap::new();
+    input.insert(Preview::INPUT, image.clone());
+
+    let mut preview = Preview::new(4);
+    let output = preview
+        .execute(input.clone(), &EvalContext::default())
+        .unwrap();
+    assert_eq!(image, output[Preview::OUTPUT]);
+    assert!(!output.contains_key(Preview::THUMBNAIL));
+
+    preview.connect_output(
+        Preview::THUMBNAIL,
+        Port {
+            node_name: String::from("Editor"),
+            slot_name: "INPUT",
+        },
+    );
+    let output = preview.execute(input, &EvalContext::default()).unwrap();
+    assert_eq!(image, output[Preview::OUTPUT]);
+    let thumbnail = &output[Preview::THUMBNAIL];
+    assert_eq!((4, 2), (thumbnail.width, thumbnail.height));
+}
+
+#[test]
+fn accumulate_mean() {
+    let frame = |v: f32| ImageData {
+        data: vec![v, v * 2.0, 0.0, 1.0],
+        width: 1,
+        height: 1,
+        origin: None,
+    };
+
+    let mut accumulate = Accumulate::new(AccumulateMode::Mean);
+    let feed = |accumulate: &mut Accumulate, v| {
+        let mut input = HashMap::new();
+        input.insert(Accumulate::INPUT, frame(v));
+        accumulate
+            .execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(Accumulate::OUTPUT)
+            .unwrap()
+            .data
+    };
+
+    assert_eq!(vec![0.3, 0.6, 0.0, 1.0], feed(&mut accumulate, 0.3));
+    feed(&mut accumulate, 0.1);
+    let mean = feed(&mut accumulate, 0.2);
+    assert!((mean[0] - 0.2).abs() < 1e-6);
+    assert!((mean[1] - 0.4).abs() < 1e-6);
+    assert_eq!(1.0, mean[3]);
+    assert_eq!(3, accumulate.frames);
+    assert!(accumulate.is_stateful());
+
+    accumulate.reset();
+    assert_eq!(vec![0.5, 1.0, 0.0, 1.0], feed(&mut accumulate, 0.5));
+
+    let mut max = Accumulate::new(AccumulateMode::Max);
+    feed(&mut max, 0.1);
+    feed(&mut max, 0.4);
+    assert_eq!(vec![0.4, 0.8, 0.0, 1.0], feed(&mut max, 0.2));
+}
+
+#[test]
+fn lut3d_identity_and_invert() {
+    use crate::lut;
+
+    let dir = std::env::temp_dir();
+    let identity_path = dir.join(format!("yocto-canvas-identity-{}.cube", std::process::id()));
+    let invert_path = dir.join(format!("yocto-canvas-invert-{}.cube", std::process::id()));
+    std::fs::write(&identity_path, lut::cube_text(5, |r, g, b| [r, g, b])).unwrap();
+    std::fs::write(
+        &invert_path,
+        lut::cube_text(2, |r, g, b| [1.0 - r, 1.0 - g, 1.0 - b]),
+    )
+    .unwrap();
+
+    let image = ImageData {
+        data: vec![0.0, 0.25, 0.6, 1.0, 1.0, 0.5, 0.1, 0.5],
+        width: 2,
+        height: 1,
+        origin: None,
+    };
+    let apply = |path: &std::path::Path| {
+        let mut input = HashMap::new();
+        input.insert(Lut3d::INPUT, image.clone());
+        Lut3d::new(path.to_string_lossy().into_owned())
+            .execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(Lut3d::OUTPUT)
+            .unwrap()
+            .data
+    };
+
+    let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+    assert!(close(&image.data, &apply(&identity_path)));
+    assert!(close(
+        &[1.0, 0.75, 0.4, 1.0, 0.0, 0.5, 0.9, 0.5],
+        &apply(&invert_path)
+    ));
+
+    std::fs::remove_file(&identity_path).unwrap();
+    std::fs::remove_file(&invert_path).unwrap();
+
+    // the loaded table doesn't stop the output from being cached
+    assert!(!Lut3d::new(String::new()).is_stateful());
+
+    let mut input = HashMap::new();
+    input.insert(Lut3d::INPUT, image);
+    assert!(matches!(
+        Lut3d::new(String::from("no such file.cube")).execute(input, &EvalContext::default()),
+        Err(NodeError::Resource { .. })
+    ));
+}
+
+#[test]
+fn bloom_glows() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    let mut image = Image::from_solid(9, 9, black).unwrap();
+    image.set_pixel(4, 4, white);
+
+    let bloom = |threshold| {
+        let mut input = HashMap::new();
+        input.insert(Bloom::INPUT, image.clone().into_data());
+        Image::from(
+            Bloom::new(threshold, 4.0, 2.0)
+                .execute(input, &EvalContext::default())
+                .unwrap()
+                .remove(Bloom::OUTPUT)
+                .unwrap(),
+        )
+    };
+
+    // a halo that fades out with distance, and stops at the radius
+    let glow = bloom(0.5);
+    assert_eq!(white, glow.pixel_at(4, 4));
+    let near = glow.pixel_at(5, 4).r;
+    let far = glow.pixel_at(6, 5).r;
+    assert!(near > far && far > 0.0, "{} {}", near, far);
+    assert!(near < 1.0);
+    assert!((near - glow.pixel_at(4, 3).r).abs() < 1e-6);
+    assert_eq!(black, glow.pixel_at(1, 4));
+    assert_eq!(black, glow.pixel_at(0, 0));
+
+    // nothing is bright enough
+    assert_eq!(image, bloom(1.5));
+}
+
+#[test]
+fn vignette_darkens_corners() {
+    let gray = Pixel {
+        r: 0.8,
+        g: 0.6,
+        b: 0.4,
+        a: 0.5,
+    };
+
+    let mut input = HashMap::new();
+    input.insert(
+        Vignette::INPUT,
+        Image::from_solid(9, 9, gray).unwrap().into_data(),
+    );
+    let output = Image::from(
+        Vignette::new(0.75, 0.2, 0.3)
+            .execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(Vignette::OUTPUT)
+            .unwrap(),
+    );
+
+    assert_eq!(gray, output.pixel_at(4, 4));
+
+    // the corner is past radius + softness, so it gets the full amount
+    let corner = output.pixel_at(0, 8);
+    assert!((corner.r - 0.8 * 0.25).abs() < 1e-6);
+    assert!((corner.g - 0.6 * 0.25).abs() < 1e-6);
+    assert!((corner.b - 0.4 * 0.25).abs() < 1e-6);
+    assert_eq!(0.5, corner.a);
+
+    // and in between is somewhere in between
+    let edge = output.pixel_at(4, 1);
+    assert!(edge.r < gray.r && edge.r > corner.r, "{}", edge.r);
+}
+
+#[test]
+fn text_draws_a() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    let mut text = Text::new(String::from("A"), 32.0, white, 4, 6, 40, 48, None);
+    let image = Image::from(
+        text.execute(HashMap::new(), &EvalContext::default())
+            .unwrap()
+            .remove(Text::OUTPUT)
+            .unwrap(),
+    );
+    assert_eq!((40, 48), (image.width(), image.height()));
+
+    // a capital A fits in a box the size of the font starting where the text was put, and has
+    // solid strokes
+    let inside = |x: u32, y: u32| (4..4 + 32).contains(&x) && (6..6 + 40).contains(&y);
+    let mut opaque = 0;
+    for (x, y, pixel) in image.region_pixels(image.bounds()) {
+        if inside(x, y) {
+            if pixel.a == 1.0 {
+                assert_eq!(white, pixel);
+                opaque += 1;
+            }
+        } else {
+            assert_eq!(Pixel::TRANSPARENT, pixel, "at {}, {}", x, y);
+        }
+    }
+    assert!(opaque > 20, "only {} opaque pixels", opaque);
+
+    // the middle of the A, between its legs, is empty
+    assert_eq!(0.0, image.pixel_at(4 + 9, 6 + 32).a);
+}
+
+#[test]
+fn fractal_noise_octaves() {
+    let noise = |seed, octaves| {
+        let mut node = FractalNoise::new(seed, octaves, 0.5, 2.0, 16.0, 64, 64);
+        node.execute(HashMap::new(), &EvalContext::default())
+            .unwrap()
+            .remove(FractalNoise::OUTPUT)
+            .unwrap()
+    };
+
+    let image = noise(7, 4);
+    assert_eq!((64, 64), (image.width, image.height));
+    assert_eq!(image.data, noise(7, 4).data);
+    assert_ne!(image.data, noise(8, 4).data);
+    assert!(image.data.iter().all(|value| (0.0..=1.0).contains(value)));
+
+    // differences between neighboring pixels grow with the finer octaves
+    let detail = |image: &ImageData| {
+        let red = image.data.iter().step_by(4).copied().collect::<Vec<_>>();
+        let differences = red
+            .chunks(image.width as usize)
+            .flat_map(|row| row.windows(2).map(|pair| pair[1] - pair[0]))
+            .collect::<Vec<_>>();
+        let mean = differences.iter().sum::<f32>() / differences.len() as f32;
+        differences
+            .iter()
+            .map(|difference| (difference - mean).powi(2))
+            .sum::<f32>()
+            / differences.len() as f32
+    };
+    let smooth = detail(&noise(7, 1));
+    let rough = detail(&noise(7, 5));
+    assert!(rough > smooth, "{} should be more than {}", rough, smooth);
+
+    // and tiles agree with the whole thing
+    let region = Rect::new(10, 20, 8, 8);
+    let mut node = FractalNoise::new(7, 4, 0.5, 2.0, 16.0, 64, 64);
+    let context = EvalContext {
+        region: Some(region),
+        ..EvalContext::default()
+    };
+    let tile = node
+        .execute(HashMap::new(), &context)
+        .unwrap()
+        .remove(FractalNoise::OUTPUT)
+        .unwrap();
+    assert_eq!(image.crop(region), tile);
+}
+
+#[test]
+fn frequency_separation_round_trip() {
+    let mut image = Image::from_solid(9, 7, Pixel::TRANSPARENT).unwrap();
+    for y in 0..7 {
+        for x in 0..9 {
+            let value = ((x * 5 + y * 3) % 7) as f32 / 6.0;
+            image.set_rgba(
+                x,
+                y,
+                value,
+                1.0 - value,
+                (x % 2) as f32,
+                (y + 1) as f32 / 7.0,
+            );
+        }
+    }
+
+    let mut separate = FrequencySeparation::new(2.0);
+    let mut input = HashMap::new();
+    input.insert(FrequencySeparation::INPUT, image.clone().into_data());
+    let mut bands = separate.execute(input, &EvalContext::default()).unwrap();
+    let low = bands.remove(FrequencySeparation::LOW).unwrap();
+    let high = bands.remove(FrequencySeparation::HIGH).unwrap();
+    assert_eq!(image.blur(2).into_data(), low);
+
+    // a flat image has no detail at all
+    let mut flat_input = HashMap::new();
+    flat_input.insert(
+        FrequencySeparation::INPUT,
+        Image::from_solid(4, 4, Pixel::from_slice(&[0.2, 0.4, 0.6, 1.0]))
+            .unwrap()
+            .into_data(),
+    );
+    let flat = separate
+        .execute(flat_input, &EvalContext::default())
+        .unwrap()
+        .remove(FrequencySeparation::HIGH)
+        .unwrap();
+    for pixel in flat.data.chunks_exact(4) {
+        assert!(pixel[..3].iter().all(|value| (value - 0.5).abs() < 1e-6));
+    }
+
+    let mut recombine = FrequencyRecombine::new();
+    let mut input = HashMap::new();
+    input.insert(FrequencyRecombine::LOW, low);
+    input.insert(FrequencyRecombine::HIGH, high);
+    let output = recombine
+        .execute(input, &EvalContext::default())
+        .unwrap()
+        .remove(FrequencyRecombine::OUTPUT)
+        .unwrap();
+    for (a, b) in image.into_data().data.iter().zip(output.data.iter()) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn stroke_outside_square() {
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    // a 3x3 square in the middle of a 7x7 image
+    let mut image = Image::from_solid(7, 7, Pixel::TRANSPARENT).unwrap();
+    for y in 2..5 {
+        for x in 2..5 {
+            image.set_pixel(x, y, blue);
+        }
+    }
+
+    let stroke = |position| {
+        let mut node = Stroke::new(1.0, red, position);
+        let mut input = HashMap::new();
+        input.insert(Stroke::INPUT, image.clone().into_data());
+        Image::from(
+            node.execute(input, &EvalContext::default())
+                .unwrap()
+                .remove(Stroke::OUTPUT)
+                .unwrap(),
+        )
+    };
+
+    let outside = stroke(StrokePosition::Outside);
+    for y in 0..7 {
+        for x in 0..7 {
+            let pixel = outside.pixel_at(x, y);
+            let ring = |v| (1..=5).contains(&v);
+            if (2..5).contains(&x) && (2..5).contains(&y) {
+                assert_eq!(blue, pixel, "interior changed at {}, {}", x, y);
+            } else if ring(x) && ring(y) && (x == 1 || x == 5) != (y == 1 || y == 5) {
+                assert_eq!(red, pixel, "no ring at {}, {}", x, y);
+            } else if ring(x) && ring(y) {
+                // the corners are a diagonal away, so they're only partly covered
+                assert_eq!((1.0, 0.0), (pixel.r, pixel.b));
+                assert!(pixel.a > 0.0 && pixel.a < 1.0, "corner {}, {}", x, y);
+            } else {
+                assert_eq!(Pixel::TRANSPARENT, pixel, "too far at {}, {}", x, y);
+            }
+        }
+    }
+
+    // inside, the outline stays where it was and the edge of the square turns red
+    let inside = stroke(StrokePosition::Inside);
+    assert_eq!(Pixel::TRANSPARENT, inside.pixel_at(1, 3));
+    assert_eq!(red, inside.pixel_at(2, 3));
+    assert_eq!(blue, inside.pixel_at(3, 3));
+}
+
+#[test]
+fn perspective_warp_trapezoid() {
+    let color = |r, g, b| Pixel { r, g, b, a: 1.0 };
+    let quadrants = [
+        color(1.0, 0.0, 0.0),
+        color(0.0, 1.0, 0.0),
+        color(0.0, 0.0, 1.0),
+        color(1.0, 1.0, 0.0),
+    ];
+
+    let mut image = Image::from_solid(16, 16, Pixel::TRANSPARENT).unwrap();
+    for y in 0..16 {
+        for x in 0..16 {
+            image.set_pixel(x, y, quadrants[(y / 8) * 2 + x / 8]);
+        }
+    }
+
+    // the top edge squeezed into the middle half
+    let mut node = PerspectiveWarp::new((4.0, 0.0), (12.0, 0.0), (16.0, 16.0), (0.0, 16.0));
+    let mut input = HashMap::new();
+    input.insert(PerspectiveWarp::INPUT, image.into_data());
+    let warped = Image::from(
+        node.execute(input, &EvalContext::default())
+            .unwrap()
+            .remove(PerspectiveWarp::OUTPUT)
+            .unwrap(),
+    );
+
+    // each corner of the input lands on its corner of the trapezoid
+    assert_eq!(quadrants[0], warped.pixel_at(4, 0));
+    assert_eq!(quadrants[1], warped.pixel_at(11, 0));
+    assert_eq!(quadrants[2], warped.pixel_at(0, 15));
+    assert_eq!(quadrants[3], warped.pixel_at(15, 15));
+
+    // and outside of it there's nothing
+    assert_eq!(Pixel::TRANSPARENT, warped.pixel_at(0, 0));
+    assert_eq!(Pixel::TRANSPARENT, warped.pixel_at(2, 1));
+    assert_eq!(Pixel::TRANSPARENT, warped.pixel_at(15, 0));
+
+    // corners in a line can't make a quadrilateral
+    let mut node = PerspectiveWarp::new((0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0));
+    let mut input = HashMap::new();
+    input.insert(
+        PerspectiveWarp::INPUT,
+        Image::from_solid(4, 4, Pixel::TRANSPARENT)
+            .unwrap()
+            .into_data(),
+    );
+    assert!(node.execute(input, &EvalContext::default()).is_err());
+}
+
+#[test]
+fn luma_key_bright_background() {
+    let gray = |v| Pixel {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    };
+    let midtone = Pixel {
+        r: 0.6,
+        g: 0.4,
+        b: 0.3,
+        a: 1.0,
+    };
+
+    // white background, a midtone subject, and the fade between them
+    let pixels = [gray(1.0), midtone, gray(0.8), gray(0.75)];
+    let mut image = Image::from_solid(pixels.len() as u32, 1, Pixel::TRANSPARENT).unwrap();
+    for (x, &pixel) in pixels.iter().enumerate() {
+        image.set_pixel(x, 0, pixel);
+    }
+
+    let key = |spill| {
+        let mut node = LumaKey::new(0.0, 0.7, 0.2, spill);
+        let mut input = HashMap::new();
+        input.insert(LumaKey::INPUT, image.clone().into_data());
+        let mut output = node.execute(input, &EvalContext::default()).unwrap();
+        (
+            Image::from(output.remove(LumaKey::OUTPUT).unwrap()),
+            Image::from(output.remove(LumaKey::MASK).unwrap()),
+        )
+    };
+
+    let (keyed, mask) = key(0.0);
+    assert_eq!(0.0, keyed.pixel_at(0, 0).a);
+    assert_eq!(midtone, keyed.pixel_at(1, 0));
+    assert_eq!(1.0, mask.pixel_at(1, 0).r);
+    assert_eq!(0.0, mask.pixel_at(0, 0).r);
+
+    // softly fading out between the high threshold and the high threshold plus the softness
+    let (halfway, closer) = (keyed.pixel_at(2, 0).a, keyed.pixel_at(3, 0).a);
+    assert!((halfway - 0.5).abs() < 1e-4, "{}", halfway);
+    assert!(closer > halfway && closer < 1.0, "{}", closer);
+    assert_eq!(halfway, mask.pixel_at(2, 0).r);
+    assert_eq!(0.8, keyed.pixel_at(2, 0).r);
+
+    // with spill suppression the fringe is darkened down to the threshold, and the subject is
+    // left alone
+    let (suppressed, _) = key(1.0);
+    assert!((suppressed.pixel_at(2, 0).r - 0.7).abs() < 1e-6);
+    assert_eq!(midtone, suppressed.pixel_at(1, 0));
+
+    let mut backwards = LumaKey::new(0.7, 0.0, 0.2, 1.0);
+    let mut input = HashMap::new();
+    input.insert(LumaKey::INPUT, image.clone().into_data());
+    assert!(matches!(
+        backwards.execute(input, &EvalContext::default()),
+        Err(NodeError::InvalidProperty { .. })
+    ));
+}
+
+#[test]
+fn black_and_white_channel_weights() {
+    let pixel = |r, g, b| Pixel { r, g, b, a: 1.0 };
+    let gray = |node: &mut BlackAndWhite, pixel| {
+        let mut input = HashMap::new();
+        input.insert(
+            BlackAndWhite::INPUT,
+            Image::from_solid(1, 1, pixel).unwrap().into_data(),
+        );
+        let mut output = node.execute(input, &EvalContext::default()).unwrap();
+        let result = Image::from(output.remove(BlackAndWhite::OUTPUT).unwrap()).pixel_at(0, 0);
+        assert_eq!((result.r, result.r), (result.g, result.b));
+        result.r
+    };
+
+    let red = pixel(1.0, 0.0, 0.0);
+    let blue = pixel(0.0, 0.0, 1.0);
+    let mut node = BlackAndWhite::new(0.4, 0.6, 0.4, 0.6, 0.2, 0.8);
+    assert_eq!(0.4, gray(&mut node, red));
+    assert_eq!(0.2, gray(&mut node, blue));
+
+    // red only answers to the red weight, and blue to the blue weight
+    node.red = 0.9;
+    assert_eq!(0.9, gray(&mut node, red));
+    assert_eq!(0.2, gray(&mut node, blue));
+    node.blue = 0.05;
+    assert_eq!(0.9, gray(&mut node, red));
+    assert_eq!(0.05, gray(&mut node, blue));
+
+    // secondaries and mixes of them
+    assert_eq!(0.6, gray(&mut node, pixel(1.0, 1.0, 0.0)));
+    assert_eq!(0.8, gray(&mut node, pixel(1.0, 0.0, 1.0)));
+    // half orange is a quarter red and a quarter yellow on a bit of gray
+    let orange = gray(&mut node, pixel(0.8, 0.55, 0.3));
+    assert!(
+        (orange - (0.3 + 0.25 * 0.9 + 0.25 * 0.6)).abs() < 1e-6,
+        "{}",
+        orange
+    );
+
+    // grays stay the same
+    assert_eq!(0.5, gray(&mut node, pixel(0.5, 0.5, 0.5)));
+}
+
+#[test]
+fn math_blend_operations() {
+    let a = Pixel {
+        r: 0.75,
+        g: 0.5,
+        b: 0.2,
+        a: 0.8,
+    };
+    let b = Pixel {
+        r: 0.5,
+        g: 0.0,
+        b: 0.6,
+        a: 0.3,
+    };
+
+    let blend = |operation, clamp| {
+        let mut node = MathBlend::new(operation, clamp);
+        let mut input = HashMap::new();
+        input.insert(
+            MathBlend::INPUT_A,
+            Image::from_solid(1, 1, a).unwrap().into_data(),
+        );
+        input.insert(
+            MathBlend::INPUT_B,
+            Image::from_solid(1, 1, b).unwrap().into_data(),
+        );
+        let mut output = node.execute(input, &EvalContext::default()).unwrap();
+        let pixel = Image::from(output.remove(MathBlend::OUTPUT).unwrap()).pixel_at(0, 0);
+        assert_eq!(a.a, pixel.a);
+        [pixel.r, pixel.g, pixel.b]
+    };
+
+    #[rustfmt::skip]
+    let cases = [
+        // operation, unclamped, clamped
+        (MathOperation::Add, [1.25, 0.5, 0.8], [1.0, 0.5, 0.8]),
+        (MathOperation::Subtract, [0.25, 0.5, -0.4], [0.25, 0.5, 0.0]),
+        (MathOperation::Multiply, [0.375, 0.0, 0.12], [0.375, 0.0, 0.12]),
+        (MathOperation::Divide, [1.5, 0.0, 0.333333], [1.0, 0.0, 0.333333]),
+        (MathOperation::Min, [0.5, 0.0, 0.2], [0.5, 0.0, 0.2]),
+        (MathOperation::Max, [0.75, 0.5, 0.6], [0.75, 0.5, 0.6]),
+        (MathOperation::Difference, [0.25, 0.5, 0.4], [0.25, 0.5, 0.4]),
+    ];
+
+    let close =
+        |a: [f32; 3], b: [f32; 3]| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-5);
+    for &(operation, unclamped, clamped) in cases.iter() {
+        let result = blend(operation, false);
+        assert!(
+            close(unclamped, result),
+            "{:?} gave {:?}",
+            operation,
+            result
+        );
+        let result = blend(operation, true);
+        assert!(
+            close(clamped, result),
+            "{:?} clamped gave {:?}",
+            operation,
+            result
+        );
+    }
+
+    // dividing by zero is zero, even zero by zero
+    assert_eq!(0.0, MathOperation::Divide.apply(0.0, 0.0));
+    assert_eq!(0.0, MathOperation::Divide.apply(-1.0, 0.0));
+}
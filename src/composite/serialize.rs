@@ -0,0 +1,144 @@
+use super::{nodes, NodeGraph, Port};
+
+use crate::Result;
+
+use anyhow::{anyhow, Context};
+
+use serde_json::{json, Map, Value};
+
+// {
+//   "nodes": {
+//     "MixRgba": {
+//       "type": "MixRgba",
+//       "properties": { "mix": 0.5 },
+//       "inputs": { "INPUT_A": { "node": "Source", "slot": "OUTPUT" } },
+//       "position": [10.0, 20.0]
+//     },
+//     ...
+//   },
+//   "settings": { "blend_space": "Srgb" }
+// }
+//
+// outputs aren't written since they're implied by the inputs of the other nodes
+
+impl NodeGraph {
+    /// Serialize the graph, including editor positions, to JSON.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    /// The graph as it's written by `to_json`, to be embedded in other JSON.
+    pub fn to_value(&self) -> Value {
+        let mut nodes = Map::new();
+        for (name, node) in self.nodes.iter() {
+            let mut inputs = Map::new();
+            for &slot in node.inputs() {
+                if let Some(source) = node.input_source(slot) {
+                    inputs.insert(
+                        slot.to_string(),
+                        json!({ "node": source.node_name, "slot": source.slot_name }),
+                    );
+                }
+            }
+
+            let mut object = Map::new();
+            object.insert(String::from("type"), json!(node.name()));
+            object.insert(String::from("properties"), node.properties());
+            object.insert(String::from("inputs"), Value::Object(inputs));
+            if let Some((x, y)) = self.position(name) {
+                object.insert(String::from("position"), json!([x, y]));
+            }
+
+            nodes.insert(name.clone(), Value::Object(object));
+        }
+
+        // serde_json's map is sorted, so the output is stable
+        json!({ "nodes": nodes, "settings": self.settings })
+    }
+
+    /// Load a graph written by `to_json`.
+    pub fn from_json(json: &str) -> Result<NodeGraph> {
+        let value: Value = serde_json::from_str(json).context("Couldn't parse node graph")?;
+        NodeGraph::from_value(&value)
+    }
+
+    /// Load a graph from JSON that's already been parsed, as written by `to_value`.
+    pub fn from_value(value: &Value) -> Result<NodeGraph> {
+        let nodes = value["nodes"]
+            .as_object()
+            .ok_or_else(|| anyhow!("Node graph has no nodes"))?;
+
+        let mut graph = NodeGraph::new();
+
+        // older graphs don't have settings
+        if let Some(settings) = value.get("settings") {
+            graph.settings =
+                serde_json::from_value(settings.clone()).context("Couldn't read graph settings")?;
+        }
+
+        for (name, object) in nodes {
+            let type_name = object["type"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Node {} has no type", name))?;
+
+            let node = nodes::from_properties(type_name, &object["properties"])
+                .ok_or_else(|| anyhow!("Node {} has unknown type {}", name, type_name))?
+                .with_context(|| format!("Couldn't read properties of node {}", name))?;
+
+            graph.nodes.insert(name.clone(), node);
+
+            if let Some([x, y]) = object["position"]
+                .as_array()
+                .and_then(|position| position.get(0..2))
+            {
+                let (x, y) = (x.as_f64(), y.as_f64());
+                if let (Some(x), Some(y)) = (x, y) {
+                    graph.set_position(name, (x as f32, y as f32));
+                }
+            }
+        }
+
+        // connect once every node exists
+        for (name, object) in nodes {
+            let inputs = match object["inputs"].as_object() {
+                Some(inputs) => inputs,
+                None => continue,
+            };
+
+            for (slot, source) in inputs {
+                let to = graph.port(name, slot, true)?;
+
+                let source_name = source["node"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Input {}.{} has no source node", name, slot))?;
+                let source_slot = source["slot"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Input {}.{} has no source slot", name, slot))?;
+                let from = graph.port(source_name, source_slot, false)?;
+
+                graph.connect(from, to);
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Find the port for a slot on a node by the slot's name.
+    pub fn port(&self, node_name: &str, slot_name: &str, input: bool) -> Result<Port> {
+        let node = self
+            .nodes
+            .get(node_name)
+            .ok_or_else(|| anyhow!("No node named {}", node_name))?;
+
+        let slots = if input { node.inputs() } else { node.outputs() };
+        let slot_name = slots
+            .iter()
+            .find(|slot| **slot == slot_name)
+            .ok_or_else(|| anyhow!("Node {} has no slot {}", node_name, slot_name))?;
+
+        Ok(Port {
+            node_name: node_name.to_string(),
+            slot_name,
+        })
+    }
+}
@@ -0,0 +1,160 @@
+use super::NodeGraph;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// How big and how tangled a node graph is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphStats {
+    pub nodes: usize,
+    /// Connections, counting each connected input once.
+    pub edges: usize,
+    /// Groups of nodes that are connected to each other, ignoring direction.
+    pub components: usize,
+    /// How many nodes are on the longest chain of connections. Zero for an empty graph.
+    pub depth: usize,
+    /// How many of each kind of node there are, by `Node::name`.
+    pub node_types: BTreeMap<&'static str, usize>,
+}
+
+impl NodeGraph {
+    /// Count up the nodes and connections in the graph.
+    pub fn stats(&self) -> GraphStats {
+        let mut stats = GraphStats {
+            nodes: self.nodes.len(),
+            ..GraphStats::default()
+        };
+
+        // union-find over the node names, joining both ends of every connection
+        let mut parents = self
+            .nodes
+            .keys()
+            .map(|name| (name.as_str(), name.as_str()))
+            .collect::<HashMap<_, _>>();
+
+        for (name, node) in self.nodes.iter() {
+            *stats.node_types.entry(node.name()).or_default() += 1;
+
+            for source in self.sources(name) {
+                stats.edges += 1;
+                let a = find(&mut parents, name);
+                let b = find(&mut parents, source);
+                parents.insert(a, b);
+            }
+        }
+
+        let names = self.nodes.keys().map(String::as_str).collect::<Vec<_>>();
+        stats.components = names
+            .iter()
+            .filter(|&&name| find(&mut parents, name) == name)
+            .count();
+
+        let mut depths = HashMap::new();
+        stats.depth = names
+            .iter()
+            .map(|name| self.depth(name, &mut depths))
+            .max()
+            .unwrap_or(0);
+
+        stats
+    }
+
+    // names of the nodes connected to the inputs of a node, that are in the graph
+    fn sources<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a str> {
+        let node = &self.nodes[name];
+        node.inputs()
+            .iter()
+            .filter_map(move |&slot| node.input_source(slot))
+            .map(|source| source.node_name.as_str())
+            .filter(move |source| self.nodes.contains_key(*source))
+    }
+
+    // the longest chain of nodes ending at a node. a node that's still being visited counts as
+    // zero, so a cycle can't recurse forever
+    fn depth<'a>(&'a self, name: &'a str, depths: &mut HashMap<&'a str, usize>) -> usize {
+        if let Some(&depth) = depths.get(name) {
+            return depth;
+        }
+
+        depths.insert(name, 0);
+        let depth = 1 + self
+            .sources(name)
+            .map(|source| self.depth(source, depths))
+            .max()
+            .unwrap_or(0);
+        depths.insert(name, depth);
+        depth
+    }
+}
+
+fn find<'a>(parents: &mut HashMap<&'a str, &'a str>, name: &'a str) -> &'a str {
+    let parent = parents[name];
+    if parent == name {
+        return name;
+    }
+
+    let root = find(parents, parent);
+    parents.insert(name, root);
+    root
+}
+
+#[test]
+fn stats_of_known_graph() {
+    use super::{
+        nodes::{MixRgba, MotionBlur, Source},
+        Port,
+    };
+    use crate::image::{Image, Pixel};
+
+    let image = || {
+        Image::from_solid(2, 2, Pixel::TRANSPARENT)
+            .unwrap()
+            .into_data()
+    };
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+
+    assert_eq!(GraphStats::default(), NodeGraph::new().stats());
+
+    // source -> blur -> blur -> mix, with the source also going straight into the mix, and
+    // another source off by itself
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(image())));
+    let blur1 = graph.add(Box::new(MotionBlur::new(0.0, 2.0)));
+    let blur2 = graph.add(Box::new(MotionBlur::new(90.0, 2.0)));
+    let mix = graph.add(Box::new(MixRgba::new(0.5)));
+    graph.add(Box::new(Source::new(image())));
+
+    graph.connect(
+        port(&source, Source::OUTPUT),
+        port(&blur1, MotionBlur::INPUT),
+    );
+    graph.connect(
+        port(&blur1, MotionBlur::OUTPUT),
+        port(&blur2, MotionBlur::INPUT),
+    );
+    graph.connect(
+        port(&blur2, MotionBlur::OUTPUT),
+        port(&mix, MixRgba::INPUT_A),
+    );
+    graph.connect(port(&source, Source::OUTPUT), port(&mix, MixRgba::INPUT_B));
+
+    let stats = graph.stats();
+    assert_eq!(5, stats.nodes);
+    assert_eq!(4, stats.edges);
+    assert_eq!(2, stats.components);
+    assert_eq!(4, stats.depth);
+    assert_eq!(
+        vec![("MixRgba", 1), ("MotionBlur", 2), ("Source", 2)],
+        stats.node_types.into_iter().collect::<Vec<_>>()
+    );
+
+    // cutting the chain splits it in two
+    graph.remove_node(&blur2);
+    let stats = graph.stats();
+    assert_eq!(4, stats.nodes);
+    assert_eq!(2, stats.edges);
+    assert_eq!(2, stats.components);
+    assert_eq!(2, stats.depth);
+}
@@ -0,0 +1,261 @@
+use super::{EvalContext, NodeError, NodeGraph, Port, Tiling};
+
+use crate::{
+    geometry::{Rect, Size},
+    image::{Image, ImageData},
+};
+
+use std::collections::HashMap;
+
+// nodes that can't be tiled are evaluated whole once, and the tiles are cut out of that. this also
+// keeps stateful nodes from advancing once per tile
+type Cache = HashMap<Port, ImageData>;
+
+impl NodeGraph {
+    /// Evaluate the graph one `tile_size` square at a time, handing each finished tile to `sink`
+    /// with its `origin` set to where it goes in the output. Returns the size of the whole output.
+    ///
+    /// Nodes that can be tiled only ever see their part of the image, plus however much around it
+    /// they say they need, so peak memory depends on the tile size rather than the image size.
+    /// Nodes that can't be tiled are evaluated whole, once.
+    pub fn evaluate_tiles(
+        &mut self,
+        output: &Port,
+        context: &EvalContext,
+        tile_size: u32,
+        mut sink: impl FnMut(ImageData),
+    ) -> Result<Size, NodeError> {
+        let tile_size = tile_size.max(1);
+        let context = EvalContext {
+            region: None,
+            ..*context
+        };
+
+        let mut cache = Cache::new();
+        let size = self.output_size(output, &context, &mut cache)?;
+
+        for y in (0..size.height).step_by(tile_size as usize) {
+            for x in (0..size.width).step_by(tile_size as usize) {
+                let tile = Rect::new(x as i32, y as i32, tile_size, tile_size).clamp_to(size);
+                sink(self.evaluate_region(output, tile, &context, &mut cache)?);
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// Evaluate the graph a tile at a time, and put the tiles together. The result is the same
+    /// as `evaluate_with`.
+    pub fn evaluate_tiled(
+        &mut self,
+        output: &Port,
+        context: &EvalContext,
+        tile_size: u32,
+    ) -> Result<ImageData, NodeError> {
+        let mut tiles = Vec::new();
+        let size = self.evaluate_tiles(output, context, tile_size, |tile| tiles.push(tile))?;
+
+        let mut image = Image::from(ImageData {
+            data: vec![0.; size.area() * 4],
+            width: size.width,
+            height: size.height,
+            origin: None,
+        });
+        for tile in tiles {
+            let at = tile.origin.unwrap_or_default();
+            image.paste(&Image::from(tile), at);
+        }
+
+        Ok(image.into_data())
+    }
+
+    // how big the output of a port is, without evaluating anything that can be tiled
+    fn output_size(
+        &mut self,
+        port: &Port,
+        context: &EvalContext,
+        cache: &mut Cache,
+    ) -> Result<Size, NodeError> {
+        let node = self
+            .nodes
+            .get(&port.node_name)
+            .ok_or_else(|| NodeError::NoSuchNode(port.node_name.clone()))?;
+
+        match node.tiling(context) {
+            Some(Tiling::Generator { width, height }) => Ok(Size::new(width, height)),
+            Some(Tiling::Local { .. }) => {
+                // every input is the same size as the output
+                let slot = node.inputs()[0];
+                let source = node
+                    .input_source(slot)
+                    .ok_or_else(|| NodeError::MissingInput {
+                        node: port.node_name.clone(),
+                        slot,
+                    })?
+                    .clone();
+                self.output_size(&source, context, cache)
+            }
+            None => {
+                let whole = self.evaluate_whole(port, context, cache)?;
+                Ok(Size::new(whole.width, whole.height))
+            }
+        }
+    }
+
+    // evaluate just the part of the output of a port in `region`
+    fn evaluate_region(
+        &mut self,
+        port: &Port,
+        region: Rect,
+        context: &EvalContext,
+        cache: &mut Cache,
+    ) -> Result<ImageData, NodeError> {
+        let node = self
+            .nodes
+            .get(&port.node_name)
+            .ok_or_else(|| NodeError::NoSuchNode(port.node_name.clone()))?;
+
+        let output = match node.tiling(context) {
+            Some(Tiling::Generator { .. }) => {
+                let context = EvalContext {
+                    region: Some(region),
+                    ..*context
+                };
+                self.execute_node(port, HashMap::new(), &context)?
+            }
+
+            Some(Tiling::Local { halo }) => {
+                // the inputs are clipped to the image, so nodes see the real edges of the image
+                // and nothing else
+                let halo = halo as i32;
+                let needed = Rect::new(
+                    region.left() - halo,
+                    region.top() - halo,
+                    region.size.width + 2 * halo as u32,
+                    region.size.height + 2 * halo as u32,
+                );
+
+                let sources = node
+                    .inputs()
+                    .iter()
+                    .filter_map(|&slot| Some((slot, node.input_source(slot)?.clone())))
+                    .collect::<Vec<_>>();
+
+                let mut input = HashMap::new();
+                for (slot, source) in sources {
+                    input.insert(slot, self.evaluate_region(&source, needed, context, cache)?);
+                }
+
+                self.execute_node(port, input, context)?
+            }
+
+            None => self.evaluate_whole(port, context, cache)?.clone(),
+        };
+
+        // trim the halo, or whatever a generator made past the region
+        let origin = output.origin.unwrap_or_default();
+        Ok(output.crop(region.offset(-origin.x, -origin.y)))
+    }
+
+    fn evaluate_whole<'a>(
+        &mut self,
+        port: &Port,
+        context: &EvalContext,
+        cache: &'a mut Cache,
+    ) -> Result<&'a ImageData, NodeError> {
+        if !cache.contains_key(port) {
+            let whole = self.evaluate_with(port, context)?;
+            cache.insert(port.clone(), whole);
+        }
+
+        Ok(&cache[port])
+    }
+
+    fn execute_node(
+        &mut self,
+        port: &Port,
+        input: HashMap<&'static str, ImageData>,
+        context: &EvalContext,
+    ) -> Result<ImageData, NodeError> {
+        self.nodes
+            .get_mut(&port.node_name)
+            .unwrap()
+            .execute(input, context)
+            .map_err(|error| error.with_node_name(&port.node_name))?
+            .remove(port.slot_name)
+            .ok_or_else(|| NodeError::MissingOutput {
+                node: port.node_name.clone(),
+                slot: port.slot_name,
+            })
+    }
+}
+
+#[test]
+fn tiled_matches_whole() {
+    use super::nodes::{MixRgba, MotionBlur, Source};
+    use crate::image::Pixel;
+
+    // something with detail everywhere, so a wrong seam would show
+    let (width, height) = (23, 17);
+    let mut image = Image::from_solid(width, height, Pixel::TRANSPARENT).unwrap();
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            image.set_rgba(
+                x,
+                y,
+                ((x * 7 + y * 3) % 11) as f32 / 10.0,
+                ((x * y) % 5) as f32 / 4.0,
+                (x % 2) as f32,
+                ((x + y) % 4) as f32 / 3.0,
+            );
+        }
+    }
+
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(image.into_data())));
+    let horizontal = graph.add(Box::new(MotionBlur::new(0.0, 5.0)));
+    let diagonal = graph.add(Box::new(MotionBlur::new(45.0, 7.0)));
+    let mix = graph.add(Box::new(MixRgba::new(0.3)));
+
+    let port = |node_name: &String, slot_name| Port {
+        node_name: node_name.clone(),
+        slot_name,
+    };
+    graph.connect(
+        port(&source, Source::OUTPUT),
+        port(&horizontal, MotionBlur::INPUT),
+    );
+    graph.connect(
+        port(&horizontal, MotionBlur::OUTPUT),
+        port(&diagonal, MotionBlur::INPUT),
+    );
+    graph.connect(
+        port(&diagonal, MotionBlur::OUTPUT),
+        port(&mix, MixRgba::INPUT_A),
+    );
+    graph.connect(port(&source, Source::OUTPUT), port(&mix, MixRgba::INPUT_B));
+
+    let output = port(&mix, MixRgba::OUTPUT_MIX);
+    let whole = graph.evaluate(&output).unwrap();
+
+    for &tile_size in &[1, 4, 5, 16, 100] {
+        let mut tiles = 0;
+        let mut largest = 0;
+        graph
+            .evaluate_tiles(&output, &EvalContext::default(), tile_size, |tile| {
+                tiles += 1;
+                largest = largest.max(tile.data.len());
+            })
+            .unwrap();
+        assert!(largest <= (tile_size * tile_size * 4) as usize);
+        assert_eq!(
+            width.div_ceil(tile_size) * height.div_ceil(tile_size),
+            tiles
+        );
+
+        let tiled = graph
+            .evaluate_tiled(&output, &EvalContext::default(), tile_size)
+            .unwrap();
+        assert_eq!(whole.data, tiled.data, "tile size {}", tile_size);
+    }
+}
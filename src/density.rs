@@ -0,0 +1,164 @@
+//! Physical pixel density metadata, so images keep their print size.
+
+use crate::{Context, Result};
+
+use anyhow::anyhow;
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+const METERS_PER_INCH: f32 = 0.0254;
+
+/// How many pixels fit in an inch when the image is printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Density {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Density {
+    #[allow(dead_code)] // only the tests need the same density both ways so far
+    pub fn dpi(dpi: f32) -> Density {
+        Density { x: dpi, y: dpi }
+    }
+}
+
+/// Read the density of a PNG from its `pHYs` chunk, or of a JPEG from its JFIF header. `None` if
+/// the file doesn't say, or only gives an aspect ratio, or is some other format.
+pub fn read(path: impl AsRef<Path>) -> Result<Option<Density>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let (_, reader) = decoder
+            .read_info()
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        Ok(reader.info().pixel_dims.and_then(|dims| match dims.unit {
+            png::Unit::Meter => Some(Density {
+                x: dims.xppu as f32 * METERS_PER_INCH,
+                y: dims.yppu as f32 * METERS_PER_INCH,
+            }),
+            png::Unit::Unspecified => None,
+        }))
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        Ok(parse_jfif(&bytes))
+    } else {
+        Ok(None)
+    }
+}
+
+// the density fields of the APP0 segment, which comes right after the start of image marker
+fn parse_jfif(bytes: &[u8]) -> Option<Density> {
+    let app0 = bytes.get(2..20)?;
+    if app0[..2] != [0xff, 0xe0] || &app0[4..9] != b"JFIF\0" {
+        return None;
+    }
+
+    let x = u16::from_be_bytes([app0[12], app0[13]]) as f32;
+    let y = u16::from_be_bytes([app0[14], app0[15]]) as f32;
+    match app0[11] {
+        1 => Some(Density { x, y }),
+        2 => Some(Density {
+            x: x * 2.54,
+            y: y * 2.54,
+        }),
+        _ => None,
+    }
+}
+
+/// Write 8 bit RGBA pixels as a PNG, with a `pHYs` chunk if there's a density.
+pub fn save_png(
+    image: &image_library::RgbaImage,
+    path: impl AsRef<Path>,
+    density: Option<Density>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width(), image.height());
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let write = || -> std::result::Result<(), png::EncodingError> {
+        let mut writer = encoder.write_header()?;
+        if let Some(density) = density {
+            let per_meter = |dpi: f32| ((dpi / METERS_PER_INCH).round() as u32).to_be_bytes();
+            let mut phys = Vec::with_capacity(9);
+            phys.extend_from_slice(&per_meter(density.x));
+            phys.extend_from_slice(&per_meter(density.y));
+            phys.push(png::Unit::Meter as u8);
+            writer.write_chunk(*b"pHYs", &phys)?;
+        }
+        writer.write_image_data(image.as_raw())
+    };
+
+    write()
+        .map_err(|error| anyhow!(error))
+        .with_context(|| format!("Couldn't save {}", path.display()))
+}
+
+#[test]
+fn png_density_round_trip() {
+    let path = |name| {
+        std::env::temp_dir().join(format!(
+            "yocto-canvas-density-test-{}-{}.png",
+            name,
+            std::process::id()
+        ))
+    };
+    let image =
+        image_library::RgbaImage::from_vec(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 128]).unwrap();
+
+    // the bytes of the pHYs chunk, after its length and type
+    let phys = |path: &Path| {
+        let bytes = std::fs::read(path).unwrap();
+        let at = bytes.windows(4).position(|w| w == b"pHYs").unwrap();
+        bytes[at + 4..at + 13].to_vec()
+    };
+
+    let first = path("first");
+    save_png(&image, &first, Some(Density { x: 300.0, y: 150.0 })).unwrap();
+    // pHYs counts whole pixels per meter, so inches don't come back exactly
+    let density = read(&first).unwrap().unwrap();
+    assert!((density.x - 300.0).abs() < 0.05, "{:?}", density);
+    assert!((density.y - 150.0).abs() < 0.05, "{:?}", density);
+
+    // saving what was read writes the same chunk back out, and the same pixels
+    let second = path("second");
+    save_png(&image, &second, Some(density)).unwrap();
+    assert_eq!(phys(&first), phys(&second));
+    assert_eq!(
+        image,
+        image_library::open(&second).unwrap().to_rgba8(),
+        "pixels changed"
+    );
+
+    // and without a density there's no chunk at all
+    save_png(&image, &second, None).unwrap();
+    assert_eq!(None, read(&second).unwrap());
+
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+}
+
+#[test]
+fn jfif_density() {
+    let header = |units, x: u16, y: u16| {
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xe0, 0, 16];
+        bytes.extend_from_slice(b"JFIF\0");
+        bytes.extend_from_slice(&[1, 1, units]);
+        bytes.extend_from_slice(&x.to_be_bytes());
+        bytes.extend_from_slice(&y.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    };
+
+    assert_eq!(Some(Density::dpi(72.0)), parse_jfif(&header(1, 72, 72)));
+    assert_eq!(
+        Some(Density { x: 254.0, y: 127.0 }),
+        parse_jfif(&header(2, 100, 50))
+    );
+    // just an aspect ratio
+    assert_eq!(None, parse_jfif(&header(0, 1, 1)));
+    assert_eq!(None, parse_jfif(&[0xff, 0xd8, 0xff, 0xdb]));
+}
@@ -0,0 +1,491 @@
+//! Points and rectangles on the canvas, and the clipping math that goes with them.
+
+/// A position on the canvas, in pixels. Can be off of the canvas.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize,
+)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32) -> Point {
+        Point {
+            x: self.x + dx,
+            y: self.y + dy,
+        }
+    }
+
+    /// The point closest to `to` that's straight across, straight up or down, or diagonal from
+    /// this one, for drawing lines at multiples of 45 degrees.
+    pub fn snap_45(self, to: Point) -> Point {
+        let (dx, dy) = (to.x - self.x, to.y - self.y);
+        let (width, height) = (dx.abs() as f32, dy.abs() as f32);
+        // halfway between a side and a diagonal
+        let tan = std::f32::consts::FRAC_PI_8.tan();
+
+        if height <= width * tan {
+            Point::new(to.x, self.y)
+        } else if width <= height * tan {
+            Point::new(self.x, to.y)
+        } else {
+            // as far along the diagonal as `to` is
+            let along = ((width + height) / 2.).round() as i32;
+            self.offset(along * dx.signum(), along * dy.signum())
+        }
+    }
+
+    /// Every point on the line from this point to another, including both ends, with each one
+    /// touching the last by an edge or a corner. Bresenham's algorithm.
+    pub fn line_to(self, to: Point) -> Vec<Point> {
+        let (dx, dy) = ((to.x - self.x).abs(), -(to.y - self.y).abs());
+        let (step_x, step_y) = ((to.x - self.x).signum(), (to.y - self.y).signum());
+
+        let mut points = Vec::with_capacity(dx.max(-dy) as usize + 1);
+        let mut point = self;
+        let mut error = dx + dy;
+        loop {
+            points.push(point);
+            if point == to {
+                return points;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                point.x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                point.y += step_y;
+            }
+        }
+    }
+}
+
+/// The dimensions of an image or a region of one, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub fn new(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The number of pixels in the area.
+    pub fn area(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+/// A rectangle of pixels. The left and top edges are inclusive, the right and bottom edges are
+/// exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub origin: Point,
+    pub size: Size,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            origin: Point::new(x, y),
+            size: Size::new(width, height),
+        }
+    }
+
+    /// A rectangle covering an entire image of size `size`.
+    pub fn from_size(size: Size) -> Rect {
+        Rect {
+            origin: Point::default(),
+            size,
+        }
+    }
+
+    pub fn left(&self) -> i32 {
+        self.origin.x
+    }
+
+    pub fn top(&self) -> i32 {
+        self.origin.y
+    }
+
+    /// One past the rightmost column of the rectangle.
+    pub fn right(&self) -> i32 {
+        self.origin.x + self.size.width as i32
+    }
+
+    /// One past the bottom row of the rectangle.
+    pub fn bottom(&self) -> i32 {
+        self.origin.y + self.size.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.is_empty()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.left()
+            && point.x < self.right()
+            && point.y >= self.top()
+            && point.y < self.bottom()
+    }
+
+    /// The overlap of two rectangles, or `None` if they don't overlap at all.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = self.left().max(other.left());
+        let top = self.top().max(other.top());
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if left >= right || top >= bottom {
+            return None;
+        }
+
+        Some(Rect::new(
+            left,
+            top,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        ))
+    }
+
+    /// The smallest rectangle containing both. An empty rectangle doesn't add anything.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let left = self.left().min(other.left());
+        let top = self.top().min(other.top());
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// The part of the rectangle that is on a canvas of size `bounds`. Empty if none of it is,
+    /// in which case the origin is meaningless.
+    pub fn clamp_to(&self, bounds: Size) -> Rect {
+        self.intersection(&Rect::from_size(bounds))
+            .unwrap_or_default()
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            origin: self.origin.offset(dx, dy),
+            size: self.size,
+        }
+    }
+
+    /// Every point in the rectangle, row by row.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let Rect { origin, size } = *self;
+        (0..size.height as i32)
+            .flat_map(move |y| (0..size.width as i32).map(move |x| origin.offset(x, y)))
+    }
+}
+
+/// Lines every so many pixels across and down the canvas, like the edges of 8x8 tiles, for things
+/// to snap to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    /// How far apart the lines are each way. Nothing snaps along an axis with a spacing of 0.
+    pub spacing: Size,
+    /// Where one of the intersections is, so the grid doesn't have to start at the corner of the
+    /// canvas.
+    pub offset: Point,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            spacing: Size::new(8, 8),
+            offset: Point::new(0, 0),
+        }
+    }
+}
+
+impl Grid {
+    /// Parse a grid written like `16x8`, or `16x8+4+0` with an offset, like X11 geometry.
+    pub fn parse(spec: &str) -> Option<Grid> {
+        let (size, offset) = match spec.find('+') {
+            Some(plus) => (&spec[..plus], Some(&spec[plus + 1..])),
+            None => (spec, None),
+        };
+        let (width, height) = size.split_once('x')?;
+        let offset = match offset {
+            Some(offset) => {
+                let (x, y) = offset.split_once('+')?;
+                Point::new(x.parse().ok()?, y.parse().ok()?)
+            }
+            None => Point::default(),
+        };
+
+        Some(Grid {
+            spacing: Size::new(width.parse().ok()?, height.parse().ok()?),
+            offset,
+        })
+    }
+
+    /// The intersection of the grid closest to a point on the canvas. Halfway between two goes
+    /// to the one down and to the right.
+    pub fn snap(&self, point: Point) -> Point {
+        let snap = |value: i32, spacing: u32, offset: i32| {
+            if spacing == 0 {
+                return value;
+            }
+            let spacing = spacing as i32;
+            offset + (value - offset + spacing / 2).div_euclid(spacing) * spacing
+        };
+        Point::new(
+            snap(point.x, self.spacing.width, self.offset.x),
+            snap(point.y, self.spacing.height, self.offset.y),
+        )
+    }
+}
+
+/// A projective transform of the plane, which can map any quadrilateral onto any other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography {
+    /// Row major, acting on `(x, y, 1)`.
+    matrix: [[f64; 3]; 3],
+}
+
+impl Homography {
+    /// The transform taking each corner in `from` to the same corner in `to`. `None` if three of
+    /// the corners of either are in a line, or if any corner isn't finite.
+    pub fn from_quads(from: [(f32, f32); 4], to: [(f32, f32); 4]) -> Option<Homography> {
+        if from
+            .iter()
+            .chain(to.iter())
+            .any(|&(x, y)| !x.is_finite() || !y.is_finite())
+        {
+            return None;
+        }
+
+        // two equations per corner for the first eight entries of the matrix, with the last one
+        // fixed at 1
+        let mut system = [[0.; 9]; 8];
+        for (i, (&(x, y), &(u, v))) in from.iter().zip(to.iter()).enumerate() {
+            let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+            system[2 * i] = [x, y, 1., 0., 0., 0., -x * u, -y * u, u];
+            system[2 * i + 1] = [0., 0., 0., x, y, 1., -x * v, -y * v, v];
+        }
+
+        // gaussian elimination with partial pivoting
+        for column in 0..8 {
+            let pivot = (column..8)
+                .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+            if system[pivot][column].abs() < 1e-10 {
+                return None;
+            }
+            system.swap(column, pivot);
+
+            let pivot_row = system[column];
+            for (row, equation) in system.iter_mut().enumerate() {
+                if row != column {
+                    let factor = equation[column] / pivot_row[column];
+                    for (entry, pivot_entry) in
+                        equation[column..].iter_mut().zip(&pivot_row[column..])
+                    {
+                        *entry -= factor * pivot_entry;
+                    }
+                }
+            }
+        }
+
+        let h = |i: usize| system[i][8] / system[i][i];
+        Some(Homography {
+            matrix: [[h(0), h(1), h(2)], [h(3), h(4), h(5)], [h(6), h(7), 1.]],
+        })
+    }
+
+    /// Transform a point. `None` if it goes off to infinity.
+    pub fn apply(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let [a, b, c] = self.matrix;
+        let (x, y) = (x as f64, y as f64);
+        let w = c[0] * x + c[1] * y + c[2];
+        if w.abs() < 1e-10 {
+            return None;
+        }
+
+        Some((
+            ((a[0] * x + a[1] * y + a[2]) / w) as f32,
+            ((b[0] * x + b[1] * y + b[2]) / w) as f32,
+        ))
+    }
+}
+
+#[test]
+fn rect_intersection() {
+    let a = Rect::new(0, 0, 4, 4);
+    let b = Rect::new(2, 1, 4, 2);
+    assert_eq!(Some(Rect::new(2, 1, 2, 2)), a.intersection(&b));
+    assert_eq!(a.intersection(&b), b.intersection(&a));
+    assert_eq!(Some(a), a.intersection(&Rect::new(-5, -5, 20, 20)));
+}
+
+#[test]
+fn rect_clamp_to_bounds() {
+    let bounds = Size::new(8, 6);
+    assert_eq!(
+        Rect::new(0, 0, 3, 2),
+        Rect::new(-2, -3, 5, 5).clamp_to(bounds)
+    );
+    assert_eq!(
+        Rect::new(6, 4, 2, 2),
+        Rect::new(6, 4, 10, 10).clamp_to(bounds)
+    );
+    assert_eq!(
+        Rect::from_size(bounds),
+        Rect::new(-1, -1, 100, 100).clamp_to(bounds)
+    );
+}
+
+#[test]
+fn rect_empty_intersection() {
+    let a = Rect::new(0, 0, 4, 4);
+
+    // touching edges don't overlap
+    assert_eq!(None, a.intersection(&Rect::new(4, 0, 4, 4)));
+    assert_eq!(None, a.intersection(&Rect::new(0, -4, 4, 4)));
+    assert_eq!(None, a.intersection(&Rect::new(10, 10, 1, 1)));
+    assert_eq!(None, a.intersection(&Rect::new(1, 1, 0, 2)));
+
+    let off = Rect::new(-10, 2, 3, 3).clamp_to(Size::new(4, 4));
+    assert!(off.is_empty());
+    assert_eq!(0, off.points().count());
+
+    assert!(a.contains(Point::new(3, 3)));
+    assert!(!a.contains(Point::new(4, 3)));
+}
+
+#[test]
+fn line_has_no_gaps() {
+    let segments = [
+        (Point::new(0, 0), Point::new(10, 0)),
+        (Point::new(3, 9), Point::new(3, -4)),
+        (Point::new(0, 0), Point::new(7, 7)),
+        (Point::new(-5, 2), Point::new(12, 7)),
+        (Point::new(4, 20), Point::new(1, -3)),
+        (Point::new(6, 6), Point::new(6, 6)),
+    ];
+
+    for &(from, to) in segments.iter() {
+        let line = from.line_to(to);
+        assert_eq!(Some(&from), line.first());
+        assert_eq!(Some(&to), line.last());
+
+        // one point per step along the longer axis, each next to the last
+        let length = (to.x - from.x).abs().max((to.y - from.y).abs());
+        assert_eq!(length as usize + 1, line.len(), "{:?} to {:?}", from, to);
+        for pair in line.windows(2) {
+            let (dx, dy) = (pair[1].x - pair[0].x, pair[1].y - pair[0].y);
+            assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+        }
+
+        // and it doesn't stray from the ideal line
+        for point in line.iter() {
+            let cross = (to.x - from.x) * (point.y - from.y) - (to.y - from.y) * (point.x - from.x);
+            let distance = cross.abs() as f32 / (length.max(1) as f32);
+            assert!(
+                distance <= 1.0,
+                "{:?} is off the line {:?} to {:?}",
+                point,
+                from,
+                to
+            );
+        }
+    }
+}
+
+#[test]
+fn homography_maps_corners() {
+    let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+    let trapezoid = [(3.0, 1.0), (7.0, 1.0), (12.0, 9.0), (-2.0, 9.0)];
+
+    let forward = Homography::from_quads(square, trapezoid).unwrap();
+    let backward = Homography::from_quads(trapezoid, square).unwrap();
+    for (&(x, y), &(u, v)) in square.iter().zip(trapezoid.iter()) {
+        let (mapped_x, mapped_y) = forward.apply(x, y).unwrap();
+        assert!((mapped_x - u).abs() < 1e-4 && (mapped_y - v).abs() < 1e-4);
+
+        let (back_x, back_y) = backward.apply(u, v).unwrap();
+        assert!((back_x - x).abs() < 1e-4 && (back_y - y).abs() < 1e-4);
+    }
+
+    // the middle stays on the vertical center line, but closer to the narrow end
+    let (x, y) = forward.apply(5.0, 5.0).unwrap();
+    assert!((x - 5.0).abs() < 1e-4);
+    assert!(y < 5.0);
+
+    assert_eq!(
+        None,
+        Homography::from_quads(square, [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)])
+    );
+    assert_eq!(
+        None,
+        Homography::from_quads(
+            square,
+            [(f32::NAN, 1.0), (7.0, 1.0), (12.0, 9.0), (-2.0, 9.0)]
+        )
+    );
+}
+
+#[test]
+fn snap_to_45_degrees() {
+    let from = Point::new(10, 10);
+    assert_eq!(Point::new(20, 10), from.snap_45(Point::new(20, 13)));
+    assert_eq!(Point::new(0, 10), from.snap_45(Point::new(0, 6)));
+    assert_eq!(Point::new(10, 30), from.snap_45(Point::new(12, 30)));
+    assert_eq!(Point::new(10, -5), from.snap_45(Point::new(5, -5)));
+    assert_eq!(Point::new(15, 15), from.snap_45(Point::new(16, 14)));
+    assert_eq!(Point::new(4, 16), from.snap_45(Point::new(5, 17)));
+    assert_eq!(from, from.snap_45(from));
+}
+
+#[test]
+fn rect_union() {
+    let a = Rect::new(0, 0, 2, 2);
+    let b = Rect::new(5, -1, 1, 1);
+    assert_eq!(Rect::new(0, -1, 6, 3), a.union(&b));
+    assert_eq!(a, a.union(&Rect::new(9, 9, 0, 3)));
+    assert_eq!(b, Rect::default().union(&b));
+}
+
+#[test]
+fn grid_snapping() {
+    let grid = Grid::default();
+    assert_eq!(Point::new(0, 8), grid.snap(Point::new(3, 5)));
+    assert_eq!(Point::new(8, -8), grid.snap(Point::new(4, -5)));
+
+    let grid = Grid::parse("16x4+3+1").unwrap();
+    assert_eq!(Size::new(16, 4), grid.spacing);
+    assert_eq!(Point::new(3, 1), grid.offset);
+    assert_eq!(Point::new(3, 1), grid.snap(Point::new(10, 2)));
+    assert_eq!(Point::new(19, 5), grid.snap(Point::new(11, 4)));
+    assert_eq!(Point::new(-13, -3), grid.snap(Point::new(-9, -2)));
+
+    // no snapping along an axis without any lines
+    let grid = Grid::parse("0x8").unwrap();
+    assert_eq!(Point::new(5, 8), grid.snap(Point::new(5, 5)));
+
+    assert_eq!(None, Grid::parse("8"));
+    assert_eq!(None, Grid::parse("8x8+1"));
+    assert_eq!(None, Grid::parse("axb"));
+}
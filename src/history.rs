@@ -0,0 +1,621 @@
+//! Undoing and redoing changes to the canvas.
+
+use crate::{
+    geometry::{Point, Rect, Size},
+    image::{CompressedImage, Image, ImageBuilder, Pixel},
+};
+
+use std::collections::VecDeque;
+
+// one change to the canvas: the part of it that changed, from before and after
+#[derive(Debug, Clone)]
+struct Edit {
+    at: Point,
+    before: CompressedImage,
+    after: CompressedImage,
+}
+
+impl Edit {
+    fn size_in_bytes(&self) -> usize {
+        self.before.size_in_bytes() + self.after.size_in_bytes()
+    }
+}
+
+/// The edits that can be undone, and the ones that were undone and can be redone.
+///
+/// An edit is everything that happens to the canvas between `begin` and `end`, like a whole
+/// stroke from button press to release. Whatever changes the canvas in between calls `touch` with
+/// where it's about to, so only that much of the canvas is copied. Only the part of the canvas
+/// that changed is kept, from before and after, compressed. The oldest edits are forgotten once
+/// there are more than `depth` of them, or once they take up more than `max_memory` bytes.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    depth: usize,
+    max_memory: usize,
+    // the size of the canvas when the edit in progress began, and as much of it from then as the
+    // edit has touched
+    before: Option<(Size, Snapshot)>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(History::DEFAULT_DEPTH)
+    }
+}
+
+impl History {
+    pub const DEFAULT_DEPTH: usize = 100;
+    pub const DEFAULT_MAX_MEMORY: usize = 512 * 1024 * 1024;
+
+    /// Keeping at most `depth` edits to undo.
+    pub fn new(depth: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+            max_memory: History::DEFAULT_MAX_MEMORY,
+            before: None,
+        }
+    }
+
+    #[allow(dead_code)] // nothing shows the limits yet
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Change how many edits are kept, forgetting the oldest ones if there are too many now.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.undo.len() > depth {
+            self.undo.pop_front();
+        }
+        // the next edit to redo is at the end
+        let excess = self.redo.len().saturating_sub(depth);
+        self.redo.drain(..excess);
+    }
+
+    #[allow(dead_code)] // nothing shows the limits yet
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    /// Change how much memory the edits can take up, forgetting the oldest ones if they take up
+    /// too much now.
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.max_memory = max_memory;
+        self.evict();
+    }
+
+    /// Roughly how many bytes the edits that can be undone and redone take up.
+    pub fn memory(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .map(Edit::size_in_bytes)
+            .sum()
+    }
+
+    // forget the oldest edits until they all fit in memory, the ones to undo first, then the ones
+    // furthest from being redone
+    fn evict(&mut self) {
+        let mut memory = self.memory();
+        while memory > self.max_memory {
+            let edit = match self.undo.pop_front() {
+                Some(edit) => edit,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => break,
+            };
+            memory -= edit.size_in_bytes();
+        }
+    }
+
+    /// Forget every edit, like when a different image replaces the canvas.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.before = None;
+    }
+
+    /// Whether there's an edit in progress.
+    pub fn editing(&self) -> bool {
+        self.before.is_some()
+    }
+
+    #[allow(dead_code)] // nothing greys out undo and redo yet
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[allow(dead_code)] // nothing greys out undo and redo yet
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Start an edit to the canvas. Does nothing if one is already in progress, so the edit keeps
+    /// going until the matching `end`.
+    pub fn begin(&mut self, canvas: &Image) {
+        if self.before.is_none() {
+            self.before = Some((canvas.size(), Snapshot::default()));
+        }
+    }
+
+    /// Note that the edit in progress is about to change the canvas inside of `rect`, so what's
+    /// there now can be kept. Does nothing if there's no edit in progress.
+    pub fn touch(&mut self, canvas: &Image, rect: Rect) {
+        if let Some((_, before)) = &mut self.before {
+            before.grow(canvas, rect);
+        }
+    }
+
+    /// Finish the edit in progress, returning true if it changed anything and can be undone. A
+    /// new edit means the ones that were undone can't be redone anymore.
+    pub fn end(&mut self, canvas: &Image) -> bool {
+        let (size, before) = match self.before.take() {
+            Some(before) => before,
+            None => return false,
+        };
+
+        // the old edits wouldn't line up with a canvas of a different size
+        if size != canvas.size() {
+            log::warn!("the canvas changed size, forgetting the undo history");
+            self.undo.clear();
+            self.redo.clear();
+            return false;
+        }
+
+        // nothing outside of what was touched can have changed
+        let touched = before.rect();
+        let rect = match changed(before.image(), &canvas.crop(touched)) {
+            Some(rect) => rect.offset(touched.left(), touched.top()),
+            None => return false,
+        };
+
+        self.redo.clear();
+        if self.depth == 0 {
+            return false;
+        }
+        if self.undo.len() == self.depth {
+            self.undo.pop_front();
+        }
+        let edit = Edit {
+            at: rect.origin,
+            before: CompressedImage::from_image(
+                &before
+                    .image()
+                    .crop(rect.offset(-touched.left(), -touched.top())),
+            ),
+            after: CompressedImage::from_image(&canvas.crop(rect)),
+        };
+        if edit.size_in_bytes() > self.max_memory {
+            log::warn!(
+                "an edit of {} bytes is too big to undo, the limit is {}",
+                edit.size_in_bytes(),
+                self.max_memory
+            );
+        }
+        self.undo.push_back(edit);
+        self.evict();
+        log::debug!("the undo history takes up {} bytes", self.memory());
+        !self.undo.is_empty()
+    }
+
+    /// Put the canvas back how it was before the last edit, returning false if there's nothing
+    /// to undo.
+    pub fn undo(&mut self, canvas: &mut Image) -> bool {
+        match self.undo.pop_back() {
+            Some(edit) => {
+                canvas.paste(&edit.before.to_image(), edit.at);
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make the last edit that was undone again, returning false if there's nothing to redo.
+    pub fn redo(&mut self, canvas: &mut Image) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                canvas.paste(&edit.after.to_image(), edit.at);
+                self.undo.push_back(edit);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Part of an image from before it was changed, which grows to cover more of it as the changes
+/// spread. Copying only what's about to change is much cheaper than the whole image, for the small
+/// changes that most of them are.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    rect: Rect,
+    pixels: Image,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Snapshot {
+            rect: Rect::default(),
+            pixels: ImageBuilder::new().size(0, 0).build(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Cover `rect` too, clipped to the image. Whatever isn't covered yet is copied from `image`,
+    /// so it shouldn't have changed there yet.
+    pub fn grow(&mut self, image: &Image, rect: Rect) {
+        let rect = rect.clamp_to(image.size());
+        if rect.is_empty() || self.rect.union(&rect) == self.rect {
+            return;
+        }
+        if self.rect.is_empty() {
+            self.rect = rect;
+            self.pixels = image.crop(rect);
+            return;
+        }
+
+        // grow by at least half again on each side that has to, so something creeping across the
+        // image like a long stroke doesn't copy everything it's covered so far for every step
+        let (slack_x, slack_y) = (
+            self.rect.size.width as i32 / 2,
+            self.rect.size.height as i32 / 2,
+        );
+        let old = self.rect;
+        let left = if rect.left() < old.left() {
+            rect.left().min(old.left() - slack_x)
+        } else {
+            old.left()
+        };
+        let top = if rect.top() < old.top() {
+            rect.top().min(old.top() - slack_y)
+        } else {
+            old.top()
+        };
+        let right = if rect.right() > old.right() {
+            rect.right().max(old.right() + slack_x)
+        } else {
+            old.right()
+        };
+        let bottom = if rect.bottom() > old.bottom() {
+            rect.bottom().max(old.bottom() + slack_y)
+        } else {
+            old.bottom()
+        };
+        let grown = Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+            .clamp_to(image.size());
+
+        let mut pixels = image.crop(grown);
+        pixels.paste(
+            &self.pixels,
+            Point::new(old.left() - grown.left(), old.top() - grown.top()),
+        );
+        self.rect = grown;
+        self.pixels = pixels;
+    }
+
+    /// The part of the image that's covered, which is empty until it grows.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// What was in `rect` before it changed.
+    pub fn image(&self) -> &Image {
+        &self.pixels
+    }
+
+    /// How a pixel of the image was before it changed, if it's covered.
+    pub fn pixel_at(&self, point: Point) -> Option<Pixel> {
+        if !self.rect.contains(point) {
+            return None;
+        }
+        let (x, y) = (point.x - self.rect.left(), point.y - self.rect.top());
+        Some(self.pixels.pixel_at(x as usize, y as usize))
+    }
+
+    /// Put back how the image was inside of `rect`, where it's covered.
+    pub fn restore(&self, image: &mut Image, rect: Rect) {
+        if let Some(rect) = self.rect.intersection(&rect) {
+            let inside = rect.offset(-self.rect.left(), -self.rect.top());
+            image.paste(&self.pixels.crop(inside), rect.origin);
+        }
+    }
+}
+
+// the smallest rectangle around every pixel that's different between two images the same size
+fn changed(before: &Image, after: &Image) -> Option<Rect> {
+    let same = |a: Pixel, b: Pixel| {
+        a.r.to_bits() == b.r.to_bits()
+            && a.g.to_bits() == b.g.to_bits()
+            && a.b.to_bits() == b.b.to_bits()
+            && a.a.to_bits() == b.a.to_bits()
+    };
+
+    let mut bounds: Option<(i32, i32, i32, i32)> = None;
+    for point in after.bounds().points() {
+        let (x, y) = (point.x as usize, point.y as usize);
+        if same(before.pixel_at(x, y), after.pixel_at(x, y)) {
+            continue;
+        }
+
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => (
+                left.min(point.x),
+                top.min(point.y),
+                right.max(point.x + 1),
+                bottom.max(point.y + 1),
+            ),
+            None => (point.x, point.y, point.x + 1, point.y + 1),
+        });
+    }
+
+    bounds.map(|(left, top, right, bottom)| {
+        Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+    })
+}
+
+#[test]
+fn undo_redo_edits() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let original = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+    let mut canvas = original.clone();
+    let mut history = History::new(2);
+
+    // an edit that doesn't change anything isn't one
+    history.begin(&canvas);
+    assert!(history.editing());
+    assert!(!history.end(&canvas));
+    assert!(!history.can_undo());
+
+    history.begin(&canvas);
+    history.touch(&canvas, Rect::new(1, 1, 1, 1));
+    canvas.set_pixel(1, 1, red);
+    history.begin(&canvas);
+    history.touch(&canvas, Rect::new(5, 3, 1, 1));
+    canvas.set_pixel(5, 3, red);
+    assert!(history.end(&canvas));
+    assert!(!history.editing());
+    let painted = canvas.clone();
+
+    // both pixels were one edit
+    assert!(history.undo(&mut canvas));
+    assert_eq!(original, canvas);
+    assert!(!history.undo(&mut canvas));
+    assert!(history.redo(&mut canvas));
+    assert_eq!(painted, canvas);
+    assert!(!history.redo(&mut canvas));
+
+    // only the last two are kept
+    for x in 0..3 {
+        history.begin(&canvas);
+        history.touch(&canvas, Rect::new(x as i32, 7, 1, 1));
+        canvas.set_pixel(x, 7, red);
+        history.end(&canvas);
+    }
+    assert!(history.undo(&mut canvas));
+    assert!(history.undo(&mut canvas));
+    assert!(!history.undo(&mut canvas));
+    assert_eq!(red, canvas.pixel_at(0, 7));
+    assert_eq!(Pixel::TRANSPARENT, canvas.pixel_at(1, 7));
+
+    history.set_depth(1);
+    assert_eq!(1, history.depth());
+    assert!(history.redo(&mut canvas));
+    assert!(!history.redo(&mut canvas));
+    assert_eq!(red, canvas.pixel_at(1, 7));
+    assert_eq!(Pixel::TRANSPARENT, canvas.pixel_at(2, 7));
+}
+
+#[test]
+fn changed_rect() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let before = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+    let mut after = before.clone();
+    assert_eq!(None, changed(&before, &after));
+
+    after.set_pixel(6, 1, red);
+    after.set_pixel(2, 4, red);
+    assert_eq!(Some(Rect::new(2, 1, 5, 4)), changed(&before, &after));
+}
+
+#[test]
+fn snapshot_grows() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let original = ImageBuilder::new()
+        .size(32, 32)
+        .checker(1, red, Pixel::TRANSPARENT)
+        .build();
+    let mut image = original.clone();
+    let mut snapshot = Snapshot::default();
+    assert!(snapshot.rect().is_empty());
+    assert_eq!(None, snapshot.pixel_at(Point::new(0, 0)));
+
+    // change each part only after it's covered, like a stroke would
+    let mut paint = |snapshot: &mut Snapshot, rect: Rect| {
+        snapshot.grow(&image, rect);
+        for point in rect.clamp_to(image.size()).points() {
+            image.set_pixel(point.x as usize, point.y as usize, Pixel::TRANSPARENT);
+        }
+    };
+    paint(&mut snapshot, Rect::new(10, 10, 4, 4));
+    assert_eq!(Rect::new(10, 10, 4, 4), snapshot.rect());
+    paint(&mut snapshot, Rect::new(11, 11, 2, 2));
+    assert_eq!(Rect::new(10, 10, 4, 4), snapshot.rect());
+
+    // with some room to spare, but not off the image
+    paint(&mut snapshot, Rect::new(14, 10, 1, 1));
+    assert_eq!(Rect::new(10, 10, 6, 4), snapshot.rect());
+    paint(&mut snapshot, Rect::new(-5, 12, 8, 1));
+    assert_eq!(Rect::new(0, 10, 16, 4), snapshot.rect());
+
+    assert_eq!(Some(red), snapshot.pixel_at(Point::new(10, 10)));
+    assert_eq!(
+        Some(Pixel::TRANSPARENT),
+        snapshot.pixel_at(Point::new(11, 10))
+    );
+    assert_eq!(None, snapshot.pixel_at(Point::new(10, 20)));
+    snapshot.restore(&mut image, Rect::new(0, 0, 12, 32));
+    assert_eq!(original.pixel_at(11, 11), image.pixel_at(11, 11));
+    assert_eq!(Pixel::TRANSPARENT, image.pixel_at(12, 12));
+    snapshot.restore(&mut image, Rect::new(0, 0, 32, 32));
+    assert_eq!(original, image);
+}
+
+#[test]
+fn untouched_edits_change_nothing() {
+    let canvas = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+    let mut history = History::default();
+    history.begin(&canvas);
+    assert!(!history.end(&canvas));
+
+    // touched but not changed
+    history.begin(&canvas);
+    history.touch(&canvas, Rect::new(2, 2, 3, 3));
+    assert!(!history.end(&canvas));
+    assert!(!history.can_undo());
+}
+
+#[test]
+fn memory_limit_forgets_oldest() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+    let mut history = History::default();
+    let edit = |history: &mut History, canvas: &mut Image, x| {
+        history.begin(canvas);
+        history.touch(canvas, Rect::new(x as i32, 0, 1, 1));
+        canvas.set_pixel(x, 0, red);
+        history.end(canvas)
+    };
+
+    // a pixel before and after is one run each
+    assert!(edit(&mut history, &mut canvas, 0));
+    let one = history.memory();
+    assert!(one > 0);
+    assert!(edit(&mut history, &mut canvas, 1));
+    assert_eq!(2 * one, history.memory());
+
+    history.set_max_memory(2 * one);
+    assert!(edit(&mut history, &mut canvas, 2));
+    assert_eq!(2 * one, history.memory());
+    assert!(history.undo(&mut canvas));
+    assert!(history.undo(&mut canvas));
+    assert!(!history.undo(&mut canvas));
+    assert_eq!(red, canvas.pixel_at(0, 0));
+
+    // the edits waiting to be redone count too
+    history.set_max_memory(one);
+    assert_eq!(one, history.memory());
+    assert!(history.redo(&mut canvas));
+    assert!(!history.redo(&mut canvas));
+    assert_eq!(red, canvas.pixel_at(1, 0));
+    assert_eq!(Pixel::TRANSPARENT, canvas.pixel_at(2, 0));
+
+    // an edit too big for the limit can't be undone at all
+    history.set_max_memory(0);
+    assert_eq!(0, history.memory());
+    assert!(!edit(&mut history, &mut canvas, 3));
+    assert!(!history.can_undo());
+}
+
+#[test]
+fn matches_full_snapshots() {
+    // the simplest history there is, a copy of the whole canvas for every edit
+    #[derive(Default)]
+    struct Snapshots {
+        undo: Vec<Image>,
+        redo: Vec<Image>,
+    }
+
+    // xorshift, so the edits are the same every time
+    let mut seed = 0x2545_f491_u32;
+    let mut random = move |below: u32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed % below
+    };
+
+    for _ in 0..20 {
+        let mut canvas = Image::from_solid(16, 12, Pixel::TRANSPARENT).unwrap();
+        let mut naive_canvas = canvas.clone();
+        let mut history = History::new(8);
+        let mut naive = Snapshots::default();
+
+        for _ in 0..60 {
+            match random(4) {
+                // fill a rectangle with a color, which sometimes changes nothing
+                0 | 1 => {
+                    let color = Pixel {
+                        r: random(3) as f32 / 2.,
+                        g: 0.5,
+                        b: 0.0,
+                        a: 1.0,
+                    };
+                    let rect = Rect::new(
+                        random(16) as i32,
+                        random(12) as i32,
+                        random(6) + 1,
+                        random(6) + 1,
+                    );
+
+                    history.begin(&canvas);
+                    history.touch(&canvas, rect);
+                    let before = naive_canvas.clone();
+                    for point in rect.clamp_to(canvas.size()).points() {
+                        canvas.set_pixel(point.x as usize, point.y as usize, color);
+                        naive_canvas.set_pixel(point.x as usize, point.y as usize, color);
+                    }
+                    history.end(&canvas);
+                    if before != naive_canvas {
+                        naive.undo.push(before);
+                        if naive.undo.len() > 8 {
+                            naive.undo.remove(0);
+                        }
+                        naive.redo.clear();
+                    }
+                }
+                2 => {
+                    let undone = history.undo(&mut canvas);
+                    assert_eq!(!naive.undo.is_empty(), undone);
+                    if let Some(before) = naive.undo.pop() {
+                        naive
+                            .redo
+                            .push(std::mem::replace(&mut naive_canvas, before));
+                    }
+                }
+                _ => {
+                    let redone = history.redo(&mut canvas);
+                    assert_eq!(!naive.redo.is_empty(), redone);
+                    if let Some(after) = naive.redo.pop() {
+                        naive.undo.push(std::mem::replace(&mut naive_canvas, after));
+                    }
+                }
+            }
+            assert_eq!(naive_canvas, canvas);
+        }
+    }
+}
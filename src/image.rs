@@ -1,3 +1,15 @@
+use crate::{
+    density::{self, Density},
+    geometry::{Point, Rect, Size},
+    selection::Selection,
+    Context, Result,
+};
+
+use anyhow::bail;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Pixel {
     pub r: f32,
     pub g: f32,
@@ -5,8 +17,435 @@ pub struct Pixel {
     pub a: f32,
 }
 
+impl Pixel {
+    pub const TRANSPARENT: Pixel = Pixel {
+        r: 0.,
+        g: 0.,
+        b: 0.,
+        a: 0.,
+    };
+
+    /// Relative luminance of the color, ignoring alpha. Assumes linear Rec. 709 primaries.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Linearly interpolate between two pixels, straight alpha and all.
+    pub fn lerp(self, other: Pixel, t: f32) -> Pixel {
+        Pixel {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+
+    /// Composite this pixel over `below` with the "over" operator. Both are straight alpha.
+    pub fn over(self, below: Pixel) -> Pixel {
+        let a = self.a + below.a * (1. - self.a);
+        if a <= 0. {
+            return Pixel::TRANSPARENT;
+        }
+
+        let mix = |top: f32, bottom: f32| (top * self.a + bottom * below.a * (1. - self.a)) / a;
+        Pixel {
+            r: mix(self.r, below.r),
+            g: mix(self.g, below.g),
+            b: mix(self.b, below.b),
+            a,
+        }
+    }
+
+    /// Read a pixel from four floats in RGBA order.
+    pub fn from_slice(rgba: &[f32]) -> Pixel {
+        Pixel {
+            r: rgba[0],
+            g: rgba[1],
+            b: rgba[2],
+            a: rgba[3],
+        }
+    }
+
+    /// Decode sRGB-encoded color channels to linear light. Alpha is already linear.
+    pub fn to_linear(self) -> Pixel {
+        Pixel {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Encode linear color channels as sRGB. The inverse of `to_linear`.
+    pub fn to_srgb(self) -> Pixel {
+        Pixel {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Convert sRGB-encoded color channels to hue, saturation, and value, in the red, green, and
+    /// blue channels. Hue is in `[0, 1)`, starting at red.
+    pub fn to_hsv(self) -> Pixel {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+
+        let hue = if chroma <= 0. {
+            0.
+        } else if max == self.r {
+            ((self.g - self.b) / chroma).rem_euclid(6.)
+        } else if max == self.g {
+            (self.b - self.r) / chroma + 2.
+        } else {
+            (self.r - self.g) / chroma + 4.
+        };
+
+        Pixel {
+            r: hue / 6.,
+            g: if max <= 0. { 0. } else { chroma / max },
+            b: max,
+            a: self.a,
+        }
+    }
+
+    /// Convert hue, saturation, and value back to sRGB. The inverse of `to_hsv`.
+    pub fn hsv_to_srgb(self) -> Pixel {
+        let (hue, saturation, value) = (self.r.rem_euclid(1.) * 6., self.g, self.b);
+        let chroma = value * saturation;
+        let x = chroma * (1. - (hue.rem_euclid(2.) - 1.).abs());
+
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x),
+        };
+        let min = value - chroma;
+
+        Pixel {
+            r: r + min,
+            g: g + min,
+            b: b + min,
+            a: self.a,
+        }
+    }
+
+    /// Convert sRGB-encoded color channels to CIELAB with a D65 white point, in the red, green,
+    /// and blue channels. Lightness goes from 0 to 100, and a and b are roughly -128 to 128.
+    pub fn to_lab(self) -> Pixel {
+        let linear = self.to_linear();
+        let [x, y, z] = multiply(&SRGB_TO_XYZ, [linear.r, linear.g, linear.b]);
+
+        let f = |t: f32| {
+            if t > LAB_EPSILON {
+                t.cbrt()
+            } else {
+                t / (3. * LAB_DELTA * LAB_DELTA) + 4. / 29.
+            }
+        };
+        let (fx, fy, fz) = (f(x / D65[0]), f(y / D65[1]), f(z / D65[2]));
+
+        Pixel {
+            r: 116. * fy - 16.,
+            g: 500. * (fx - fy),
+            b: 200. * (fy - fz),
+            a: self.a,
+        }
+    }
+
+    /// Convert CIELAB back to sRGB. The inverse of `to_lab`.
+    pub fn lab_to_srgb(self) -> Pixel {
+        let fy = (self.r + 16.) / 116.;
+        let (fx, fz) = (fy + self.g / 500., fy - self.b / 200.);
+
+        let f = |t: f32| {
+            if t > LAB_DELTA {
+                t * t * t
+            } else {
+                3. * LAB_DELTA * LAB_DELTA * (t - 4. / 29.)
+            }
+        };
+        let xyz = [f(fx) * D65[0], f(fy) * D65[1], f(fz) * D65[2]];
+        let [r, g, b] = multiply(&XYZ_TO_SRGB, xyz);
+
+        Pixel { r, g, b, a: self.a }.to_srgb()
+    }
+}
+
+/// The color spaces pixels can be converted between. Pixels are stored as sRGB unless something
+/// says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ColorSpace {
+    /// sRGB-encoded red, green, and blue.
+    Srgb,
+    /// Linear-light red, green, and blue, with sRGB primaries.
+    Linear,
+    /// Hue, saturation, and value. See `Pixel::to_hsv`.
+    Hsv,
+    /// CIELAB lightness, a, and b. See `Pixel::to_lab`.
+    Lab,
+}
+
+impl ColorSpace {
+    /// Convert an sRGB pixel into this space.
+    pub fn decode(&self, pixel: Pixel) -> Pixel {
+        match self {
+            ColorSpace::Srgb => pixel,
+            ColorSpace::Linear => pixel.to_linear(),
+            ColorSpace::Hsv => pixel.to_hsv(),
+            ColorSpace::Lab => pixel.to_lab(),
+        }
+    }
+
+    /// Convert a pixel in this space back to sRGB.
+    pub fn encode(&self, pixel: Pixel) -> Pixel {
+        match self {
+            ColorSpace::Srgb => pixel,
+            ColorSpace::Linear => pixel.to_srgb(),
+            ColorSpace::Hsv => pixel.hsv_to_srgb(),
+            ColorSpace::Lab => pixel.lab_to_srgb(),
+        }
+    }
+}
+
+/// Convert a pixel from one color space to another. Alpha is left alone.
+pub fn convert_color(pixel: Pixel, from: ColorSpace, to: ColorSpace) -> Pixel {
+    if from == to {
+        return pixel;
+    }
+
+    to.decode(from.encode(pixel))
+}
+
+// linear sRGB to CIE XYZ, and back
+const SRGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175],
+    [0.019_333_9, 0.119_192, 0.950_304_1],
+];
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+// the D65 white point in XYZ, and the constants of the piecewise CIELAB curve
+const D65: [f32; 3] = [0.950_47, 1., 1.088_83];
+const LAB_DELTA: f32 = 6. / 29.;
+const LAB_EPSILON: f32 = LAB_DELTA * LAB_DELTA * LAB_DELTA;
+
+fn multiply(matrix: &[[f32; 3]; 3], [x, y, z]: [f32; 3]) -> [f32; 3] {
+    [
+        matrix[0][0] * x + matrix[0][1] * y + matrix[0][2] * z,
+        matrix[1][0] * x + matrix[1][1] * y + matrix[1][2] * z,
+        matrix[2][0] * x + matrix[2][1] * y + matrix[2][2] * z,
+    ]
+}
+
+/// The sRGB transfer function, from an encoded value to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The inverse sRGB transfer function, from linear light to an encoded value.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ImageData {
     pub data: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+    /// Where the top left corner of the image was in the image it was cropped out of, if it was.
+    #[serde(default)]
+    pub origin: Option<Point>,
+}
+
+impl ImageData {
+    /// Copy a rectangle out of the image. See `Image::crop`.
+    pub fn crop(&self, rect: Rect) -> ImageData {
+        let rect = rect.clamp_to(Size::new(self.width, self.height));
+
+        let mut data = Vec::with_capacity(rect.size.area() * 4);
+        for y in rect.top()..rect.bottom() {
+            let start = (y as usize * self.width as usize + rect.left() as usize) * 4;
+            let end = start + rect.size.width as usize * 4;
+            data.extend_from_slice(&self.data[start..end]);
+        }
+
+        let base = self.origin.unwrap_or_default();
+        ImageData {
+            data,
+            width: rect.size.width,
+            height: rect.size.height,
+            origin: Some(base.offset(rect.left(), rect.top())),
+        }
+    }
+
+    /// A hash of the dimensions, origin, and every pixel of the image, for noticing when an image
+    /// has changed without keeping a copy of it around. Fast rather than collision resistant.
+    pub fn content_hash(&self) -> u64 {
+        // the same mixing step as rustc's FxHasher, which is plenty for this
+        const K: u64 = 0x517c_c1b7_2722_0a95;
+        let mix = |hash: u64, word: u64| (hash.rotate_left(5) ^ word).wrapping_mul(K);
+
+        let origin = self
+            .origin
+            .map_or((0, 0, 0), |origin| (1, origin.x, origin.y));
+        let header = [
+            self.width as u64,
+            self.height as u64,
+            origin.0,
+            origin.1 as u32 as u64,
+            origin.2 as u32 as u64,
+        ];
+
+        let hash = header.iter().fold(0, |hash, &word| mix(hash, word));
+        self.data
+            .iter()
+            .fold(hash, |hash, value| mix(hash, value.to_bits() as u64))
+    }
+
+    /// Apply a function to every pixel.
+    pub fn map_pixels(mut self, f: impl Fn(Pixel) -> Pixel) -> ImageData {
+        for chunk in self.data.chunks_exact_mut(4) {
+            let pixel = f(Pixel {
+                r: chunk[0],
+                g: chunk[1],
+                b: chunk[2],
+                a: chunk[3],
+            });
+            chunk.copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+        self
+    }
+}
+
+/// Sample a gradient made of `(position, color)` stops at `t`.
+///
+/// The stops must be sorted by position. Before the first stop and after the last, the gradient is
+/// the color of that stop. An empty gradient is transparent.
+pub fn sample_gradient(stops: &[(f32, Pixel)], t: f32) -> Pixel {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Pixel::TRANSPARENT,
+    };
+
+    if t <= first.0 {
+        return first.1;
+    }
+
+    for pair in stops.windows(2) {
+        let ((start, a), (end, b)) = (pair[0], pair[1]);
+        if t <= end {
+            let span = end - start;
+            return if span <= 0. {
+                b
+            } else {
+                a.lerp(b, (t - start) / span)
+            };
+        }
+    }
+
+    last.1
+}
+
+/// Euclidean distance from every pixel to the nearest pixel set in `mask`, which is `width` by
+/// `height`. Pixels in the mask are 0. If nothing is set, everything is infinitely far away.
+///
+/// Uses the two-pass separable transform from Felzenszwalb and Huttenlocher, "Distance Transforms
+/// of Sampled Functions", which is linear in the number of pixels.
+pub fn distance_transform(mask: &[bool], width: usize, height: usize) -> Vec<f32> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let mut squared = mask
+        .iter()
+        .map(|&set| if set { 0. } else { f32::INFINITY })
+        .collect::<Vec<_>>();
+
+    let mut column = vec![0.; height];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = squared[y * width + x];
+        }
+        let transformed = distance_transform_1d(&column);
+        for y in 0..height {
+            squared[y * width + x] = transformed[y];
+        }
+    }
+
+    for row in squared.chunks_exact_mut(width) {
+        let transformed = distance_transform_1d(row);
+        row.copy_from_slice(&transformed);
+    }
+
+    squared.into_iter().map(f32::sqrt).collect()
+}
+
+// squared distance transform of one row, the lower envelope of parabolas rooted at each sample
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut out = vec![f32::INFINITY; n];
+
+    // locations of the parabolas in the envelope and where each one starts being the lowest
+    let mut v = vec![0; n];
+    let mut z = vec![0.; n + 1];
+    let mut k = 0;
+
+    let first = match f.iter().position(|value| value.is_finite()) {
+        Some(first) => first,
+        None => return out,
+    };
+    v[0] = first;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    for q in first + 1..n {
+        if !f[q].is_finite() {
+            continue;
+        }
+
+        let intersect =
+            |p: usize| ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32;
+
+        let mut s = intersect(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(v[k]);
+        }
+
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, out) in out.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let d = q as f32 - v[k] as f32;
+        *out = d * d + f[v[k]];
+    }
+
+    out
 }
 
 impl IntoIterator for ImageData {
@@ -18,69 +457,2368 @@ impl IntoIterator for ImageData {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Image {
     data: ImageData,
-    width: u32,
-    height: u32,
 }
 
 impl Image {
+    /// A new `width` by `height` image filled with one color. An image with no pixels couldn't be
+    /// painted on or even shown, so a zero width or height is an error.
+    pub fn new(width: u32, height: u32, fill: Pixel) -> Result<Image> {
+        if width == 0 || height == 0 {
+            bail!("Can't make a {}x{} image", width, height);
+        }
+        Ok(ImageBuilder::new().size(width, height).fill(fill).build())
+    }
+
+    /// A new fully transparent image. See `Image::new`.
+    pub fn transparent(width: u32, height: u32) -> Result<Image> {
+        Image::new(width, height, Pixel::TRANSPARENT)
+    }
+
+    /// Create an image filled with a single color. See `Image::new`.
+    pub fn from_solid(width: u32, height: u32, pixel: Pixel) -> Result<Image> {
+        Image::new(width, height, pixel)
+    }
+
+    // where the pixel at `(x, y)` starts in the data, if it's on the image
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.data.width as usize && y < self.data.height as usize {
+            Some((self.data.width as usize * y + x) * 4)
+        } else {
+            None
+        }
+    }
+
+    /// The pixel at `(x, y)`. Debug builds panic if that's off the image, release builds might
+    /// read a pixel from the next row instead. See `try_pixel_at`.
     pub fn pixel_at(&self, x: usize, y: usize) -> Pixel {
+        debug_assert!(
+            self.index(x, y).is_some(),
+            "pixel {}, {} is off the {}x{} image",
+            x,
+            y,
+            self.data.width,
+            self.data.height
+        );
         Pixel {
-            r: self.data.data[(self.width as usize * y + x) * 4],
-            g: self.data.data[(self.width as usize * y + x) * 4 + 1],
-            b: self.data.data[(self.width as usize * y + x) * 4 + 2],
-            a: self.data.data[(self.width as usize * y + x) * 4 + 3],
+            r: self.data.data[(self.data.width as usize * y + x) * 4],
+            g: self.data.data[(self.data.width as usize * y + x) * 4 + 1],
+            b: self.data.data[(self.data.width as usize * y + x) * 4 + 2],
+            a: self.data.data[(self.data.width as usize * y + x) * 4 + 3],
         }
     }
 
+    /// The pixel at `(x, y)`, or nothing if that's off the image.
+    pub fn try_pixel_at(&self, x: usize, y: usize) -> Option<Pixel> {
+        let i = self.index(x, y)?;
+        let data = &self.data.data;
+        Some(Pixel {
+            r: data[i],
+            g: data[i + 1],
+            b: data[i + 2],
+            a: data[i + 3],
+        })
+    }
+
+    /// Set the pixel at `(x, y)`. Debug builds panic if that's off the image, release builds
+    /// might set a pixel in the next row instead. See `try_set_pixel`.
     pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
-        self.data.data[(self.width as usize * y + x) * 4] = pixel.r;
-        self.data.data[(self.width as usize * y + x) * 4 + 1] = pixel.g;
-        self.data.data[(self.width as usize * y + x) * 4 + 2] = pixel.b;
-        self.data.data[(self.width as usize * y + x) * 4 + 3] = pixel.a;
+        self.set_rgba(x, y, pixel.r, pixel.g, pixel.b, pixel.a);
+    }
+
+    /// Set the pixel at `(x, y)`, returning what was there, or leave the image alone and return
+    /// nothing if that's off the image.
+    pub fn try_set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) -> Option<Pixel> {
+        let before = self.try_pixel_at(x, y)?;
+        self.set_pixel(x, y, pixel);
+        Some(before)
+    }
+
+    /// Composite a pixel over the one at `(x, y)` with the "over" operator, with its alpha scaled
+    /// by `opacity`.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, pixel: Pixel, opacity: f32) {
+        let top = Pixel {
+            a: pixel.a * opacity.clamp(0., 1.),
+            ..pixel
+        };
+        self.set_pixel(x, y, top.over(self.pixel_at(x, y)));
     }
 
     pub fn set_rgba(&mut self, x: usize, y: usize, r: f32, g: f32, b: f32, a: f32) {
-        self.data.data[(self.width as usize * y + x) * 4] = r;
-        self.data.data[(self.width as usize * y + x) * 4 + 1] = g;
-        self.data.data[(self.width as usize * y + x) * 4 + 2] = b;
-        self.data.data[(self.width as usize * y + x) * 4 + 3] = a;
+        debug_assert!(
+            self.index(x, y).is_some(),
+            "pixel {}, {} is off the {}x{} image",
+            x,
+            y,
+            self.data.width,
+            self.data.height
+        );
+        self.data.data[(self.data.width as usize * y + x) * 4] = r;
+        self.data.data[(self.data.width as usize * y + x) * 4 + 1] = g;
+        self.data.data[(self.data.width as usize * y + x) * 4 + 2] = b;
+        self.data.data[(self.data.width as usize * y + x) * 4 + 3] = a;
     }
 
+    /// Quantize to 8 bits per channel. Values outside of `[0, 1]` are clamped.
     pub fn as_raw(&self) -> Vec<u8> {
+        #[cfg(debug_assertions)]
+        {
+            use std::sync::atomic::{AtomicBool, Ordering};
+            static WARNED: AtomicBool = AtomicBool::new(false);
+
+            // only scan until the first warning, this runs on every upload
+            if !WARNED.load(Ordering::Relaxed) {
+                if let Some(value) = self.data.data.iter().find(|v| !(0.0..=1.0).contains(*v)) {
+                    WARNED.store(true, Ordering::Relaxed);
+                    log::warn!(
+                        "out of range color value {} in as_raw, something upstream is overshooting",
+                        value
+                    );
+                }
+            }
+        }
+
         self.data
             .data
             .iter()
-            .map(|float| (float * 256.).floor() as u8)
+            .map(|float| (float.clamp(0., 1.) * 255.).round() as u8)
             .collect()
     }
 
+    pub fn to_rgba_image(&self) -> image_library::RgbaImage {
+        image_library::RgbaImage::from_vec(self.width(), self.height(), self.as_raw()).unwrap()
+    }
+
+    /// Quantize to 8 bits per channel, premultiplying the color if asked to.
+    pub fn to_rgba_image_with(&self, alpha: Alpha) -> image_library::RgbaImage {
+        match alpha {
+            Alpha::Straight => self.to_rgba_image(),
+            Alpha::Premultiplied => Image::from(self.data.clone().map_pixels(|pixel| Pixel {
+                r: pixel.r * pixel.a,
+                g: pixel.g * pixel.a,
+                b: pixel.b * pixel.a,
+                ..pixel
+            }))
+            .to_rgba_image(),
+        }
+    }
+
+    /// Read an 8 bit image, treating its color as `alpha` says.
+    pub fn from_rgba_image(image: image_library::RgbaImage, alpha: Alpha) -> Image {
+        let image = Image::from(image);
+        match alpha {
+            Alpha::Straight => image,
+            // fully transparent pixels have no color left to recover, so they become black
+            Alpha::Premultiplied => Image::from(image.into_data().map_pixels(|pixel| {
+                let unpremultiply = |value: f32| {
+                    if pixel.a > 0. {
+                        (value / pixel.a).min(1.)
+                    } else {
+                        0.
+                    }
+                };
+                Pixel {
+                    r: unpremultiply(pixel.r),
+                    g: unpremultiply(pixel.g),
+                    b: unpremultiply(pixel.b),
+                    ..pixel
+                }
+            })),
+        }
+    }
+
+    /// Look for signs that the image was loaded with the wrong kind of alpha. Doesn't change
+    /// anything. An empty image reports alpha from 1 to 0.
+    pub fn analyze_alpha(&self) -> AlphaReport {
+        let mut report = AlphaReport {
+            color_within_alpha: true,
+            transparent_with_color: 0,
+            partially_transparent: 0,
+            min_alpha: 1.,
+            max_alpha: 0.,
+        };
+
+        for pixel in self.data.data.chunks_exact(4) {
+            let (color, alpha) = (&pixel[..3], pixel[3]);
+            report.min_alpha = report.min_alpha.min(alpha);
+            report.max_alpha = report.max_alpha.max(alpha);
+
+            if color.iter().any(|&value| value > alpha) {
+                report.color_within_alpha = false;
+            }
+            if alpha <= 0. && color.iter().any(|&value| value > 0.) {
+                report.transparent_with_color += 1;
+            }
+            if alpha > 0. && alpha < 1. {
+                report.partially_transparent += 1;
+            }
+        }
+
+        report
+    }
+
+    /// Load an image file in any format the image library knows. Nothing in a PNG says whether
+    /// it's premultiplied, so that has to come from whoever asks for it.
+    pub fn load(path: impl AsRef<Path>, alpha: Alpha) -> Result<Image> {
+        let path = path.as_ref();
+        let image = image_library::open(path)
+            .with_context(|| format!("Couldn't load {}", path.display()))?;
+        Ok(Image::from_rgba_image(image.to_rgba8(), alpha))
+    }
+
+    /// Load an image file along with its pixel density, if it has one.
+    pub fn load_with_density(
+        path: impl AsRef<Path>,
+        alpha: Alpha,
+    ) -> Result<(Image, Option<Density>)> {
+        let path = path.as_ref();
+        Ok((Image::load(path, alpha)?, density::read(path)?))
+    }
+
+    /// Save to an image file, in the format the extension of `path` asks for.
+    pub fn save(&self, path: impl AsRef<Path>, alpha: Alpha) -> Result<()> {
+        let path = path.as_ref();
+        self.to_rgba_image_with(alpha)
+            .save(path)
+            .with_context(|| format!("Couldn't save {}", path.display()))
+    }
+
+    /// Save to an image file with a pixel density. Only PNGs can store it, other formats are
+    /// saved without.
+    pub fn save_with_density(
+        &self,
+        path: impl AsRef<Path>,
+        alpha: Alpha,
+        density: Option<Density>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let is_png = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+
+        match density {
+            Some(_) if is_png => density::save_png(&self.to_rgba_image_with(alpha), path, density),
+            Some(_) => {
+                log::warn!("{} can't store pixel density, dropping it", path.display());
+                self.save(path, alpha)
+            }
+            None => self.save(path, alpha),
+        }
+    }
+
+    #[allow(dead_code)] // nothing writes the raw channels yet
     pub fn as_mut(&mut self) -> &mut [f32] {
         &mut self.data.data
     }
 
     pub fn width(&self) -> u32 {
-        self.width
+        self.data.width
     }
 
     pub fn height(&self) -> u32 {
-        self.height
+        self.data.height
     }
-}
 
-impl From<image_library::RgbaImage> for Image {
-    fn from(image: image_library::RgbaImage) -> Image {
+    pub fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+
+    /// Every pixel inside `rect`, clipped to the image, with its coordinates. Goes row by row.
+    #[allow(dead_code)] // only the tests read a region at a time so far
+    pub fn region_pixels(&self, rect: Rect) -> impl Iterator<Item = (u32, u32, Pixel)> + '_ {
+        let rect = rect.clamp_to(self.size());
+        rect.points().map(move |point| {
+            let (x, y) = (point.x as u32, point.y as u32);
+            (x, y, self.pixel_at(x as usize, y as usize))
+        })
+    }
+
+    /// Like `region_pixels`, but with the RGBA channels of each pixel to modify in place.
+    pub fn region_pixels_mut(
+        &mut self,
+        rect: Rect,
+    ) -> impl Iterator<Item = (u32, u32, &mut [f32])> + '_ {
+        let rect = rect.clamp_to(self.size());
+        let (left, right) = (rect.left() as usize, rect.right() as usize);
+        let width = self.width() as usize;
+
+        self.data
+            .data
+            // at least one so an empty image doesn't panic, there are no rows to take anyway
+            .chunks_exact_mut((width * 4).max(1))
+            .enumerate()
+            .skip(rect.top() as usize)
+            .take(rect.size.height as usize)
+            .flat_map(move |(y, row)| {
+                row[left * 4..right * 4]
+                    .chunks_exact_mut(4)
+                    .enumerate()
+                    .map(move |(i, pixel)| ((left + i) as u32, y as u32, pixel))
+            })
+    }
+
+    /// Where the image was cropped out of a larger one, if it was.
+    pub fn origin(&self) -> Option<Point> {
+        self.data.origin
+    }
+
+    /// A rectangle covering the whole image.
+    pub fn bounds(&self) -> Rect {
+        Rect::from_size(self.size())
+    }
+
+    pub fn into_data(self) -> ImageData {
+        self.data
+    }
+
+    /// Bilinearly filter the pixels around a point, where pixel centers are at `x + 0.5` and
+    /// `y + 0.5`. Points past the edges get the edge pixels. Filtered with premultiplied alpha,
+    /// like `resize`.
+    pub fn sample_bilinear(&self, x: f32, y: f32) -> Pixel {
+        let (max_x, max_y) = (self.width() as f32 - 1., self.height() as f32 - 1.);
+        let (sx, sy) = ((x - 0.5).clamp(0., max_x), (y - 0.5).clamp(0., max_y));
+        let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(max_x as usize), (y0 + 1).min(max_y as usize));
+        let (tx, ty) = (sx - x0 as f32, sy - y0 as f32);
+
+        let mut sum = [0.; 4];
+        for &(px, py, weight) in &[
+            (x0, y0, (1. - tx) * (1. - ty)),
+            (x1, y0, tx * (1. - ty)),
+            (x0, y1, (1. - tx) * ty),
+            (x1, y1, tx * ty),
+        ] {
+            let pixel = self.pixel_at(px, py);
+            sum[0] += pixel.r * pixel.a * weight;
+            sum[1] += pixel.g * pixel.a * weight;
+            sum[2] += pixel.b * pixel.a * weight;
+            sum[3] += pixel.a * weight;
+        }
+
+        let alpha = sum[3];
+        if alpha > 0. {
+            Pixel {
+                r: sum[0] / alpha,
+                g: sum[1] / alpha,
+                b: sum[2] / alpha,
+                a: alpha,
+            }
+        } else {
+            Pixel::TRANSPARENT
+        }
+    }
+
+    /// The color of the image at a point, where pixel centers are at `x + 0.5` and `y + 0.5`.
+    /// Points off of the image are transparent.
+    pub fn sample(&self, x: f32, y: f32, filter: Filter) -> Pixel {
+        let (width, height) = (self.width() as f32, self.height() as f32);
+        if !(0. ..width).contains(&x) || !(0. ..height).contains(&y) {
+            return Pixel::TRANSPARENT;
+        }
+
+        match filter {
+            Filter::Nearest => self.pixel_at(x as usize, y as usize),
+            Filter::Bilinear => self.sample_bilinear(x, y),
+        }
+    }
+
+    /// Resize the image with bilinear filtering.
+    ///
+    /// Filtering happens with premultiplied alpha, so the color of fully transparent pixels
+    /// doesn't bleed into their opaque neighbors. Resizing an image with no pixels gives a
+    /// transparent one.
+    pub fn resize(&self, width: u32, height: u32) -> Image {
+        if width == 0 || height == 0 || self.width() == 0 || self.height() == 0 {
+            return Image::from(ImageData {
+                data: vec![0.; width as usize * height as usize * 4],
+                width,
+                height,
+                origin: None,
+            });
+        }
+
+        let x_ratio = self.width() as f32 / width as f32;
+        let y_ratio = self.height() as f32 / height as f32;
+        let max_x = self.width() as usize - 1;
+        let max_y = self.height() as usize - 1;
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            // position of this pixel's center in the source image
+            let sy = ((y as f32 + 0.5) * y_ratio - 0.5).max(0.);
+            let y0 = (sy.floor() as usize).min(max_y);
+            let y1 = (y0 + 1).min(max_y);
+            let ty = sy - y0 as f32;
+
+            for x in 0..width {
+                let sx = ((x as f32 + 0.5) * x_ratio - 0.5).max(0.);
+                let x0 = (sx.floor() as usize).min(max_x);
+                let x1 = (x0 + 1).min(max_x);
+                let tx = sx - x0 as f32;
+
+                let mut sum = [0.; 4];
+                for &(px, py, weight) in &[
+                    (x0, y0, (1. - tx) * (1. - ty)),
+                    (x1, y0, tx * (1. - ty)),
+                    (x0, y1, (1. - tx) * ty),
+                    (x1, y1, tx * ty),
+                ] {
+                    let pixel = self.pixel_at(px, py);
+                    sum[0] += pixel.r * pixel.a * weight;
+                    sum[1] += pixel.g * pixel.a * weight;
+                    sum[2] += pixel.b * pixel.a * weight;
+                    sum[3] += pixel.a * weight;
+                }
+
+                let alpha = sum[3];
+                if alpha > 0. {
+                    data.extend_from_slice(&[
+                        sum[0] / alpha,
+                        sum[1] / alpha,
+                        sum[2] / alpha,
+                        alpha,
+                    ]);
+                } else {
+                    data.extend_from_slice(&[0., 0., 0., 0.]);
+                }
+            }
+        }
+
         Image {
-            width: image.width(),
-            height: image.height(),
             data: ImageData {
-                data: image
-                    .into_vec()
-                    .into_iter()
-                    .map(|byte| byte as f32 / 256.0)
-                    .collect(),
+                data,
+                width,
+                height,
+                origin: None,
+            },
+        }
+    }
+
+    /// Shrink the image to fit in a `max_size` pixel square, keeping its aspect ratio. Images that
+    /// already fit are copied as they are.
+    pub fn thumbnail(&self, max_size: u32) -> Image {
+        let (width, height) = (self.width(), self.height());
+        if width <= max_size && height <= max_size {
+            return self.clone();
+        }
+
+        self.downscale_average(width.max(height) as f32 / max_size.max(1) as f32)
+    }
+
+    /// Shrink the image by `factor` by averaging together each `factor` by `factor` block of
+    /// pixels. Pixels on the edge of a block count for as much of them as is in the block, so
+    /// factors don't have to be whole numbers.
+    ///
+    /// Unlike `resize`, every pixel of the image counts towards the result, so shrinking a lot
+    /// doesn't alias. Factors of 1 or less copy the image as it is.
+    pub fn downscale_average(&self, factor: f32) -> Image {
+        if factor <= 1. || self.size().is_empty() {
+            return self.clone();
+        }
+
+        let width = ((self.width() as f32 / factor).round() as u32).max(1);
+        let height = ((self.height() as f32 / factor).round() as u32).max(1);
+
+        // the factor that actually fits the rounded size, so the blocks cover the whole image
+        let x_ratio = self.width() as f32 / width as f32;
+        let y_ratio = self.height() as f32 / height as f32;
+
+        // the pixels a block covers, and how much of each
+        let overlaps = |start: f32, end: f32| {
+            (start.floor() as usize..end.ceil() as usize).map(move |i| {
+                let covered = (end.min(i as f32 + 1.) - start.max(i as f32)).max(0.);
+                (i, covered)
+            })
+        };
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            let (top, bottom) = (y as f32 * y_ratio, (y + 1) as f32 * y_ratio);
+            for x in 0..width {
+                let (left, right) = (x as f32 * x_ratio, (x + 1) as f32 * x_ratio);
+
+                // premultiplied, so transparent pixels don't tint the block
+                let mut sum = [0.; 4];
+                let mut area = 0.;
+                for (py, y_weight) in overlaps(top, bottom.min(self.height() as f32)) {
+                    for (px, x_weight) in overlaps(left, right.min(self.width() as f32)) {
+                        let weight = x_weight * y_weight;
+                        let pixel = self.pixel_at(px, py);
+                        sum[0] += pixel.r * pixel.a * weight;
+                        sum[1] += pixel.g * pixel.a * weight;
+                        sum[2] += pixel.b * pixel.a * weight;
+                        sum[3] += pixel.a * weight;
+                        area += weight;
+                    }
+                }
+
+                if sum[3] > 0. {
+                    data.extend_from_slice(&[
+                        sum[0] / sum[3],
+                        sum[1] / sum[3],
+                        sum[2] / sum[3],
+                        sum[3] / area,
+                    ]);
+                } else {
+                    data.extend_from_slice(&[0., 0., 0., 0.]);
+                }
+            }
+        }
+
+        Image {
+            data: ImageData {
+                data,
+                width,
+                height,
+                origin: None,
+            },
+        }
+    }
+
+    /// Shift the image right by `dx` and down by `dy`, wrapping whatever goes off one edge around
+    /// to the opposite edge. Makes the seams of a texture visible in the middle, for making it
+    /// tile.
+    pub fn offset_wrap(&self, dx: i32, dy: i32) -> Image {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        if width == 0 || height == 0 {
+            return self.clone();
+        }
+
+        let dx = (dx as i64).rem_euclid(width as i64) as usize;
+        let dy = (dy as i64).rem_euclid(height as i64) as usize;
+
+        let mut data = vec![0.; self.data.data.len()];
+        for y in 0..height {
+            // each row moves down in one piece, and is rotated right
+            let from = &self.data.data[y * width * 4..(y + 1) * width * 4];
+            let to_y = (y + dy) % height;
+            let to = &mut data[to_y * width * 4..(to_y + 1) * width * 4];
+            to.copy_from_slice(from);
+            to.rotate_right(dx * 4);
+        }
+
+        Image {
+            data: ImageData {
+                data,
+                width: self.width(),
+                height: self.height(),
+                origin: self.data.origin,
+            },
+        }
+    }
+
+    /// Convolve the image with a `kw` by `kh` kernel, given row by row. The kernel must have odd
+    /// dimensions, and is centered on each pixel.
+    ///
+    /// Kernels that don't sum to zero are normalized to sum to one, and are applied to
+    /// premultiplied colors so transparent pixels don't bleed into their neighbors, like a blur.
+    /// Kernels that do sum to zero (edge detection and such) are applied to the color channels
+    /// only, and the alpha of each pixel is left as it was.
+    pub fn convolve(&self, kernel: &[f32], kw: usize, kh: usize, edge: EdgeMode) -> Image {
+        assert_eq!(kw * kh, kernel.len(), "kernel is not {}x{}", kw, kh);
+        assert!(kw % 2 == 1 && kh % 2 == 1, "kernel dimensions must be odd");
+
+        let sum: f32 = kernel.iter().sum();
+        let normalize = sum.abs() > f32::EPSILON;
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let (rx, ry) = ((kw / 2) as i64, (kh / 2) as i64);
+
+        let mut data = Vec::with_capacity(self.data.data.len());
+        for y in 0..height {
+            for x in 0..width {
+                let mut acc = [0.; 4];
+                for ky in 0..kh {
+                    for kx in 0..kw {
+                        let weight = kernel[ky * kw + kx];
+                        let sample = edge.sample(
+                            self,
+                            x + kx as i64 - rx,
+                            y + ky as i64 - ry,
+                            width,
+                            height,
+                        );
+                        let premultiply = if normalize { sample.a } else { 1. };
+                        acc[0] += sample.r * premultiply * weight;
+                        acc[1] += sample.g * premultiply * weight;
+                        acc[2] += sample.b * premultiply * weight;
+                        acc[3] += sample.a * weight;
+                    }
+                }
+
+                if normalize {
+                    let alpha = acc[3] / sum;
+                    if alpha > 0. {
+                        data.extend_from_slice(&[
+                            acc[0] / sum / alpha,
+                            acc[1] / sum / alpha,
+                            acc[2] / sum / alpha,
+                            alpha,
+                        ]);
+                    } else {
+                        data.extend_from_slice(&[0., 0., 0., 0.]);
+                    }
+                } else {
+                    let alpha = self.pixel_at(x as usize, y as usize).a;
+                    data.extend_from_slice(&[acc[0], acc[1], acc[2], alpha]);
+                }
+            }
+        }
+
+        Image {
+            data: ImageData {
+                data,
+                width: self.width(),
+                height: self.height(),
+                origin: self.data.origin,
             },
         }
     }
+
+    /// Gaussian blur, reaching `radius` pixels out from each pixel. Edges are clamped.
+    pub fn blur(&self, radius: u32) -> Image {
+        if radius == 0 {
+            return self.clone();
+        }
+
+        // most of the curve fits in the radius
+        let sigma = radius as f32 / 2.;
+        let kernel = (-(radius as i32)..=radius as i32)
+            .map(|x| (-(x * x) as f32 / (2. * sigma * sigma)).exp())
+            .collect::<Vec<_>>();
+
+        // separably, one axis at a time
+        let size = kernel.len();
+        self.convolve(&kernel, size, 1, EdgeMode::Clamp)
+            .convolve(&kernel, 1, size, EdgeMode::Clamp)
+    }
+
+    /// Copy a rectangle out of the image, clipped to the image. The result remembers where it
+    /// came from, relative to wherever this image came from.
+    pub fn crop(&self, rect: Rect) -> Image {
+        Image {
+            data: self.data.crop(rect),
+        }
+    }
+
+    /// Replace the pixels under `other` with its pixels, with its top left corner at `at`.
+    /// Whatever lands off of this image is dropped.
+    pub fn paste(&mut self, other: &Image, at: Point) {
+        let placed = Rect {
+            origin: at,
+            size: other.size(),
+        };
+        let visible = match placed.intersection(&self.bounds()) {
+            Some(visible) => visible,
+            None => return,
+        };
+
+        for point in visible.points() {
+            let pixel = other.pixel_at((point.x - at.x) as usize, (point.y - at.y) as usize);
+            self.set_pixel(point.x as usize, point.y as usize, pixel);
+        }
+    }
+
+    /// Composite `other` over this image with its top left corner at `at`. Whatever lands off of
+    /// this image is dropped.
+    pub fn blit(&mut self, other: &Image, at: Point) {
+        let placed = Rect {
+            origin: at,
+            size: other.size(),
+        };
+        let visible = match placed.intersection(&self.bounds()) {
+            Some(visible) => visible,
+            None => return,
+        };
+
+        for point in visible.points() {
+            let top = other.pixel_at((point.x - at.x) as usize, (point.y - at.y) as usize);
+            let (x, y) = (point.x as usize, point.y as usize);
+            self.set_pixel(x, y, top.over(self.pixel_at(x, y)));
+        }
+    }
+
+    /// Composite `other` over this image with its top left corner at `(x, y)`, letting through
+    /// only as much of each pixel as `mask` does. White opaque mask pixels let everything
+    /// through, and black or transparent ones let nothing through. Whatever lands off of this
+    /// image is dropped.
+    ///
+    /// `mask` has to be the same size as `other`.
+    #[allow(dead_code)] // nothing pastes through a mask yet
+    pub fn paste_with_mask(&mut self, other: &Image, mask: &Image, x: i32, y: i32) -> Result<()> {
+        if other.size() != mask.size() {
+            bail!(
+                "Mask is {}x{}, but the image is {}x{}",
+                mask.width(),
+                mask.height(),
+                other.width(),
+                other.height()
+            );
+        }
+
+        let at = Point::new(x, y);
+        let placed = Rect {
+            origin: at,
+            size: other.size(),
+        };
+        let visible = match placed.intersection(&self.bounds()) {
+            Some(visible) => visible,
+            None => return Ok(()),
+        };
+
+        for point in visible.points() {
+            let (sx, sy) = ((point.x - at.x) as usize, (point.y - at.y) as usize);
+            let coverage = mask.pixel_at(sx, sy);
+            let coverage = (coverage.luminance() * coverage.a).clamp(0., 1.);
+
+            let above = other.pixel_at(sx, sy);
+            let above = Pixel {
+                a: above.a * coverage,
+                ..above
+            };
+            let below = self.pixel_at(point.x as usize, point.y as usize);
+            self.set_pixel(point.x as usize, point.y as usize, above.over(below));
+        }
+
+        Ok(())
+    }
+
+    /// Replace every pixel within `tolerance` of `from` with `to`, anywhere in the image.
+    ///
+    /// Two pixels are within tolerance when none of their channels, alpha included, differ by
+    /// more than `tolerance`. With `keep_alpha`, replaced pixels keep their own alpha instead of
+    /// taking the alpha of `to`, so antialiased edges stay antialiased.
+    #[allow(dead_code)] // the window always goes through `replace_color_in`
+    pub fn replace_color(&mut self, from: Pixel, to: Pixel, tolerance: f32, keep_alpha: bool) {
+        self.replace_color_in(
+            &Selection::from(self.bounds()),
+            from,
+            to,
+            tolerance,
+            keep_alpha,
+        );
+    }
+
+    /// The same as `replace_color`, but only inside of `selection`. Returns how many pixels were
+    /// replaced.
+    pub fn replace_color_in(
+        &mut self,
+        selection: &Selection,
+        from: Pixel,
+        to: Pixel,
+        tolerance: f32,
+        keep_alpha: bool,
+    ) -> usize {
+        let mut replaced = 0;
+        let rect = selection.bounds().clamp_to(self.size());
+        for point in rect.points().filter(|&point| selection.contains(point)) {
+            let (x, y) = (point.x as usize, point.y as usize);
+            let pixel = self.pixel_at(x, y);
+            if within_tolerance(pixel, from, tolerance) {
+                let a = if keep_alpha { pixel.a } else { to.a };
+                self.set_pixel(x, y, Pixel { a, ..to });
+                replaced += 1;
+            }
+        }
+        replaced
+    }
+
+    /// Fill the region around `(x, y)` with `color`, returning how many pixels were filled.
+    ///
+    /// The region is every pixel connected to the starting one through its four neighbors that's
+    /// within `tolerance` of the starting pixel's color, in the same sense as `replace_color`.
+    /// Does nothing if the starting point is off of the image.
+    ///
+    /// This is the simple version, which keeps every pixel it still has to look at on a stack.
+    /// `scanline_fill` does the same thing with much less memory.
+    #[allow(dead_code)] // kept as the simple version `scanline_fill` is checked against
+    pub fn flood_fill(&mut self, x: usize, y: usize, color: Pixel, tolerance: f32) -> usize {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        if x >= width || y >= height {
+            return 0;
+        }
+
+        let target = self.pixel_at(x, y);
+        let mut filled = vec![false; width * height];
+        let mut count = 0;
+        let mut stack = vec![(x, y)];
+
+        while let Some((x, y)) = stack.pop() {
+            if filled[y * width + x] || !within_tolerance(self.pixel_at(x, y), target, tolerance) {
+                continue;
+            }
+
+            self.set_pixel(x, y, color);
+            filled[y * width + x] = true;
+            count += 1;
+
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < width {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < height {
+                stack.push((x, y + 1));
+            }
+        }
+
+        count
+    }
+
+    /// The same as `flood_fill`, but a horizontal run at a time, using `region`.
+    #[allow(dead_code)] // the bucket uses `region` directly, to follow the connectivity
+    pub fn scanline_fill(&mut self, x: usize, y: usize, color: Pixel, tolerance: f32) -> usize {
+        let width = self.width() as usize;
+        let region = self.region(x, y, tolerance, Connectivity::Four);
+        let mut count = 0;
+        for (i, _) in region.iter().enumerate().filter(|(_, &inside)| inside) {
+            self.set_pixel(i % width, i / width, color);
+            count += 1;
+        }
+        count
+    }
+
+    /// Which pixels are in the region around `(x, y)` that `flood_fill` would fill, row by row,
+    /// with neighbors on the diagonals counting too if the connectivity says so. Nothing is if the
+    /// starting point is off of the image.
+    ///
+    /// The region is found a horizontal run at a time. Each run is taken all at once, and only the
+    /// start of each run of matching pixels above and below it goes on the stack, rather than
+    /// every pixel. On big areas of one color that's the difference between a stack the size of
+    /// the area and one about the size of its height.
+    pub fn region(
+        &self,
+        x: usize,
+        y: usize,
+        tolerance: f32,
+        connectivity: Connectivity,
+    ) -> Vec<bool> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let mut region = vec![false; width * height];
+        if x >= width || y >= height {
+            return region;
+        }
+
+        let target = self.pixel_at(x, y);
+        let matches = |region: &[bool], x: usize, y: usize| {
+            !region[y * width + x] && within_tolerance(self.pixel_at(x, y), target, tolerance)
+        };
+        let mut stack = vec![(x, y)];
+
+        while let Some((x, y)) = stack.pop() {
+            if !matches(&region, x, y) {
+                continue;
+            }
+
+            // find the whole run this seed is in
+            let mut left = x;
+            while left > 0 && matches(&region, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && matches(&region, right + 1, y) {
+                right += 1;
+            }
+
+            for x in left..=right {
+                region[y * width + x] = true;
+            }
+
+            // one seed for each run of matching pixels that touches this one, above and below.
+            // diagonally, that's one pixel further out on either side
+            let (left, right) = match connectivity {
+                Connectivity::Four => (left, right),
+                Connectivity::Eight => (left.saturating_sub(1), (right + 1).min(width - 1)),
+            };
+            let rows = [
+                y.checked_sub(1),
+                Some(y + 1).filter(|&below| below < height),
+            ];
+            for row in rows.iter().flatten().copied() {
+                let mut in_run = false;
+                for x in left..=right {
+                    let matches = matches(&region, x, row);
+                    if matches && !in_run {
+                        stack.push((x, row));
+                    }
+                    in_run = matches;
+                }
+            }
+        }
+
+        region
+    }
+}
+
+/// Which neighbors of a pixel count as connected to it, for `Image::region`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// The pixels above, below, and to either side.
+    Four,
+    /// Those and the ones on the diagonals.
+    #[allow(dead_code)] // nothing switches the connectivity over yet
+    Eight,
+}
+
+/// How `Image::sample` finds the color between pixel centers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// The pixel the point is in, for keeping pixel art crisp.
+    Nearest,
+    /// The four pixels around the point, weighted by how close it is to each. See
+    /// `Image::sample_bilinear`.
+    Bilinear,
+}
+
+impl Filter {
+    /// The other filter, to switch between them.
+    pub fn next(&self) -> Filter {
+        match self {
+            Filter::Nearest => Filter::Bilinear,
+            Filter::Bilinear => Filter::Nearest,
+        }
+    }
+}
+
+// whether no channel of two pixels, alpha included, differs by more than `tolerance`
+fn within_tolerance(a: Pixel, b: Pixel, tolerance: f32) -> bool {
+    let distance = (a.r - b.r)
+        .abs()
+        .max((a.g - b.g).abs())
+        .max((a.b - b.b).abs())
+        .max((a.a - b.a).abs());
+    distance <= tolerance
+}
+
+/// What `Image::convolve` sees past the edges of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum EdgeMode {
+    /// The nearest pixel on the edge.
+    Clamp,
+    /// The pixel on the opposite side of the image, as if it were tiled.
+    Wrap,
+    /// Transparent black.
+    Zero,
+}
+
+impl EdgeMode {
+    fn sample(&self, image: &Image, x: i64, y: i64, width: i64, height: i64) -> Pixel {
+        let (x, y) = match self {
+            EdgeMode::Clamp => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+            EdgeMode::Wrap => (x.rem_euclid(width), y.rem_euclid(height)),
+            EdgeMode::Zero => {
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    return Pixel::TRANSPARENT;
+                }
+                (x, y)
+            }
+        };
+
+        image.pixel_at(x as usize, y as usize)
+    }
+}
+
+impl From<ImageData> for Image {
+    fn from(data: ImageData) -> Image {
+        Image { data }
+    }
+}
+
+/// How the color of an 8 bit image relates to its alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alpha {
+    /// Color is independent of alpha. What PNG specifies, and what `Image` always holds.
+    Straight,
+    /// Color has already been multiplied by alpha, which some tools write anyway.
+    Premultiplied,
+}
+
+/// Clues about whether an image's alpha is straight or premultiplied. See `Image::analyze_alpha`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaReport {
+    /// No color channel is ever more than alpha, which has to be true of premultiplied images.
+    /// Also true of any opaque image.
+    pub color_within_alpha: bool,
+    /// How many pixels have no alpha but still have color, which premultiplying can't make.
+    pub transparent_with_color: usize,
+    /// How many pixels are neither opaque nor fully transparent. Without any, there's nothing to
+    /// tell the two apart by.
+    pub partially_transparent: usize,
+    pub min_alpha: f32,
+    pub max_alpha: f32,
+}
+
+impl AlphaReport {
+    /// Which kind of alpha the image most likely has, or `None` if it could be either.
+    pub fn likely_alpha(&self) -> Option<Alpha> {
+        if self.transparent_with_color > 0 || !self.color_within_alpha {
+            Some(Alpha::Straight)
+        } else if self.partially_transparent > 0 {
+            Some(Alpha::Premultiplied)
+        } else {
+            None
+        }
+    }
+}
+
+/// Assumes straight alpha, see `Image::from_rgba_image` for premultiplied images.
+impl From<image_library::RgbaImage> for Image {
+    fn from(image: image_library::RgbaImage) -> Image {
+        Image {
+            data: ImageData {
+                width: image.width(),
+                height: image.height(),
+                data: image
+                    .into_vec()
+                    .into_iter()
+                    .map(|byte| byte as f32 / 255.0)
+                    .collect(),
+                origin: None,
+            },
+        }
+    }
+}
+
+/// Builds images procedurally.
+///
+/// ```ignore
+/// let image = ImageBuilder::new().size(16, 16).checker(4, white, black).build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageBuilder {
+    width: u32,
+    height: u32,
+    fill: Pixel,
+    checker: Option<(u32, Pixel, Pixel)>,
+}
+
+impl ImageBuilder {
+    /// A 1x1 transparent image, until told otherwise.
+    pub fn new() -> ImageBuilder {
+        ImageBuilder {
+            width: 1,
+            height: 1,
+            fill: Pixel {
+                r: 0.,
+                g: 0.,
+                b: 0.,
+                a: 0.,
+            },
+            checker: None,
+        }
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> ImageBuilder {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Fill the whole image with one color. Replaces any checkerboard.
+    pub fn fill(mut self, pixel: Pixel) -> ImageBuilder {
+        self.fill = pixel;
+        self.checker = None;
+        self
+    }
+
+    /// Fill the image with a checkerboard of `cell` by `cell` squares, starting with `a` in the
+    /// top left. Replaces any fill.
+    #[allow(dead_code)] // only the tests draw checkerboards so far
+    pub fn checker(mut self, cell: u32, a: Pixel, b: Pixel) -> ImageBuilder {
+        self.checker = Some((cell.max(1), a, b));
+        self
+    }
+
+    pub fn build(self) -> Image {
+        let mut data = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = match self.checker {
+                    Some((cell, a, _)) if (x / cell + y / cell) % 2 == 0 => a,
+                    Some((_, _, b)) => b,
+                    None => self.fill,
+                };
+                data.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+            }
+        }
+
+        Image::from(ImageData {
+            data,
+            width: self.width,
+            height: self.height,
+            origin: None,
+        })
+    }
+}
+
+impl Default for ImageBuilder {
+    fn default() -> Self {
+        ImageBuilder::new()
+    }
+}
+
+/// An image that stores an index into a palette for each pixel instead of the color itself.
+#[allow(dead_code)] // nothing saves indexed images yet
+pub struct IndexedImage {
+    palette: Vec<Pixel>,
+    indices: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+#[allow(dead_code)] // nothing saves indexed images yet
+impl IndexedImage {
+    /// Convert an image to indexed, mapping each pixel to the nearest color in the palette.
+    ///
+    /// The palette must have between 1 and 256 colors.
+    pub fn from_image(image: &Image, palette: Vec<Pixel>) -> IndexedImage {
+        assert!(
+            !palette.is_empty() && palette.len() <= 256,
+            "palette must have between 1 and 256 colors, has {}",
+            palette.len()
+        );
+
+        let mut indices = Vec::with_capacity(image.width() as usize * image.height() as usize);
+        for y in 0..image.height() as usize {
+            for x in 0..image.width() as usize {
+                indices.push(nearest_index(&palette, image.pixel_at(x, y)));
+            }
+        }
+
+        IndexedImage {
+            palette,
+            indices,
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+
+    /// Convert back to a full color image.
+    pub fn to_image(&self) -> Image {
+        let mut data = Vec::with_capacity(self.indices.len() * 4);
+        for &index in &self.indices {
+            let pixel = self.palette[index as usize];
+            data.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+
+        Image::from(ImageData {
+            data,
+            width: self.width,
+            height: self.height,
+            origin: None,
+        })
+    }
+
+    pub fn index_at(&self, x: usize, y: usize) -> u8 {
+        self.indices[self.width as usize * y + x]
+    }
+
+    pub fn pixel_at(&self, x: usize, y: usize) -> Pixel {
+        self.palette[self.index_at(x, y) as usize]
+    }
+
+    /// Paint a pixel with the palette color nearest to `pixel`.
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
+        self.indices[self.width as usize * y + x] = nearest_index(&self.palette, pixel);
+    }
+
+    pub fn palette(&self) -> &[Pixel] {
+        &self.palette
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// An image stored as runs of identical pixels, for keeping copies around that are mostly one
+/// color, like the difference a brush stroke made to the canvas.
+///
+/// Lossless, down to the bits of each channel. Images that wouldn't get any smaller, like
+/// gradients, are kept as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedImage {
+    pixels: Compressed,
+    width: u32,
+    height: u32,
+    origin: Option<Point>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Compressed {
+    /// How many times each pixel repeats, left to right and top to bottom.
+    Runs(Vec<(u32, [f32; 4])>),
+    /// The channels of each pixel, when there are too many runs for them to be worth it.
+    Raw(Vec<f32>),
+}
+
+impl CompressedImage {
+    pub fn from_image(image: &Image) -> CompressedImage {
+        let same =
+            |a: &[f32; 4], b: &[f32]| a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits());
+
+        let mut runs: Vec<(u32, [f32; 4])> = Vec::new();
+        for pixel in image.data.data.chunks_exact(4) {
+            match runs.last_mut() {
+                Some((count, last)) if same(last, pixel) => *count += 1,
+                _ => runs.push((1, [pixel[0], pixel[1], pixel[2], pixel[3]])),
+            }
+        }
+
+        let raw = image.data.data.len() * std::mem::size_of::<f32>();
+        let pixels = if runs.len() * std::mem::size_of::<(u32, [f32; 4])>() >= raw {
+            Compressed::Raw(image.data.data.clone())
+        } else {
+            Compressed::Runs(runs)
+        };
+
+        CompressedImage {
+            pixels,
+            width: image.width(),
+            height: image.height(),
+            origin: image.data.origin,
+        }
+    }
+
+    pub fn to_image(&self) -> Image {
+        let data = match &self.pixels {
+            Compressed::Runs(runs) => {
+                let mut data = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+                for (count, pixel) in runs.iter() {
+                    for _ in 0..*count {
+                        data.extend_from_slice(pixel);
+                    }
+                }
+                data
+            }
+            Compressed::Raw(data) => data.clone(),
+        };
+
+        Image::from(ImageData {
+            data,
+            width: self.width,
+            height: self.height,
+            origin: self.origin,
+        })
+    }
+
+    /// Roughly how much memory the pixels take up compressed.
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.pixels {
+            Compressed::Runs(runs) => runs.len() * std::mem::size_of::<(u32, [f32; 4])>(),
+            Compressed::Raw(data) => data.len() * std::mem::size_of::<f32>(),
+        }
+    }
+
+    #[allow(dead_code)] // only the tests check the size so far
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[allow(dead_code)] // only the tests check the size so far
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// Find the palette entry closest to `pixel`. Exact matches always win.
+#[allow(dead_code)] // nothing saves indexed images yet
+pub fn nearest_index(palette: &[Pixel], pixel: Pixel) -> u8 {
+    let distance = |other: &Pixel| {
+        (other.r - pixel.r).powi(2)
+            + (other.g - pixel.g).powi(2)
+            + (other.b - pixel.b).powi(2)
+            + (other.a - pixel.a).powi(2)
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(i, _)| i as u8)
+        .unwrap()
+}
+
+#[test]
+fn new_blank_images() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let image = Image::new(3, 2, white).unwrap();
+    assert_eq!(Size::new(3, 2), image.size());
+    assert!(image
+        .bounds()
+        .points()
+        .all(|point| { image.pixel_at(point.x as usize, point.y as usize) == white }));
+    assert_eq!(
+        Image::from_solid(5, 7, Pixel::TRANSPARENT).unwrap(),
+        Image::transparent(5, 7).unwrap()
+    );
+
+    assert!(Image::new(0, 2, white).is_err());
+    assert!(Image::new(3, 0, white).is_err());
+    assert!(Image::transparent(0, 0).is_err());
+}
+
+#[test]
+fn checked_pixel_access() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut image = Image::from_solid(4, 3, Pixel::TRANSPARENT).unwrap();
+
+    // the last pixel on each edge is fine
+    assert_eq!(Some(Pixel::TRANSPARENT), image.try_set_pixel(3, 0, red));
+    assert_eq!(Some(Pixel::TRANSPARENT), image.try_set_pixel(0, 2, red));
+    assert_eq!(Some(Pixel::TRANSPARENT), image.try_set_pixel(3, 2, red));
+    assert_eq!(Some(red), image.try_set_pixel(3, 2, red));
+    assert_eq!(Some(red), image.try_pixel_at(3, 2));
+
+    // one past them isn't, and doesn't wrap around into the next row
+    let before = image.clone();
+    assert_eq!(None, image.try_set_pixel(4, 0, red));
+    assert_eq!(None, image.try_set_pixel(0, 3, red));
+    assert_eq!(None, image.try_set_pixel(usize::MAX, 1, red));
+    assert_eq!(before, image);
+    assert_eq!(None, image.try_pixel_at(4, 0));
+    assert_eq!(None, image.try_pixel_at(0, 3));
+    assert_eq!(Pixel::TRANSPARENT, image.pixel_at(0, 1));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "pixel 4, 0 is off the 4x3 image")]
+fn unchecked_access_past_the_edge_panics() {
+    Image::from_solid(4, 3, Pixel::TRANSPARENT)
+        .unwrap()
+        .set_pixel(4, 0, Pixel::TRANSPARENT);
+}
+
+#[test]
+fn resize_does_not_bleed_transparent_rgb() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let clear = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
+    };
+
+    let mut image = Image::from(ImageData {
+        data: vec![0.; 3 * 4],
+        width: 3,
+        height: 1,
+        origin: None,
+    });
+    image.set_pixel(0, 0, red);
+    image.set_pixel(1, 0, red);
+    image.set_pixel(2, 0, clear);
+
+    let small = image.resize(2, 1);
+    assert_eq!(2, small.width());
+    assert_eq!(1, small.height());
+    assert_eq!(red, small.pixel_at(0, 0));
+
+    // the right pixel straddles the edge of the red region: it gets partial coverage, but the
+    // black of the transparent pixel doesn't darken it
+    let edge = small.pixel_at(1, 0);
+    assert_eq!(1.0, edge.r);
+    assert_eq!(0.0, edge.g);
+    assert_eq!(0.25, edge.a);
+
+    assert_eq!(0, image.resize(0, 4).width());
+    let from_nothing = Image::from(ImageData {
+        data: Vec::new(),
+        width: 0,
+        height: 0,
+        origin: None,
+    })
+    .resize(2, 2);
+    assert_eq!(Pixel::TRANSPARENT, from_nothing.pixel_at(1, 1));
+}
+
+#[test]
+fn indexed_round_trip() {
+    let palette = vec![
+        Pixel {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        },
+        Pixel {
+            r: 1.0,
+            g: 0.5,
+            b: 0.25,
+            a: 1.0,
+        },
+        Pixel {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 0.0,
+        },
+    ];
+
+    let mut image = Image::from(ImageData {
+        data: vec![0.; 2 * 2 * 4],
+        width: 2,
+        height: 2,
+        origin: None,
+    });
+    image.set_pixel(0, 0, palette[1]);
+    image.set_pixel(1, 0, palette[0]);
+    image.set_pixel(0, 1, palette[2]);
+    image.set_pixel(1, 1, palette[1]);
+
+    let indexed = IndexedImage::from_image(&image, palette);
+    assert_eq!(1, indexed.index_at(0, 0));
+    assert_eq!(0, indexed.index_at(1, 0));
+    assert_eq!(2, indexed.index_at(0, 1));
+    assert_eq!(1, indexed.index_at(1, 1));
+
+    assert_eq!(image.as_mut(), indexed.to_image().as_mut());
+}
+
+#[test]
+fn indexed_nearest_match() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let light_gray = Pixel {
+        r: 0.8,
+        g: 0.7,
+        b: 0.9,
+        a: 1.0,
+    };
+
+    let palette = vec![black, white];
+    assert_eq!(1, nearest_index(&palette, light_gray));
+
+    // doesn't panic on a pixel that isn't a number
+    let nan = Pixel {
+        r: f32::NAN,
+        ..light_gray
+    };
+    assert!((nearest_index(&palette, nan) as usize) < palette.len());
+
+    let mut indexed = IndexedImage::from_image(
+        &Image::from(ImageData {
+            data: vec![0., 0., 0., 1.],
+            width: 1,
+            height: 1,
+            origin: None,
+        }),
+        palette,
+    );
+    assert_eq!(black, indexed.pixel_at(0, 0));
+
+    indexed.set_pixel(0, 0, light_gray);
+    assert_eq!(white, indexed.pixel_at(0, 0));
+}
+
+#[test]
+fn builder_checkerboard() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    let image = ImageBuilder::new()
+        .size(4, 4)
+        .checker(1, white, black)
+        .build();
+    assert_eq!(4, image.width());
+    assert_eq!(4, image.height());
+    assert_eq!(white, image.pixel_at(0, 0));
+    assert_eq!(black, image.pixel_at(3, 0));
+    assert_eq!(black, image.pixel_at(0, 3));
+    assert_eq!(white, image.pixel_at(3, 3));
+    assert_eq!(black, image.pixel_at(1, 0));
+
+    let image = ImageBuilder::new()
+        .size(4, 4)
+        .checker(2, white, black)
+        .build();
+    assert_eq!(white, image.pixel_at(1, 1));
+    assert_eq!(black, image.pixel_at(2, 1));
+}
+
+#[test]
+fn from_solid() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    let image = Image::from_solid(3, 2, red).unwrap();
+    assert_eq!(3, image.width());
+    assert_eq!(2, image.height());
+    assert!((0..2).all(|y| (0..3).all(|x| image.pixel_at(x, y) == red)));
+    assert!(Image::from_solid(0, 2, red).is_err());
+}
+
+#[test]
+fn as_raw_clamps() {
+    let image = Image::from(ImageData {
+        data: vec![-0.5, 1.5, 0.0, 1.0],
+        width: 1,
+        height: 1,
+        origin: None,
+    });
+    assert_eq!(vec![0, 255, 0, 255], image.as_raw());
+}
+
+#[test]
+fn as_raw_round_trip() {
+    let bytes = (0..=255).collect::<Vec<u8>>();
+    let rgba = image_library::RgbaImage::from_vec(64, 1, bytes.clone()).unwrap();
+    assert_eq!(bytes, Image::from(rgba).as_raw());
+}
+
+#[test]
+fn convolve_identity() {
+    let image = ImageBuilder::new()
+        .size(4, 3)
+        .checker(
+            1,
+            Pixel {
+                r: 1.0,
+                g: 0.5,
+                b: 0.25,
+                a: 1.0,
+            },
+            Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 1.0,
+                a: 0.5,
+            },
+        )
+        .build();
+
+    let identity = [0., 0., 0., 0., 1., 0., 0., 0., 0.];
+    for &edge in &[EdgeMode::Clamp, EdgeMode::Wrap, EdgeMode::Zero] {
+        assert_eq!(image, image.convolve(&identity, 3, 3, edge));
+    }
+}
+
+#[test]
+fn convolve_box_blur() {
+    let gray = |v| Pixel {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    };
+
+    // a single white pixel in the middle of black
+    let mut image = Image::from_solid(3, 3, gray(0.0)).unwrap();
+    image.set_pixel(1, 1, gray(1.0));
+
+    // unnormalized kernel, gets divided by 9
+    let blurred = image.convolve(&[1.; 9], 3, 3, EdgeMode::Clamp);
+    for y in 0..3 {
+        for x in 0..3 {
+            let pixel = blurred.pixel_at(x, y);
+            assert!((pixel.r - 1. / 9.).abs() < 1e-6);
+            assert_eq!(1.0, pixel.a);
+        }
+    }
+}
+
+#[test]
+fn convolve_edge_modes() {
+    let gray = |v| Pixel {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    };
+
+    // 3x1 gradient: 0, 0.5, 1
+    let mut image = Image::from_solid(3, 1, gray(0.0)).unwrap();
+    image.set_pixel(1, 0, gray(0.5));
+    image.set_pixel(2, 0, gray(1.0));
+
+    // average of the pixel and its left and right neighbors, at the left edge
+    let kernel = [1., 1., 1.];
+    let at_left = |edge| image.convolve(&kernel, 3, 1, edge).pixel_at(0, 0);
+
+    // left neighbor is the pixel itself
+    let clamp = at_left(EdgeMode::Clamp);
+    assert!((clamp.r - 0.5 / 3.).abs() < 1e-6);
+    assert_eq!(1.0, clamp.a);
+
+    // left neighbor is the right edge
+    let wrap = at_left(EdgeMode::Wrap);
+    assert!((wrap.r - 1.5 / 3.).abs() < 1e-6);
+    assert_eq!(1.0, wrap.a);
+
+    // left neighbor is transparent, so only dilutes alpha and not color
+    let zero = at_left(EdgeMode::Zero);
+    assert!((zero.r - 0.25).abs() < 1e-6);
+    assert!((zero.a - 2. / 3.).abs() < 1e-6);
+}
+
+#[test]
+fn replace_color_exact() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let almost_red = Pixel { g: 0.01, ..red };
+
+    let mut image = Image::from_solid(3, 1, red).unwrap();
+    image.set_pixel(1, 0, almost_red);
+    image.set_pixel(2, 0, Pixel { a: 0.5, ..red });
+
+    image.replace_color(red, blue, 0.0, false);
+    assert_eq!(blue, image.pixel_at(0, 0));
+    assert_eq!(almost_red, image.pixel_at(1, 0));
+    assert_eq!(Pixel { a: 0.5, ..red }, image.pixel_at(2, 0));
+}
+
+#[test]
+fn replace_color_tolerant() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let orange = Pixel { g: 0.5, ..red };
+
+    let mut image = Image::from_solid(4, 1, red).unwrap();
+    image.set_pixel(1, 0, Pixel { g: 0.05, ..red });
+    image.set_pixel(2, 0, Pixel { a: 0.95, ..red });
+    image.set_pixel(3, 0, orange);
+
+    image.replace_color(red, blue, 0.1, true);
+    assert_eq!(blue, image.pixel_at(0, 0));
+    assert_eq!(blue, image.pixel_at(1, 0));
+    assert_eq!(Pixel { a: 0.95, ..blue }, image.pixel_at(2, 0));
+    assert_eq!(orange, image.pixel_at(3, 0));
+}
+
+#[test]
+fn replace_color_in_rect_near_misses() {
+    let gray = Pixel {
+        r: 0.5,
+        g: 0.5,
+        b: 0.5,
+        a: 1.0,
+    };
+    let green = Pixel {
+        r: 0.0,
+        g: 1.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    // each pixel is off from gray in one channel, by a bit less or a bit more than the tolerance
+    let near = [
+        Pixel { r: 0.59, ..gray },
+        Pixel { g: 0.41, ..gray },
+        Pixel { b: 0.599, ..gray },
+        Pixel { a: 0.91, ..gray },
+    ];
+    let far = [
+        Pixel { r: 0.61, ..gray },
+        Pixel { g: 0.39, ..gray },
+        Pixel { b: 0.601, ..gray },
+        Pixel { a: 0.89, ..gray },
+    ];
+
+    let mut image = Image::from_solid(4, 3, gray).unwrap();
+    for x in 0..4 {
+        image.set_pixel(x, 0, near[x]);
+        image.set_pixel(x, 1, far[x]);
+    }
+
+    // only inside of the rect, and the rect is clipped to the image
+    assert_eq!(
+        3,
+        image.replace_color_in(
+            &Selection::from(Rect::new(1, -1, 10, 3)),
+            gray,
+            green,
+            0.1,
+            false
+        )
+    );
+    assert_eq!(near[0], image.pixel_at(0, 0));
+    for (x, &far) in far.iter().enumerate().skip(1) {
+        assert_eq!(green, image.pixel_at(x, 0), "{}", x);
+        assert_eq!(far, image.pixel_at(x, 1), "{}", x);
+    }
+    assert_eq!(gray, image.pixel_at(1, 2));
+
+    // keeping the alpha of what was there
+    let mut image = Image::from_solid(1, 1, near[3]).unwrap();
+    assert_eq!(
+        1,
+        image.replace_color_in(&Selection::from(image.bounds()), gray, green, 0.1, true)
+    );
+    assert_eq!(Pixel { a: 0.91, ..green }, image.pixel_at(0, 0));
+}
+
+#[test]
+fn region_pixels_sub_region() {
+    let mut image = Image::from_solid(5, 4, Pixel::TRANSPARENT).unwrap();
+    for y in 0..4 {
+        for x in 0..5 {
+            image.set_rgba(x, y, x as f32, y as f32, 0.0, 1.0);
+        }
+    }
+
+    let region = image
+        .region_pixels(Rect::new(2, 1, 2, 2))
+        .collect::<Vec<_>>();
+    assert_eq!(4, region.len());
+    for (&(x, y, pixel), &(ex, ey)) in region.iter().zip(&[(2, 1), (3, 1), (2, 2), (3, 2)]) {
+        assert_eq!((ex, ey), (x, y));
+        assert_eq!((x as f32, y as f32), (pixel.r, pixel.g));
+    }
+
+    // clipped to the image
+    assert_eq!(1, image.region_pixels(Rect::new(4, 3, 5, 5)).count());
+    assert_eq!(0, image.region_pixels(Rect::new(-3, 0, 2, 2)).count());
+
+    for (_, _, pixel) in image.region_pixels_mut(Rect::new(2, 1, 2, 2)) {
+        pixel[2] = 1.0;
+    }
+    for y in 0..4 {
+        for x in 0..5 {
+            let inside = (2..4).contains(&x) && (1..3).contains(&y);
+            assert_eq!(inside, image.pixel_at(x, y).b == 1.0, "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn offset_wrap_round_trip() {
+    let mut image = Image::from_solid(5, 3, Pixel::TRANSPARENT).unwrap();
+    for y in 0..3 {
+        for x in 0..5 {
+            image.set_rgba(x, y, x as f32 / 4.0, y as f32 / 2.0, 0.5, 1.0);
+        }
+    }
+
+    let half = image.offset_wrap(2, 1);
+    assert_ne!(image, half);
+    assert_eq!(image.pixel_at(0, 0), half.pixel_at(2, 1));
+    assert_eq!(image.pixel_at(4, 2), half.pixel_at(1, 0));
+
+    assert_eq!(image, half.offset_wrap(3, 2));
+    assert_eq!(image, image.offset_wrap(-5, 6));
+    assert_eq!(image.offset_wrap(-1, 0), image.offset_wrap(4, 0));
+}
+
+#[test]
+fn paste_with_mask_half() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    // the left half of the mask is on
+    let source = Image::from_solid(4, 2, red).unwrap();
+    let mut mask = Image::from_solid(4, 2, black).unwrap();
+    for y in 0..2 {
+        for x in 0..2 {
+            mask.set_pixel(x, y, white);
+        }
+    }
+
+    let mut canvas = Image::from_solid(6, 4, black).unwrap();
+    canvas.paste_with_mask(&source, &mask, 1, 1).unwrap();
+    for y in 0..4 {
+        for x in 0..6 {
+            let expected = if (1..3).contains(&x) && (1..3).contains(&y) {
+                red
+            } else {
+                black
+            };
+            assert_eq!(expected, canvas.pixel_at(x, y), "at {}, {}", x, y);
+        }
+    }
+
+    // hanging off of the edge is fine, but mismatched sizes aren't
+    canvas.paste_with_mask(&source, &mask, -1, 3).unwrap();
+    assert_eq!(red, canvas.pixel_at(0, 3));
+    assert!(canvas
+        .paste_with_mask(&source, &Image::from_solid(2, 2, white).unwrap(), 0, 0)
+        .is_err());
+}
+
+#[test]
+fn color_space_round_trips() {
+    let spaces = [
+        ColorSpace::Srgb,
+        ColorSpace::Linear,
+        ColorSpace::Hsv,
+        ColorSpace::Lab,
+    ];
+    let colors = [
+        (0.0, 0.0, 0.0),
+        (1.0, 1.0, 1.0),
+        (0.5, 0.5, 0.5),
+        (1.0, 0.0, 0.0),
+        (0.2, 0.9, 0.4),
+        (0.1, 0.3, 0.95),
+        (0.8, 0.2, 0.7),
+        (0.02, 0.01, 0.03),
+    ];
+
+    for &(r, g, b) in colors.iter() {
+        let pixel = Pixel { r, g, b, a: 0.5 };
+        for &from in spaces.iter() {
+            for &to in spaces.iter() {
+                let start = convert_color(pixel, ColorSpace::Srgb, from);
+                let there = convert_color(start, from, to);
+                let back = convert_color(there, to, ColorSpace::Srgb);
+
+                assert_eq!(0.5, back.a);
+                for (expected, actual) in [(r, back.r), (g, back.g), (b, back.b)] {
+                    assert!(
+                        (expected - actual).abs() < 1e-4,
+                        "{:?} through {:?} to {:?}: {:?}",
+                        pixel,
+                        from,
+                        to,
+                        back
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn color_space_known_values() {
+    let gray = Pixel {
+        r: 0.5,
+        g: 0.5,
+        b: 0.5,
+        a: 1.0,
+    };
+    let linear = convert_color(gray, ColorSpace::Srgb, ColorSpace::Linear);
+    assert!((linear.r - 0.214_041).abs() < 1e-5, "{}", linear.r);
+
+    // white has no chroma, and full lightness
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let lab = white.to_lab();
+    assert!((lab.r - 100.0).abs() < 1e-3, "{:?}", lab);
+    assert!(lab.g.abs() < 1e-2 && lab.b.abs() < 1e-2, "{:?}", lab);
+
+    let hsv = Pixel {
+        r: 0.0,
+        g: 1.0,
+        b: 0.0,
+        a: 1.0,
+    }
+    .to_hsv();
+    assert_eq!((1.0 / 3.0, 1.0, 1.0), (hsv.r, hsv.g, hsv.b));
+}
+
+#[test]
+fn downscale_average_checkerboard() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let gray = Pixel {
+        r: 0.5,
+        g: 0.5,
+        b: 0.5,
+        a: 1.0,
+    };
+
+    let image = ImageBuilder::new()
+        .size(4, 4)
+        .checker(1, white, black)
+        .build();
+    let small = image.downscale_average(4.0);
+    assert_eq!((1, 1), (small.width(), small.height()));
+    assert_eq!(gray, small.pixel_at(0, 0));
+
+    // blocks that split pixels weigh them by how much is in the block
+    let stripes = ImageBuilder::new()
+        .size(3, 1)
+        .checker(1, white, black)
+        .build();
+    let half = stripes.downscale_average(1.5);
+    assert_eq!((2, 1), (half.width(), half.height()));
+    assert!((half.pixel_at(0, 0).r - 2.0 / 3.0).abs() < 1e-6);
+    assert!((half.pixel_at(1, 0).r - 2.0 / 3.0).abs() < 1e-6);
+    assert_eq!(1.0, half.pixel_at(1, 0).a);
+}
+
+#[test]
+fn premultiplied_import_and_export() {
+    // half transparent orange, a transparent pixel that still has color in it, and an opaque one
+    let premultiplied = image_library::RgbaImage::from_vec(
+        3,
+        1,
+        vec![128, 64, 0, 128, 10, 20, 30, 0, 255, 0, 51, 255],
+    )
+    .unwrap();
+
+    let image = Image::from_rgba_image(premultiplied.clone(), Alpha::Premultiplied);
+    let pixel = image.pixel_at(0, 0);
+    assert_eq!((1.0, 0.5, 0.0), (pixel.r, pixel.g, pixel.b));
+    assert_eq!(128. / 255., pixel.a);
+    assert_eq!(Pixel::TRANSPARENT, image.pixel_at(1, 0));
+    assert_eq!(
+        Image::from(premultiplied.clone()).pixel_at(2, 0),
+        image.pixel_at(2, 0)
+    );
+
+    // straight alpha is left alone
+    let straight = Image::from_rgba_image(premultiplied.clone(), Alpha::Straight);
+    assert_eq!(Image::from(premultiplied.clone()), straight);
+    assert_eq!(premultiplied, straight.to_rgba_image_with(Alpha::Straight));
+
+    // and premultiplying again gets back the same bytes, apart from the lost transparent color
+    let exported = image.to_rgba_image_with(Alpha::Premultiplied);
+    assert_eq!(&[128, 64, 0, 128], &exported.as_raw()[..4]);
+    assert_eq!(&[0, 0, 0, 0], &exported.as_raw()[4..8]);
+    assert_eq!(&premultiplied.as_raw()[8..], &exported.as_raw()[8..]);
+
+    let path = std::env::temp_dir().join(format!(
+        "yocto-canvas-premultiplied-test-{}.png",
+        std::process::id()
+    ));
+    image.save(&path, Alpha::Premultiplied).unwrap();
+    let loaded = Image::load(&path, Alpha::Premultiplied);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image, loaded.unwrap());
+}
+
+#[test]
+fn analyze_alpha_tells_premultiplied_from_straight() {
+    let bytes = |data: Vec<u8>| {
+        Image::from(image_library::RgbaImage::from_vec(data.len() as u32 / 4, 1, data).unwrap())
+    };
+
+    // a fading red, and a transparent pixel that remembers it was blue
+    let straight = bytes(vec![255, 0, 0, 255, 255, 0, 0, 128, 0, 0, 255, 0]);
+    let report = straight.analyze_alpha();
+    assert!(!report.color_within_alpha);
+    assert_eq!(1, report.transparent_with_color);
+    assert_eq!(1, report.partially_transparent);
+    assert_eq!((0.0, 1.0), (report.min_alpha, report.max_alpha));
+    assert_eq!(Some(Alpha::Straight), report.likely_alpha());
+
+    // the same red premultiplied, with nothing left in the transparent pixel
+    let premultiplied = bytes(vec![255, 0, 0, 255, 128, 0, 0, 128, 0, 0, 0, 0]);
+    let report = premultiplied.analyze_alpha();
+    assert!(report.color_within_alpha);
+    assert_eq!(0, report.transparent_with_color);
+    assert_eq!(Some(Alpha::Premultiplied), report.likely_alpha());
+
+    // and once it's been converted, it looks straight
+    let converted = Image::from_rgba_image(premultiplied.to_rgba_image(), Alpha::Premultiplied);
+    assert_eq!(
+        Some(Alpha::Straight),
+        converted.analyze_alpha().likely_alpha()
+    );
+
+    // opaque images could be either
+    let opaque = bytes(vec![10, 20, 30, 255, 40, 50, 60, 255]);
+    let report = opaque.analyze_alpha();
+    assert!(report.color_within_alpha);
+    assert_eq!((1.0, 1.0), (report.min_alpha, report.max_alpha));
+    assert_eq!(None, report.likely_alpha());
+}
+
+// a maze of walls with gaps, so fills have to wind around, plus some noise in the color that a
+// tolerance can see past
+#[cfg(test)]
+fn fill_test_image(width: u32, height: u32) -> Image {
+    let wall = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut image = Image::from_solid(width, height, Pixel::TRANSPARENT).unwrap();
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let is_wall = (x % 7 == 3 && y % 11 != 5) || (y % 13 == 6 && x % 17 != 2);
+            let pixel = if is_wall {
+                wall
+            } else {
+                let noise = ((x * 31 + y * 17) % 5) as f32 / 100.0;
+                Pixel {
+                    r: 0.9 + noise,
+                    g: 0.9,
+                    b: 0.9 - noise,
+                    a: 1.0,
+                }
+            };
+            image.set_pixel(x, y, pixel);
+        }
+    }
+    image
+}
+
+#[test]
+fn scanline_fill_matches_flood_fill() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let image = fill_test_image(200, 150);
+
+    for &(x, y, tolerance) in &[(0, 0, 0.05), (100, 100, 0.05), (0, 0, 0.0), (3, 0, 0.1)] {
+        let (mut naive, mut scanline) = (image.clone(), image.clone());
+        let count = naive.flood_fill(x, y, red, tolerance);
+        assert!(count > 0);
+        assert_eq!(count, scanline.scanline_fill(x, y, red, tolerance));
+        assert_eq!(naive, scanline, "at {}, {} within {}", x, y, tolerance);
+    }
+
+    // filling with a color the fill would match doesn't go on forever
+    let mut naive = image.clone();
+    let mut scanline = image.clone();
+    let everything = naive.flood_fill(0, 0, image.pixel_at(0, 0), 1.0);
+    assert_eq!(200 * 150, everything);
+    assert_eq!(
+        everything,
+        scanline.scanline_fill(0, 0, image.pixel_at(0, 0), 1.0)
+    );
+
+    assert_eq!(0, scanline.scanline_fill(200, 0, red, 0.0));
+}
+
+#[test]
+fn sample_nearest_and_bilinear() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut image = Image::from_solid(2, 1, black).unwrap();
+    image.set_pixel(1, 0, white);
+
+    assert_eq!(black, image.sample(0.9, 0.5, Filter::Nearest));
+    assert_eq!(white, image.sample(1.1, 0.5, Filter::Nearest));
+    assert_eq!(black, image.sample(0.5, 0.5, Filter::Bilinear));
+    let between = image.sample(1.0, 0.5, Filter::Bilinear);
+    assert!((between.r - 0.5).abs() < 1e-6);
+    assert_eq!(1.0, between.a);
+
+    for &filter in &[Filter::Nearest, Filter::Bilinear] {
+        assert_eq!(Pixel::TRANSPARENT, image.sample(-0.1, 0.5, filter));
+        assert_eq!(Pixel::TRANSPARENT, image.sample(2.0, 0.5, filter));
+        assert_eq!(Pixel::TRANSPARENT, image.sample(0.5, 1.0, filter));
+    }
+}
+
+#[test]
+fn region_tolerance_and_connectivity() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    // a white diagonal line, getting lighter at the end
+    let mut image = Image::from_solid(4, 4, black).unwrap();
+    for i in 0..3 {
+        image.set_pixel(i, i, white);
+    }
+    image.set_pixel(3, 3, Pixel { r: 0.75, ..white });
+    let count = |region: Vec<bool>| {
+        assert_eq!(16, region.len());
+        region.into_iter().filter(|&inside| inside).count()
+    };
+
+    // the line only holds together diagonally
+    assert_eq!(1, count(image.region(0, 0, 0.0, Connectivity::Four)));
+    assert_eq!(3, count(image.region(0, 0, 0.0, Connectivity::Eight)));
+    assert!(image.region(0, 0, 0.0, Connectivity::Eight)[2 * 4 + 2]);
+
+    // tolerance includes the pixels right at the edge of it
+    assert_eq!(3, count(image.region(0, 0, 0.2, Connectivity::Eight)));
+    assert_eq!(4, count(image.region(0, 0, 0.25, Connectivity::Eight)));
+
+    // and it cuts the background in two, except diagonally
+    assert_eq!(6, count(image.region(1, 0, 0.0, Connectivity::Four)));
+    assert_eq!(12, count(image.region(1, 0, 0.0, Connectivity::Eight)));
+
+    assert_eq!(0, count(image.region(4, 0, 1.0, Connectivity::Eight)));
+}
+
+// cargo test fill_benchmark --release -- --ignored --nocapture
+#[test]
+#[ignore]
+fn fill_benchmark() {
+    use std::time::Instant;
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let solid = Image::from_solid(2048, 2048, Pixel::TRANSPARENT).unwrap();
+    let maze = fill_test_image(2048, 2048);
+
+    for (name, image) in &[("solid", solid), ("maze", maze)] {
+        let mut naive = image.clone();
+        let start = Instant::now();
+        naive.flood_fill(0, 0, red, 0.05);
+        let naive_time = start.elapsed();
+
+        let mut scanline = image.clone();
+        let start = Instant::now();
+        scanline.scanline_fill(0, 0, red, 0.05);
+        let scanline_time = start.elapsed();
+
+        assert_eq!(naive, scanline);
+        println!(
+            "{}: flood fill {:?}, scanline fill {:?}",
+            name, naive_time, scanline_time
+        );
+    }
+}
+
+#[test]
+fn compressed_round_trip() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    // a short stroke on an otherwise untouched layer
+    let mut sparse = Image::from_solid(512, 512, Pixel::TRANSPARENT).unwrap();
+    for x in 100..140 {
+        for y in 200..203 {
+            sparse.set_pixel(x, y, red.lerp(Pixel::TRANSPARENT, (x - 100) as f32 / 40.0));
+        }
+    }
+    let sparse = sparse.crop(Rect::new(0, 10, 512, 500));
+
+    let compressed = CompressedImage::from_image(&sparse);
+    assert_eq!(sparse, compressed.to_image());
+
+    let raw = sparse.width() as usize * sparse.height() as usize * 4 * 4;
+    let ratio = compressed.size_in_bytes() as f32 / raw as f32;
+    assert!(
+        ratio < 0.001,
+        "sparse edit compressed to {:.4} of its size",
+        ratio
+    );
+
+    // noise doesn't compress, but still comes back the same, even the odd bits
+    let mut noisy = fill_test_image(40, 30);
+    noisy.set_pixel(0, 0, Pixel { r: -0.0, ..red });
+    noisy.set_pixel(1, 0, red);
+    let compressed = CompressedImage::from_image(&noisy);
+    let restored = compressed.to_image();
+    assert_eq!(noisy, restored);
+    assert!(restored.pixel_at(0, 0).r.is_sign_negative());
+    assert_eq!((40, 30), (compressed.width(), compressed.height()));
+}
+
+#[test]
+fn compressed_gradient_is_no_bigger() {
+    // a soft edge, where no two pixels in a row are the same
+    let mut gradient = Image::from_solid(64, 16, Pixel::TRANSPARENT).unwrap();
+    for y in 0..16 {
+        for x in 0..64 {
+            let t = (y * 64 + x) as f32 / 1024.0;
+            gradient.set_pixel(
+                x,
+                y,
+                Pixel {
+                    r: t,
+                    g: t,
+                    b: t,
+                    a: 1.0 - t,
+                },
+            );
+        }
+    }
+
+    let compressed = CompressedImage::from_image(&gradient);
+    assert_eq!(gradient, compressed.to_image());
+    assert_eq!(64 * 16 * 4 * 4, compressed.size_in_bytes());
+}
+
+#[test]
+fn blend_pixel_builds_up() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut image = Image::from_solid(2, 1, white).unwrap();
+
+    image.blend_pixel(0, 0, black, 0.3);
+    assert!((image.pixel_at(0, 0).r - 0.7).abs() < 1e-6);
+    image.blend_pixel(0, 0, black, 0.3);
+    assert!((image.pixel_at(0, 0).r - 0.49).abs() < 1e-6);
+    assert_eq!(1.0, image.pixel_at(0, 0).a);
+
+    // full opacity replaces, none leaves it alone
+    image.blend_pixel(1, 0, black, 0.0);
+    assert_eq!(white, image.pixel_at(1, 0));
+    image.blend_pixel(1, 0, black, 1.0);
+    assert_eq!(black, image.pixel_at(1, 0));
+
+    // onto nothing, the color stays and only the alpha is scaled
+    let mut image = Image::from_solid(1, 1, Pixel::TRANSPARENT).unwrap();
+    image.blend_pixel(0, 0, white, 0.5);
+    assert_eq!(Pixel { a: 0.5, ..white }, image.pixel_at(0, 0));
+}
+
+#[test]
+fn blit_composites_and_clips() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut top = Image::from_solid(3, 2, red).unwrap();
+    top.set_pixel(0, 0, Pixel::TRANSPARENT);
+    top.set_pixel(1, 0, Pixel { a: 0.5, ..red });
+
+    let mut image = Image::from_solid(4, 4, blue).unwrap();
+    image.blit(&top, Point::new(2, -1));
+    assert_eq!(red, image.pixel_at(2, 0));
+    assert_eq!(red, image.pixel_at(3, 0));
+    assert_eq!(blue, image.pixel_at(2, 1));
+    assert_eq!(blue, image.pixel_at(1, 0));
+
+    image.blit(&top, Point::new(0, 2));
+    assert_eq!(blue, image.pixel_at(0, 2));
+    assert_eq!(
+        Pixel {
+            r: 0.5,
+            g: 0.0,
+            b: 0.5,
+            a: 1.0
+        },
+        image.pixel_at(1, 2)
+    );
+    assert_eq!(red, image.pixel_at(2, 3));
+
+    // entirely off of it
+    let before = image.clone();
+    image.blit(&top, Point::new(-3, 0));
+    assert_eq!(before, image);
 }
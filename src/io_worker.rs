@@ -0,0 +1,221 @@
+use crate::{
+    density::Density,
+    image::{Alpha, Image},
+    Result,
+};
+
+use anyhow::Context;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Identifies a job submitted to an `IoWorker`, to match it up with its result.
+pub type JobId = u64;
+
+/// Slow work that shouldn't happen on the UI thread.
+#[derive(Debug)]
+pub enum Job {
+    /// Load an image, along with its pixel density if it has one.
+    LoadImage(PathBuf),
+    /// Save an image, with a pixel density if it has one.
+    SaveImage(PathBuf, Image, Option<Density>),
+    /// Read a whole text file.
+    LoadText(PathBuf),
+    /// Write text to a file, replacing whatever was there.
+    SaveText(PathBuf, String),
+    /// Shrink an image to fit in a square of this many pixels.
+    #[allow(dead_code)] // TODO layer panel
+    GenerateThumbnail(Image, u32),
+}
+
+/// What came of a `Job`.
+#[derive(Debug)]
+pub enum JobResult {
+    Loaded(PathBuf, Result<(Image, Option<Density>)>),
+    LoadedText(PathBuf, Result<String>),
+    Saved(PathBuf, Result<()>),
+    #[allow(dead_code)] // TODO layer panel
+    Thumbnail(Image),
+}
+
+impl Job {
+    fn run(self) -> JobResult {
+        match self {
+            Job::LoadImage(path) => {
+                let loaded = Image::load_with_density(&path, Alpha::Straight);
+                JobResult::Loaded(path, loaded)
+            }
+            Job::SaveImage(path, image, density) => {
+                let saved = image.save_with_density(&path, Alpha::Straight, density);
+                JobResult::Saved(path, saved)
+            }
+            Job::LoadText(path) => {
+                let loaded = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Couldn't load {}", path.display()));
+                JobResult::LoadedText(path, loaded)
+            }
+            Job::SaveText(path, text) => {
+                let saved = std::fs::write(&path, text)
+                    .with_context(|| format!("Couldn't save {}", path.display()));
+                JobResult::Saved(path, saved)
+            }
+            Job::GenerateThumbnail(image, max_size) => {
+                JobResult::Thumbnail(image.thumbnail(max_size))
+            }
+        }
+    }
+}
+
+/// Background threads for disk access and other slow jobs.
+///
+/// Results are collected with `poll`. The `notify` callback runs on the worker thread whenever a
+/// job finishes, so the event loop can be woken up to go collect it.
+pub struct IoWorker {
+    jobs: Option<Sender<(JobId, Job)>>,
+    results: Receiver<(JobId, JobResult)>,
+    threads: Vec<JoinHandle<()>>,
+    next_id: JobId,
+}
+
+impl IoWorker {
+    pub const DEFAULT_THREADS: usize = 2;
+
+    /// Start `threads` worker threads, at least one.
+    pub fn new(threads: usize, notify: impl Fn() + Send + Sync + 'static) -> IoWorker {
+        let (jobs, job_receiver) = mpsc::channel::<(JobId, Job)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let notify = Arc::new(notify);
+
+        let threads = (0..threads.max(1))
+            .map(|i| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                let notify = Arc::clone(&notify);
+
+                thread::Builder::new()
+                    .name(format!("io worker {}", i))
+                    .spawn(move || loop {
+                        // the lock is only held while waiting, not while working
+                        let job = job_receiver.lock().unwrap().recv();
+                        let (id, job) = match job {
+                            Ok(job) => job,
+                            // the worker was dropped
+                            Err(_) => break,
+                        };
+
+                        if result_sender.send((id, job.run())).is_err() {
+                            break;
+                        }
+                        notify();
+                    })
+                    .expect("Couldn't start io worker thread")
+            })
+            .collect();
+
+        IoWorker {
+            jobs: Some(jobs),
+            results,
+            threads,
+            next_id: 0,
+        }
+    }
+
+    /// Queue up a job, returning its ID.
+    pub fn submit(&mut self, job: Job) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // the threads only stop once the sender is dropped
+        self.jobs.as_ref().unwrap().send((id, job)).unwrap();
+        id
+    }
+
+    /// Take every result that's ready, without waiting.
+    pub fn poll(&self) -> Vec<(JobId, JobResult)> {
+        self.results.try_iter().collect()
+    }
+
+    /// Block until the result of a particular job is ready, up to `timeout`. Results of other
+    /// jobs that finish first are returned along with it.
+    #[allow(dead_code)] // only the tests wait on a job so far
+    pub fn wait_for(
+        &self,
+        id: JobId,
+        timeout: Duration,
+    ) -> Option<(JobResult, Vec<(JobId, JobResult)>)> {
+        let deadline = Instant::now() + timeout;
+        let mut others = Vec::new();
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.results.recv_timeout(remaining) {
+                Ok((done, result)) if done == id => return Some((result, others)),
+                Ok(other) => others.push(other),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+}
+
+impl Drop for IoWorker {
+    // finish whatever's queued, so saves aren't lost on exit
+    fn drop(&mut self) {
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[test]
+fn save_job_writes_file() {
+    use crate::image::Pixel;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let notified = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&notified);
+    let mut worker = IoWorker::new(1, move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let path = std::env::temp_dir().join(format!(
+        "yocto-canvas-io-worker-test-{}.png",
+        std::process::id()
+    ));
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let image = Image::from_solid(3, 2, red).unwrap();
+
+    let id = worker.submit(Job::SaveImage(path.clone(), image.clone(), None));
+    match worker.wait_for(id, Duration::from_secs(10)) {
+        Some((JobResult::Saved(saved, Ok(())), _)) => assert_eq!(path, saved),
+        other => panic!("save didn't finish: {:?}", other.map(|(result, _)| result)),
+    }
+
+    // and it can be read back
+    let id = worker.submit(Job::LoadImage(path.clone()));
+    match worker.wait_for(id, Duration::from_secs(10)) {
+        Some((JobResult::Loaded(_, Ok((loaded, None))), _)) => assert_eq!(image, loaded),
+        other => panic!("load didn't finish: {:?}", other.map(|(result, _)| result)),
+    }
+
+    std::fs::remove_file(&path).unwrap();
+
+    // dropping waits for the threads, which notify after sending the result
+    drop(worker);
+    assert_eq!(2, notified.load(Ordering::SeqCst));
+}
@@ -0,0 +1,227 @@
+//! A stack of layers that flattens down into a single image.
+
+use crate::{
+    composite::{EvalContext, Node, NodeError},
+    geometry::Size,
+    image::{Image, Pixel},
+};
+
+use std::collections::HashMap;
+
+/// What a layer contributes to the stack.
+#[derive(Debug)]
+#[allow(dead_code)] // TODO layer panel
+pub enum LayerContent {
+    /// An image composited over everything below it.
+    Pixels(Image),
+    /// A node applied to everything below it, like a brightness or desaturate adjustment.
+    ///
+    /// The flattened image so far goes to the node's first input, and the node's first output
+    /// replaces it. Nothing is stored, so the adjustment can be changed or removed later without
+    /// losing anything.
+    Adjustment(Box<dyn Node>),
+}
+
+#[derive(Debug)]
+#[allow(dead_code)] // TODO layer panel
+pub struct Layer {
+    pub name: String,
+    pub content: LayerContent,
+    pub opacity: f32,
+    pub visible: bool,
+}
+
+#[allow(dead_code)] // TODO layer panel
+impl Layer {
+    pub fn pixels(name: &str, image: Image) -> Layer {
+        Layer {
+            name: name.to_string(),
+            content: LayerContent::Pixels(image),
+            opacity: 1.,
+            visible: true,
+        }
+    }
+
+    pub fn adjustment(name: &str, node: Box<dyn Node>) -> Layer {
+        Layer {
+            name: name.to_string(),
+            content: LayerContent::Adjustment(node),
+            opacity: 1.,
+            visible: true,
+        }
+    }
+}
+
+/// Layers of the same size, from the bottom up.
+#[derive(Debug)]
+#[allow(dead_code)] // TODO layer panel
+pub struct LayerStack {
+    size: Size,
+    layers: Vec<Layer>,
+}
+
+#[allow(dead_code)] // TODO layer panel
+impl LayerStack {
+    pub fn new(width: u32, height: u32) -> LayerStack {
+        LayerStack {
+            size: Size::new(width, height),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Put a layer on top of the stack.
+    pub fn push(&mut self, layer: Layer) {
+        self.layers.push(layer);
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Layer] {
+        &mut self.layers
+    }
+
+    /// Composite every visible layer together, starting from transparent. Adjustment layers are
+    /// applied to the image flattened so far, and faded in by their opacity.
+    ///
+    /// Takes `&mut self` because executing a node does. Layers that are a different size than
+    /// the stack are an error, and so is an adjustment producing a different size.
+    pub fn flatten(&mut self, context: &EvalContext) -> Result<Image, NodeError> {
+        let Size { width, height } = self.size;
+        let mut flattened =
+            Image::transparent(width, height).map_err(|error| NodeError::InvalidProperty {
+                node: String::from("layer stack"),
+                message: format!("{:#}", error),
+            })?;
+
+        for layer in self.layers.iter_mut().filter(|layer| layer.visible) {
+            let opacity = layer.opacity.clamp(0., 1.);
+            match &mut layer.content {
+                LayerContent::Pixels(image) => {
+                    check_size(&layer.name, self.size, image.size())?;
+                    for (x, y, pixel) in flattened.region_pixels_mut(image.bounds()) {
+                        let above = image.pixel_at(x as usize, y as usize);
+                        let above = Pixel {
+                            a: above.a * opacity,
+                            ..above
+                        };
+                        let composited = above.over(Pixel::from_slice(pixel));
+                        pixel.copy_from_slice(&[
+                            composited.r,
+                            composited.g,
+                            composited.b,
+                            composited.a,
+                        ]);
+                    }
+                }
+
+                LayerContent::Adjustment(node) => {
+                    let (slot, output_slot) = match (node.inputs().first(), node.outputs().first())
+                    {
+                        (Some(&slot), Some(&output_slot)) => (slot, output_slot),
+                        (None, _) => {
+                            return Err(NodeError::MissingInput {
+                                node: layer.name.clone(),
+                                slot: "",
+                            })
+                        }
+                        (_, None) => {
+                            return Err(NodeError::MissingOutput {
+                                node: layer.name.clone(),
+                                slot: "",
+                            })
+                        }
+                    };
+
+                    let mut input = HashMap::new();
+                    input.insert(slot, flattened.clone().into_data());
+                    let adjusted = node
+                        .execute(input, context)?
+                        .remove(output_slot)
+                        .map(Image::from)
+                        .ok_or_else(|| NodeError::MissingOutput {
+                            node: layer.name.clone(),
+                            slot: output_slot,
+                        })?;
+                    check_size(&layer.name, self.size, adjusted.size())?;
+
+                    for (x, y, pixel) in flattened.region_pixels_mut(adjusted.bounds()) {
+                        let below = Pixel::from_slice(pixel);
+                        let faded = below.lerp(adjusted.pixel_at(x as usize, y as usize), opacity);
+                        pixel.copy_from_slice(&[faded.r, faded.g, faded.b, faded.a]);
+                    }
+                }
+            }
+        }
+
+        Ok(flattened)
+    }
+}
+
+#[allow(dead_code)] // TODO layer panel
+fn check_size(name: &str, expected: Size, actual: Size) -> Result<(), NodeError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(NodeError::SizeMismatch {
+            node: name.to_string(),
+            a: (expected.width, expected.height),
+            b: (actual.width, actual.height),
+        })
+    }
+}
+
+#[test]
+fn desaturate_adjustment_layer() {
+    use crate::composite::nodes::Desaturate;
+
+    let mut base = Image::from_solid(3, 2, Pixel::TRANSPARENT).unwrap();
+    for y in 0..2 {
+        for x in 0..3 {
+            base.set_rgba(x, y, x as f32 / 2.0, y as f32, 0.25, 1.0);
+        }
+    }
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut spot = Image::from_solid(3, 2, Pixel::TRANSPARENT).unwrap();
+    spot.set_pixel(1, 1, red);
+
+    let mut stack = LayerStack::new(3, 2);
+    stack.push(Layer::pixels("base", base.clone()));
+    stack.push(Layer::pixels("spot", spot));
+    stack.push(Layer::adjustment(
+        "desaturate",
+        Box::new(Desaturate::new(1.0)),
+    ));
+
+    let flattened = stack.flatten(&EvalContext::default()).unwrap();
+    for y in 0..2 {
+        for x in 0..3 {
+            let pixel = flattened.pixel_at(x, y);
+            assert!((pixel.r - pixel.g).abs() < 1e-6, "at {}, {}", x, y);
+            assert!((pixel.g - pixel.b).abs() < 1e-6, "at {}, {}", x, y);
+            assert_eq!(1.0, pixel.a);
+        }
+    }
+    assert!((flattened.pixel_at(1, 1).r - red.luminance()).abs() < 1e-6);
+
+    // the layers underneath are untouched, and hiding the adjustment brings the color back
+    stack.layers_mut()[2].visible = false;
+    let flattened = stack.flatten(&EvalContext::default()).unwrap();
+    assert_eq!(base.pixel_at(2, 0), flattened.pixel_at(2, 0));
+    assert_eq!(red, flattened.pixel_at(1, 1));
+
+    assert!(matches!(
+        LayerStack::new(0, 2).flatten(&EvalContext::default()),
+        Err(NodeError::InvalidProperty { .. })
+    ));
+}
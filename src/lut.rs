@@ -0,0 +1,191 @@
+//! 3D color lookup tables in the `.cube` format, as used by grading software.
+
+use crate::{image::Pixel, Context, Result};
+
+use anyhow::{anyhow, bail};
+
+use std::path::Path;
+
+/// A 3D color lookup table, mapping each RGB color to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    /// Number of entries along each axis.
+    size: usize,
+    /// Input colors are clamped to this range, which is stretched over the table.
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// `size`³ output colors, red changing fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub fn load(path: impl AsRef<Path>) -> Result<CubeLut> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        CubeLut::parse(&text).with_context(|| format!("Couldn't parse {}", path.display()))
+    }
+
+    /// Parse the contents of a `.cube` file.
+    pub fn parse(text: &str) -> Result<CubeLut> {
+        let mut size = None;
+        let mut domain_min = [0.; 3];
+        let mut domain_max = [1.; 3];
+        let mut table = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap();
+            let context = || format!("on line {}", number + 1);
+
+            match keyword {
+                // the title is quoted and can have spaces in it, and nobody needs it
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let n = words
+                        .next()
+                        .ok_or_else(|| anyhow!("LUT_3D_SIZE without a size"))
+                        .and_then(|n| Ok(n.parse::<usize>()?))
+                        .with_context(context)?;
+                    if n < 2 {
+                        bail!("LUT_3D_SIZE {} is too small {}", n, context());
+                    }
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => bail!("1D LUTs aren't supported"),
+                "DOMAIN_MIN" => domain_min = parse_triple(words).with_context(context)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(words).with_context(context)?,
+                _ => {
+                    let entry = parse_triple(line.split_whitespace()).with_context(context)?;
+                    table.push(entry);
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("No LUT_3D_SIZE"))?;
+        if table.len() != size * size * size {
+            bail!(
+                "Expected {} entries for a LUT of size {}, found {}",
+                size * size * size,
+                size,
+                table.len()
+            );
+        }
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            bail!("Empty domain {:?} to {:?}", domain_min, domain_max);
+        }
+
+        Ok(CubeLut {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Look up a color with trilinear interpolation between the nearest entries. Alpha is left
+    /// alone.
+    pub fn apply(&self, pixel: Pixel) -> Pixel {
+        let max_index = (self.size - 1) as f32;
+        let position = |value: f32, i: usize| {
+            let t = (value - self.domain_min[i]) / (self.domain_max[i] - self.domain_min[i]);
+            t.clamp(0., 1.) * max_index
+        };
+
+        let (r, g, b) = (
+            position(pixel.r, 0),
+            position(pixel.g, 1),
+            position(pixel.b, 2),
+        );
+        let (r0, g0, b0) = (r.floor() as usize, g.floor() as usize, b.floor() as usize);
+        let (r1, g1, b1) = (
+            (r0 + 1).min(self.size - 1),
+            (g0 + 1).min(self.size - 1),
+            (b0 + 1).min(self.size - 1),
+        );
+        let (tr, tg, tb) = (r - r0 as f32, g - g0 as f32, b - b0 as f32);
+
+        let at = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+            ]
+        };
+
+        let c00 = lerp(at(r0, g0, b0), at(r1, g0, b0), tr);
+        let c10 = lerp(at(r0, g1, b0), at(r1, g1, b0), tr);
+        let c01 = lerp(at(r0, g0, b1), at(r1, g0, b1), tr);
+        let c11 = lerp(at(r0, g1, b1), at(r1, g1, b1), tr);
+        let [r, g, b] = lerp(lerp(c00, c10, tg), lerp(c01, c11, tg), tb);
+
+        Pixel {
+            r,
+            g,
+            b,
+            a: pixel.a,
+        }
+    }
+}
+
+fn parse_triple<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<[f32; 3]> {
+    let mut triple = [0.; 3];
+    for value in triple.iter_mut() {
+        *value = words
+            .next()
+            .ok_or_else(|| anyhow!("Expected three numbers"))?
+            .parse()?;
+    }
+
+    if words.next().is_some() {
+        bail!("Expected three numbers");
+    }
+
+    Ok(triple)
+}
+
+/// Write out a `.cube` LUT of the given size, computing each entry with `f`.
+#[cfg(test)]
+pub fn cube_text(size: usize, f: impl Fn(f32, f32, f32) -> [f32; 3]) -> String {
+    let mut text = format!("TITLE \"test\"\n# comment\nLUT_3D_SIZE {}\n", size);
+    let step = |i| i as f32 / (size - 1) as f32;
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                let [r, g, b] = f(step(r), step(g), step(b));
+                text.push_str(&format!("{} {} {}\n", r, g, b));
+            }
+        }
+    }
+    text
+}
+
+#[test]
+fn parse_cube_header() {
+    let lut = CubeLut::parse(&format!(
+        "DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n{}",
+        cube_text(2, |r, g, b| [r, g, b])
+    ))
+    .unwrap();
+    assert_eq!(2, lut.size);
+    assert_eq!([2.; 3], lut.domain_max);
+
+    // values are scaled to the domain, and clamped to it
+    let pixel = lut.apply(Pixel {
+        r: 1.0,
+        g: 3.0,
+        b: -1.0,
+        a: 0.5,
+    });
+    assert_eq!((0.5, 1.0, 0.0, 0.5), (pixel.r, pixel.g, pixel.b, pixel.a));
+
+    assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    assert!(CubeLut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0\n").is_err());
+}
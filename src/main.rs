@@ -7,14 +7,100 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use wgpu::SwapChainError;
+use wgpu::{PowerPreference, SwapChainError};
 
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+mod autosave;
 mod backend_wgpu;
 mod composite;
+mod density;
+mod geometry;
+mod history;
 mod image;
+mod io_worker;
+mod layers;
+mod lut;
+mod minimap;
+mod noise;
+mod playback;
+mod selection;
+mod text;
 mod texture;
+mod tool;
+mod view;
+
+use crate::{
+    autosave::AutoSave,
+    backend_wgpu::{features::PipelineConfig, WgpuBackend},
+    composite::{EvalContext, NodeGraph, Port, Quality},
+    density::Density,
+    geometry::{Grid, Point, Rect},
+    history::History,
+    image::{Alpha, Connectivity, Image, Pixel},
+    io_worker::{IoWorker, Job, JobId, JobResult},
+    minimap::Minimap,
+    playback::Playback,
+    selection::{FloatingSelection, Selection},
+    tool::{Brush, BrushTool, Keymap, Palette, PointerTool, Symmetry, Tool, ToolCtx},
+    view::{View, ZoomAnimation},
+};
+
+// which palette slot a number key is for, in the order they are on the keyboard, so 0 is last
+fn palette_slot(key: VirtualKeyCode) -> Option<usize> {
+    let keys = [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+        VirtualKeyCode::Key0,
+    ];
+    keys.iter().position(|&slot_key| slot_key == key)
+}
+
+// a color as hex, like #ff8000ff
+fn color_name(color: Pixel) -> String {
+    let [r, g, b, a] =
+        [color.r, color.g, color.b, color.a].map(|c| (c.clamp(0., 1.) * 255.).round());
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        r as u8, g as u8, b as u8, a as u8
+    )
+}
 
-use crate::{backend_wgpu::WgpuBackend, image::Pixel};
+// where Ctrl+Shift+S saves to, until there's a file dialog
+const EXPORT_PATH: &str = "export.png";
+// the size of the blank canvas there is when nothing is opened
+const NEW_CANVAS_SIZE: (u32, u32) = (512, 512);
+
+// about half of an 8 bit step either way, so colors that were the same before they were saved
+// still count as the same
+const DEFAULT_REPLACE_TOLERANCE: f32 = 0.5 / 255.;
+const DEFAULT_FILL_TOLERANCE: f32 = 0.5 / 255.;
+// how far ctrl and the arrow keys turn the floating selection, in degrees
+const ROTATE_STEP: f32 = 15.;
+// and how far comma and period turn the view
+const VIEW_ROTATE_STEP: f32 = 15.;
+const FINE_VIEW_ROTATE_STEP: f32 = 5.;
+// how far a trackpad has to scroll to zoom as much as one notch of a wheel
+const PIXELS_PER_ZOOM_STEP: f32 = 50.;
+// how much room fitting the canvas in the window leaves around it, in window pixels
+const FIT_MARGIN: f32 = 16.;
+// how close to the edge of the window dragging starts panning the view, and how fast it pans at
+// the very edge, in window pixels and window pixels per second
+const AUTOSCROLL_EDGE: f32 = 32.;
+const AUTOSCROLL_SPEED: f32 = 600.;
+// the least time between changes to the window title
+const TITLE_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 struct Mouse {
@@ -24,20 +110,142 @@ struct Mouse {
     right: ElementState,
 }
 
+// what the pointer did, to pass on to a tool
+#[derive(Debug, Clone, Copy)]
+enum Pointer {
+    // with where to draw a straight line from, when shift-clicking
+    Down { line_from: Option<Point> },
+    Move,
+    Up,
+}
+
+// the floating selection being dragged around by the move tool
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    // the canvas pixel the drag started on
+    grab: Point,
+    // where the floating pixels were when the drag started
+    from: Point,
+}
+
+// what became of a frame that couldn't be drawn
+#[derive(Debug, PartialEq)]
+enum RenderFailure {
+    // the swapchain was lost and has been recreated, the next frame should be fine
+    Recreated,
+    // worth reporting, but the next frame might still work
+    Skipped(String),
+    // no point going on
+    Fatal(String),
+}
+
+// where the floating selection was lifted from, to put it back if it's cancelled
+#[derive(Debug, Clone)]
+struct Lifted {
+    from: Point,
+    // what was cut out of the canvas, or nothing if the pixels were copied
+    cut: Option<Selection>,
+}
+
 #[allow(dead_code)]
 struct State {
     size: PhysicalSize<u32>,
     mouse: Mouse,
-    zoom: f32,
+    view: View,
+    // whether the scroll wheel zooms the axes separately
+    independent_zoom: bool,
+    // whether zooming eases into the new zoom instead of snapping to it
+    smooth_zoom: bool,
+    zoom_animation: Option<ZoomAnimation>,
+    // when the view last panned from dragging near the edge of the window, while it's doing that
+    autoscroll_at: Option<Instant>,
+    show_minimap: bool,
+    // whether the left button was pressed on the minimap, so dragging moves the view
+    navigating: bool,
+    // the title the window has and when it got it, so it isn't set on every little move
+    shown_title: Option<(String, Instant)>,
+    modifiers: ModifiersState,
+    tool: Tool,
+    brush: Brush,
+    // what the left button does, if the active tool has been moved out of here
+    pointer_tool: Option<Box<dyn PointerTool>>,
+    // what the right button does
+    eraser: BrushTool,
+    symmetry: Symmetry,
+    // where the symmetry axes are, in half pixels. the center of the canvas to start with
+    symmetry_axis: Point,
+    // what the brush paints with. straight alpha, like the canvas
+    primary: Pixel,
+    // swapped with the primary color to paint with it
+    secondary: Pixel,
+    palette: Palette,
+    // how far off the secondary color a pixel can be and still be replaced, in each channel
+    replace_tolerance: f32,
+    // how far off the clicked pixel's color a pixel can be and still be part of the region the
+    // wand selects or the bucket fills, and which of its neighbors it spreads to
+    fill_tolerance: f32,
+    connectivity: Connectivity,
+    keymap: Keymap,
+    // the canvas pixel the last brush stroke ended on, for shift-clicking a straight line from it
+    last_point: Option<Point>,
+    canvas: Image,
+    /// How big the canvas is meant to be printed, from the file it was loaded from. Kept in the
+    /// recovery file too.
+    density: Option<Density>,
+    floating: Option<FloatingSelection>,
+    /// The part of the canvas that painting is limited to. Never empty, `None` instead.
+    selection: Option<Selection>,
+    // where the selection being dragged out started, on a pixel corner
+    selection_anchor: Option<Point>,
+    // the path of the lasso selection being dragged out, in canvas pixels
+    lasso: Option<Vec<(f32, f32)>>,
+    grid: Grid,
+    // whether selections and the ends of straight lines snap to the grid
+    snapping: bool,
+    moving: Option<Move>,
+    lifted: Option<Lifted>,
+    history: History,
+    // the node graph being previewed, and the output that's shown. the only way to get one so
+    // far is to restore it from the recovery files
+    graph: Option<(NodeGraph, Port)>,
+    // the output of the graph at the current playback time, shown instead of the canvas
+    preview: Option<Image>,
+    playback: Playback,
+    autosave: Option<AutoSave>,
+    // the recovery files from a session that didn't exit cleanly are waiting to be restored,
+    // until they're overwritten by the next autosave
+    recovery_offered: bool,
+    io: Option<IoWorker>,
     // *perhaps* eventually have my own cpu backend? not sure
     wgpu_backend: Option<WgpuBackend>,
     cpu_backend: Option<()>,
 }
 
 impl State {
-    async fn new(window: &Window) -> Result<Self> {
-        let size = window.inner_size();
+    // starts on a blank canvas, with `open` loading in the background to replace it
+    async fn new(
+        window: &Window,
+        config: PipelineConfig,
+        io: IoWorker,
+        open: Option<PathBuf>,
+    ) -> Result<Self> {
+        let (width, height) = NEW_CANVAS_SIZE;
+        let mut state =
+            State::without_backend(window.inner_size(), Image::transparent(width, height)?);
+        state.autosave = Some(AutoSave::new(
+            AutoSave::default_path(),
+            AutoSave::DEFAULT_INTERVAL,
+            Instant::now(),
+        ));
+        state.io = Some(io);
+        state.wgpu_backend = Some(WgpuBackend::new(window, config, &state.canvas).await?);
+        if let Some(path) = open {
+            state.load(path);
+        }
+        Ok(state)
+    }
 
+    fn without_backend(size: PhysicalSize<u32>, canvas: Image) -> Self {
         let mouse = Mouse {
             x: size.width as f32 / 2.,
             y: size.height as f32 / 2.,
@@ -45,17 +253,665 @@ impl State {
             right: ElementState::Released,
         };
 
-        let zoom = 1.0;
-
-        let wgpu_backend = Some(WgpuBackend::new(window).await?);
-
-        Ok(Self {
+        Self {
             size,
             mouse,
-            zoom,
-            wgpu_backend,
+            view: View::default(),
+            independent_zoom: false,
+            autoscroll_at: None,
+            show_minimap: true,
+            navigating: false,
+            shown_title: None,
+            smooth_zoom: true,
+            zoom_animation: None,
+            modifiers: ModifiersState::empty(),
+            tool: Tool::Brush,
+            brush: Brush::default(),
+            pointer_tool: Tool::Brush.pointer_tool(),
+            eraser: BrushTool::new(true),
+            symmetry: Symmetry::Off,
+            symmetry_axis: Point::new(canvas.width() as i32, canvas.height() as i32),
+            primary: Pixel {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+            },
+            secondary: Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            palette: Palette::default(),
+            replace_tolerance: DEFAULT_REPLACE_TOLERANCE,
+            fill_tolerance: DEFAULT_FILL_TOLERANCE,
+            connectivity: Connectivity::Four,
+            keymap: Keymap::default(),
+            last_point: None,
+            canvas,
+            density: None,
+            floating: None,
+            selection: None,
+            selection_anchor: None,
+            lasso: None,
+            grid: Grid::default(),
+            snapping: false,
+            moving: None,
+            lifted: None,
+            history: History::default(),
+            graph: None,
+            preview: None,
+            playback: Playback::default(),
+            autosave: None,
+            recovery_offered: false,
+            io: None,
+            wgpu_backend: None,
             cpu_backend: None,
-        })
+        }
+    }
+
+    fn active_tool(&self) -> Tool {
+        self.tool
+    }
+
+    // switching tools abandons whatever the old tool was in the middle of
+    fn set_active_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.pointer_tool = tool.pointer_tool();
+        self.moving = None;
+    }
+
+    fn mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        match button {
+            // the minimap takes clicks on it for itself, and the drags that start there
+            MouseButton::Left
+                if self.navigating || (state == ElementState::Pressed && self.on_minimap()) =>
+            {
+                self.navigating = state == ElementState::Pressed;
+                if self.navigating {
+                    self.navigate();
+                }
+            }
+            MouseButton::Left => {
+                self.mouse.left = state;
+                let point = (self.mouse.x, self.mouse.y);
+                match state {
+                    ElementState::Pressed => {
+                        self.history.begin(&self.canvas);
+                        let line_from = self.last_point.filter(|_| self.modifiers.shift());
+                        if self.picking() {
+                            self.pick();
+                        } else if self.pointer_tool.is_some() {
+                            self.pointer(button, Pointer::Down { line_from });
+                        } else if self.active_tool() == Tool::Select {
+                            self.selection_anchor = Some(self.canvas_corner(point));
+                            self.select();
+                        } else if self.active_tool() == Tool::Lasso {
+                            self.lasso = Some(vec![self.canvas_position(point)]);
+                        } else if self.active_tool() == Tool::Wand {
+                            self.wand();
+                        } else if self.active_tool() == Tool::Bucket {
+                            self.fill();
+                        } else if self.active_tool() == Tool::Move {
+                            self.pick_up();
+                        }
+                    }
+                    ElementState::Released => {
+                        self.pointer(button, Pointer::Up);
+                        if let Some(end) = self.pointer_tool.as_ref().and_then(|tool| tool.ended())
+                        {
+                            self.last_point = Some(end);
+                        }
+                        self.selection_anchor = None;
+                        self.close_lasso();
+                        self.drop();
+                        self.end_edit();
+                    }
+                }
+            }
+            // the right button always erases, with a fresh stroke of its own
+            MouseButton::Right => {
+                self.mouse.right = state;
+                match state {
+                    ElementState::Pressed => {
+                        self.history.begin(&self.canvas);
+                        self.pointer(button, Pointer::Down { line_from: None });
+                    }
+                    ElementState::Released => {
+                        self.pointer(button, Pointer::Up);
+                        self.end_edit();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        if key == VirtualKeyCode::S && self.modifiers.ctrl() && self.modifiers.shift() {
+            self.export(std::path::PathBuf::from(EXPORT_PATH));
+            return true;
+        }
+
+        if self.modifiers.ctrl() {
+            let undo = match key {
+                VirtualKeyCode::Z => Some(!self.modifiers.shift()),
+                VirtualKeyCode::Y => Some(false),
+                _ => None,
+            };
+            if let Some(undo) = undo {
+                return if undo { self.undo() } else { self.redo() };
+            }
+        }
+
+        if key == VirtualKeyCode::O && self.modifiers.ctrl() && self.modifiers.shift() {
+            return self.restore();
+        }
+
+        if key == VirtualKeyCode::R && self.modifiers.ctrl() {
+            return self.replace_color(self.modifiers.shift()) > 0;
+        }
+
+        if key == VirtualKeyCode::G && self.modifiers.ctrl() {
+            self.snapping = !self.snapping;
+            return true;
+        }
+
+        if key == VirtualKeyCode::T && self.modifiers.ctrl() {
+            return self.float_selection();
+        }
+
+        if key == VirtualKeyCode::Escape && self.lifted.is_some() {
+            self.cancel_floating();
+            self.end_edit();
+            return true;
+        }
+
+        let deselect = (key == VirtualKeyCode::D && self.modifiers.ctrl())
+            || (key == VirtualKeyCode::Escape && self.selection.is_some());
+        if deselect {
+            self.selection = None;
+            return true;
+        }
+
+        if let Some(tool) = self.keymap.tool_for(key) {
+            self.set_active_tool(tool);
+            return true;
+        }
+
+        // play the graph, or pause it and go back to showing the canvas
+        if key == VirtualKeyCode::Space && self.graph.is_some() {
+            self.playback.toggle(Instant::now());
+            if !self.playback.is_playing() {
+                self.preview = None;
+            }
+            return true;
+        }
+
+        // the arrow keys nudge the floating pixels, or rotate and scale them with ctrl held. the
+        // rest of the keys do what they always do
+        if let Some(floating) = &mut self.floating {
+            let ctrl = self.modifiers.ctrl();
+            let mut handled = true;
+            match key {
+                VirtualKeyCode::Left if ctrl => floating.rotate_by(-ROTATE_STEP),
+                VirtualKeyCode::Right if ctrl => floating.rotate_by(ROTATE_STEP),
+                VirtualKeyCode::Up if ctrl => floating.scale_by(1),
+                VirtualKeyCode::Down if ctrl => floating.scale_by(-1),
+                VirtualKeyCode::Left => floating.nudge(-1, 0),
+                VirtualKeyCode::Right => floating.nudge(1, 0),
+                VirtualKeyCode::Up => floating.nudge(0, -1),
+                VirtualKeyCode::Down => floating.nudge(0, 1),
+                VirtualKeyCode::N => floating.set_filter(floating.filter().next()),
+                VirtualKeyCode::Return => {
+                    self.history.begin(&self.canvas);
+                    self.follow_floating();
+                    self.commit_floating();
+                    self.end_edit();
+                }
+                _ => handled = false,
+            }
+
+            if handled {
+                return true;
+            }
+        }
+
+        // alt and 0 fits the canvas in the window, and alt and 1 shows it at its actual size
+        if self.modifiers.alt() && matches!(key, VirtualKeyCode::Key0 | VirtualKeyCode::Key1) {
+            let (width, height) = (self.canvas.width(), self.canvas.height());
+            let mut target = self.view;
+            if key == VirtualKeyCode::Key0 {
+                target.fit(width, height, self.size, FIT_MARGIN);
+            } else {
+                target.actual_pixels(width, height, self.size);
+            }
+            self.animate_view_to(target);
+            return true;
+        }
+
+        // the number keys pick a color from the palette, or put the primary color there with
+        // shift held, or set the brush opacity in tenths with ctrl held
+        if let Some(slot) = palette_slot(key) {
+            if self.modifiers.ctrl() {
+                self.brush.opacity = (slot + 1) as f32 / 10.;
+            } else if self.modifiers.shift() {
+                self.palette.set(slot, self.primary);
+            } else {
+                self.primary = self.palette.get(slot);
+            }
+            return true;
+        }
+
+        match key {
+            VirtualKeyCode::Comma => self.view.rotate_by(-self.view_rotate_step()),
+            VirtualKeyCode::Period => self.view.rotate_by(self.view_rotate_step()),
+            VirtualKeyCode::R => self.view.rotation = 0.0,
+            VirtualKeyCode::X => std::mem::swap(&mut self.primary, &mut self.secondary),
+            VirtualKeyCode::Tab => self.show_minimap = !self.show_minimap,
+            VirtualKeyCode::LBracket => self.brush.shrink(),
+            VirtualKeyCode::RBracket => self.brush.grow(),
+            VirtualKeyCode::Y => self.symmetry = self.symmetry.next(),
+            VirtualKeyCode::P => self.brush.pixel_perfect = !self.brush.pixel_perfect,
+            VirtualKeyCode::D => self.brush.dither = self.brush.dither.next(),
+            VirtualKeyCode::S => self.brush.smoothing = self.brush.smoothing.next(),
+            VirtualKeyCode::H => {
+                let falloff = self.brush.falloff().next();
+                self.brush.set_falloff(falloff);
+            }
+            VirtualKeyCode::Z if self.modifiers.shift() => {
+                self.view.integer_zoom = !self.view.integer_zoom;
+            }
+            VirtualKeyCode::Z => {
+                self.independent_zoom = !self.independent_zoom;
+                if !self.independent_zoom {
+                    self.zoom_animation = None;
+                    self.view.set_zoom(self.view.zoom());
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    // how far comma and period turn the view, in finer steps with shift held
+    fn view_rotate_step(&self) -> f32 {
+        if self.modifiers.shift() {
+            FINE_VIEW_ROTATE_STEP
+        } else {
+            VIEW_ROTATE_STEP
+        }
+    }
+
+    // the pointer moved, or the view moved under it, returning whether a button is down
+    fn drag(&mut self) -> bool {
+        if self.mouse.left == ElementState::Pressed && self.picking() {
+            self.pick();
+        } else if self.mouse.left == ElementState::Pressed && self.selection_anchor.is_some() {
+            self.select();
+        } else if self.mouse.left == ElementState::Pressed && self.lasso.is_some() {
+            let point = self.canvas_position((self.mouse.x, self.mouse.y));
+            if let Some(lasso) = &mut self.lasso {
+                lasso.push(point);
+            }
+        } else if self.mouse.left == ElementState::Pressed && self.active_tool() == Tool::Move {
+            // which does nothing if there's nothing being moved
+            self.drag_floating();
+        } else if self.mouse.left == ElementState::Pressed {
+            self.pointer(MouseButton::Left, Pointer::Move);
+        }
+        if self.mouse.right == ElementState::Pressed {
+            self.pointer(MouseButton::Right, Pointer::Move);
+        }
+        self.mouse.left == ElementState::Pressed || self.mouse.right == ElementState::Pressed
+    }
+
+    // how fast the view pans while dragging near the edges of the window, in window pixels per
+    // second, faster the closer the pointer is
+    fn autoscroll_velocity(&self) -> (f32, f32) {
+        let dragging =
+            self.mouse.left == ElementState::Pressed || self.mouse.right == ElementState::Pressed;
+        if !dragging || self.navigating {
+            return (0., 0.);
+        }
+
+        // towards the low edge pans the canvas forward, so more of what's past that edge shows
+        let speed = |position: f32, length: u32| {
+            let low = (AUTOSCROLL_EDGE - position) / AUTOSCROLL_EDGE;
+            let high = (position - (length as f32 - AUTOSCROLL_EDGE)) / AUTOSCROLL_EDGE;
+            (low.clamp(0., 1.) - high.clamp(0., 1.)) * AUTOSCROLL_SPEED
+        };
+        (
+            speed(self.mouse.x, self.size.width),
+            speed(self.mouse.y, self.size.height),
+        )
+    }
+
+    // pan towards the edge being dragged at, and keep the drag going where the pointer ends up on
+    // the canvas. returns whether it's still scrolling
+    fn autoscroll(&mut self, now: Instant) -> bool {
+        let last = match self.autoscroll_at {
+            Some(last) => last,
+            None => return false,
+        };
+
+        let (speed_x, speed_y) = self.autoscroll_velocity();
+        if (speed_x, speed_y) == (0., 0.) {
+            self.autoscroll_at = None;
+            return false;
+        }
+
+        let seconds = now.saturating_duration_since(last).as_secs_f32();
+        self.view.pan_by(speed_x * seconds, speed_y * seconds);
+        self.autoscroll_at = Some(now);
+        self.drag();
+        true
+    }
+
+    fn minimap(&self) -> Option<Minimap> {
+        if self.show_minimap {
+            Minimap::new(&self.view, self.canvas.size(), self.size)
+        } else {
+            None
+        }
+    }
+
+    fn on_minimap(&self) -> bool {
+        self.minimap()
+            .is_some_and(|minimap| minimap.contains(self.mouse.x, self.mouse.y))
+    }
+
+    // move the view to wherever the pointer is on the minimap
+    fn navigate(&mut self) {
+        if let Some(minimap) = self.minimap() {
+            let (x, y) = (self.mouse.x, self.mouse.y);
+            minimap.pan_to(&mut self.view, self.canvas.size(), self.size, x, y);
+        }
+    }
+
+    // the canvas changed
+    fn mark_dirty(&mut self) {
+        if let Some(autosave) = &mut self.autosave {
+            autosave.mark_dirty();
+        }
+    }
+
+    // write the recovery files if it's time, returning when to check again
+    fn autosave(&mut self) -> Option<Instant> {
+        let autosave = self.autosave.as_mut()?;
+        if let Some(io) = &mut self.io {
+            let graph = self.graph.as_ref().map(|(graph, output)| (graph, output));
+            if autosave.tick(&self.canvas, self.density, graph, Instant::now(), io)
+                && self.recovery_offered
+            {
+                log::info!("the recovery files from last time were overwritten");
+                self.recovery_offered = false;
+            }
+        }
+        autosave.deadline()
+    }
+
+    // load the recovery files from a session that didn't exit cleanly. they're deleted once
+    // they've been read back in
+    fn restore(&mut self) -> bool {
+        let (canvas_path, graph_path) = match &self.autosave {
+            Some(autosave) if autosave.has_recovery() => {
+                (autosave.canvas_path().to_path_buf(), autosave.graph_path())
+            }
+            _ => return false,
+        };
+
+        self.recovery_offered = false;
+        if self.load(canvas_path).is_none() {
+            return false;
+        }
+        if graph_path.exists() {
+            if let Some(io) = &mut self.io {
+                io.submit(Job::LoadText(graph_path));
+            }
+        }
+        true
+    }
+
+    // the window is closing normally, so nothing needs recovering next time. the recovery files
+    // are kept if they were never restored
+    fn exit(&mut self) {
+        // finish any saves first, so they don't write the recovery files back
+        self.io = None;
+        if let Some(autosave) = &self.autosave {
+            if !self.recovery_offered {
+                if let Err(e) = autosave.discard() {
+                    log::warn!("{:#}", e);
+                }
+            }
+        }
+    }
+
+    // pick up whatever the io worker finished, returning true if the canvas changed
+    fn poll_io(&mut self) -> bool {
+        let results = match &self.io {
+            Some(io) => io.poll(),
+            None => return false,
+        };
+
+        let mut changed = false;
+        for (_, result) in results {
+            match result {
+                JobResult::Saved(path, Ok(())) => log::info!("saved {}", path.display()),
+                JobResult::Loaded(path, Ok((image, density))) => {
+                    if self.open(&path, image, density) {
+                        self.restored(&path);
+                        changed = true;
+                    }
+                }
+                // the only text that's loaded is a recovered graph
+                JobResult::LoadedText(path, Ok(json)) => match autosave::graph_from_json(&json) {
+                    Ok(graph) => {
+                        log::info!("restored the graph from {}", path.display());
+                        self.graph = Some(graph);
+                        self.preview = None;
+                        self.restored(&path);
+                        changed = true;
+                    }
+                    Err(e) => log::warn!("{:#}", e),
+                },
+                JobResult::Saved(_, Err(e))
+                | JobResult::Loaded(_, Err(e))
+                | JobResult::LoadedText(_, Err(e)) => log::warn!("{:#}", e),
+                JobResult::Thumbnail(_) => {}
+            }
+        }
+
+        changed
+    }
+
+    // delete a recovery file once it's been read back in
+    fn restored(&mut self, path: &Path) {
+        if let Some(autosave) = &mut self.autosave {
+            if let Err(e) = autosave.restored(path) {
+                log::warn!("{:#}", e);
+            }
+        }
+    }
+
+    // load an image in the background, to replace the canvas once it's ready
+    fn load(&mut self, path: PathBuf) -> Option<JobId> {
+        log::info!("loading {}", path.display());
+        Some(self.io.as_mut()?.submit(Job::LoadImage(path)))
+    }
+
+    // replace the canvas with an image that finished loading, returning false if it couldn't be
+    fn open(&mut self, path: &Path, canvas: Image, density: Option<Density>) -> bool {
+        log::info!(
+            "loaded {} ({}x{}{})",
+            path.display(),
+            canvas.width(),
+            canvas.height(),
+            density.map_or(String::new(), |density| format!(
+                ", {}x{} dpi",
+                density.x, density.y
+            ))
+        );
+
+        if canvas.analyze_alpha().likely_alpha() == Some(Alpha::Premultiplied) {
+            log::warn!(
+                "{} looks like it has premultiplied alpha, it might not look right",
+                path.display()
+            );
+        }
+
+        if canvas.size() != self.canvas.size() {
+            if let Some(wgpu_backend) = &mut self.wgpu_backend {
+                if let Err(e) = wgpu_backend.replace_canvas(&canvas) {
+                    log::error!("couldn't show {}: {:#}", path.display(), e);
+                    return false;
+                }
+            }
+            self.view
+                .fit(canvas.width(), canvas.height(), self.size, FIT_MARGIN);
+            self.symmetry_axis = Point::new(canvas.width() as i32, canvas.height() as i32);
+        }
+
+        // nothing from the old canvas applies to the new one, including a stroke in progress
+        self.set_active_tool(self.tool);
+        self.eraser = BrushTool::new(true);
+        self.last_point = None;
+        self.canvas = canvas;
+        self.density = density;
+        self.selection = None;
+        self.floating = None;
+        self.lifted = None;
+        self.history.clear();
+        true
+    }
+
+    // what gets exported: the part of the canvas that's selected, or under the floating selection
+    // if there is one, otherwise all of it, with the floating selection on top either way. what
+    // isn't selected around the edges of a lasso selection is left transparent
+    fn export_image(&self) -> Image {
+        let display = selection::composited(&self.canvas, self.floating.as_ref());
+        let region = self.selection.clone().or_else(|| {
+            self.floating
+                .as_ref()
+                .map(|floating| Selection::from(floating.rect()))
+        });
+        match region {
+            Some(region) => {
+                let rect = region.bounds().clamp_to(display.size());
+                let mut image = display.crop(rect);
+                for point in rect.points().filter(|&point| !region.contains(point)) {
+                    let (x, y) = (point.x - rect.left(), point.y - rect.top());
+                    image.set_pixel(x as usize, y as usize, Pixel::TRANSPARENT);
+                }
+                image
+            }
+            None => display.into_owned(),
+        }
+    }
+
+    // save the export image in the background
+    fn export(&mut self, path: std::path::PathBuf) -> Option<JobId> {
+        let image = self.export_image();
+        if image.width() == 0 || image.height() == 0 {
+            log::warn!("nothing to export, the selection is off of the canvas");
+            return None;
+        }
+
+        log::info!(
+            "exporting {}x{} to {}",
+            image.width(),
+            image.height(),
+            path.display()
+        );
+        let density = self.density;
+        Some(
+            self.io
+                .as_mut()?
+                .submit(Job::SaveImage(path, image, density)),
+        )
+    }
+
+    // finish the edit in progress once both buttons are up and nothing's floating, so it can be
+    // undone
+    fn end_edit(&mut self) {
+        if self.mouse.left == ElementState::Released
+            && self.mouse.right == ElementState::Released
+            && self.floating.is_none()
+        {
+            self.history.end(&self.canvas);
+        }
+    }
+
+    // both refuse to in the middle of something, rather than undo half of it
+    fn undo(&mut self) -> bool {
+        if self.history.editing() || self.floating.is_some() {
+            return false;
+        }
+
+        let undone = self.history.undo(&mut self.canvas);
+        if undone {
+            self.mark_dirty();
+        }
+        undone
+    }
+
+    fn redo(&mut self) -> bool {
+        if self.history.editing() || self.floating.is_some() {
+            return false;
+        }
+
+        let redone = self.history.redo(&mut self.canvas);
+        if redone {
+            self.mark_dirty();
+        }
+        redone
+    }
+
+    // replace the secondary color with the primary color everywhere in the selection, or the whole
+    // canvas if there isn't one, as one edit. pick the color to replace with the eyedropper, then
+    // swap it into the secondary color
+    fn replace_color(&mut self, keep_alpha: bool) -> usize {
+        if self.history.editing() || self.floating.is_some() {
+            return 0;
+        }
+
+        let selection = self
+            .selection
+            .clone()
+            .unwrap_or_else(|| Selection::from(self.canvas.bounds()));
+        self.history.begin(&self.canvas);
+        self.history.touch(&self.canvas, selection.bounds());
+        let replaced = self.canvas.replace_color_in(
+            &selection,
+            self.secondary,
+            self.primary,
+            self.replace_tolerance,
+            keep_alpha,
+        );
+        self.history.end(&self.canvas);
+
+        log::info!(
+            "replaced {} pixels of {} with {}",
+            replaced,
+            color_name(self.secondary),
+            color_name(self.primary)
+        );
+        if replaced > 0 {
+            self.mark_dirty();
+        }
+        replaced
+    }
+
+    // put the floating selection down for good
+    fn commit_floating(&mut self) {
+        self.lifted = None;
+        if let Some(floating) = self.floating.take() {
+            self.history.touch(&self.canvas, floating.rect());
+            floating.composite_onto(&mut self.canvas);
+            self.mark_dirty();
+        }
     }
 
     // returns true if state captured the event, false otherwise
@@ -63,113 +919,750 @@ impl State {
     fn input(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::MouseInput { state, button, .. } => {
-                match button {
-                    MouseButton::Left => self.mouse.left = *state,
-                    MouseButton::Right => self.mouse.right = *state,
-                    _ => {}
-                }
-
+                self.mouse_button(*button, *state);
                 true
             }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => self.key_pressed(*key),
             WindowEvent::CursorMoved { position, .. } => {
                 self.mouse.x = position.x as f32;
                 self.mouse.y = position.y as f32;
-                self.mouse.left == ElementState::Pressed
-                    || self.mouse.right == ElementState::Pressed
+                if self.navigating {
+                    self.navigate();
+                    return true;
+                }
+                let dragging = self.drag();
+                if dragging
+                    && self.autoscroll_at.is_none()
+                    && self.autoscroll_velocity() != (0., 0.)
+                {
+                    self.autoscroll_at = Some(Instant::now());
+                }
+                dragging
             }
             WindowEvent::MouseWheel {
                 delta: MouseScrollDelta::LineDelta(_x, y),
                 ..
             } => {
-                self.zoom = (self.zoom + y.signum()).clamp(1.0, 10.0);
+                self.zoom_by(*y);
+                true
+            }
+            // trackpads scroll in pixels rather than lines, with two fingers. that pans, or zooms
+            // smoothly with ctrl held
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(delta),
+                ..
+            } => {
+                if self.modifiers.ctrl() {
+                    self.zoom_by(delta.y as f32 / PIXELS_PER_ZOOM_STEP);
+                } else {
+                    self.view.pan_by(delta.x as f32, delta.y as f32);
+                }
                 true
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                false
+            }
             _ => false,
         }
     }
 
-    fn update(&mut self) {
-        // backend-agnostic stuff that's done slightly differently goes here
-        if let Some(wgpu_backend) = &mut self.wgpu_backend {
-            if self.mouse.left == ElementState::Pressed {
-                wgpu_backend.canvas_pipeline.canvas_image.set_pixel(
-                    40,
-                    20,
-                    Pixel {
-                        r: 1.0,
-                        g: 1.0,
-                        b: 1.0,
-                        a: 1.0,
-                    },
-                );
-            } else {
-                wgpu_backend.canvas_pipeline.canvas_image.set_pixel(
-                    40,
-                    20,
-                    Pixel {
-                        r: 1.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 1.0,
-                    },
-                );
+    // in independent zoom mode the wheel zooms horizontally, or vertically with shift held
+    fn zoom_by(&mut self, steps: f32) {
+        let step = |zoom: f32| View::step_zoom(zoom, steps);
+
+        // keep scrolling from where an animation in progress is headed, not where it is now, and
+        // let it finish panning if it was
+        let (zoom_x, zoom_y) = match &self.zoom_animation {
+            Some(animation) => animation.target(),
+            None => (self.view.zoom_x, self.view.zoom_y),
+        };
+        let pan = self
+            .zoom_animation
+            .as_ref()
+            .and_then(ZoomAnimation::target_pan);
+
+        let (zoom_x, zoom_y) = if !self.independent_zoom {
+            (step(zoom_x), step(zoom_x))
+        } else if self.modifiers.shift() {
+            (zoom_x, step(zoom_y))
+        } else {
+            (step(zoom_x), zoom_y)
+        };
+
+        if self.smooth_zoom {
+            let animation = ZoomAnimation::new(
+                &self.view,
+                zoom_x,
+                zoom_y,
+                Instant::now(),
+                ZoomAnimation::DEFAULT_DURATION,
+            );
+            self.zoom_animation = Some(match pan {
+                Some((pan_x, pan_y)) => animation.with_pan(&self.view, pan_x, pan_y),
+                None => animation,
+            });
+        } else {
+            self.view.zoom_x = zoom_x;
+            self.view.zoom_y = zoom_y;
+        }
+    }
+
+    // zoom and pan to match `target`, smoothly if zooming is smooth. an animation in progress
+    // heads for the new target from wherever it's got to
+    fn animate_view_to(&mut self, target: View) {
+        if self.smooth_zoom {
+            let animation = ZoomAnimation::new(
+                &self.view,
+                target.zoom_x,
+                target.zoom_y,
+                Instant::now(),
+                ZoomAnimation::DEFAULT_DURATION,
+            );
+            self.zoom_animation = Some(animation.with_pan(&self.view, target.pan_x, target.pan_y));
+        } else {
+            self.zoom_animation = None;
+            self.view = target;
+        }
+    }
+
+    // pinch to zoom. winit only reports pinches from version 0.27 on, as
+    // `WindowEvent::TouchpadMagnify`, so nothing calls this until it's upgraded
+    #[allow(dead_code)]
+    fn magnify(&mut self, delta: f32) {
+        // the pinch follows the fingers directly, so it takes over from any wheel zoom
+        if let Some(animation) = self.zoom_animation.take() {
+            let (zoom_x, zoom_y) = animation.target();
+            self.view.zoom_x = zoom_x;
+            self.view.zoom_y = zoom_y;
+            if let Some((pan_x, pan_y)) = animation.target_pan() {
+                self.view.pan_x = pan_x;
+                self.view.pan_y = pan_y;
             }
+        }
+        self.view.magnify(delta);
+    }
+
+    // step the zoom animation, returning whether it's still going
+    fn animate(&mut self, now: Instant) -> bool {
+        let animation = match &self.zoom_animation {
+            Some(animation) => animation,
+            None => return false,
+        };
+
+        // the view always has the zoom as it is on screen, so painting lines up with it
+        let (zoom_x, zoom_y) = animation.zoom_at(now);
+        self.view.zoom_x = zoom_x;
+        self.view.zoom_y = zoom_y;
+        if let Some((pan_x, pan_y)) = animation.pan_at(now) {
+            self.view.pan_x = pan_x;
+            self.view.pan_y = pan_y;
+        }
 
-            // and backend-specific stuff goes in these methods
-            wgpu_backend.update(&self.size, self.zoom);
+        if animation.is_finished(now) {
+            self.zoom_animation = None;
         }
+
+        self.zoom_animation.is_some()
     }
 
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        self.size = new_size;
-        if let Some(wgpu_backend) = &mut self.wgpu_backend {
-            wgpu_backend.resize(new_size);
+    // move playback along, returning whether there's a new frame to show
+    fn advance_playback(&mut self, now: Instant) -> bool {
+        if !self.playback.tick(now) {
+            return false;
         }
+
+        self.evaluate_preview();
+        true
     }
 
-    fn render(&mut self) -> Result<()> {
-        if let Some(wgpu_backend) = &mut self.wgpu_backend {
-            wgpu_backend.render(&self.size)?;
+    // fast and rough while the graph is playing or something's being dragged, and sharp once
+    // things settle down
+    fn preview_quality(&self) -> Quality {
+        let dragging =
+            self.mouse.left == ElementState::Pressed || self.mouse.right == ElementState::Pressed;
+        if self.playback.is_playing() || dragging {
+            Quality::Preview
+        } else {
+            Quality::Full
         }
+    }
 
-        Ok(())
+    // evaluate the graph at the current playback time
+    fn evaluate_preview(&mut self) {
+        let quality = self.preview_quality();
+        let (graph, output) = match &mut self.graph {
+            Some(graph) => graph,
+            None => return,
+        };
+
+        let context = EvalContext {
+            time: self.playback.time(),
+            ..graph.context(quality)
+        };
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        match graph.evaluate_with(output, &context) {
+            Ok(image) if (image.width, image.height) == (width, height) => {
+                self.preview = Some(Image::from(image));
+            }
+            // scaled back up to cover the canvas
+            Ok(image)
+                if (image.width, image.height)
+                    == (context.scale_size(width), context.scale_size(height)) =>
+            {
+                self.preview = Some(Image::from(image).resize(width, height));
+            }
+            // the preview is shown in the canvas's place, and the view and the tools all go by the
+            // canvas's size, so a differently sized output would need a new canvas, not a new
+            // texture
+            Ok(image) => log::warn!(
+                "can't preview a {}x{} graph output on a {}x{} canvas",
+                image.width,
+                image.height,
+                self.canvas.width(),
+                self.canvas.height()
+            ),
+            Err(e) => log::warn!("couldn't evaluate the graph: {}", e),
+        }
     }
-}
 
-fn main() -> Result<()> {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop)?;
-    window.set_inner_size(PhysicalSize {
-        width: 800,
-        height: 675,
-    });
+    // where a position in the window is on the canvas, in pixels, which might be off the canvas
+    fn canvas_position(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        self.view.screen_to_canvas(width, height, self.size, x, y)
+    }
 
-    let mut state = futures::executor::block_on(State::new(&window))?;
+    // the canvas pixel under a position in the window, which might be off the canvas
+    fn canvas_point(&self, position: (f32, f32)) -> Point {
+        let (x, y) = self.canvas_position(position);
+        Point::new(x.floor() as i32, y.floor() as i32)
+    }
+
+    // the pixel corner nearest a position in the window, or the nearest grid intersection when
+    // snapping, which might be off the canvas
+    fn canvas_corner(&self, position: (f32, f32)) -> Point {
+        let (x, y) = self.canvas_position(position);
+        let corner = Point::new(x.round() as i32, y.round() as i32);
+        match self.snap_grid() {
+            Some(grid) => grid.snap(corner),
+            None => corner,
+        }
+    }
+
+    // the grid to snap to, if snapping is on
+    fn snap_grid(&self) -> Option<Grid> {
+        Some(self.grid).filter(|_| self.snapping)
+    }
+
+    // select from where the selection drag started to the cursor
+    fn select(&mut self) {
+        let anchor = match self.selection_anchor {
+            Some(anchor) => anchor,
+            None => return,
+        };
+
+        let corner = self.canvas_corner((self.mouse.x, self.mouse.y));
+        let (left, top) = (anchor.x.min(corner.x), anchor.y.min(corner.y));
+        let (right, bottom) = (anchor.x.max(corner.x), anchor.y.max(corner.y));
+        let rect = Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+            .clamp_to(self.canvas.size());
+        self.selection = if rect.is_empty() {
+            None
+        } else {
+            Some(Selection::from(rect))
+        };
+    }
+
+    // select everything inside of the lasso path, closing it back to where it started
+    fn close_lasso(&mut self) {
+        if let Some(path) = self.lasso.take() {
+            self.selection = Selection::lasso(&path, self.canvas.size());
+        }
+    }
+
+    // select the region of similar color around the pixel under the cursor, adding it to the
+    // selection with shift held or taking it away with alt. does nothing off the canvas
+    fn wand(&mut self) {
+        let point = self.canvas_point((self.mouse.x, self.mouse.y));
+        if !self.canvas.bounds().contains(point) {
+            return;
+        }
+
+        let region = self.canvas.region(
+            point.x as usize,
+            point.y as usize,
+            self.fill_tolerance,
+            self.connectivity,
+        );
+        let region = match Selection::from_mask(self.canvas.size(), &region) {
+            Some(region) => region,
+            None => return,
+        };
+        self.selection = match self.selection.take() {
+            Some(selection) if self.modifiers.shift() => Some(selection.union(&region)),
+            Some(selection) if self.modifiers.alt() => selection.subtract(&region),
+            None if self.modifiers.alt() => None,
+            _ => Some(region),
+        };
+    }
+
+    // fill the region of similar color around the pixel under the cursor with the primary color,
+    // only inside of the selection if there is one. does nothing off the canvas
+    fn fill(&mut self) {
+        let point = self.canvas_point((self.mouse.x, self.mouse.y));
+        if !self.canvas.bounds().contains(point) {
+            return;
+        }
+
+        let region = self.canvas.region(
+            point.x as usize,
+            point.y as usize,
+            self.fill_tolerance,
+            self.connectivity,
+        );
+        let region = match Selection::from_mask(self.canvas.size(), &region) {
+            Some(region) => region,
+            None => return,
+        };
+
+        self.history.touch(&self.canvas, region.bounds());
+        let mut filled = 0;
+        for point in region.points() {
+            if let Some(selection) = &self.selection {
+                if !selection.contains(point) {
+                    continue;
+                }
+            }
+            self.canvas
+                .set_pixel(point.x as usize, point.y as usize, self.primary);
+            filled += 1;
+        }
+
+        if filled > 0 {
+            self.mark_dirty();
+        }
+    }
+
+    // float the selected pixels under the cursor to be dragged around, cutting them out of the
+    // canvas, or copying them with ctrl held. does nothing without a selection
+    fn pick_up(&mut self) {
+        // only one thing floats at a time
+        self.follow_floating();
+        self.commit_floating();
+
+        let selection = match &self.selection {
+            Some(selection) => selection.clone(),
+            None => return,
+        };
+        let (floating, cut) = if self.modifiers.ctrl() {
+            (FloatingSelection::copy(&self.canvas, &selection), None)
+        } else {
+            self.history.touch(&self.canvas, selection.bounds());
+            (
+                FloatingSelection::lift(&mut self.canvas, &selection),
+                Some(selection),
+            )
+        };
+
+        self.moving = Some(Move {
+            grab: self.canvas_point((self.mouse.x, self.mouse.y)),
+            from: floating.position,
+        });
+        self.lifted = Some(Lifted {
+            from: floating.position,
+            cut,
+        });
+        self.floating = Some(floating);
+        self.mark_dirty();
+    }
+
+    // cut the selection out of the canvas to float above it, where it stays to be nudged, scaled,
+    // and rotated until it's committed with return or put back with escape, all as one edit
+    fn float_selection(&mut self) -> bool {
+        let selection = match &self.selection {
+            Some(selection) if self.floating.is_none() => selection.clone(),
+            _ => return false,
+        };
+
+        self.history.begin(&self.canvas);
+        self.history.touch(&self.canvas, selection.bounds());
+        let floating = FloatingSelection::lift(&mut self.canvas, &selection);
+        self.lifted = Some(Lifted {
+            from: floating.position,
+            cut: Some(selection),
+        });
+        self.floating = Some(floating);
+        self.mark_dirty();
+        true
+    }
+
+    // keep the floating pixels where they were relative to the cursor when they were picked up
+    fn drag_floating(&mut self) {
+        let Move { grab, from } = match self.moving {
+            Some(moving) => moving,
+            None => return,
+        };
+
+        let cursor = self.canvas_point((self.mouse.x, self.mouse.y));
+        if let Some(floating) = &mut self.floating {
+            floating.position = from.offset(cursor.x - grab.x, cursor.y - grab.y);
+        }
+    }
+
+    // put the dragged pixels down where they are
+    fn drop(&mut self) {
+        if self.moving.take().is_none() {
+            return;
+        }
+
+        self.follow_floating();
+        self.commit_floating();
+    }
+
+    // move the selection along with the floating pixels, keeping whatever part of it is on the
+    // canvas, or select around all of them if they've been scaled or rotated
+    fn follow_floating(&mut self) {
+        let (floating, lifted) = match (&self.floating, &self.lifted) {
+            (Some(floating), Some(lifted)) => (floating, lifted),
+            _ => return,
+        };
+
+        let size = self.canvas.size();
+        if floating.is_transformed() {
+            let rect = floating.rect().clamp_to(size);
+            self.selection = Some(Selection::from(rect)).filter(|_| !rect.is_empty());
+            return;
+        }
+
+        let (dx, dy) = (
+            floating.position.x - lifted.from.x,
+            floating.position.y - lifted.from.y,
+        );
+        self.selection = self
+            .selection
+            .as_ref()
+            .and_then(|selection| selection.clamp_to(size))
+            .and_then(|selection| selection.offset(dx, dy).clamp_to(size));
+    }
+
+    // put the floating pixels back exactly how they were, wherever they've been moved and however
+    // they've been transformed
+    fn cancel_floating(&mut self) {
+        self.moving = None;
+        let lifted = match self.lifted.take() {
+            Some(lifted) => lifted,
+            None => return,
+        };
+
+        let floating = self.floating.take();
+        if let (Some(floating), Some(cut)) = (floating, lifted.cut) {
+            // only the selected pixels were cut out, and the rest of the floating ones are blank
+            self.history.touch(&self.canvas, cut.bounds());
+            let bounds = self.canvas.bounds();
+            for point in cut.points().filter(|&point| bounds.contains(point)) {
+                let (x, y) = (point.x - lifted.from.x, point.y - lifted.from.y);
+                let pixel = floating.image().pixel_at(x as usize, y as usize);
+                self.canvas
+                    .set_pixel(point.x as usize, point.y as usize, pixel);
+            }
+            self.mark_dirty();
+        }
+    }
+
+    // whether the left button picks up colors instead of painting. alt takes away from the
+    // selection with the wand instead
+    fn picking(&self) -> bool {
+        match self.active_tool() {
+            Tool::Eyedropper => true,
+            Tool::Wand => false,
+            _ => self.modifiers.alt(),
+        }
+    }
+
+    // make the color under the cursor the paint color, alpha and all. does nothing off the canvas
+    fn pick(&mut self) -> Option<Pixel> {
+        let point = self.canvas_point((self.mouse.x, self.mouse.y));
+        self.primary = self
+            .canvas
+            .try_pixel_at(point.x as usize, point.y as usize)?;
+        log::info!(
+            "picked {} at {}, {}",
+            color_name(self.primary),
+            point.x,
+            point.y
+        );
+        Some(self.primary)
+    }
+
+    // the title shows the tool, the brush, and what's under the pointer, with the same mapping
+    // painting uses
+    fn title(&self) -> String {
+        let point = self.canvas_point((self.mouse.x, self.mouse.y));
+        let under = match self.canvas.try_pixel_at(point.x as usize, point.y as usize) {
+            Some(color) => format!("({}, {}) {}", point.x, point.y, color_name(color)),
+            None => String::from("-"),
+        };
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        let scale = self.view.pixel_scale(width, height, self.size);
+
+        let recovery = if self.recovery_offered {
+            " - unsaved work was found, ctrl+shift+o restores it"
+        } else {
+            ""
+        };
+
+        format!(
+            "yocto-canvas - {} - {}px - {} - {} - {}%{}",
+            self.active_tool().name(),
+            self.brush.size(),
+            color_name(self.primary),
+            under,
+            (scale * 100.).round(),
+            recovery
+        )
+    }
+
+    // the title, if it's changed since it was last shown and it's been long enough since then
+    fn refresh_title(&mut self, now: Instant) -> Option<String> {
+        let due = match self.shown_title {
+            Some(_) => self
+                .title_deadline()
+                .is_some_and(|deadline| now >= deadline),
+            None => true,
+        };
+        if !due {
+            return None;
+        }
+
+        let title = self.title();
+        self.shown_title = Some((title.clone(), now));
+        Some(title)
+    }
+
+    // when the title can next be shown, if it's out of date
+    fn title_deadline(&self) -> Option<Instant> {
+        match &self.shown_title {
+            Some((title, _)) if *title == self.title() => None,
+            Some((_, at)) => Some(*at + TITLE_INTERVAL),
+            None => Some(Instant::now()),
+        }
+    }
+
+    // hand what the pointer did to the tool for a button. the right button always erases
+    fn pointer(&mut self, button: MouseButton, event: Pointer) {
+        let mut ctx = ToolCtx {
+            canvas: &mut self.canvas,
+            history: &mut self.history,
+            color: &mut self.primary,
+            selection: &mut self.selection,
+            brush: &self.brush,
+            symmetry: self.symmetry,
+            symmetry_axis: self.symmetry_axis,
+            view: &self.view,
+            window: self.size,
+            pointer: (self.mouse.x, self.mouse.y),
+            line_from: None,
+            modifiers: self.modifiers,
+            grid: if self.snapping { Some(self.grid) } else { None },
+        };
+        let tool: &mut dyn PointerTool = match (button, &mut self.pointer_tool) {
+            (MouseButton::Right, _) => &mut self.eraser,
+            (_, Some(tool)) => tool.as_mut(),
+            (_, None) => return,
+        };
+
+        let changed = match event {
+            Pointer::Down { line_from } => {
+                ctx.line_from = line_from;
+                tool.on_pointer_down(&mut ctx)
+            }
+            Pointer::Move => tool.on_pointer_move(&mut ctx),
+            Pointer::Up => tool.on_pointer_up(&mut ctx),
+        };
+        if changed {
+            self.mark_dirty();
+        }
+    }
+
+    fn update(&mut self) {
+        // backend-specific stuff goes in these methods
+        let minimap = self.minimap();
+        if let Some(wgpu_backend) = &mut self.wgpu_backend {
+            wgpu_backend.update(&self.size, &self.view, &self.canvas, minimap.as_ref());
+        }
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+        if let Some(wgpu_backend) = &mut self.wgpu_backend {
+            wgpu_backend.resize(new_size);
+        }
+    }
+
+    // deal with a frame that couldn't be drawn, leaving the reporting to the caller
+    fn render_failed(&mut self, error: anyhow::Error) -> RenderFailure {
+        match error.downcast::<SwapChainError>() {
+            Ok(SwapChainError::Lost) => {
+                self.resize(self.size);
+                RenderFailure::Recreated
+            }
+            Ok(SwapChainError::OutOfMemory) => {
+                RenderFailure::Fatal(String::from("out of memory while rendering"))
+            }
+            Ok(error) => RenderFailure::Skipped(format!("couldn't render: {}", error)),
+            Err(error) => RenderFailure::Skipped(format!("couldn't render: {:#}", error)),
+        }
+    }
+
+    fn render(&mut self) -> Result<()> {
+        if let Some(wgpu_backend) = &mut self.wgpu_backend {
+            let shown = self.preview.as_mut().unwrap_or(&mut self.canvas);
+            let size = self.size;
+            selection::with_floating(shown, self.floating.as_ref(), |display| {
+                wgpu_backend.render(&size, display)
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let event_loop = EventLoop::with_user_event();
+    let window = WindowBuilder::new().build(&event_loop)?;
+    window.set_inner_size(PhysicalSize {
+        width: 800,
+        height: 675,
+    });
+
+    let recover = std::env::args().any(|arg| arg == "--recover");
+    let mut config = PipelineConfig::default();
+    let mut undo_depth = None;
+    let mut undo_memory = None;
+    let mut grid = None;
+    for arg in std::env::args() {
+        match arg.as_str() {
+            "--high-performance" => config.power_preference = PowerPreference::HighPerformance,
+            "--low-power" => config.power_preference = PowerPreference::LowPower,
+            "--gpu-timing" => config.gpu_timing = true,
+            other => {
+                if let Some(samples) = other.strip_prefix("--minify-samples=") {
+                    config.minify_samples = samples
+                        .parse()
+                        .context("--minify-samples should be a number")?;
+                } else if let Some(depth) = other.strip_prefix("--undo-depth=") {
+                    undo_depth = Some(depth.parse().context("--undo-depth should be a number")?);
+                } else if let Some(megabytes) = other.strip_prefix("--undo-memory=") {
+                    let megabytes: usize = megabytes
+                        .parse()
+                        .context("--undo-memory should be a number of megabytes")?;
+                    undo_memory = Some(megabytes * 1024 * 1024);
+                } else if let Some(spec) = other.strip_prefix("--grid=") {
+                    grid = Some(
+                        Grid::parse(spec)
+                            .context("--grid should look like 8x8, or 8x8+4+4 with an offset")?,
+                    );
+                }
+            }
+        }
+    }
+
+    // wake the event loop up when the io worker has something
+    let proxy = Mutex::new(event_loop.create_proxy());
+    let io = IoWorker::new(IoWorker::DEFAULT_THREADS, move || {
+        let _ = proxy.lock().unwrap().send_event(());
+    });
+
+    let open = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--") && !recover)
+        .map(PathBuf::from);
+    let mut state = futures::executor::block_on(State::new(&window, config, io, open))?;
+
+    // pick up where a crash left off, or offer to
+    if recover {
+        if !state.restore() {
+            log::warn!("there's nothing to recover");
+        }
+    } else if state
+        .autosave
+        .as_ref()
+        .is_some_and(|autosave| autosave.has_recovery())
+    {
+        log::warn!("found recovery files from last time, ctrl+shift+o restores them");
+        state.recovery_offered = true;
+    }
+    state.smooth_zoom = !std::env::args().any(|arg| arg == "--no-smooth-zoom");
+    if let Some(depth) = undo_depth {
+        state.history.set_depth(depth);
+    }
+    if let Some(memory) = undo_memory {
+        state.history.set_max_memory(memory);
+    }
+    if let Some(grid) = grid {
+        state.grid = grid;
+    }
+
+    event_loop.run(move |event, _, control_flow| {
+        let deadline = [
+            state.autosave(),
+            state.playback.next_frame(),
+            state.title_deadline(),
+        ]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+        *control_flow = match deadline {
+            // keep the frames coming until the zoom settles, and while dragging at the edge of
+            // the window
+            _ if state.zoom_animation.is_some() || state.autoscroll_at.is_some() => {
+                ControlFlow::Poll
+            }
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        };
 
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
         match event {
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                let zooming = state.zoom_animation.is_some();
+                if zooming {
+                    state.animate(now);
+                }
+
+                let scrolling = state.autoscroll(now);
+                if state.advance_playback(now) || zooming || scrolling {
+                    state.update();
+                    window.request_redraw();
+                }
+
+                if let Some(title) = state.refresh_title(now) {
+                    window.set_title(&title);
+                }
+            }
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if window_id == window.id() => {
-                if state.input(&event) {
+                if state.input(event) {
                     state.update();
                     window.request_redraw();
                 } else {
                     match event {
-                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        // TODO remove later
-                        WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
+                        WindowEvent::CloseRequested => {
+                            state.exit();
+                            *control_flow = ControlFlow::Exit;
+                        }
                         WindowEvent::Resized(size) => {
                             state.resize(*size);
                             state.update();
@@ -184,18 +1677,1913 @@ fn main() -> Result<()> {
                     }
                 }
             }
-            Event::RedrawRequested(window_id) if window_id == window.id() => match state.render() {
-                Ok(_) => {}
-                Err(e) => match e.downcast::<SwapChainError>() {
-                    Ok(e) => match e {
-                        SwapChainError::Lost => {}
-                        SwapChainError::OutOfMemory => *control_flow = ControlFlow::Exit,
-                        e => println!("{}", e),
-                    },
-                    Err(e) => println!("{}", e),
-                },
-            },
+            Event::UserEvent(()) if state.poll_io() => {
+                state.update();
+                window.request_redraw();
+            }
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
+                if let Err(e) = state.render() {
+                    match state.render_failed(e) {
+                        RenderFailure::Recreated => log::debug!("swapchain lost, recreated it"),
+                        RenderFailure::Skipped(message) => log::error!("{}", message),
+                        RenderFailure::Fatal(message) => {
+                            log::error!("{}, exiting", message);
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                }
+            }
             _ => {}
         }
     });
 }
+
+#[test]
+fn tool_switch_keymap() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    assert_eq!(Tool::Brush, state.active_tool());
+
+    assert!(state.key_pressed(VirtualKeyCode::E));
+    assert_eq!(Tool::Eraser, state.active_tool());
+
+    assert!(!state.key_pressed(VirtualKeyCode::Q));
+    assert_eq!(Tool::Eraser, state.active_tool());
+}
+
+#[test]
+fn tool_switch_cancels_stroke() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+
+    state.key_pressed(VirtualKeyCode::L);
+    assert_eq!(Tool::Line, state.active_tool());
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert_eq!(None, state.last_point);
+}
+
+#[test]
+fn shift_click_draws_line() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(200, 200),
+        Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.show_minimap = false;
+    let click = |state: &mut State, x, y| {
+        state.mouse.x = x;
+        state.mouse.y = y;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+        state.canvas_point((x, y))
+    };
+    let painted = |state: &State| {
+        state
+            .canvas
+            .bounds()
+            .points()
+            .filter(|point| {
+                state.canvas.pixel_at(point.x as usize, point.y as usize) != Pixel::TRANSPARENT
+            })
+            .count()
+    };
+
+    // nothing to draw a line from yet
+    state.modifiers = ModifiersState::SHIFT;
+    let start = click(&mut state, 55.0, 55.0);
+    assert_eq!(Some(start), state.last_point);
+    assert_eq!(1, painted(&state));
+
+    // the line is in canvas pixels, wherever the view is
+    state.view.pan_by(10.0, 0.0);
+    let end = click(&mut state, 65.0, 145.0);
+    assert_eq!(start.x, end.x);
+    assert_eq!(Some(end), state.last_point);
+    for point in start.line_to(end) {
+        assert_ne!(
+            Pixel::TRANSPARENT,
+            state.canvas.pixel_at(point.x as usize, point.y as usize)
+        );
+    }
+    let line = painted(&state);
+    assert_eq!(start.line_to(end).len(), line);
+
+    // clicks with tools that don't paint don't move where the next line starts
+    state.set_active_tool(Tool::Select);
+    click(&mut state, 145.0, 145.0);
+    state.selection = None;
+    state.set_active_tool(Tool::Brush);
+    assert_eq!(Some(end), state.last_point);
+
+    // without shift it's freehand
+    state.modifiers = ModifiersState::empty();
+    let freehand = click(&mut state, 145.0, 55.0);
+    assert_eq!(Some(freehand), state.last_point);
+    assert_eq!(line + 1, painted(&state));
+}
+
+#[test]
+fn paint_under_cursor() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    // a square canvas in a wide window, with 100 pixel bars on either side
+    let mut state = State::without_backend(
+        PhysicalSize::new(400, 200),
+        Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap(),
+    );
+    let click = |state: &mut State, x, y| {
+        state.mouse.x = x;
+        state.mouse.y = y;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+
+    click(&mut state, 105.0, 195.0);
+    assert_eq!(white, state.canvas.pixel_at(0, 19));
+
+    state.view.set_zoom(2.0);
+    click(&mut state, 200.0, 100.0);
+    assert_eq!(white, state.canvas.pixel_at(10, 10));
+
+    // off of the canvas, including right on its right edge, which mustn't wrap to the next row
+    let before = state.canvas.clone();
+    state.view.set_zoom(1.0);
+    for &(x, y) in &[(50.0, 100.0), (401.0, 100.0), (200.0, -5.0), (300.0, 50.0)] {
+        click(&mut state, x, y);
+        assert_eq!(before, state.canvas, "painted at {}, {}", x, y);
+    }
+}
+
+#[test]
+fn bracket_keys_resize_brush() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    assert_eq!(1, state.brush.size());
+
+    assert!(state.key_pressed(VirtualKeyCode::RBracket));
+    assert!(state.key_pressed(VirtualKeyCode::RBracket));
+    assert_eq!(3, state.brush.size());
+
+    // a 3 pixel brush in the middle of the canvas
+    state.mouse.x = 400.0;
+    state.mouse.y = 300.0;
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    let painted = (0..8)
+        .flat_map(|y| (0..8).map(move |x| (x, y)))
+        .filter(|&(x, y)| state.canvas.pixel_at(x, y) != Pixel::TRANSPARENT)
+        .count();
+    assert_eq!(9, painted);
+
+    for _ in 0..5 {
+        assert!(state.key_pressed(VirtualKeyCode::LBracket));
+    }
+    assert_eq!(1, state.brush.size());
+}
+
+#[test]
+fn fast_strokes_are_joined() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    let painted = |state: &State| {
+        (0..100)
+            .flat_map(|y| (0..100).map(move |x| (x, y)))
+            .filter(|&(x, y)| state.canvas.pixel_at(x, y) != Pixel::TRANSPARENT)
+            .collect::<Vec<_>>()
+    };
+    let move_to = |state: &mut State, x: f32, y: f32| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(x as f64, y as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+
+    // moving without the button down doesn't paint
+    move_to(&mut state, 10.5, 10.5);
+    move_to(&mut state, 50.5, 10.5);
+    assert!(painted(&state).is_empty());
+
+    // one big jump, which is all the way along the row
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    move_to(&mut state, 90.5, 10.5);
+    assert_eq!(
+        (50..=90).map(|x| (x, 10)).collect::<Vec<_>>(),
+        painted(&state)
+    );
+
+    // and then diagonally, still touching
+    move_to(&mut state, 60.5, 40.5);
+    assert_eq!(41 + 30, painted(&state).len());
+    assert!(painted(&state).contains(&(75, 25)));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+
+    // the next stroke starts where it's pressed, not where the last one ended
+    move_to(&mut state, 10.5, 80.5);
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    assert_eq!(41 + 30 + 1, painted(&state).len());
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(35, 60));
+}
+
+#[test]
+fn right_button_and_eraser_tool_erase() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let erased = Pixel { a: 0.0, ..red };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, red).unwrap(),
+    );
+    let drag = |state: &mut State, button, from: (f32, f32), to: (f32, f32)| {
+        state.mouse.x = from.0;
+        state.mouse.y = from.1;
+        state.mouse_button(button, ElementState::Pressed);
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+        state.mouse_button(button, ElementState::Released);
+    };
+
+    // the whole drag is erased, keeping the color underneath
+    drag(&mut state, MouseButton::Right, (10.5, 20.5), (30.5, 20.5));
+    assert!((10..=30).all(|x| state.canvas.pixel_at(x, 20) == erased));
+    assert_eq!(red, state.canvas.pixel_at(31, 20));
+    assert_eq!(red, state.canvas.pixel_at(20, 21));
+
+    // with the brush size too
+    state.brush.set_size(3);
+    drag(&mut state, MouseButton::Right, (50.5, 50.5), (50.5, 50.5));
+    assert_eq!(erased, state.canvas.pixel_at(51, 51));
+    assert_eq!(red, state.canvas.pixel_at(52, 50));
+
+    // the left button erases with the eraser tool, and paints otherwise
+    state.brush.set_size(1);
+    assert!(state.key_pressed(VirtualKeyCode::E));
+    drag(&mut state, MouseButton::Left, (10.5, 60.5), (10.5, 70.5));
+    assert!((60..=70).all(|y| state.canvas.pixel_at(10, y) == erased));
+
+    assert!(state.key_pressed(VirtualKeyCode::B));
+    drag(&mut state, MouseButton::Left, (10.5, 60.5), (10.5, 70.5));
+    assert_eq!(1.0, state.canvas.pixel_at(10, 65).a);
+}
+
+#[test]
+fn eyedropper_picks_color() {
+    let orange = Pixel {
+        r: 1.0,
+        g: 0.5,
+        b: 0.0,
+        a: 0.25,
+    };
+    let mut canvas = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    canvas.set_pixel(20, 30, orange);
+    let mut state = State::without_backend(PhysicalSize::new(100, 100), canvas);
+    let click = |state: &mut State, x, y| {
+        state.mouse.x = x;
+        state.mouse.y = y;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+
+    // alt-clicking picks without painting, alpha included
+    state.modifiers = ModifiersState::ALT;
+    click(&mut state, 20.5, 30.5);
+    assert_eq!(orange, state.primary);
+    assert_eq!(orange, state.canvas.pixel_at(20, 30));
+    assert_eq!(
+        "yocto-canvas - brush - 1px - #ff800040 - (20, 30) #ff800040 - 100%",
+        state.title()
+    );
+
+    // so painting with it puts the same thing down
+    state.modifiers = ModifiersState::empty();
+    click(&mut state, 60.5, 60.5);
+    assert_eq!(orange, state.canvas.pixel_at(60, 60));
+
+    // picking off the canvas, with the eyedropper tool this time, changes nothing
+    state.set_active_tool(Tool::Eyedropper);
+    state.size = PhysicalSize::new(200, 100);
+    click(&mut state, 10.0, 50.0);
+    assert_eq!(orange, state.primary);
+    assert_eq!(None, state.pick());
+
+    // and on it, picks up the transparent background
+    click(&mut state, 100.5, 50.5);
+    assert_eq!(Pixel::TRANSPARENT, state.primary);
+    assert_eq!(orange, state.canvas.pixel_at(60, 60));
+}
+
+#[test]
+fn translucent_strokes_darken_once() {
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, white).unwrap(),
+    );
+    state.primary = black;
+    state.modifiers = ModifiersState::CTRL;
+    assert!(state.key_pressed(VirtualKeyCode::Key3));
+    state.modifiers = ModifiersState::empty();
+    assert_eq!(0.3, state.brush.opacity);
+
+    // back and forth over the same row in one stroke
+    let move_to = |state: &mut State, x: f64| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(x, 10.5),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    move_to(&mut state, 10.5);
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    move_to(&mut state, 30.5);
+    move_to(&mut state, 10.5);
+    move_to(&mut state, 20.5);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!((10..=30).all(|x| (state.canvas.pixel_at(x, 10).r - 0.7).abs() < 1e-6));
+
+    // a second stroke goes over the first
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    assert!((state.canvas.pixel_at(20, 10).r - 0.49).abs() < 1e-6);
+    assert!((state.canvas.pixel_at(21, 10).r - 0.7).abs() < 1e-6);
+}
+
+#[test]
+fn selection_masks_painting() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    let drag = |state: &mut State, from: (f32, f32), to: (f32, f32)| {
+        state.mouse.x = from.0;
+        state.mouse.y = from.1;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+
+    // dragged from the bottom right, which doesn't paint anything
+    assert!(state.key_pressed(VirtualKeyCode::M));
+    drag(&mut state, (60.2, 40.4), (19.8, 20.3));
+    assert_eq!(
+        Some(Selection::from(Rect::new(20, 20, 40, 20))),
+        state.selection
+    );
+    assert_eq!(
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+        state.canvas
+    );
+
+    // strokes across it only paint inside of it, brush and all
+    assert!(state.key_pressed(VirtualKeyCode::B));
+    state.brush.set_size(5);
+    drag(&mut state, (10.5, 30.5), (90.5, 30.5));
+    for y in 0..100 {
+        for x in 0..100 {
+            let inside = (20..60).contains(&x) && (28..33).contains(&y);
+            let expected = if inside { white } else { Pixel::TRANSPARENT };
+            assert_eq!(expected, state.canvas.pixel_at(x, y), "{}, {}", x, y);
+        }
+    }
+
+    // and so does erasing
+    state.input(&WindowEvent::MouseInput {
+        device_id: unsafe { winit::event::DeviceId::dummy() },
+        state: ElementState::Pressed,
+        button: MouseButton::Right,
+        modifiers: ModifiersState::empty(),
+    });
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(90, 30));
+    state.mouse_button(MouseButton::Right, ElementState::Released);
+
+    // escape clears it
+    assert!(state.key_pressed(VirtualKeyCode::Escape));
+    assert_eq!(None, state.selection);
+    assert!(!state.key_pressed(VirtualKeyCode::Escape));
+
+    // so does ctrl+d, and a selection with no area is no selection at all
+    state.set_active_tool(Tool::Select);
+    drag(&mut state, (10.0, 10.0), (30.0, 20.0));
+    assert!(state.selection.is_some());
+    state.modifiers = ModifiersState::CTRL;
+    assert!(state.key_pressed(VirtualKeyCode::D));
+    assert_eq!(None, state.selection);
+    state.modifiers = ModifiersState::empty();
+    drag(&mut state, (10.0, 10.0), (30.0, 10.2));
+    assert_eq!(None, state.selection);
+    drag(&mut state, (-10.0, 10.0), (-1.0, 50.0));
+    assert_eq!(None, state.selection);
+
+    state.set_active_tool(Tool::Brush);
+    drag(&mut state, (80.5, 80.5), (80.5, 80.5));
+    assert_eq!(white, state.canvas.pixel_at(80, 80));
+}
+
+#[test]
+fn move_selected_pixels() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, blue).unwrap(),
+    );
+    for point in Rect::new(10, 10, 10, 10).points() {
+        state
+            .canvas
+            .set_pixel(point.x as usize, point.y as usize, red);
+    }
+    let press = |state: &mut State, at: (f32, f32)| {
+        state.mouse.x = at.0;
+        state.mouse.y = at.1;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    };
+    let move_to = |state: &mut State, to: (f32, f32)| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    let drag = |state: &mut State, from: (f32, f32), to: (f32, f32)| {
+        press(state, from);
+        move_to(state, to);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    // the pixels that are red, as a rect, if they make one
+    let red_rect = |state: &State| {
+        let red_points = state
+            .canvas
+            .bounds()
+            .points()
+            .filter(|point| state.canvas.pixel_at(point.x as usize, point.y as usize) == red)
+            .collect::<Vec<_>>();
+        let left = red_points.iter().map(|point| point.x).min().unwrap();
+        let top = red_points.iter().map(|point| point.y).min().unwrap();
+        let right = red_points.iter().map(|point| point.x).max().unwrap() + 1;
+        let bottom = red_points.iter().map(|point| point.y).max().unwrap() + 1;
+        let rect = Rect::new(left, top, (right - left) as u32, (bottom - top) as u32);
+        assert_eq!(rect.points().count(), red_points.len());
+        rect
+    };
+
+    // without a selection, the move tool does nothing at all
+    let before = state.canvas.clone();
+    assert!(state.key_pressed(VirtualKeyCode::V));
+    drag(&mut state, (15.5, 15.5), (45.5, 25.5));
+    assert_eq!(before, state.canvas);
+
+    // cut out of the canvas and dropped further along, where it's selected
+    state.set_active_tool(Tool::Select);
+    drag(&mut state, (10.0, 10.0), (20.0, 20.0));
+    state.set_active_tool(Tool::Move);
+    press(&mut state, (15.5, 15.5));
+    move_to(&mut state, (30.5, 15.5));
+    move_to(&mut state, (45.5, 25.5));
+    assert!(state.floating.is_some());
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(10, 10));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!(state.floating.is_none());
+    assert_eq!(Rect::new(40, 20, 10, 10), red_rect(&state));
+    assert_eq!(
+        Some(Selection::from(Rect::new(40, 20, 10, 10))),
+        state.selection
+    );
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(19, 19));
+    assert_eq!(blue, state.canvas.pixel_at(20, 19));
+
+    // copied with ctrl held, leaving the original where it was
+    state.modifiers = ModifiersState::CTRL;
+    drag(&mut state, (45.5, 25.5), (55.5, 25.5));
+    state.modifiers = ModifiersState::empty();
+    assert_eq!(Rect::new(40, 20, 20, 10), red_rect(&state));
+    assert_eq!(
+        Some(Selection::from(Rect::new(50, 20, 10, 10))),
+        state.selection
+    );
+
+    // dropped partway off the canvas, only what's left on it is kept and selected
+    drag(&mut state, (50.5, 20.5), (95.5, 20.5));
+    assert_eq!(
+        Some(Selection::from(Rect::new(95, 20, 5, 10))),
+        state.selection
+    );
+    assert_eq!(red, state.canvas.pixel_at(99, 29));
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(50, 20));
+    assert_eq!(red, state.canvas.pixel_at(49, 20));
+
+    // escape in the middle of a drag puts everything back exactly
+    let before = state.canvas.clone();
+    press(&mut state, (97.5, 25.5));
+    move_to(&mut state, (60.5, 60.5));
+    assert_ne!(before, state.canvas);
+    assert!(state.key_pressed(VirtualKeyCode::Escape));
+    assert!(state.floating.is_none());
+    assert_eq!(before, state.canvas);
+    // without dropping the selection, and the rest of the drag doesn't paint
+    assert_eq!(
+        Some(Selection::from(Rect::new(95, 20, 5, 10))),
+        state.selection
+    );
+    move_to(&mut state, (70.5, 70.5));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert_eq!(before, state.canvas);
+
+    // and so does escape while copying
+    state.modifiers = ModifiersState::CTRL;
+    press(&mut state, (97.5, 25.5));
+    move_to(&mut state, (60.5, 60.5));
+    assert!(state.floating.is_some());
+    assert!(state.key_pressed(VirtualKeyCode::Escape));
+    assert_eq!(before, state.canvas);
+    assert!(state.floating.is_none());
+}
+
+#[test]
+fn lasso_selection() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, white).unwrap(),
+    );
+    state.primary = red;
+    let move_to = |state: &mut State, to: (f32, f32)| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    let stroke = |state: &mut State, points: &[(f32, f32)]| {
+        move_to(state, points[0]);
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        for &point in &points[1..] {
+            move_to(state, point);
+        }
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+
+    // closed back to where it started when the button comes up
+    assert!(state.key_pressed(VirtualKeyCode::O));
+    stroke(&mut state, &[(10.0, 10.0), (60.0, 10.0), (10.0, 60.0)]);
+    let selection = state.selection.clone().unwrap();
+    assert!(!selection.is_rect());
+    assert!(selection.contains(Point::new(12, 12)));
+    assert!(!selection.contains(Point::new(40, 40)));
+
+    // painting only reaches inside of it
+    state.set_active_tool(Tool::Brush);
+    stroke(&mut state, &[(5.5, 20.5), (70.5, 20.5)]);
+    assert_eq!(white, state.canvas.pixel_at(5, 20));
+    assert_eq!(red, state.canvas.pixel_at(30, 20));
+    assert_eq!(white, state.canvas.pixel_at(55, 20));
+
+    // moving it cuts out just the selected pixels, and putting them back is exact
+    let painted = state.canvas.clone();
+    state.set_active_tool(Tool::Move);
+    move_to(&mut state, (20.5, 20.5));
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    move_to(&mut state, (40.5, 40.5));
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(12, 12));
+    assert_eq!(white, state.canvas.pixel_at(55, 20));
+    assert!(state.key_pressed(VirtualKeyCode::Escape));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert_eq!(painted, state.canvas);
+
+    // and dropping them moves the selection with them
+    stroke(&mut state, &[(20.5, 20.5), (40.5, 40.5)]);
+    let moved = state.selection.clone().unwrap();
+    assert!(moved.contains(Point::new(32, 32)));
+    assert!(!moved.contains(Point::new(12, 12)));
+    assert_eq!(red, state.canvas.pixel_at(50, 40));
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(12, 12));
+
+    // a click without dragging out a path selects nothing
+    state.set_active_tool(Tool::Lasso);
+    stroke(&mut state, &[(10.0, 10.0)]);
+    assert_eq!(None, state.selection);
+}
+
+#[test]
+fn magic_wand_selection() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, white).unwrap(),
+    );
+    for point in Rect::new(10, 10, 10, 10)
+        .points()
+        .chain(Rect::new(50, 50, 10, 10).points())
+    {
+        state
+            .canvas
+            .set_pixel(point.x as usize, point.y as usize, red);
+    }
+    let click = |state: &mut State, modifiers, x: f32, y: f32| {
+        state.modifiers = modifiers;
+        state.mouse.x = x;
+        state.mouse.y = y;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    let selected = |state: &State| state.selection.as_ref().unwrap().points().count();
+
+    assert!(state.key_pressed(VirtualKeyCode::W));
+    click(&mut state, ModifiersState::empty(), 15.5, 15.5);
+    assert_eq!(
+        Rect::new(10, 10, 10, 10),
+        state.selection.as_ref().unwrap().bounds()
+    );
+    assert_eq!(100, selected(&state));
+
+    // shift adds the other square, and alt takes the first one away without picking its color
+    click(&mut state, ModifiersState::SHIFT, 55.5, 55.5);
+    assert_eq!(200, selected(&state));
+    click(&mut state, ModifiersState::ALT, 15.5, 15.5);
+    assert_eq!(
+        Rect::new(50, 50, 10, 10),
+        state.selection.as_ref().unwrap().bounds()
+    );
+    assert_eq!(100, selected(&state));
+    assert_eq!(white, state.primary);
+    click(&mut state, ModifiersState::ALT, 55.5, 55.5);
+    assert_eq!(None, state.selection);
+
+    // the background goes around the squares
+    click(&mut state, ModifiersState::empty(), 5.5, 5.5);
+    assert_eq!(100 * 100 - 200, selected(&state));
+
+    // and nothing changes clicking off the canvas
+    state.size = PhysicalSize::new(200, 100);
+    click(&mut state, ModifiersState::empty(), 10.0, 50.0);
+    assert_eq!(100 * 100 - 200, selected(&state));
+}
+
+#[test]
+fn bucket_fill() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, white).unwrap(),
+    );
+    state.show_minimap = false;
+    for point in Rect::new(10, 10, 10, 10).points() {
+        state
+            .canvas
+            .set_pixel(point.x as usize, point.y as usize, red);
+    }
+    state.primary = blue;
+    let click = |state: &mut State, x: f32, y: f32| {
+        state.mouse.x = x;
+        state.mouse.y = y;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    let count = |state: &State, color| {
+        Rect::new(0, 0, 100, 100)
+            .points()
+            .filter(|point| state.canvas.pixel_at(point.x as usize, point.y as usize) == color)
+            .count()
+    };
+
+    assert!(state.key_pressed(VirtualKeyCode::G));
+    click(&mut state, 15.5, 15.5);
+    assert_eq!(100, count(&state, blue));
+    assert_eq!(0, count(&state, red));
+    assert!(state.undo());
+    assert_eq!(100, count(&state, red));
+
+    // only what's selected gets filled
+    state.selection = Some(Selection::from(Rect::new(0, 0, 50, 100)));
+    click(&mut state, 5.5, 5.5);
+    assert_eq!(50 * 100 - 100, count(&state, blue));
+    assert_eq!(50 * 100, count(&state, white));
+}
+
+#[test]
+fn transform_floating_selection() {
+    use crate::image::Filter;
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, blue).unwrap(),
+    );
+    for point in Rect::new(10, 10, 10, 10).points() {
+        state
+            .canvas
+            .set_pixel(point.x as usize, point.y as usize, red);
+    }
+    let original = state.canvas.clone();
+    let key = |state: &mut State, modifiers, key| {
+        state.modifiers = modifiers;
+        assert!(state.key_pressed(key));
+    };
+
+    // nothing to float without a selection
+    state.modifiers = ModifiersState::CTRL;
+    assert!(!state.key_pressed(VirtualKeyCode::T));
+
+    // turned and scaled up around the middle, then put back how it was
+    state.selection = Some(Selection::from(Rect::new(10, 10, 10, 10)));
+    key(&mut state, ModifiersState::CTRL, VirtualKeyCode::T);
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(15, 15));
+    for _ in 0..6 {
+        key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Right);
+    }
+    for _ in 0..10 {
+        key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Up);
+    }
+    let floating = state.floating.as_ref().unwrap();
+    assert_eq!(90., floating.rotation());
+    assert!((floating.scale() - 2.).abs() < 1e-6);
+    assert_eq!(Rect::new(5, 5, 20, 20), floating.rect());
+    key(&mut state, ModifiersState::empty(), VirtualKeyCode::Escape);
+    assert!(state.floating.is_none());
+    assert_eq!(original, state.canvas);
+    assert!(!state.history.can_undo());
+
+    // committed where it ends up, clipped to the canvas, and selected
+    key(&mut state, ModifiersState::CTRL, VirtualKeyCode::T);
+    for _ in 0..10 {
+        key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Up);
+    }
+    key(&mut state, ModifiersState::empty(), VirtualKeyCode::N);
+    assert_eq!(Filter::Bilinear, state.floating.as_ref().unwrap().filter());
+    for _ in 0..10 {
+        key(&mut state, ModifiersState::empty(), VirtualKeyCode::Left);
+    }
+    // clicking while it floats doesn't end the edit
+    state.set_active_tool(Tool::Eyedropper);
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    key(&mut state, ModifiersState::empty(), VirtualKeyCode::Return);
+    assert!(state.floating.is_none());
+    assert_eq!(
+        Some(Selection::from(Rect::new(0, 5, 15, 20))),
+        state.selection
+    );
+    assert_eq!(red, state.canvas.pixel_at(0, 5));
+    assert_eq!(red, state.canvas.pixel_at(14, 24));
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(15, 15));
+    assert_eq!(blue, state.canvas.pixel_at(30, 15));
+
+    // all as one edit
+    key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z);
+    assert_eq!(original, state.canvas);
+}
+
+#[test]
+fn snap_to_grid() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    let move_to = |state: &mut State, to: (f32, f32)| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    let drag = |state: &mut State, from: (f32, f32), to: (f32, f32)| {
+        move_to(state, from);
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        move_to(state, to);
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    let painted = |state: &State| {
+        state
+            .canvas
+            .bounds()
+            .points()
+            .filter(|point| state.canvas.pixel_at(point.x as usize, point.y as usize).a > 0.0)
+            .collect::<Vec<_>>()
+    };
+
+    state.modifiers = ModifiersState::CTRL;
+    assert!(state.key_pressed(VirtualKeyCode::G));
+    assert!(state.snapping);
+    state.modifiers = ModifiersState::empty();
+
+    // selections go from one intersection of the 8x8 grid to another
+    state.set_active_tool(Tool::Select);
+    drag(&mut state, (3.0, 5.0), (21.0, 19.0));
+    assert_eq!(
+        Some(Selection::from(Rect::new(0, 8, 24, 8))),
+        state.selection
+    );
+
+    // and ones that snap down to a line or a point aren't anything
+    drag(&mut state, (1.0, 1.0), (3.0, 3.0));
+    assert_eq!(None, state.selection);
+    drag(&mut state, (1.0, 1.0), (3.0, 20.0));
+    assert_eq!(None, state.selection);
+
+    // straight lines too, after they're snapped to 45 degrees
+    state.set_active_tool(Tool::Brush);
+    state.modifiers = ModifiersState::SHIFT;
+    state.last_point = None;
+    drag(&mut state, (10.5, 10.5), (30.5, 11.5));
+    let line = (8..=32).map(|x| Point::new(x, 8)).collect::<Vec<_>>();
+    assert_eq!(line, painted(&state));
+
+    let before = state.canvas.clone();
+    state.last_point = None;
+    drag(&mut state, (50.5, 50.5), (51.5, 51.5));
+    assert_eq!(before, state.canvas);
+
+    // until it's turned off again
+    state.modifiers = ModifiersState::CTRL;
+    assert!(state.key_pressed(VirtualKeyCode::G));
+    assert!(!state.snapping);
+}
+
+#[test]
+fn symmetric_painting() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let drag = |state: &mut State, button, from: (f32, f32), to: (f32, f32)| {
+        state.mouse.x = from.0;
+        state.mouse.y = from.1;
+        state.mouse_button(button, ElementState::Pressed);
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+        state.mouse_button(button, ElementState::Released);
+    };
+    let mirrored = |image: &Image, flip_x: bool, flip_y: bool| {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        (0..height).all(|y| {
+            (0..width).all(|x| {
+                let mx = if flip_x { width - 1 - x } else { x };
+                let my = if flip_y { height - 1 - y } else { y };
+                image.pixel_at(x, y) == image.pixel_at(mx, my)
+            })
+        })
+    };
+
+    // an even width, with a brush that's even too
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    assert!(state.key_pressed(VirtualKeyCode::Y));
+    assert_eq!(Symmetry::Vertical, state.symmetry);
+    state.brush.set_size(4);
+    drag(&mut state, MouseButton::Left, (10.5, 10.5), (60.5, 30.5));
+    assert_eq!(white, state.canvas.pixel_at(10, 10));
+    assert_eq!(white, state.canvas.pixel_at(89, 10));
+    assert!(mirrored(&state.canvas, true, false));
+    assert!(!mirrored(&state.canvas, false, true));
+
+    // erasing is mirrored too
+    drag(&mut state, MouseButton::Right, (12.5, 12.5), (12.5, 12.5));
+    assert_eq!(0.0, state.canvas.pixel_at(87, 12).a);
+    assert!(mirrored(&state.canvas, true, false));
+
+    // an odd width has a center column, which is painted once, and both axes mirror all around
+    let mut state = State::without_backend(
+        PhysicalSize::new(99, 99),
+        Image::from_solid(99, 99, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.symmetry = Symmetry::Both;
+    state.brush.set_size(3);
+    state.brush.opacity = 0.5;
+    drag(&mut state, MouseButton::Left, (40.5, 20.5), (55.5, 20.5));
+    assert!(mirrored(&state.canvas, true, false));
+    assert!(mirrored(&state.canvas, false, true));
+    for x in 39..60 {
+        assert_eq!(0.5, state.canvas.pixel_at(x, 20).a, "{}", x);
+        assert_eq!(0.5, state.canvas.pixel_at(x, 78).a, "{}", x);
+    }
+
+    // and off, it's just the stroke
+    let mut state = State::without_backend(
+        PhysicalSize::new(99, 99),
+        Image::from_solid(99, 99, Pixel::TRANSPARENT).unwrap(),
+    );
+    for _ in 0..4 {
+        state.key_pressed(VirtualKeyCode::Y);
+    }
+    drag(&mut state, MouseButton::Left, (10.5, 10.5), (10.5, 10.5));
+    assert_eq!(white, state.canvas.pixel_at(10, 10));
+    assert_eq!(Pixel::TRANSPARENT, state.canvas.pixel_at(88, 10));
+}
+
+#[test]
+fn undo_and_redo_strokes() {
+    let blank = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    let mut state = State::without_backend(PhysicalSize::new(100, 100), blank.clone());
+    let move_to = |state: &mut State, to: (f32, f32)| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(to.0 as f64, to.1 as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    let stroke = |state: &mut State, points: &[(f32, f32)]| {
+        state.mouse.x = points[0].0;
+        state.mouse.y = points[0].1;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        for &point in &points[1..] {
+            move_to(state, point);
+        }
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    let key = |state: &mut State, modifiers, key| {
+        state.modifiers = modifiers;
+        let handled = state.key_pressed(key);
+        state.modifiers = ModifiersState::empty();
+        handled
+    };
+
+    // a stroke with a few bends in it is undone all at once
+    stroke(
+        &mut state,
+        &[(10.5, 10.5), (30.5, 10.5), (30.5, 40.5), (5.5, 60.5)],
+    );
+    let first = state.canvas.clone();
+    assert_ne!(blank, first);
+    stroke(&mut state, &[(70.5, 70.5), (90.5, 90.5)]);
+    let second = state.canvas.clone();
+
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(first, state.canvas);
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(blank, state.canvas);
+    assert!(!key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+
+    // and redone with either shortcut
+    assert!(key(
+        &mut state,
+        ModifiersState::CTRL | ModifiersState::SHIFT,
+        VirtualKeyCode::Z
+    ));
+    assert_eq!(first, state.canvas);
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Y));
+    assert_eq!(second, state.canvas);
+    assert!(!key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Y));
+
+    // erasing is an edit too, and a new edit after undoing means there's nothing to redo
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    state.input(&WindowEvent::MouseInput {
+        device_id: unsafe { winit::event::DeviceId::dummy() },
+        state: ElementState::Pressed,
+        button: MouseButton::Right,
+        modifiers: ModifiersState::empty(),
+    });
+    move_to(&mut state, (30.5, 10.5));
+    state.mouse_button(MouseButton::Right, ElementState::Released);
+    assert_ne!(first, state.canvas);
+    assert!(!key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Y));
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(first, state.canvas);
+
+    // nothing happens in the middle of a stroke
+    state.mouse.x = 50.5;
+    state.mouse.y = 50.5;
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    assert!(!key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(first, state.canvas);
+
+    // switching tools isn't an edit, but plain z still toggles independent zoom
+    assert!(key(&mut state, ModifiersState::empty(), VirtualKeyCode::M));
+    assert!(key(&mut state, ModifiersState::empty(), VirtualKeyCode::Z));
+    assert!(state.independent_zoom);
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(blank, state.canvas);
+}
+
+#[test]
+fn primary_secondary_and_palette() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    let key = |state: &mut State, modifiers, key| {
+        state.modifiers = modifiers;
+        let handled = state.key_pressed(key);
+        state.modifiers = ModifiersState::empty();
+        handled
+    };
+    let paint = |state: &mut State, button, x: f32| {
+        state.mouse.x = x;
+        state.mouse.y = 10.5;
+        state.mouse_button(button, ElementState::Pressed);
+        state.mouse_button(button, ElementState::Released);
+    };
+
+    // x swaps the colors, and the brush paints with whichever is primary
+    assert_eq!((white, black), (state.primary, state.secondary));
+    assert!(key(&mut state, ModifiersState::empty(), VirtualKeyCode::X));
+    assert_eq!((black, white), (state.primary, state.secondary));
+    paint(&mut state, MouseButton::Left, 10.5);
+    assert_eq!(black, state.canvas.pixel_at(10, 10));
+
+    // the number keys pick from the palette, in keyboard order
+    assert!(key(
+        &mut state,
+        ModifiersState::empty(),
+        VirtualKeyCode::Key4
+    ));
+    assert_eq!(red, state.primary);
+    assert_eq!(white, state.secondary);
+    paint(&mut state, MouseButton::Left, 20.5);
+    assert_eq!(red, state.canvas.pixel_at(20, 10));
+
+    // and shift puts the primary color into a slot
+    let orange = Pixel {
+        r: 1.0,
+        g: 0.5,
+        b: 0.25,
+        a: 1.0,
+    };
+    state.primary = orange;
+    assert!(key(&mut state, ModifiersState::SHIFT, VirtualKeyCode::Key0));
+    assert_eq!(orange, state.palette.get(Palette::SLOTS - 1));
+    assert!(key(
+        &mut state,
+        ModifiersState::empty(),
+        VirtualKeyCode::Key1
+    ));
+    assert_eq!(black, state.primary);
+    assert!(key(
+        &mut state,
+        ModifiersState::empty(),
+        VirtualKeyCode::Key0
+    ));
+    assert_eq!(orange, state.primary);
+
+    // with ctrl they set the opacity instead, leaving the colors alone
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Key5));
+    assert_eq!(0.5, state.brush.opacity);
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Key0));
+    assert_eq!(1.0, state.brush.opacity);
+    assert_eq!(orange, state.primary);
+
+    // the eraser doesn't care what color is picked
+    paint(&mut state, MouseButton::Right, 20.5);
+    assert_eq!(Pixel { a: 0.0, ..red }, state.canvas.pixel_at(20, 10));
+}
+
+#[test]
+fn pixel_perfect_strokes() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let stairs = [
+        (10.5, 10.5),
+        (11.5, 10.5),
+        (11.5, 11.5),
+        (12.5, 11.5),
+        (12.5, 12.5),
+    ];
+    let stroke = |state: &mut State| {
+        state.mouse.x = stairs[0].0;
+        state.mouse.y = stairs[0].1;
+        state.mouse_button(MouseButton::Left, ElementState::Pressed);
+        for &(x, y) in &stairs[1..] {
+            state.input(&WindowEvent::CursorMoved {
+                device_id: unsafe { winit::event::DeviceId::dummy() },
+                position: winit::dpi::PhysicalPosition::new(x as f64, y as f64),
+                modifiers: ModifiersState::empty(),
+            });
+        }
+        state.mouse_button(MouseButton::Left, ElementState::Released);
+    };
+    let painted = |state: &State| {
+        state
+            .canvas
+            .bounds()
+            .points()
+            .filter(|point| state.canvas.pixel_at(point.x as usize, point.y as usize) == white)
+            .map(|point| (point.x, point.y))
+            .collect::<Vec<_>>()
+    };
+
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    stroke(&mut state);
+    assert_eq!(5, painted(&state).len());
+
+    // p turns the corners of the stairs into a diagonal
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    assert!(state.key_pressed(VirtualKeyCode::P));
+    stroke(&mut state);
+    assert_eq!(vec![(10, 10), (11, 11), (12, 12)], painted(&state));
+
+    // but only with a 1 pixel brush
+    assert!(state.key_pressed(VirtualKeyCode::RBracket));
+    state.canvas = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    stroke(&mut state);
+    assert!(painted(&state).contains(&(11, 10)));
+}
+
+#[test]
+fn replace_picked_color() {
+    let gray = Pixel {
+        r: 0.5,
+        g: 0.5,
+        b: 0.5,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(100, 100, gray).unwrap();
+    canvas.set_pixel(50, 50, Pixel { r: 0.6, ..gray });
+    canvas.set_pixel(60, 60, Pixel { a: 0.5, ..gray });
+    let original = canvas.clone();
+    let mut state = State::without_backend(PhysicalSize::new(100, 100), canvas);
+    let key = |state: &mut State, modifiers, key| {
+        state.modifiers = modifiers;
+        let handled = state.key_pressed(key);
+        state.modifiers = ModifiersState::empty();
+        handled
+    };
+
+    // pick the gray, swap it into the secondary color, and pick red to replace it with
+    state.set_active_tool(Tool::Eyedropper);
+    state.mouse.x = 10.5;
+    state.mouse.y = 10.5;
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!(key(&mut state, ModifiersState::empty(), VirtualKeyCode::X));
+    assert!(key(
+        &mut state,
+        ModifiersState::empty(),
+        VirtualKeyCode::Key4
+    ));
+    assert_eq!((red, gray), (state.primary, state.secondary));
+
+    // only inside of the selection
+    state.selection = Some(Selection::from(Rect::new(0, 0, 20, 100)));
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::R));
+    assert_eq!(red, state.canvas.pixel_at(19, 99));
+    assert_eq!(gray, state.canvas.pixel_at(20, 0));
+
+    // and then everywhere, leaving colors that are too far off alone, as one undo
+    state.selection = None;
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::R));
+    assert_eq!(red, state.canvas.pixel_at(99, 99));
+    assert_eq!(Pixel { r: 0.6, ..gray }, state.canvas.pixel_at(50, 50));
+    assert_eq!(Pixel { a: 0.5, ..gray }, state.canvas.pixel_at(60, 60));
+    assert!(!key(&mut state, ModifiersState::CTRL, VirtualKeyCode::R));
+
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(red, state.canvas.pixel_at(19, 99));
+    assert_eq!(gray, state.canvas.pixel_at(20, 0));
+    assert!(key(&mut state, ModifiersState::CTRL, VirtualKeyCode::Z));
+    assert_eq!(original, state.canvas);
+
+    // with shift the replaced pixels keep their alpha
+    state.replace_tolerance = 0.5;
+    assert!(key(
+        &mut state,
+        ModifiersState::CTRL | ModifiersState::SHIFT,
+        VirtualKeyCode::R
+    ));
+    assert_eq!(Pixel { a: 0.5, ..red }, state.canvas.pixel_at(60, 60));
+    assert_eq!(red, state.canvas.pixel_at(50, 50));
+}
+
+#[test]
+fn nudge_floating_selection() {
+    use crate::{geometry::Rect, image::ImageBuilder};
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    let canvas = ImageBuilder::new().size(4, 1).fill(blue).build();
+    let mut state = State::without_backend(PhysicalSize::new(800, 600), canvas);
+    state.canvas.set_pixel(3, 0, red);
+    state.floating = Some(FloatingSelection::lift(
+        &mut state.canvas,
+        &Selection::from(Rect::new(3, 0, 1, 1)),
+    ));
+
+    // nudged off the edge of the canvas and clipped, leaving the hole it was lifted from
+    assert!(state.key_pressed(VirtualKeyCode::Right));
+    assert_eq!(4, state.floating.as_ref().unwrap().position.x);
+    let display = selection::composited(&state.canvas, state.floating.as_ref());
+    assert_eq!(Pixel::TRANSPARENT, display.pixel_at(3, 0));
+
+    assert!(state.key_pressed(VirtualKeyCode::Left));
+    assert!(state.key_pressed(VirtualKeyCode::Left));
+    assert_eq!(2, state.floating.as_ref().unwrap().position.x);
+    let display = selection::composited(&state.canvas, state.floating.as_ref());
+    assert_eq!(red, display.pixel_at(2, 0));
+    assert_eq!(Pixel::TRANSPARENT, display.pixel_at(3, 0));
+
+    // keys that don't do anything to the floating pixels work like usual
+    let (primary, secondary) = (state.primary, state.secondary);
+    assert!(state.key_pressed(VirtualKeyCode::X));
+    assert_eq!((secondary, primary), (state.primary, state.secondary));
+    assert!(state.floating.is_some());
+    assert!(!state.key_pressed(VirtualKeyCode::F12));
+
+    // the canvas itself isn't touched until the selection is committed
+    assert_eq!(blue, state.canvas.pixel_at(2, 0));
+    assert!(state.key_pressed(VirtualKeyCode::Return));
+    assert!(state.floating.is_none());
+    assert_eq!(red, state.canvas.pixel_at(2, 0));
+}
+
+#[test]
+fn export_selection_or_canvas() {
+    use crate::{geometry::Rect, image::ImageBuilder};
+    use std::time::Duration;
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    let canvas = ImageBuilder::new().size(4, 3).fill(blue).build();
+    let mut state = State::without_backend(PhysicalSize::new(800, 600), canvas);
+    state.canvas.set_pixel(2, 1, red);
+    state.io = Some(IoWorker::new(1, || {}));
+
+    let path = std::env::temp_dir().join(format!(
+        "yocto-canvas-export-test-{}.png",
+        std::process::id()
+    ));
+    let export = |state: &mut State| {
+        let id = state.export(path.clone()).unwrap();
+        match state
+            .io
+            .as_ref()
+            .unwrap()
+            .wait_for(id, Duration::from_secs(10))
+        {
+            Some((JobResult::Saved(_, Ok(())), _)) => {}
+            other => panic!(
+                "export didn't finish: {:?}",
+                other.map(|(result, _)| result)
+            ),
+        }
+        Image::load(&path, Alpha::Straight).unwrap()
+    };
+
+    // just what's under the selection
+    state.floating = Some(FloatingSelection::lift(
+        &mut state.canvas,
+        &Selection::from(Rect::new(1, 1, 2, 1)),
+    ));
+    let exported = export(&mut state);
+    assert_eq!((2, 1), (exported.width(), exported.height()));
+    assert_eq!(blue, exported.pixel_at(0, 0));
+    assert_eq!(red, exported.pixel_at(1, 0));
+
+    // and the whole canvas without one
+    state.commit_floating();
+    let exported = export(&mut state);
+    assert_eq!((4, 3), (exported.width(), exported.height()));
+    assert_eq!(red, exported.pixel_at(2, 1));
+    assert_eq!(blue, exported.pixel_at(0, 0));
+
+    // a selection off the canvas has nothing to export
+    state.floating = Some(FloatingSelection::lift(
+        &mut state.canvas,
+        &Selection::from(Rect::new(0, 0, 1, 1)),
+    ));
+    state.floating.as_mut().unwrap().nudge(-5, 0);
+    assert_eq!(None, state.export(path.clone()));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn load_replaces_canvas_in_the_background() {
+    use crate::geometry::Size;
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let path =
+        std::env::temp_dir().join(format!("yocto-canvas-load-test-{}.png", std::process::id()));
+    Image::from_solid(3, 2, red)
+        .unwrap()
+        .save(&path, Alpha::Straight)
+        .unwrap();
+
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.io = Some(IoWorker::new(1, || {}));
+    state.selection = Some(Selection::from(Rect::new(0, 0, 2, 2)));
+    state.last_point = Some(Point::new(4, 4));
+    state.load(path.clone()).unwrap();
+
+    // nothing changes until the event loop picks up the result
+    assert_eq!(Size::new(8, 8), state.canvas.size());
+    let start = Instant::now();
+    while !state.poll_io() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "load didn't finish"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(Size::new(3, 2), state.canvas.size());
+    assert_eq!(red, state.canvas.pixel_at(2, 1));
+    assert_eq!(None, state.selection);
+    assert_eq!(None, state.last_point);
+    // symmetry mirrors around the middle of the new canvas
+    assert_eq!(Point::new(3, 2), state.symmetry_axis);
+    assert!(!state.undo());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recovery_is_offered_then_deleted() {
+    use crate::{composite::nodes::Source, geometry::Size};
+
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let path = std::env::temp_dir().join(format!(
+        "yocto-canvas-recovery-test-{}.png",
+        std::process::id()
+    ));
+    let session = |canvas| {
+        let mut state = State::without_backend(PhysicalSize::new(800, 600), canvas);
+        state.io = Some(IoWorker::new(1, || {}));
+        state.autosave = Some(AutoSave::new(&path, Duration::ZERO, Instant::now()));
+        state
+    };
+
+    // crash after an autosave, without exiting
+    let mut crashed = session(Image::from_solid(3, 2, red).unwrap());
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(crashed.canvas.clone().into_data())));
+    crashed.graph = Some((
+        graph,
+        Port {
+            node_name: source,
+            slot_name: Source::OUTPUT,
+        },
+    ));
+    crashed.mark_dirty();
+    crashed.autosave();
+    drop(crashed);
+
+    let mut state = session(Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap());
+    state.recovery_offered = true;
+    assert!(state.title().contains("ctrl+shift+o"));
+
+    state.modifiers = ModifiersState::CTRL | ModifiersState::SHIFT;
+    assert!(state.key_pressed(VirtualKeyCode::O));
+    assert!(!state.recovery_offered);
+    let start = Instant::now();
+    while state.graph.is_none() || state.canvas.size() != Size::new(3, 2) {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "restore didn't finish"
+        );
+        state.poll_io();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(red, state.canvas.pixel_at(2, 1));
+    assert!(!path.exists());
+    assert!(!path.with_extension("json").exists());
+
+    // the restored work is saved again, until a clean exit
+    assert!(state.autosave().is_none());
+    state.exit();
+    assert!(!path.exists());
+    assert!(!path.with_extension("json").exists());
+}
+
+#[test]
+fn render_failures() {
+    let canvas = Image::from_solid(4, 4, Pixel::TRANSPARENT).unwrap();
+    let mut state = State::without_backend(PhysicalSize::new(800, 600), canvas);
+
+    assert!(matches!(
+        state.render_failed(SwapChainError::Timeout.into()),
+        RenderFailure::Skipped(message) if message.starts_with("couldn't render")
+    ));
+    assert!(matches!(
+        state.render_failed(SwapChainError::OutOfMemory.into()),
+        RenderFailure::Fatal(_)
+    ));
+    assert_eq!(
+        RenderFailure::Recreated,
+        state.render_failed(SwapChainError::Lost.into())
+    );
+    assert!(matches!(
+        state.render_failed(anyhow::anyhow!("no canvas")),
+        RenderFailure::Skipped(message) if message.ends_with("no canvas")
+    ));
+}
+
+#[test]
+fn space_toggles_playback() {
+    use crate::composite::nodes::Source;
+
+    let canvas = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+    let mut state = State::without_backend(PhysicalSize::new(800, 600), canvas.clone());
+    assert!(!state.playback.is_playing());
+
+    // there's nothing to play without a graph
+    assert!(!state.key_pressed(VirtualKeyCode::Space));
+    assert!(!state.playback.is_playing());
+
+    let mut graph = NodeGraph::new();
+    let source = graph.add(Box::new(Source::new(canvas.into_data())));
+    state.graph = Some((
+        graph,
+        Port {
+            node_name: source,
+            slot_name: Source::OUTPUT,
+        },
+    ));
+
+    assert_eq!(Quality::Full, state.preview_quality());
+    assert!(state.key_pressed(VirtualKeyCode::Space));
+    assert!(state.playback.is_playing());
+    assert_eq!(Quality::Preview, state.preview_quality());
+
+    // evaluated small, but shown over the whole canvas
+    state.evaluate_preview();
+    assert_eq!(
+        Some(state.canvas.size()),
+        state.preview.as_ref().map(Image::size)
+    );
+
+    // pausing shows the canvas again
+    assert!(state.key_pressed(VirtualKeyCode::Space));
+    assert!(!state.playback.is_playing());
+    assert_eq!(None, state.preview);
+
+    state.mouse.left = ElementState::Pressed;
+    assert_eq!(Quality::Preview, state.preview_quality());
+}
+
+#[test]
+fn pinch_takes_over_from_wheel_zoom() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.smooth_zoom = true;
+
+    // the wheel starts animating towards one step in, and the pinch picks up from there
+    state.zoom_by(1.0);
+    assert!(state.zoom_animation.is_some());
+    state.magnify(0.25);
+    assert!(state.zoom_animation.is_none());
+    assert_eq!(View::ZOOM_STEP * 1.25, state.view.zoom());
+
+    state.zoom_by(1.0);
+    state.animate(Instant::now() + ZoomAnimation::DEFAULT_DURATION);
+    assert_eq!(View::ZOOM_STEP * 1.25 * View::ZOOM_STEP, state.view.zoom());
+}
+
+#[test]
+fn trackpad_zooms_with_ctrl() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.smooth_zoom = false;
+    let scroll = |state: &mut State, y: f32| {
+        #[allow(deprecated)]
+        state.input(&WindowEvent::MouseWheel {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            delta: MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition::new(0.0, y as f64)),
+            phase: winit::event::TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        });
+    };
+
+    // two fingers pan
+    scroll(&mut state, 10.0);
+    assert_eq!(1.0, state.view.zoom());
+    assert_eq!(10.0, state.view.pan_y);
+
+    // and zoom in proportion to how far they went with ctrl
+    state.modifiers = ModifiersState::CTRL;
+    scroll(&mut state, PIXELS_PER_ZOOM_STEP / 2.0);
+    scroll(&mut state, PIXELS_PER_ZOOM_STEP / 2.0);
+    assert!((state.view.zoom() - View::ZOOM_STEP).abs() < 1e-5);
+    scroll(&mut state, -PIXELS_PER_ZOOM_STEP * 10.0);
+    assert!(state.view.zoom() < 1.0);
+    assert_eq!(10.0, state.view.pan_y);
+}
+
+#[test]
+fn fit_and_actual_size_keys() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(100, 50, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.smooth_zoom = false;
+    state.view.set_zoom(5.0);
+    state.view.pan_by(40.0, -20.0);
+    state.modifiers = ModifiersState::ALT;
+
+    // the palette is left alone
+    let primary = state.primary;
+    assert!(state.key_pressed(VirtualKeyCode::Key1));
+    assert_eq!(primary, state.primary);
+    assert_eq!(
+        Some((0, 0)),
+        state.view.canvas_pixel(100, 50, state.size, 350.5, 275.5)
+    );
+    assert_eq!(
+        None,
+        state.view.canvas_pixel(100, 50, state.size, 349.5, 275.5)
+    );
+
+    // fitting again after the window changes size uses the new size, leaving the margin at the
+    // sides, which are the tight fit for a wide canvas
+    let left_edge = |state: &State| {
+        let (x, _) = state
+            .view
+            .screen_to_canvas(100, 50, state.size, FIT_MARGIN, 300.);
+        x
+    };
+    assert!(state.key_pressed(VirtualKeyCode::Key0));
+    assert!(left_edge(&state).abs() < 1e-3);
+    state.resize(PhysicalSize::new(400, 600));
+    assert!(left_edge(&state).abs() > 1.0);
+    assert!(state.key_pressed(VirtualKeyCode::Key0));
+    assert!(left_edge(&state).abs() < 1e-3);
+    assert_eq!((0.0, 0.0), (state.view.pan_x, state.view.pan_y));
+}
+
+#[test]
+fn paint_under_the_cursor_in_a_rotated_view() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.last_point = None;
+
+    state.modifiers = ModifiersState::SHIFT;
+    assert!(state.key_pressed(VirtualKeyCode::Period));
+    assert_eq!(FINE_VIEW_ROTATE_STEP, state.view.rotation);
+    state.modifiers = ModifiersState::empty();
+    assert!(state.key_pressed(VirtualKeyCode::R));
+    for _ in 0..6 {
+        assert!(state.key_pressed(VirtualKeyCode::Period));
+    }
+    assert_eq!(90., state.view.rotation);
+
+    // turning the view doesn't touch the pixels
+    assert_eq!(
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+        state.canvas
+    );
+
+    state.input(&WindowEvent::CursorMoved {
+        device_id: unsafe { winit::event::DeviceId::dummy() },
+        position: winit::dpi::PhysicalPosition::new(10.5, 20.5),
+        modifiers: ModifiersState::empty(),
+    });
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+
+    // the top left of the window is the top right of the canvas, turned a quarter to the left
+    let painted = (0..100)
+        .flat_map(|y| (0..100).map(move |x| (x, y)))
+        .filter(|&(x, y)| state.canvas.pixel_at(x, y) != Pixel::TRANSPARENT)
+        .collect::<Vec<_>>();
+    assert_eq!(vec![(79, 10)], painted);
+}
+
+#[test]
+fn minimap_clicks_move_the_view() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(400, 200, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.view.set_zoom(4.0);
+    let move_to = |state: &mut State, x: f32, y: f32| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(x as f64, y as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+    let centered = |state: &State| {
+        let (x, y) = state
+            .view
+            .screen_to_canvas(400, 200, state.size, 400., 300.);
+        (x.round(), y.round())
+    };
+
+    // pressing on the minimap jumps there, and dragging follows, without painting
+    let minimap = state.minimap().unwrap();
+    let (left, top) = (minimap.bounds.left() as f32, minimap.bounds.top() as f32);
+    move_to(&mut state, left + 15., top + 15.);
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    assert_eq!((40., 40.), centered(&state));
+    move_to(&mut state, left + 75., top + 30.);
+    assert_eq!((200., 80.), centered(&state));
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!(!state.navigating);
+    assert_eq!(
+        Image::from_solid(400, 200, Pixel::TRANSPARENT).unwrap(),
+        state.canvas
+    );
+
+    // hidden, it doesn't get in the way
+    assert!(state.key_pressed(VirtualKeyCode::Tab));
+    assert_eq!(None, state.minimap());
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert_ne!(
+        Image::from_solid(400, 200, Pixel::TRANSPARENT).unwrap(),
+        state.canvas
+    );
+}
+
+#[test]
+fn dragging_at_the_edge_scrolls() {
+    use std::time::Duration;
+
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.view.set_zoom(2.0);
+    state.last_point = None;
+    let move_to = |state: &mut State, x: f32, y: f32| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(x as f64, y as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+
+    // nothing happens near the edge without a drag
+    move_to(&mut state, 50.5, 50.5);
+    move_to(&mut state, 99.5, 50.5);
+    assert_eq!(None, state.autoscroll_at);
+
+    // the closer to the edge, the faster
+    move_to(&mut state, 50.5, 50.5);
+    state.mouse_button(MouseButton::Left, ElementState::Pressed);
+    move_to(&mut state, 80.5, 50.5);
+    let (slow, _) = state.autoscroll_velocity();
+    move_to(&mut state, 99.5, 50.5);
+    let (fast, vertical) = state.autoscroll_velocity();
+    assert!(fast < slow && slow < 0.);
+    assert_eq!(0., vertical);
+    assert!(state.autoscroll_at.is_some());
+
+    // half a second of scrolling right keeps painting along the row as the canvas moves under
+    // the pointer
+    let start = state.autoscroll_at.unwrap();
+    let before = state.canvas_position((99.5, 50.5));
+    assert!(state.autoscroll(start + Duration::from_millis(250)));
+    assert!(state.autoscroll(start + Duration::from_millis(500)));
+    let after = state.canvas_position((99.5, 50.5));
+    assert!(after.0 > before.0 + 50. && after.1 == before.1);
+    let row = state.canvas_position((0., 50.5)).1 as usize;
+    for x in (75..after.0.min(100.) as usize).step_by(5) {
+        assert_ne!(Pixel::TRANSPARENT, state.canvas.pixel_at(x, row), "{}", x);
+    }
+
+    // and stops when the button comes up
+    state.mouse_button(MouseButton::Left, ElementState::Released);
+    assert!(!state.autoscroll(start + Duration::from_millis(750)));
+    assert_eq!(None, state.autoscroll_at);
+}
+
+#[test]
+fn fit_animates_zoom_and_pan() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(800, 600),
+        Image::from_solid(100, 50, Pixel::TRANSPARENT).unwrap(),
+    );
+    state.smooth_zoom = true;
+    state.view.set_zoom(8.0);
+    state.view.pan_by(200.0, -100.0);
+    let start = Instant::now();
+
+    state.modifiers = ModifiersState::ALT;
+    assert!(state.key_pressed(VirtualKeyCode::Key0));
+    let mut target = state.view;
+    target.fit(100, 50, state.size, FIT_MARGIN);
+    assert_eq!(8.0, state.view.zoom());
+
+    // partway there, painting goes where the canvas is on screen right now
+    state.animate(start + ZoomAnimation::DEFAULT_DURATION / 3);
+    assert!(state.view.zoom() < 8.0 && state.view.zoom() > target.zoom());
+    assert!(state.view.pan_x < 200.0 && state.view.pan_x > 0.0);
+    let (x, y) = state.view.canvas_to_screen(100, 50, state.size, 10.5, 10.5);
+    assert_eq!(Point::new(10, 10), state.canvas_point((x, y)));
+
+    // the wheel retargets the zoom, but the pan still ends up centered
+    state.modifiers = ModifiersState::empty();
+    state.zoom_by(1.0);
+    assert!(state.animate(Instant::now()));
+    assert!(!state.animate(Instant::now() + ZoomAnimation::DEFAULT_DURATION));
+    assert!((state.view.zoom() - View::step_zoom(target.zoom(), 1.0)).abs() < 1e-5);
+    assert_eq!((0.0, 0.0), (state.view.pan_x, state.view.pan_y));
+}
+
+#[test]
+fn title_follows_the_pointer_but_not_too_often() {
+    let mut state = State::without_backend(
+        PhysicalSize::new(100, 100),
+        Image::from_solid(50, 50, Pixel::TRANSPARENT).unwrap(),
+    );
+    let start = Instant::now();
+    let move_to = |state: &mut State, x: f32, y: f32| {
+        state.input(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::PhysicalPosition::new(x as f64, y as f64),
+            modifiers: ModifiersState::empty(),
+        });
+    };
+
+    // two window pixels per canvas pixel
+    move_to(&mut state, 21., 41.);
+    assert_eq!(
+        Some(String::from(
+            "yocto-canvas - brush - 1px - #ffffffff - (10, 20) #00000000 - 200%"
+        )),
+        state.refresh_title(start)
+    );
+    assert_eq!(None, state.title_deadline());
+
+    // moving again soon after waits, then catches up
+    move_to(&mut state, 200., 41.);
+    assert_eq!(Some(start + TITLE_INTERVAL), state.title_deadline());
+    assert_eq!(None, state.refresh_title(start + TITLE_INTERVAL / 2));
+    assert_eq!(
+        Some(String::from(
+            "yocto-canvas - brush - 1px - #ffffffff - - - 200%"
+        )),
+        state.refresh_title(start + TITLE_INTERVAL)
+    );
+}
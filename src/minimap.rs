@@ -0,0 +1,140 @@
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    geometry::{Point, Rect, Size},
+    view::View,
+};
+
+/// A small picture of the whole canvas in the bottom right corner of the window, with the part of
+/// the canvas that's in view outlined. Clicking or dragging in it moves the view there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Minimap {
+    /// Where the minimap is in the window, in window pixels.
+    pub bounds: Rect,
+    /// The part of the canvas that's in view, as fractions of the canvas from 0 to 1, in the
+    /// order left, top, right, bottom. With the view rotated it's the box around what's visible.
+    pub visible: [f32; 4],
+}
+
+impl Minimap {
+    /// The longest side of the minimap, in window pixels.
+    pub const MAX_SIZE: u32 = 160;
+    /// Smaller windows don't get a minimap, it would just be in the way.
+    pub const MIN_SIZE: u32 = 48;
+    /// Space between the minimap and the edges of the window.
+    pub const MARGIN: u32 = 12;
+
+    /// Lay the minimap out for the canvas, or nothing if the window is too small for one.
+    pub fn new(view: &View, canvas: Size, window: PhysicalSize<u32>) -> Option<Minimap> {
+        if canvas.is_empty() {
+            return None;
+        }
+
+        let side = Minimap::MAX_SIZE.min(window.width.min(window.height) / 4);
+        if side < Minimap::MIN_SIZE {
+            return None;
+        }
+
+        // the same shape as the canvas
+        let (width, height) = if canvas.width >= canvas.height {
+            let height = side as f32 * canvas.height as f32 / canvas.width as f32;
+            (side, (height.round() as u32).max(1))
+        } else {
+            let width = side as f32 * canvas.width as f32 / canvas.height as f32;
+            ((width.round() as u32).max(1), side)
+        };
+        let bounds = Rect::new(
+            (window.width - Minimap::MARGIN - width) as i32,
+            (window.height - Minimap::MARGIN - height) as i32,
+            width,
+            height,
+        );
+
+        // where the corners of the window are on the canvas
+        let corners = [
+            (0.0, 0.0),
+            (window.width as f32, 0.0),
+            (0.0, window.height as f32),
+            (window.width as f32, window.height as f32),
+        ]
+        .map(|(x, y)| view.screen_to_canvas(canvas.width, canvas.height, window, x, y));
+        let (mut left, mut top) = (f32::INFINITY, f32::INFINITY);
+        let (mut right, mut bottom) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for (x, y) in corners {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+
+        let (width, height) = (canvas.width as f32, canvas.height as f32);
+        Some(Minimap {
+            bounds,
+            visible: [
+                (left / width).clamp(0.0, 1.0),
+                (top / height).clamp(0.0, 1.0),
+                (right / width).clamp(0.0, 1.0),
+                (bottom / height).clamp(0.0, 1.0),
+            ],
+        })
+    }
+
+    /// Whether a position in the window is on the minimap.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        self.bounds
+            .contains(Point::new(x.floor() as i32, y.floor() as i32))
+    }
+
+    /// The position on the canvas shown at a position in the window, which is clamped to the
+    /// minimap, so dragging off the edge keeps going along it.
+    pub fn canvas_position(&self, canvas: Size, x: f32, y: f32) -> (f32, f32) {
+        let fraction_x = (x - self.bounds.left() as f32) / self.bounds.size.width as f32;
+        let fraction_y = (y - self.bounds.top() as f32) / self.bounds.size.height as f32;
+        (
+            fraction_x.clamp(0.0, 1.0) * canvas.width as f32,
+            fraction_y.clamp(0.0, 1.0) * canvas.height as f32,
+        )
+    }
+
+    /// Pan the view so the part of the canvas under a position on the minimap is in the middle
+    /// of the window.
+    pub fn pan_to(&self, view: &mut View, canvas: Size, window: PhysicalSize<u32>, x: f32, y: f32) {
+        let (canvas_x, canvas_y) = self.canvas_position(canvas, x, y);
+        let (screen_x, screen_y) =
+            view.canvas_to_screen(canvas.width, canvas.height, window, canvas_x, canvas_y);
+        view.pan_by(
+            window.width as f32 / 2.0 - screen_x,
+            window.height as f32 / 2.0 - screen_y,
+        );
+    }
+}
+
+#[test]
+fn minimap_layout_and_panning() {
+    let window = PhysicalSize::new(800, 600);
+    let canvas = Size::new(400, 200);
+    let mut view = View::default();
+
+    // wide like the canvas, in the bottom right corner
+    let minimap = Minimap::new(&view, canvas, window).unwrap();
+    assert_eq!(Rect::new(638, 513, 150, 75), minimap.bounds);
+    assert_eq!([0.0, 0.0, 1.0, 1.0], minimap.visible);
+    assert!(minimap.contains(638.5, 513.5));
+    assert!(!minimap.contains(637.5, 513.5));
+    assert!(Minimap::new(&view, canvas, PhysicalSize::new(150, 150)).is_none());
+
+    // zoomed in on the middle, a quarter of the canvas is in view each way
+    view.set_zoom(4.0);
+    let minimap = Minimap::new(&view, canvas, window).unwrap();
+    let [left, top, right, bottom] = minimap.visible;
+    assert!((left - 0.375).abs() < 1e-4 && (right - 0.625).abs() < 1e-4);
+    assert!(top < 0.375 && bottom > 0.625);
+
+    // clicking the top left of the minimap brings the top left of the canvas to the middle
+    minimap.pan_to(&mut view, canvas, window, 638.0, 513.0);
+    let (x, y) = view.screen_to_canvas(400, 200, window, 400.0, 300.0);
+    assert!(x.abs() < 1e-3 && y.abs() < 1e-3);
+    let minimap = Minimap::new(&view, canvas, window).unwrap();
+    assert_eq!(0.0, minimap.visible[0]);
+    assert!(minimap.visible[2] < 0.2);
+}
@@ -0,0 +1,98 @@
+//! Gradient noise, for procedural textures.
+
+/// Settings for layering octaves of noise on top of each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fractal {
+    pub seed: u32,
+    /// How many layers of noise, at least one.
+    pub octaves: u32,
+    /// How much each octave's amplitude is multiplied by compared to the last.
+    pub persistence: f32,
+    /// How much each octave's frequency is multiplied by compared to the last.
+    pub lacunarity: f32,
+}
+
+impl Fractal {
+    /// Sum the octaves at a point, scaled back to `[0, 1]`. The first octave has features about
+    /// one unit across.
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut max = 0.;
+        let mut frequency = 1.;
+
+        for octave in 0..self.octaves.max(1) {
+            // a different seed per octave, so the octaves don't line up at the origin
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+            total += perlin(x * frequency, y * frequency, seed) * amplitude;
+            max += amplitude;
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity;
+        }
+
+        if max > 0. {
+            (total / max * 0.5 + 0.5).clamp(0., 1.)
+        } else {
+            0.5
+        }
+    }
+}
+
+/// Perlin noise, roughly in `[-1, 1]`, zero on every integer point. The same seed always gives the
+/// same noise.
+pub fn perlin(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (ix, iy) = (x0 as i32, y0 as i32);
+
+    // dot product of the distance to a corner with that corner's gradient
+    let corner = |cx: i32, cy: i32| {
+        let angle = hash(ix.wrapping_add(cx), iy.wrapping_add(cy), seed) as f32 / u32::MAX as f32
+            * std::f32::consts::TAU;
+        let (dx, dy) = (fx - cx as f32, fy - cy as f32);
+        angle.cos() * dx + angle.sin() * dy
+    };
+
+    let (u, v) = (fade(fx), fade(fy));
+    let top = lerp(corner(0, 0), corner(1, 0), u);
+    let bottom = lerp(corner(0, 1), corner(1, 1), u);
+
+    // the farthest a unit gradient can reach in a cell is sqrt(2)/2
+    lerp(top, bottom, v) * std::f32::consts::SQRT_2
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// scramble a lattice point, so neighboring points get unrelated gradients
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (y as u32).wrapping_mul(0x1656_67b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+#[test]
+fn perlin_is_smooth_and_bounded() {
+    for &seed in &[0, 1, 12345] {
+        assert_eq!(0.0, perlin(3.0, -2.0, seed));
+
+        for i in 0..200 {
+            let (x, y) = (i as f32 * 0.37 - 40.0, i as f32 * 0.11 + 5.0);
+            let value = perlin(x, y, seed);
+            assert!((-1.0..=1.0).contains(&value), "{} at {}, {}", value, x, y);
+
+            // no jumps between cells
+            assert!((value - perlin(x + 0.001, y, seed)).abs() < 0.01);
+        }
+    }
+
+    assert_ne!(perlin(0.5, 0.5, 1), perlin(0.5, 0.5, 2));
+}
@@ -0,0 +1,150 @@
+//! Playing animated node graphs back in real time.
+
+use std::time::{Duration, Instant};
+
+/// What happens when playback reaches the end of the animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndBehavior {
+    /// Start over from the beginning.
+    Loop,
+    /// Stop on the last frame.
+    Clamp,
+}
+
+/// Whether an animation is playing, and which frame it's on.
+///
+/// Time advances a whole frame at a time, so the graph is only evaluated at multiples of
+/// `1 / fps` no matter how often the event loop wakes up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    pub fps: f32,
+    pub duration: Duration,
+    pub end: EndBehavior,
+    frame: u32,
+    // when the current frame was shown, while playing
+    shown: Option<Instant>,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback::new(24.0, Duration::from_secs(2), EndBehavior::Loop)
+    }
+}
+
+impl Playback {
+    /// Paused on the first frame.
+    pub fn new(fps: f32, duration: Duration, end: EndBehavior) -> Self {
+        Playback {
+            fps,
+            duration,
+            end,
+            frame: 0,
+            shown: None,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.shown.is_some()
+    }
+
+    /// Play if paused, or pause if playing. Playing a clamped animation that's stopped at the end
+    /// starts it over.
+    pub fn toggle(&mut self, now: Instant) {
+        if self.is_playing() {
+            self.shown = None;
+        } else {
+            if self.end == EndBehavior::Clamp && self.frame + 1 >= self.frames() {
+                self.frame = 0;
+            }
+            self.shown = Some(now);
+        }
+    }
+
+    /// Seconds into the animation of the current frame.
+    pub fn time(&self) -> f32 {
+        self.frame as f32 / self.fps
+    }
+
+    /// How many frames there are in the whole animation, at least one.
+    pub fn frames(&self) -> u32 {
+        ((self.duration.as_secs_f32() * self.fps).round() as u32).max(1)
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.fps)
+    }
+
+    /// When the next frame is due, if playing.
+    pub fn next_frame(&self) -> Option<Instant> {
+        Some(self.shown? + self.frame_interval())
+    }
+
+    /// Advance to whichever frame should be showing at `now`, returning whether the frame
+    /// changed. Frames that were missed are skipped rather than played late.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let shown = match self.shown {
+            Some(shown) => shown,
+            None => return false,
+        };
+
+        let elapsed = now.saturating_duration_since(shown).as_secs_f64();
+        let behind = (elapsed * self.fps as f64).floor() as u32;
+        if behind == 0 {
+            return false;
+        }
+
+        self.shown = Some(shown + self.frame_interval() * behind);
+        let frames = self.frames();
+        let before = self.frame;
+        match self.end {
+            EndBehavior::Loop => {
+                self.frame = ((self.frame as u64 + behind as u64) % frames as u64) as u32
+            }
+            EndBehavior::Clamp => {
+                self.frame = self.frame.saturating_add(behind).min(frames - 1);
+                if self.frame == frames - 1 {
+                    self.shown = None;
+                }
+            }
+        }
+
+        self.frame != before
+    }
+}
+
+#[test]
+fn play_advances_and_pause_freezes() {
+    let start = Instant::now();
+    let second = Duration::from_secs(1);
+    let mut playback = Playback::new(10.0, second, EndBehavior::Loop);
+
+    // nothing happens while paused
+    assert!(!playback.tick(start + second));
+    assert_eq!(0.0, playback.time());
+    assert_eq!(None, playback.next_frame());
+
+    playback.toggle(start);
+    assert!(playback.is_playing());
+    assert!(!playback.tick(start + Duration::from_millis(50)));
+    assert!(playback.tick(start + Duration::from_millis(250)));
+    assert_eq!(0.2, playback.time());
+
+    playback.toggle(start + Duration::from_millis(250));
+    assert!(!playback.is_playing());
+    assert!(!playback.tick(start + Duration::from_secs(5)));
+    assert_eq!(0.2, playback.time());
+
+    // loops back around
+    playback.toggle(start);
+    assert!(playback.tick(start + Duration::from_millis(950)));
+    assert_eq!(0.1, playback.time());
+
+    // or stops at the end, and starts over when played again
+    let mut playback = Playback::new(10.0, second, EndBehavior::Clamp);
+    playback.toggle(start);
+    assert!(playback.tick(start + Duration::from_secs(3)));
+    assert_eq!(0.9, playback.time());
+    assert!(!playback.is_playing());
+    playback.toggle(start);
+    assert_eq!(0.0, playback.time());
+}
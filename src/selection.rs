@@ -0,0 +1,565 @@
+use crate::{
+    geometry::{Point, Rect, Size},
+    image::{Filter, Image, ImageBuilder, Pixel},
+};
+
+use std::borrow::Cow;
+
+/// The part of the canvas that's selected: a rectangle, or any shape of pixels inside of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    bounds: Rect,
+    // which of the pixels in `bounds` are selected, row by row. `None` when all of them are
+    mask: Option<Vec<bool>>,
+}
+
+impl From<Rect> for Selection {
+    fn from(rect: Rect) -> Selection {
+        Selection {
+            bounds: rect,
+            mask: None,
+        }
+    }
+}
+
+impl Selection {
+    /// The pixels of a canvas whose centers are inside of a closed path, by the even-odd rule, so
+    /// the parts where the path crosses over itself an even number of times are left out. The
+    /// path is closed from its last point back to its first, and is in canvas pixels. `None` if
+    /// that doesn't select anything on the canvas.
+    pub fn lasso(path: &[(f32, f32)], canvas: Size) -> Option<Selection> {
+        if path.len() < 3 {
+            return None;
+        }
+
+        let (mut left, mut top) = (f32::INFINITY, f32::INFINITY);
+        let (mut right, mut bottom) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for &(x, y) in path {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+        if !(left.is_finite() && top.is_finite() && right.is_finite() && bottom.is_finite()) {
+            return None;
+        }
+        let (left, top) = (left.floor() as i32, top.floor() as i32);
+        let (right, bottom) = (right.ceil() as i32, bottom.ceil() as i32);
+        let bounds =
+            Rect::new(left, top, (right - left) as u32, (bottom - top) as u32).clamp_to(canvas);
+        if bounds.is_empty() {
+            return None;
+        }
+
+        let width = bounds.size.width as usize;
+        let mut mask = vec![false; bounds.size.area()];
+        let mut crossings = Vec::new();
+        for row in 0..bounds.size.height as usize {
+            // where each edge crosses the middle of the row. an edge counts from its top end but
+            // not its bottom one, so a corner the path turns around at isn't counted twice
+            let cy = (bounds.top() + row as i32) as f32 + 0.5;
+            crossings.clear();
+            for (i, &(x0, y0)) in path.iter().enumerate() {
+                let (x1, y1) = path[(i + 1) % path.len()];
+                if (y0 <= cy) != (y1 <= cy) {
+                    crossings.push(x0 + (cy - y0) * (x1 - x0) / (y1 - y0));
+                }
+            }
+            crossings.sort_by(f32::total_cmp);
+
+            // inside from every odd crossing to the next one
+            for span in crossings.chunks_exact(2) {
+                for col in 0..width {
+                    let cx = (bounds.left() + col as i32) as f32 + 0.5;
+                    if span[0] <= cx && cx < span[1] {
+                        mask[row * width + col] = true;
+                    }
+                }
+            }
+        }
+
+        if mask.iter().any(|&selected| selected) {
+            Some(Selection {
+                bounds,
+                mask: Some(mask),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The pixels that are set in a mask the size of a canvas, row by row, like from
+    /// `Image::region`. `None` if none of them are.
+    pub fn from_mask(canvas: Size, mask: &[bool]) -> Option<Selection> {
+        let width = canvas.width as usize;
+        Selection::trimmed(Rect::from_size(canvas), |point| {
+            mask[point.y as usize * width + point.x as usize]
+        })
+    }
+
+    // the pixels inside of `bounds` that are selected, in a mask only as big as it needs to be
+    fn trimmed(bounds: Rect, selected: impl Fn(Point) -> bool) -> Option<Selection> {
+        let mut points = bounds.points().filter(|&point| selected(point));
+        let first = points.next()?;
+        let (mut left, mut top, mut right, mut bottom) = (first.x, first.y, first.x, first.y);
+        for point in points {
+            left = left.min(point.x);
+            top = top.min(point.y);
+            right = right.max(point.x);
+            bottom = bottom.max(point.y);
+        }
+
+        let bounds = Rect::new(
+            left,
+            top,
+            (right - left + 1) as u32,
+            (bottom - top + 1) as u32,
+        );
+        Some(Selection {
+            bounds,
+            mask: Some(bounds.points().map(selected).collect()),
+        })
+    }
+
+    /// Everything that's in either selection.
+    pub fn union(&self, other: &Selection) -> Selection {
+        let bounds = self.bounds.union(&other.bounds);
+        // neither one is empty, so this isn't either
+        Selection::trimmed(bounds, |point| {
+            self.contains(point) || other.contains(point)
+        })
+        .unwrap_or_else(|| self.clone())
+    }
+
+    /// What's in this selection but not the other one. `None` if that's nothing.
+    pub fn subtract(&self, other: &Selection) -> Option<Selection> {
+        Selection::trimmed(self.bounds, |point| {
+            self.contains(point) && !other.contains(point)
+        })
+    }
+
+    /// The smallest rectangle around everything that's selected, more or less.
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Whether it's just a rectangle rather than some other shape.
+    pub fn is_rect(&self) -> bool {
+        self.mask.is_none()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        if !self.bounds.contains(point) {
+            return false;
+        }
+        match &self.mask {
+            Some(mask) => {
+                let (x, y) = (point.x - self.bounds.left(), point.y - self.bounds.top());
+                mask[y as usize * self.bounds.size.width as usize + x as usize]
+            }
+            None => true,
+        }
+    }
+
+    /// Every point that's selected.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.bounds
+            .points()
+            .filter(move |&point| self.contains(point))
+    }
+
+    /// The same shape, moved over.
+    pub fn offset(&self, dx: i32, dy: i32) -> Selection {
+        Selection {
+            bounds: self.bounds.offset(dx, dy),
+            mask: self.mask.clone(),
+        }
+    }
+
+    /// Only the part of the selection that's on a canvas of some size. `None` if none of it is.
+    pub fn clamp_to(&self, canvas: Size) -> Option<Selection> {
+        let bounds = self.bounds.clamp_to(canvas);
+        let selection = match &self.mask {
+            Some(_) => Selection {
+                bounds,
+                mask: Some(bounds.points().map(|point| self.contains(point)).collect()),
+            },
+            None => Selection::from(bounds),
+        };
+        if selection.points().next().is_some() {
+            Some(selection)
+        } else {
+            None
+        }
+    }
+}
+
+/// Pixels lifted off of the canvas, which can be moved around, scaled, and rotated before being
+/// put back down.
+#[derive(Debug, Clone)]
+pub struct FloatingSelection {
+    // the pixels as they were lifted, before they're scaled or rotated
+    image: Image,
+    /// Where the top left corner of the floating pixels is on the canvas before they're scaled or
+    /// rotated, which happens around their center. Can be off the canvas.
+    pub position: Point,
+    scale: f32,
+    // clockwise, in degrees
+    rotation: f32,
+    filter: Filter,
+    // the pixels scaled and rotated, so they're only resampled when that changes. `None` when
+    // they aren't. moving them doesn't change how they're resampled
+    transformed: Option<Image>,
+}
+
+impl FloatingSelection {
+    /// How much `scale_by` scales by each step.
+    pub const SCALE_STEP: f32 = 0.1;
+
+    fn new(image: Image, position: Point) -> FloatingSelection {
+        FloatingSelection {
+            image,
+            position,
+            scale: 1.,
+            rotation: 0.,
+            filter: Filter::Nearest,
+            transformed: None,
+        }
+    }
+
+    /// The pixels as they were lifted, before they're scaled or rotated.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    #[cfg(test)]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Clockwise, in degrees.
+    #[cfg(test)]
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// How the pixels are resampled once they're scaled or rotated.
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.retransform();
+    }
+
+    /// Cut the selected pixels out of the canvas, leaving transparent pixels behind.
+    ///
+    /// The selection is clipped to the canvas. Whatever isn't selected around the edges of it is
+    /// transparent in the floating pixels.
+    pub fn lift(canvas: &mut Image, selection: &Selection) -> FloatingSelection {
+        let rect = selection.bounds().clamp_to(canvas.size());
+
+        let mut image = ImageBuilder::new()
+            .size(rect.size.width, rect.size.height)
+            .build();
+        for point in rect.points().filter(|&point| selection.contains(point)) {
+            let (cx, cy) = (point.x as usize, point.y as usize);
+            let (sx, sy) = (cx - rect.left() as usize, cy - rect.top() as usize);
+            image.set_pixel(sx, sy, canvas.pixel_at(cx, cy));
+            canvas.set_pixel(cx, cy, Pixel::TRANSPARENT);
+        }
+
+        FloatingSelection::new(image, rect.origin)
+    }
+
+    /// Copy the selected pixels to float above the canvas, leaving the canvas alone.
+    ///
+    /// The selection is clipped to the canvas, like with `lift`.
+    pub fn copy(canvas: &Image, selection: &Selection) -> FloatingSelection {
+        let rect = selection.bounds().clamp_to(canvas.size());
+        let mut image = canvas.crop(rect);
+        if !selection.is_rect() {
+            for point in rect.points().filter(|&point| !selection.contains(point)) {
+                let (x, y) = (point.x - rect.left(), point.y - rect.top());
+                image.set_pixel(x as usize, y as usize, Pixel::TRANSPARENT);
+            }
+        }
+
+        FloatingSelection::new(image, rect.origin)
+    }
+
+    pub fn nudge(&mut self, dx: i32, dy: i32) {
+        self.position = self.position.offset(dx, dy);
+    }
+
+    /// Grow or shrink by some number of steps of `SCALE_STEP` of the original size, down to one
+    /// step at the smallest.
+    pub fn scale_by(&mut self, steps: i32) {
+        let current = (self.scale / FloatingSelection::SCALE_STEP).round() as i32;
+        self.scale = (current + steps).max(1) as f32 * FloatingSelection::SCALE_STEP;
+        self.retransform();
+    }
+
+    /// Turn clockwise by some number of degrees, or counterclockwise if it's negative.
+    pub fn rotate_by(&mut self, degrees: f32) {
+        self.rotation = (self.rotation + degrees).rem_euclid(360.);
+        self.retransform();
+    }
+
+    pub fn is_transformed(&self) -> bool {
+        self.scale != 1. || self.rotation != 0.
+    }
+
+    // where the middle of the floating pixels is on the canvas, which stays put while they're
+    // scaled and rotated
+    fn center(&self) -> (f32, f32) {
+        (
+            self.position.x as f32 + self.image.width() as f32 / 2.,
+            self.position.y as f32 + self.image.height() as f32 / 2.,
+        )
+    }
+
+    /// Where the floating pixels are on the canvas, all of them after they're scaled and rotated.
+    /// Can be partly or entirely off the canvas.
+    pub fn rect(&self) -> Rect {
+        if !self.is_transformed() {
+            return Rect {
+                origin: self.position,
+                size: self.image.size(),
+            };
+        }
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let width = self.image.width() as f32 * self.scale;
+        let height = self.image.height() as f32 * self.scale;
+        // a hair under, so the rounding error in a quarter turn doesn't add a pixel
+        let rotated_width = (width * cos.abs() + height * sin.abs() - 1e-3)
+            .ceil()
+            .max(1.);
+        let rotated_height = (width * sin.abs() + height * cos.abs() - 1e-3)
+            .ceil()
+            .max(1.);
+
+        let (cx, cy) = self.center();
+        Rect::new(
+            (cx - rotated_width / 2.).round() as i32,
+            (cy - rotated_height / 2.).round() as i32,
+            rotated_width as u32,
+            rotated_height as u32,
+        )
+    }
+
+    /// The floating pixels scaled and rotated, to go at the origin of `rect`.
+    pub fn transformed(&self) -> &Image {
+        self.transformed.as_ref().unwrap_or(&self.image)
+    }
+
+    // resample the pixels after they've been scaled or rotated, or how they're resampled changed
+    fn retransform(&mut self) {
+        self.transformed = None;
+        if !self.is_transformed() {
+            return;
+        }
+
+        let rect = self.rect();
+        let (cx, cy) = self.center();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (half_width, half_height) = (
+            self.image.width() as f32 / 2.,
+            self.image.height() as f32 / 2.,
+        );
+
+        let mut image = ImageBuilder::new()
+            .size(rect.size.width, rect.size.height)
+            .build();
+        for point in Rect::from_size(rect.size).points() {
+            // the middle of this pixel relative to the center, turned back and scaled back to
+            // where it came from
+            let dx = (rect.left() + point.x) as f32 + 0.5 - cx;
+            let dy = (rect.top() + point.y) as f32 + 0.5 - cy;
+            let sx = (dx * cos + dy * sin) / self.scale + half_width;
+            let sy = (dy * cos - dx * sin) / self.scale + half_height;
+            image.set_pixel(
+                point.x as usize,
+                point.y as usize,
+                self.image.sample(sx, sy, self.filter),
+            );
+        }
+        self.transformed = Some(image);
+    }
+
+    /// Draw the floating pixels over the canvas, dropping whatever is off of it.
+    pub fn composite_onto(&self, canvas: &mut Image) {
+        canvas.blit(self.transformed(), self.rect().origin);
+    }
+}
+
+/// Hand `f` the image with the floating selection on top of it, if there is one. Only the part of
+/// the image under the floating pixels is copied, to be put back after, rather than all of it.
+pub fn with_floating<T>(
+    image: &mut Image,
+    floating: Option<&FloatingSelection>,
+    f: impl FnOnce(&Image) -> T,
+) -> T {
+    let floating = match floating {
+        Some(floating) => floating,
+        None => return f(image),
+    };
+
+    let rect = floating.rect().clamp_to(image.size());
+    let under = image.crop(rect);
+    floating.composite_onto(image);
+    let result = f(image);
+    if !rect.is_empty() {
+        image.paste(&under, rect.origin);
+    }
+    result
+}
+
+/// The canvas with the floating selection on top of it, if there is one.
+pub fn composited<'a>(canvas: &'a Image, floating: Option<&FloatingSelection>) -> Cow<'a, Image> {
+    match floating {
+        Some(floating) => {
+            let mut image = canvas.clone();
+            floating.composite_onto(&mut image);
+            Cow::Owned(image)
+        }
+        None => Cow::Borrowed(canvas),
+    }
+}
+
+#[test]
+fn lasso_even_odd() {
+    let canvas = Size::new(20, 20);
+    assert_eq!(None, Selection::lasso(&[(0.0, 0.0), (8.0, 8.0)], canvas));
+    assert_eq!(
+        None,
+        Selection::lasso(&[(0.0, 0.0), (4.0, 4.0), (8.0, 8.0)], canvas)
+    );
+    assert_eq!(
+        None,
+        Selection::lasso(&[(-9.0, -9.0), (-1.0, -9.0), (-1.0, -1.0)], canvas)
+    );
+
+    // pixels are in if their centers are
+    let triangle = Selection::lasso(&[(0.0, 0.0), (8.0, 0.0), (0.0, 8.0)], canvas).unwrap();
+    assert!(!triangle.is_rect());
+    assert_eq!(Rect::new(0, 0, 8, 8), triangle.bounds());
+    assert!(triangle.contains(Point::new(3, 3)));
+    assert!(!triangle.contains(Point::new(4, 4)));
+    assert_eq!(28, triangle.points().count());
+
+    // clipped to the canvas
+    let square = [(-5.0, -5.0), (5.0, -5.0), (5.0, 5.0), (-5.0, 5.0)];
+    let square = Selection::lasso(&square, canvas).unwrap();
+    assert_eq!(Rect::new(0, 0, 5, 5), square.bounds());
+    assert_eq!(25, square.points().count());
+
+    // a star crosses over itself, and its middle is left out
+    let star = (0..5)
+        .map(|i| {
+            let angle = (i as f32 * 144.0 - 90.0).to_radians();
+            (10.0 + 9.0 * angle.cos(), 10.0 + 9.0 * angle.sin())
+        })
+        .collect::<Vec<_>>();
+    let star = Selection::lasso(&star, canvas).unwrap();
+    assert!(star.contains(Point::new(10, 3)));
+    assert!(!star.contains(Point::new(10, 10)));
+
+    let moved = triangle.offset(15, 15).clamp_to(canvas).unwrap();
+    assert_eq!(Rect::new(15, 15, 5, 5), moved.bounds());
+    assert!(moved.contains(Point::new(15, 15)));
+    assert!(!moved.contains(Point::new(19, 19)));
+    assert_eq!(None, triangle.offset(20, 0).clamp_to(canvas));
+}
+
+#[test]
+fn combine_selections() {
+    let canvas = Size::new(8, 8);
+    let mut mask = vec![false; 64];
+    mask[8 + 2] = true;
+    mask[3 * 8 + 4] = true;
+    let points = Selection::from_mask(canvas, &mask).unwrap();
+    assert_eq!(Rect::new(2, 1, 3, 3), points.bounds());
+    assert_eq!(2, points.points().count());
+    assert_eq!(None, Selection::from_mask(canvas, &[false; 64]));
+
+    let square = Selection::from(Rect::new(4, 3, 2, 2));
+    let both = points.union(&square);
+    assert_eq!(Rect::new(2, 1, 4, 4), both.bounds());
+    assert_eq!(5, both.points().count());
+    assert!(both.contains(Point::new(2, 1)));
+    assert!(!both.contains(Point::new(3, 1)));
+
+    let rest = both.subtract(&points).unwrap();
+    assert_eq!(Rect::new(4, 3, 2, 2), rest.bounds());
+    assert_eq!(3, rest.points().count());
+    assert!(!rest.contains(Point::new(4, 3)));
+    assert_eq!(None, points.subtract(&both));
+}
+
+#[test]
+fn transform_floating_pixels() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    for point in Rect::new(4, 6, 4, 2).points() {
+        canvas.set_pixel(point.x as usize, point.y as usize, red);
+    }
+    let mut floating = FloatingSelection::copy(&canvas, &Selection::from(Rect::new(4, 6, 4, 2)));
+    assert_eq!(Rect::new(4, 6, 4, 2), floating.rect());
+
+    // a quarter turn around the middle, without anything lost to rounding
+    floating.rotate_by(90.);
+    assert_eq!(Rect::new(5, 5, 2, 4), floating.rect());
+    let turned = floating.transformed();
+    assert_eq!((2, 4), (turned.width(), turned.height()));
+    assert!(turned
+        .bounds()
+        .points()
+        .all(|point| turned.pixel_at(point.x as usize, point.y as usize) == red));
+
+    // all the way around is back where it started
+    for _ in 0..18 {
+        floating.rotate_by(15.);
+    }
+    assert!(!floating.is_transformed());
+
+    // an eighth turn holds the corners of the whole thing
+    floating.rotate_by(-45.);
+    assert_eq!(315., floating.rotation());
+    let rect = floating.rect();
+    assert_eq!((5, 5), (rect.size.width, rect.size.height));
+    let turned = floating.transformed();
+    assert_eq!(red, turned.pixel_at(2, 2));
+    assert_eq!(Pixel::TRANSPARENT, turned.pixel_at(0, 0));
+
+    floating.rotate_by(45.);
+    floating.scale_by(10);
+    assert_eq!(Rect::new(2, 5, 8, 4), floating.rect());
+    floating.scale_by(-100);
+    assert_eq!(FloatingSelection::SCALE_STEP, floating.scale());
+    assert_eq!(Rect::new(6, 7, 1, 1), floating.rect());
+
+    // partly off the canvas is clipped
+    floating.scale_by(29);
+    floating.nudge(-6, 0);
+    let mut onto = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    floating.composite_onto(&mut onto);
+    assert_eq!(red, onto.pixel_at(0, 4));
+    assert_eq!(Pixel::TRANSPARENT, onto.pixel_at(7, 4));
+
+    // shown over the image just while it's needed
+    let mut image = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    let shown = with_floating(&mut image, Some(&floating), |shown| shown.clone());
+    assert_eq!(onto, shown);
+    assert_eq!(
+        Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap(),
+        image
+    );
+}
@@ -0,0 +1,89 @@
+//! Drawing text onto images.
+
+use crate::{
+    geometry::Point,
+    image::{Image, Pixel},
+    Context, Result,
+};
+
+use anyhow::anyhow;
+
+use fontdue::{
+    layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle},
+    Font, FontSettings,
+};
+
+use std::{fmt, path::Path};
+
+/// DejaVu Sans Mono, for when no other font is given. See `fonts/LICENSE-DejaVu`.
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+
+/// A font that text can be drawn with.
+#[derive(Clone)]
+pub struct TextFont {
+    font: Font,
+}
+
+// fontdue's fonts aren't Debug, and the glyph tables wouldn't be interesting anyway
+impl fmt::Debug for TextFont {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextFont").finish()
+    }
+}
+
+impl Default for TextFont {
+    fn default() -> Self {
+        TextFont::parse(DEFAULT_FONT).expect("Couldn't parse the default font")
+    }
+}
+
+impl TextFont {
+    /// Load a TrueType or OpenType font file.
+    pub fn load(path: impl AsRef<Path>) -> Result<TextFont> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+        TextFont::parse(&bytes).with_context(|| format!("Couldn't parse {}", path.display()))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<TextFont> {
+        let font = Font::from_bytes(bytes, FontSettings::default()).map_err(|e| anyhow!(e))?;
+        Ok(TextFont { font })
+    }
+
+    /// Draw `text` over `image` in `color`, `size` pixels tall, with the top left corner of the
+    /// first line at `at`. Glyphs are antialiased, and whatever lands off of the image is dropped.
+    pub fn draw(&self, image: &mut Image, text: &str, size: f32, color: Pixel, at: Point) {
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            x: at.x as f32,
+            y: at.y as f32,
+            ..LayoutSettings::default()
+        });
+        layout.append(&[&self.font], &TextStyle::new(text, size, 0));
+
+        let bounds = image.bounds();
+        for glyph in layout.glyphs() {
+            if glyph.width == 0 {
+                continue;
+            }
+
+            // positions are whole pixels, and bitmaps go from the top down
+            let (_, coverage) = self.font.rasterize_config(glyph.key);
+            let origin = Point::new(glyph.x as i32, glyph.y as i32);
+            for (i, &covered) in coverage.iter().enumerate() {
+                let point = origin.offset((i % glyph.width) as i32, (i / glyph.width) as i32);
+                if covered == 0 || !bounds.contains(point) {
+                    continue;
+                }
+
+                let (x, y) = (point.x as usize, point.y as usize);
+                let above = Pixel {
+                    a: color.a * covered as f32 / 255.,
+                    ..color
+                };
+                image.set_pixel(x, y, above.over(image.pixel_at(x, y)));
+            }
+        }
+    }
+}
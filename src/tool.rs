@@ -0,0 +1,2094 @@
+use crate::{
+    geometry::{Grid, Point, Rect, Size},
+    history::{History, Snapshot},
+    image::{Image, Pixel},
+    selection::Selection,
+    view::View,
+};
+
+use winit::{
+    dpi::PhysicalSize,
+    event::{ModifiersState, VirtualKeyCode},
+};
+
+use std::collections::HashMap;
+
+/// The tools that can be active on the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tool {
+    Brush,
+    Eraser,
+    Bucket,
+    Eyedropper,
+    Line,
+    Select,
+    Lasso,
+    Wand,
+    Move,
+    Blur,
+    Smudge,
+}
+
+impl Tool {
+    /// Human-readable name of the tool, for the window title and such.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Brush => "brush",
+            Tool::Eraser => "eraser",
+            Tool::Bucket => "bucket",
+            Tool::Eyedropper => "eyedropper",
+            Tool::Line => "line",
+            Tool::Select => "select",
+            Tool::Lasso => "lasso",
+            Tool::Wand => "wand",
+            Tool::Move => "move",
+            Tool::Blur => "blur",
+            Tool::Smudge => "smudge",
+        }
+    }
+
+    /// What handles the pointer while this tool is active, for the tools that have been moved out
+    /// of `State` so far.
+    pub fn pointer_tool(&self) -> Option<Box<dyn PointerTool>> {
+        match self {
+            Tool::Brush => Some(Box::new(BrushTool::new(false))),
+            Tool::Line => Some(Box::new(BrushTool::line())),
+            Tool::Eraser => Some(Box::new(BrushTool::new(true))),
+            Tool::Blur => Some(Box::new(SmearTool::new(Smear::Blur))),
+            Tool::Smudge => Some(Box::new(SmearTool::new(Smear::Smudge))),
+            Tool::Bucket
+            | Tool::Eyedropper
+            | Tool::Select
+            | Tool::Lasso
+            | Tool::Wand
+            | Tool::Move => None,
+        }
+    }
+}
+
+/// Everything a `PointerTool` can see and change while it handles the pointer.
+pub struct ToolCtx<'a> {
+    pub canvas: &'a mut Image,
+    /// The edit this is part of. Tools `touch` whatever they're about to change on the canvas,
+    /// so it can be undone.
+    pub history: &'a mut History,
+    /// The primary color, what the brush paints with.
+    pub color: &'a mut Pixel,
+    /// The part of the canvas painting is limited to, if there is one.
+    pub selection: &'a mut Option<Selection>,
+    pub brush: &'a Brush,
+    pub symmetry: Symmetry,
+    /// Where the symmetry axes are, in half pixels. See `Symmetry::mirror`.
+    pub symmetry_axis: Point,
+    pub view: &'a View,
+    pub window: PhysicalSize<u32>,
+    /// Where the pointer is in the window.
+    pub pointer: (f32, f32),
+    /// The canvas pixel a straight line should be drawn from to the pointer, when it goes down
+    /// with shift held after a stroke.
+    pub line_from: Option<Point>,
+    pub modifiers: ModifiersState,
+    /// What the ends of straight lines snap to, if snapping is on.
+    pub grid: Option<Grid>,
+}
+
+impl ToolCtx<'_> {
+    /// The canvas pixel under a position in the window, which might be off the canvas.
+    pub fn canvas_point(&self, (x, y): (f32, f32)) -> Point {
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        let (x, y) = self.view.screen_to_canvas(width, height, self.window, x, y);
+        Point::new(x.floor() as i32, y.floor() as i32)
+    }
+
+    /// The canvas pixel under the pointer.
+    pub fn pointer_point(&self) -> Point {
+        self.canvas_point(self.pointer)
+    }
+
+    /// The grid intersection nearest a point, if snapping is on, otherwise the point.
+    pub fn snap(&self, point: Point) -> Point {
+        match self.grid {
+            Some(grid) => grid.snap(point),
+            None => point,
+        }
+    }
+}
+
+/// A tool that does something with the pointer, from when its button goes down to when it comes
+/// back up. Each returns true if it changed the canvas.
+pub trait PointerTool {
+    fn on_pointer_down(&mut self, ctx: &mut ToolCtx) -> bool;
+    fn on_pointer_move(&mut self, ctx: &mut ToolCtx) -> bool;
+    fn on_pointer_up(&mut self, ctx: &mut ToolCtx) -> bool;
+
+    /// The canvas pixel the stroke that just finished ended on, for the tools that a straight
+    /// line can be drawn from.
+    fn ended(&self) -> Option<Point> {
+        None
+    }
+}
+
+/// Paints with the brush, or erases with it, either freehand or in straight lines.
+#[derive(Debug, Clone)]
+pub struct BrushTool {
+    erase: bool,
+    // whether every stroke is a straight line from where it started, not just the ones with shift
+    // held
+    line: bool,
+    // the canvas pixel the pointer was over the last time the stroke was painted, so the next bit
+    // of the stroke can be joined to it. `None` between strokes
+    previous: Option<Point>,
+    // where the last stroke ended, until the next one does
+    ended: Option<Point>,
+    // what the current stroke has painted, so it doesn't paint anything twice
+    coverage: Coverage,
+    // the last two points of a pixel-perfect stroke, once corners are taken out
+    path: Vec<Point>,
+    // how each pixel a pixel-perfect stroke has painted looked before it, and how many points of
+    // its path paint it now. a pixel is only put back once nothing paints it
+    touched: HashMap<Point, (Pixel, u32)>,
+    // where the stroke started, for when it's a straight line
+    start: Option<Point>,
+    // the part of the canvas the stroke has painted so far
+    dirty: Rect,
+    // the canvas from before the stroke, as much of it as has been painted, to put back when it's
+    // a straight line
+    before: Snapshot,
+    stabilizer: Stabilizer,
+}
+
+impl BrushTool {
+    pub fn new(erase: bool) -> BrushTool {
+        BrushTool {
+            erase,
+            line: false,
+            previous: None,
+            ended: None,
+            coverage: Coverage::new(0, 0),
+            path: Vec::new(),
+            touched: HashMap::new(),
+            start: None,
+            dirty: Rect::default(),
+            before: Snapshot::default(),
+            stabilizer: Stabilizer::default(),
+        }
+    }
+
+    /// Paints a straight line from where the pointer goes down to where it is, snapped to 45
+    /// degrees with shift held.
+    pub fn line() -> BrushTool {
+        BrushTool {
+            line: true,
+            ..BrushTool::new(false)
+        }
+    }
+
+    fn pixel_perfect(ctx: &ToolCtx) -> bool {
+        ctx.brush.pixel_perfect && ctx.brush.size() == 1
+    }
+
+    // stamp the brush on a canvas pixel and wherever it's mirrored to, or erase under them,
+    // returning false if it's off the canvas
+    fn stamp(&mut self, ctx: &mut ToolCtx, point: Point) -> bool {
+        if !ctx.canvas.bounds().contains(point) {
+            return false;
+        }
+
+        // everything that paints comes through here, so this is the one place the selection
+        // needs to be respected, and the one place to mirror it
+        let everything = Selection::from(ctx.canvas.bounds());
+        let clip = ctx.selection.as_ref().unwrap_or(&everything);
+        let points = ctx
+            .symmetry
+            .mirror(point, ctx.symmetry_axis, ctx.brush.size());
+        // an axis off to the side can mirror past the top or left of the canvas. the brush might
+        // reach back onto it from there, but it's not worth the trouble
+        let pixel_perfect = BrushTool::pixel_perfect(ctx);
+        for point in points
+            .into_iter()
+            .filter(|point| point.x >= 0 && point.y >= 0)
+        {
+            let size = ctx.brush.size() as i32;
+            let square = Rect::new(
+                point.x - size / 2,
+                point.y - size / 2,
+                size as u32,
+                size as u32,
+            );
+            self.dirty = self.dirty.union(&square);
+            self.before.grow(ctx.canvas, square);
+            ctx.history.touch(ctx.canvas, square);
+
+            let (x, y) = (point.x as usize, point.y as usize);
+            if let Some(before) = ctx.canvas.try_pixel_at(x, y).filter(|_| pixel_perfect) {
+                self.touched.entry(point).or_insert((before, 0)).1 += 1;
+            }
+
+            if self.erase {
+                ctx.brush.erase(ctx.canvas, x, y, clip);
+            } else {
+                let color = *ctx.color;
+                ctx.brush
+                    .stamp(ctx.canvas, x, y, color, clip, &mut self.coverage);
+            }
+        }
+        true
+    }
+
+    // take a pixel-perfect stamp back out, putting back whatever nothing else in the stroke
+    // paints over
+    fn unstamp(&mut self, ctx: &mut ToolCtx, point: Point) {
+        let points = ctx.symmetry.mirror(point, ctx.symmetry_axis, 1);
+        for point in points {
+            if let Some((before, count)) = self.touched.get_mut(&point) {
+                *count -= 1;
+                if *count == 0 {
+                    let (x, y) = (point.x as usize, point.y as usize);
+                    ctx.canvas.try_set_pixel(x, y, *before);
+                    self.coverage.uncover(x, y);
+                }
+            }
+        }
+    }
+
+    // add a point to the path of the stroke. in pixel-perfect mode, a point that makes an L with
+    // the last two takes the one in the corner back out, so the path goes diagonally instead
+    fn plot(&mut self, ctx: &mut ToolCtx, point: Point) -> bool {
+        if !BrushTool::pixel_perfect(ctx) {
+            return self.stamp(ctx, point);
+        }
+        if self.path.last() == Some(&point) {
+            return false;
+        }
+
+        let mut changed = false;
+        if let [first, corner] = self.path[..] {
+            if is_corner(first, corner, point) {
+                self.unstamp(ctx, corner);
+                self.path.pop();
+                changed = true;
+            }
+        }
+
+        changed |= self.stamp(ctx, point);
+        self.path.push(point);
+        if self.path.len() > 2 {
+            self.path.remove(0);
+        }
+        changed
+    }
+
+    // paint from where the stroke was last painted to a canvas pixel, so moving quickly doesn't
+    // leave gaps. the first call in a stroke just paints that pixel
+    fn stroke_to(&mut self, ctx: &mut ToolCtx, to: Point) -> bool {
+        match self.previous.replace(to) {
+            Some(from) => {
+                let mut painted = false;
+                for point in from.line_to(to) {
+                    painted |= self.plot(ctx, point);
+                }
+                painted
+            }
+            None => self.plot(ctx, to),
+        }
+    }
+
+    // start the stroke over as a line from where it started to the pointer, snapped to 45
+    // degrees with shift held. the canvas is put back how it was first, so the line can follow the
+    // pointer around until it's let go of
+    fn straight_line(&mut self, ctx: &mut ToolCtx) -> bool {
+        let start = match self.start {
+            Some(start) => start,
+            None => return false,
+        };
+
+        self.before.restore(ctx.canvas, self.dirty);
+        self.reset(ctx);
+
+        // snapped to the grid, both ends of the line can land on the same intersection, which
+        // leaves nothing to draw
+        let end = if ctx.modifiers.shift() {
+            start.snap_45(ctx.pointer_point())
+        } else {
+            ctx.pointer_point()
+        };
+        let (start, end) = (ctx.snap(start), ctx.snap(end));
+        if start != end || ctx.grid.is_none() {
+            for point in start.line_to(end) {
+                self.plot(ctx, point);
+            }
+        }
+        self.previous = Some(end);
+        true
+    }
+
+    // forget everything about the stroke so far, except for how the canvas was before it
+    fn reset(&mut self, ctx: &ToolCtx) {
+        let size = ctx.canvas.size();
+        if self.coverage.size() == size {
+            self.coverage.clear(self.dirty);
+        } else {
+            self.coverage = Coverage::new(size.width, size.height);
+        }
+        self.path.clear();
+        self.touched.clear();
+        self.dirty = Rect::default();
+    }
+}
+
+// whether `corner` touches both of the others by an edge while they only touch by a corner, so
+// the three make an L
+fn is_corner(first: Point, corner: Point, last: Point) -> bool {
+    let touching = |a: Point, b: Point| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+    (first.x - last.x).abs() == 1
+        && (first.y - last.y).abs() == 1
+        && touching(first, corner)
+        && touching(corner, last)
+}
+
+impl PointerTool for BrushTool {
+    fn on_pointer_down(&mut self, ctx: &mut ToolCtx) -> bool {
+        self.reset(ctx);
+        self.before = Snapshot::default();
+        self.previous = ctx.line_from.map(|from| ctx.snap(from));
+        self.start = Some(self.previous.unwrap_or_else(|| ctx.pointer_point()));
+        self.stabilizer.reset();
+        let position = self
+            .stabilizer
+            .pull(ctx.pointer, ctx.brush.smoothing.radius());
+
+        // a line from the last stroke goes between grid intersections too, or nowhere if it's
+        // from the same one it would go to
+        let mut to = ctx.canvas_point(position);
+        if let Some(from) = self.previous {
+            to = ctx.snap(to);
+            if from == to && ctx.grid.is_some() {
+                self.previous = None;
+                return false;
+            }
+        }
+        self.stroke_to(ctx, to)
+    }
+
+    // with shift held, or as the line tool, the stroke is a straight line from where it started,
+    // which doesn't need smoothing
+    fn on_pointer_move(&mut self, ctx: &mut ToolCtx) -> bool {
+        if self.line || ctx.modifiers.shift() {
+            self.stabilizer.reset();
+            self.straight_line(ctx)
+        } else {
+            let position = self
+                .stabilizer
+                .pull(ctx.pointer, ctx.brush.smoothing.radius());
+            self.stroke_to(ctx, ctx.canvas_point(position))
+        }
+    }
+
+    // a smoothed stroke catches up to where the pointer let go, so it doesn't fall short
+    fn on_pointer_up(&mut self, ctx: &mut ToolCtx) -> bool {
+        let painted = match self.stabilizer.finish(ctx.pointer) {
+            Some(end) if self.previous.is_some() && !self.line => {
+                self.stroke_to(ctx, ctx.canvas_point(end))
+            }
+            _ => false,
+        };
+        self.ended = self.previous.take();
+        self.start = None;
+        self.before = Snapshot::default();
+        painted
+    }
+
+    fn ended(&self) -> Option<Point> {
+        self.ended
+    }
+}
+
+/// What a `SmearTool` does to the pixels under the brush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smear {
+    /// Average each pixel with the ones around it.
+    Blur,
+    /// Drag the colors under the brush along with it.
+    Smudge,
+}
+
+/// Pushes the colors already on the canvas around instead of painting new ones.
+///
+/// How strongly is the brush's opacity, and how far out is its size and falloff. The brush's
+/// dither pattern and the selection apply, but symmetry doesn't. Like painting, going over the
+/// same pixel again in one stroke doesn't blur it any more than the strongest part of the brush
+/// that's passed over it.
+#[derive(Debug, Clone)]
+pub struct SmearTool {
+    smear: Smear,
+    // the canvas pixel the last stamp was on, like `BrushTool`
+    previous: Option<Point>,
+    // what was under the last smudge stamp, row by row in the square the brush fits in. `None`
+    // where it was off the canvas, which doesn't get smudged in
+    carried: Vec<Option<Pixel>>,
+    // the canvas from before the stroke, as much of it as blurring has read, so pixels that have
+    // been blurred already don't feed back into their neighbors
+    before: Snapshot,
+    // how strongly each pixel has been blurred so far, like `BrushTool`
+    coverage: Coverage,
+    // the part of the canvas the stroke has blurred so far
+    dirty: Rect,
+    stabilizer: Stabilizer,
+}
+
+impl SmearTool {
+    pub fn new(smear: Smear) -> SmearTool {
+        SmearTool {
+            smear,
+            previous: None,
+            carried: Vec::new(),
+            before: Snapshot::default(),
+            coverage: Coverage::new(0, 0),
+            dirty: Rect::default(),
+            stabilizer: Stabilizer::default(),
+        }
+    }
+
+    fn stamp(&mut self, ctx: &mut ToolCtx, point: Point) -> bool {
+        if !ctx.canvas.bounds().contains(point) {
+            return false;
+        }
+
+        let everything = Selection::from(ctx.canvas.bounds());
+        let selection = ctx.selection.as_ref().unwrap_or(&everything);
+        let clip = selection.bounds().clamp_to(ctx.canvas.size());
+        let size = ctx.brush.size() as i32;
+        let (left, top) = (point.x - size / 2, point.y - size / 2);
+        let square = Rect::new(left, top, size as u32, size as u32);
+        ctx.history.touch(ctx.canvas, square);
+
+        let strength = ctx.brush.opacity;
+        let (x, y) = (point.x as usize, point.y as usize);
+        match self.smear {
+            Smear::Blur => {
+                // blurring reads a pixel around what's under the brush
+                self.before.grow(
+                    ctx.canvas,
+                    Rect::new(left - 1, top - 1, size as u32 + 2, size as u32 + 2),
+                );
+                self.dirty = self.dirty.union(&square);
+
+                let (before, coverage) = (&self.before, &mut self.coverage);
+                let canvas = &mut *ctx.canvas;
+                ctx.brush.apply(clip, x, y, |px, py, weight| {
+                    if !selection.contains(Point::new(px as i32, py as i32)) {
+                        return;
+                    }
+                    if coverage.cover(px, py, weight * strength).is_none() {
+                        return;
+                    }
+                    // a 3x3 box, of whichever of the pixels are on the canvas
+                    let (px, py) = (px as i32, py as i32);
+                    let around = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter_map(|(dx, dy)| before.pixel_at(Point::new(px + dx, py + dy)))
+                        .map(|pixel| (pixel, 1.))
+                        .collect::<Vec<_>>();
+                    let blurred = weighted_average(&around);
+                    let here = before.pixel_at(Point::new(px, py)).unwrap();
+                    let mixed = weighted_average(&[
+                        (here, 1. - weight * strength),
+                        (blurred, weight * strength),
+                    ]);
+                    canvas.set_pixel(px as usize, py as usize, mixed);
+                });
+            }
+
+            // each pixel is only read right before it's written, so this reads the canvas as is
+            Smear::Smudge => {
+                let carried = std::mem::take(&mut self.carried);
+                let mut under = vec![None; (size * size) as usize];
+                let canvas = &mut *ctx.canvas;
+                ctx.brush.apply(clip, x, y, |px, py, weight| {
+                    if !selection.contains(Point::new(px as i32, py as i32)) {
+                        return;
+                    }
+                    let i = ((py as i32 - top) * size + px as i32 - left) as usize;
+                    let here = canvas.pixel_at(px, py);
+                    let mixed = match carried.get(i).copied().flatten() {
+                        Some(dragged) => weighted_average(&[
+                            (here, 1. - weight * strength),
+                            (dragged, weight * strength),
+                        ]),
+                        None => here,
+                    };
+                    canvas.set_pixel(px, py, mixed);
+                    under[i] = Some(mixed);
+                });
+                self.carried = under;
+            }
+        }
+        true
+    }
+
+    fn stroke_to(&mut self, ctx: &mut ToolCtx, position: (f32, f32)) -> bool {
+        let to = ctx.canvas_point(position);
+        match self.previous.replace(to) {
+            Some(from) => {
+                let mut changed = false;
+                // the first point is where the last stamp was
+                for point in from.line_to(to).into_iter().skip(1) {
+                    changed |= self.stamp(ctx, point);
+                }
+                changed
+            }
+            None => self.stamp(ctx, to),
+        }
+    }
+}
+
+impl PointerTool for SmearTool {
+    fn on_pointer_down(&mut self, ctx: &mut ToolCtx) -> bool {
+        self.carried.clear();
+        self.previous = None;
+        self.before = Snapshot::default();
+        let size = ctx.canvas.size();
+        if self.coverage.size() == size {
+            self.coverage.clear(self.dirty);
+        } else {
+            self.coverage = Coverage::new(size.width, size.height);
+        }
+        self.dirty = Rect::default();
+        self.stabilizer.reset();
+        self.on_pointer_move(ctx)
+    }
+
+    fn on_pointer_move(&mut self, ctx: &mut ToolCtx) -> bool {
+        let position = self
+            .stabilizer
+            .pull(ctx.pointer, ctx.brush.smoothing.radius());
+        self.stroke_to(ctx, position)
+    }
+
+    fn on_pointer_up(&mut self, ctx: &mut ToolCtx) -> bool {
+        let changed = match self.stabilizer.finish(ctx.pointer) {
+            Some(end) if self.previous.is_some() => self.stroke_to(ctx, end),
+            _ => false,
+        };
+        self.previous = None;
+        self.carried.clear();
+        self.before = Snapshot::default();
+        changed
+    }
+}
+
+// the average of some straight alpha pixels with weights, counting each one's color by how opaque
+// it is so transparent pixels don't darken the ones around them
+fn weighted_average(pixels: &[(Pixel, f32)]) -> Pixel {
+    let total = pixels.iter().map(|(_, weight)| weight).sum::<f32>();
+    let alpha = pixels
+        .iter()
+        .map(|(pixel, weight)| pixel.a * weight)
+        .sum::<f32>();
+    if total <= 0. || alpha <= 0. {
+        return Pixel::TRANSPARENT;
+    }
+
+    let channel = |f: fn(&Pixel) -> f32| {
+        pixels
+            .iter()
+            .map(|(pixel, weight)| f(pixel) * pixel.a * weight)
+            .sum::<f32>()
+            / alpha
+    };
+    Pixel {
+        r: channel(|pixel| pixel.r),
+        g: channel(|pixel| pixel.g),
+        b: channel(|pixel| pixel.b),
+        a: alpha / total,
+    }
+}
+
+/// Which keys switch to which tool.
+#[derive(Debug)]
+pub struct Keymap {
+    bindings: HashMap<VirtualKeyCode, Tool>,
+}
+
+impl Keymap {
+    /// Bind `key` to `tool`, replacing whatever it was bound to before.
+    pub fn bind(&mut self, key: VirtualKeyCode, tool: Tool) {
+        self.bindings.insert(key, tool);
+    }
+
+    /// Get the tool bound to `key`, if there is one.
+    pub fn tool_for(&self, key: VirtualKeyCode) -> Option<Tool> {
+        self.bindings.get(&key).copied()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+
+        keymap.bind(VirtualKeyCode::B, Tool::Brush);
+        keymap.bind(VirtualKeyCode::E, Tool::Eraser);
+        keymap.bind(VirtualKeyCode::G, Tool::Bucket);
+        keymap.bind(VirtualKeyCode::I, Tool::Eyedropper);
+        keymap.bind(VirtualKeyCode::L, Tool::Line);
+        keymap.bind(VirtualKeyCode::M, Tool::Select);
+        keymap.bind(VirtualKeyCode::O, Tool::Lasso);
+        keymap.bind(VirtualKeyCode::W, Tool::Wand);
+        keymap.bind(VirtualKeyCode::V, Tool::Move);
+        keymap.bind(VirtualKeyCode::U, Tool::Blur);
+        keymap.bind(VirtualKeyCode::F, Tool::Smudge);
+
+        keymap
+    }
+}
+
+/// How a brush fades out from its center to its edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full strength right up to the edge, for pixel art.
+    Hard,
+    /// Fading evenly from the center to nothing at the edge.
+    Linear,
+    /// A bell curve, mostly faded out by halfway to the edge.
+    Gaussian,
+}
+
+impl Falloff {
+    /// How strong the brush is some fraction of the way from its center to its edge, from 0 to 1.
+    pub fn weight(&self, distance: f32) -> f32 {
+        if distance > 1. {
+            return 0.;
+        }
+
+        match self {
+            Falloff::Hard => 1.,
+            Falloff::Linear => 1. - distance,
+            // a standard deviation of a third of the radius, so the edge is almost nothing
+            Falloff::Gaussian => (-distance * distance * 4.5).exp(),
+        }
+    }
+
+    /// The next one along, for cycling through them.
+    pub fn next(&self) -> Falloff {
+        match self {
+            Falloff::Hard => Falloff::Linear,
+            Falloff::Linear => Falloff::Gaussian,
+            Falloff::Gaussian => Falloff::Hard,
+        }
+    }
+}
+
+/// A handful of colors to switch between quickly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: [Pixel; Palette::SLOTS],
+}
+
+impl Palette {
+    /// One for each number key.
+    pub const SLOTS: usize = 10;
+
+    pub fn get(&self, slot: usize) -> Pixel {
+        self.colors[slot]
+    }
+
+    pub fn set(&mut self, slot: usize, color: Pixel) {
+        self.colors[slot] = color;
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let color = |r, g, b| Pixel { r, g, b, a: 1. };
+        Palette {
+            colors: [
+                color(0., 0., 0.),
+                color(1., 1., 1.),
+                color(0.5, 0.5, 0.5),
+                color(1., 0., 0.),
+                color(1., 0.5, 0.),
+                color(1., 1., 0.),
+                color(0., 1., 0.),
+                color(0., 1., 1.),
+                color(0., 0., 1.),
+                color(1., 0., 1.),
+            ],
+        }
+    }
+}
+
+/// A repeating pattern the brush paints through, for shading pixel art.
+///
+/// Patterns are lined up with the canvas rather than with each stroke, so separate strokes fit
+/// together without seams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Every pixel.
+    Solid,
+    /// Every other pixel.
+    Checkerboard,
+    /// Some number of sixteenths of the pixels, spread out evenly with a 4x4 Bayer matrix.
+    Bayer(u8),
+}
+
+impl Dither {
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    /// Whether the brush paints a canvas pixel.
+    pub fn on(&self, x: usize, y: usize) -> bool {
+        match self {
+            Dither::Solid => true,
+            Dither::Checkerboard => (x + y).is_multiple_of(2),
+            Dither::Bayer(level) => Dither::BAYER[y % 4][x % 4] < *level,
+        }
+    }
+
+    /// The next one along, for cycling through them: solid, the checkerboard, then lightest to
+    /// heaviest.
+    pub fn next(&self) -> Dither {
+        match self {
+            Dither::Solid => Dither::Checkerboard,
+            Dither::Checkerboard => Dither::Bayer(2),
+            Dither::Bayer(2) => Dither::Bayer(4),
+            Dither::Bayer(4) => Dither::Bayer(12),
+            Dither::Bayer(_) => Dither::Solid,
+        }
+    }
+}
+
+/// How much freehand strokes are smoothed out, for steadier lines with a mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    Off,
+    Low,
+    High,
+}
+
+impl Smoothing {
+    /// The next one along, for cycling through them.
+    pub fn next(&self) -> Smoothing {
+        match self {
+            Smoothing::Off => Smoothing::Low,
+            Smoothing::Low => Smoothing::High,
+            Smoothing::High => Smoothing::Off,
+        }
+    }
+
+    /// How far behind the pointer a stroke can be, in window pixels. See `Stabilizer`.
+    pub fn radius(&self) -> f32 {
+        match self {
+            Smoothing::Off => 0.,
+            Smoothing::Low => 8.,
+            Smoothing::High => 24.,
+        }
+    }
+}
+
+/// Smooths out the path of a stroke by the "pull string" method.
+///
+/// The stroke follows the pointer on a string some radius long, only moving when the pointer pulls
+/// it tight, so jitter smaller than the string is ignored and the stroke is never further behind
+/// than that.
+#[derive(Debug, Clone, Default)]
+pub struct Stabilizer {
+    // where the stroke is, in window pixels. `None` between strokes
+    position: Option<(f32, f32)>,
+}
+
+impl Stabilizer {
+    /// Where the stroke is once the pointer moves to `pointer`, on a string `radius` long. A
+    /// stroke starts right under the pointer.
+    pub fn pull(&mut self, pointer: (f32, f32), radius: f32) -> (f32, f32) {
+        let position = match self.position {
+            Some((x, y)) => {
+                let (dx, dy) = (pointer.0 - x, pointer.1 - y);
+                let distance = dx.hypot(dy);
+                if distance > radius {
+                    let t = (distance - radius) / distance;
+                    (x + dx * t, y + dy * t)
+                } else {
+                    (x, y)
+                }
+            }
+            None => pointer,
+        };
+        self.position = Some(position);
+        position
+    }
+
+    /// End the stroke where the pointer is, returning that if the stroke is behind it and needs
+    /// to catch up.
+    pub fn finish(&mut self, pointer: (f32, f32)) -> Option<(f32, f32)> {
+        match self.position.take() {
+            Some(position) if position != pointer => Some(pointer),
+            _ => None,
+        }
+    }
+
+    /// Forget where the stroke is, without catching it up.
+    pub fn reset(&mut self) {
+        self.position = None;
+    }
+}
+
+/// Which ways brush stamps are mirrored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    Off,
+    /// Left to right, across a vertical axis.
+    Vertical,
+    /// Top to bottom, across a horizontal axis.
+    Horizontal,
+    /// Into all four quarters.
+    Both,
+}
+
+impl Symmetry {
+    /// The next one along, for cycling through them.
+    pub fn next(&self) -> Symmetry {
+        match self {
+            Symmetry::Off => Symmetry::Vertical,
+            Symmetry::Vertical => Symmetry::Horizontal,
+            Symmetry::Horizontal => Symmetry::Both,
+            Symmetry::Both => Symmetry::Off,
+        }
+    }
+
+    /// Every pixel a brush of `size` stamped at `point` should be stamped at, starting with
+    /// `point` itself and without repeats.
+    ///
+    /// `axis` is where the axes are in half pixels, so that they can run down the middle of a
+    /// column or between two, and `Point::new(width, height)` puts them at the center of the
+    /// canvas. The brush itself is mirrored rather than just its center: odd sizes are centered on
+    /// a pixel and mirror to the pixel on the other side, but even sizes are centered on a corner,
+    /// which lands one pixel further over.
+    pub fn mirror(&self, point: Point, axis: Point, size: u32) -> Vec<Point> {
+        let odd = (size % 2) as i32;
+        let across_x = Point::new(axis.x - odd - point.x, point.y);
+        let across_y = Point::new(point.x, axis.y - odd - point.y);
+        let across_both = Point::new(across_x.x, across_y.y);
+
+        let mirrored = match self {
+            Symmetry::Off => vec![],
+            Symmetry::Vertical => vec![across_x],
+            Symmetry::Horizontal => vec![across_y],
+            Symmetry::Both => vec![across_x, across_y, across_both],
+        };
+
+        let mut points = vec![point];
+        for point in mirrored {
+            if !points.contains(&point) {
+                points.push(point);
+            }
+        }
+        points
+    }
+}
+
+/// The round brush that paints and erases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Brush {
+    /// Diameter in canvas pixels.
+    size: u32,
+    falloff: Falloff,
+    /// How much of the color goes down with each stroke, from 0 to 1.
+    pub opacity: f32,
+    /// Whether 1 pixel strokes leave out the corners where they turn, so lines are one pixel thick
+    /// all the way along.
+    pub pixel_perfect: bool,
+    pub dither: Dither,
+    pub smoothing: Smoothing,
+    /// The weight of each pixel in the square the brush fits in, row by row. Remade whenever the
+    /// size or falloff changes, since big soft brushes cover a lot of pixels.
+    kernel: Vec<f32>,
+}
+
+impl Brush {
+    pub const MIN_SIZE: u32 = 1;
+    pub const MAX_SIZE: u32 = 256;
+
+    pub fn new(size: u32, falloff: Falloff) -> Brush {
+        let mut brush = Brush {
+            size,
+            falloff,
+            opacity: 1.,
+            pixel_perfect: false,
+            dither: Dither::Solid,
+            smoothing: Smoothing::Off,
+            kernel: Vec::new(),
+        };
+        brush.set_size(size);
+        brush
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size.clamp(Brush::MIN_SIZE, Brush::MAX_SIZE);
+        self.make_kernel();
+    }
+
+    pub fn falloff(&self) -> Falloff {
+        self.falloff
+    }
+
+    pub fn set_falloff(&mut self, falloff: Falloff) {
+        self.falloff = falloff;
+        self.make_kernel();
+    }
+
+    /// Make the brush a bit bigger, by a pixel while it's small and a tenth of its size after.
+    pub fn grow(&mut self) {
+        self.set_size(self.size + (self.size / 10).max(1));
+    }
+
+    /// Undo a `grow`, more or less.
+    pub fn shrink(&mut self) {
+        self.set_size(self.size.saturating_sub((self.size / 11).max(1)));
+    }
+
+    // weigh each pixel by how far its center is from the center of the brush. odd sizes are
+    // centered on a pixel, and even sizes on a corner
+    fn make_kernel(&mut self) {
+        let radius = self.size as f32 / 2.;
+        self.kernel = (0..self.size * self.size)
+            .map(|i| {
+                let (x, y) = ((i % self.size) as f32, (i / self.size) as f32);
+                let (dx, dy) = (x + 0.5 - radius, y + 0.5 - radius);
+                self.falloff.weight(dx.hypot(dy) / radius)
+            })
+            .collect();
+    }
+
+    /// Paint the brush onto the canvas around a pixel, clipped to `clip` and the canvas, through
+    /// its dither pattern, and blended over what's there at the brush's opacity. Returns how many
+    /// pixels were painted.
+    ///
+    /// Within a stroke, each pixel only gets as strong as the strongest part of the brush that's
+    /// passed over it, so going over the same spot again doesn't build up past the opacity. That's
+    /// tracked in `coverage`, so start a new one for each stroke.
+    ///
+    /// Odd sizes are centered on the pixel. Even sizes are centered on its top left corner, so the
+    /// brush reaches one pixel further up and left of it than down and right.
+    pub fn stamp(
+        &self,
+        canvas: &mut Image,
+        x: usize,
+        y: usize,
+        color: Pixel,
+        clip: &Selection,
+        coverage: &mut Coverage,
+    ) -> usize {
+        let mut painted = 0;
+        self.apply(
+            clip.bounds().clamp_to(canvas.size()),
+            x,
+            y,
+            |px, py, weight| {
+                if !clip.contains(Point::new(px as i32, py as i32)) {
+                    return;
+                }
+                if let Some(alpha) = coverage.cover(px, py, weight * self.opacity) {
+                    canvas.blend_pixel(px, py, color, alpha);
+                    painted += 1;
+                }
+            },
+        );
+        painted
+    }
+
+    /// Make every pixel the brush touches inside of `clip` and its dither pattern transparent,
+    /// however soft it is there, leaving their color alone.
+    pub fn erase(&self, canvas: &mut Image, x: usize, y: usize, clip: &Selection) -> usize {
+        let mut erased = 0;
+        self.apply(clip.bounds().clamp_to(canvas.size()), x, y, |px, py, _| {
+            if !clip.contains(Point::new(px as i32, py as i32)) {
+                return;
+            }
+            canvas.set_pixel(
+                px,
+                py,
+                Pixel {
+                    a: 0.,
+                    ..canvas.pixel_at(px, py)
+                },
+            );
+            erased += 1;
+        });
+        erased
+    }
+
+    // call `f` with every pixel the brush touches around a pixel that's inside of `bounds` and
+    // the dither pattern, and the brush's weight there
+    fn apply(&self, bounds: Rect, x: usize, y: usize, mut f: impl FnMut(usize, usize, f32)) {
+        let size = self.size as i64;
+        // the first pixel of the square the brush fits in
+        let (left, top) = (x as i64 - size / 2, y as i64 - size / 2);
+
+        let (min_x, max_x) = (bounds.left() as i64, bounds.right() as i64);
+        let (min_y, max_y) = (bounds.top() as i64, bounds.bottom() as i64);
+        for py in top.max(min_y)..(top + size).min(max_y) {
+            for px in left.max(min_x)..(left + size).min(max_x) {
+                let weight = self.kernel[((py - top) * size + px - left) as usize];
+                if weight > 0. && self.dither.on(px as usize, py as usize) {
+                    f(px as usize, py as usize, weight);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush::new(1, Falloff::Hard)
+    }
+}
+
+/// How strongly a stroke has painted each pixel of the canvas so far.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    alpha: Vec<f32>,
+    width: usize,
+}
+
+impl Coverage {
+    pub fn new(width: u32, height: u32) -> Coverage {
+        Coverage {
+            alpha: vec![0.; width as usize * height as usize],
+            width: width as usize,
+        }
+    }
+
+    /// Raise a pixel's coverage to `alpha`. Returns the opacity to blend over the pixel with to get
+    /// there from what's been painted already, or `None` if it's already at least that covered.
+    ///
+    /// Blending the same color over a pixel at `p` and then `q` is the same as blending it once at
+    /// `1 - (1 - p)(1 - q)`, so this is solved for `q`.
+    ///
+    /// Pixels outside of the size the coverage was made for are never covered.
+    pub fn cover(&mut self, x: usize, y: usize, alpha: f32) -> Option<f32> {
+        if x >= self.width {
+            return None;
+        }
+
+        let covered = self.alpha.get_mut(y * self.width + x)?;
+        let alpha = alpha.clamp(0., 1.);
+        if alpha <= *covered {
+            return None;
+        }
+
+        let more = if *covered >= 1. {
+            0.
+        } else {
+            (alpha - *covered) / (1. - *covered)
+        };
+        *covered = alpha;
+        Some(more)
+    }
+
+    /// The size of the canvas the coverage was made for.
+    pub fn size(&self) -> Size {
+        match self.alpha.len().checked_div(self.width) {
+            Some(height) => Size::new(self.width as u32, height as u32),
+            None => Size::new(0, 0),
+        }
+    }
+
+    /// Forget everything that was painted inside of `rect`, for starting a stroke over without
+    /// making a new coverage.
+    pub fn clear(&mut self, rect: Rect) {
+        let rect = rect.clamp_to(self.size());
+        for y in rect.top()..rect.bottom() {
+            let start = y as usize * self.width + rect.left() as usize;
+            let end = start + rect.size.width as usize;
+            self.alpha[start..end].fill(0.);
+        }
+    }
+
+    /// Forget that a pixel was painted, for when it's been put back how it was.
+    pub fn uncover(&mut self, x: usize, y: usize) {
+        if x < self.width {
+            if let Some(covered) = self.alpha.get_mut(y * self.width + x) {
+                *covered = 0.;
+            }
+        }
+    }
+}
+
+#[test]
+fn default_keymap() {
+    let keymap = Keymap::default();
+    assert_eq!(Some(Tool::Brush), keymap.tool_for(VirtualKeyCode::B));
+    assert_eq!(Some(Tool::Eraser), keymap.tool_for(VirtualKeyCode::E));
+    assert_eq!(Some(Tool::Bucket), keymap.tool_for(VirtualKeyCode::G));
+    assert_eq!(Some(Tool::Eyedropper), keymap.tool_for(VirtualKeyCode::I));
+    assert_eq!(Some(Tool::Line), keymap.tool_for(VirtualKeyCode::L));
+    assert_eq!(Some(Tool::Select), keymap.tool_for(VirtualKeyCode::M));
+    assert_eq!(Some(Tool::Lasso), keymap.tool_for(VirtualKeyCode::O));
+    assert_eq!(Some(Tool::Wand), keymap.tool_for(VirtualKeyCode::W));
+    assert_eq!(Some(Tool::Move), keymap.tool_for(VirtualKeyCode::V));
+    assert_eq!(Some(Tool::Blur), keymap.tool_for(VirtualKeyCode::U));
+    assert_eq!(Some(Tool::Smudge), keymap.tool_for(VirtualKeyCode::F));
+    assert_eq!(None, keymap.tool_for(VirtualKeyCode::Q));
+}
+
+#[test]
+fn brush_stamp_shapes() {
+    // bigger than any of the canvases, so only they clip
+    let everywhere = Selection::from(Rect::new(0, 0, 100, 100));
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    // the painted pixels as rows of # and .
+    let stamp = |size, x, y| {
+        let mut canvas = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+        let mut coverage = Coverage::new(8, 8);
+        let brush = Brush::new(size, Falloff::Hard);
+        let painted = brush.stamp(&mut canvas, x, y, white, &everywhere, &mut coverage);
+        let rows = (0..8)
+            .map(|y| {
+                (0..8)
+                    .map(|x| {
+                        if canvas.pixel_at(x, y) == white {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        (painted, rows)
+    };
+
+    let (painted, rows) = stamp(1, 3, 3);
+    assert_eq!(1, painted);
+    assert_eq!("...#....", rows[3]);
+
+    // even sizes reach up and left of the pixel
+    let (painted, rows) = stamp(2, 3, 3);
+    assert_eq!(4, painted);
+    assert_eq!(["..##....", "..##...."], rows[2..4]);
+
+    let (painted, rows) = stamp(5, 3, 3);
+    assert_eq!(21, painted);
+    assert_eq!(
+        ["........", "..###...", ".#####..", ".#####..", ".#####..", "..###...", "........"],
+        rows[0..7]
+    );
+
+    let (painted, rows) = stamp(4, 3, 3);
+    assert_eq!(12, painted);
+    assert_eq!(
+        ["........", "..##....", ".####...", ".####...", "..##....", "........"],
+        rows[0..6]
+    );
+
+    // clipped at the corners and edges rather than wrapping around
+    let (painted, rows) = stamp(5, 0, 0);
+    assert_eq!(8, painted);
+    assert_eq!(["###.....", "###.....", "##......", "........"], rows[0..4]);
+    let (painted, rows) = stamp(5, 7, 4);
+    assert_eq!(13, painted);
+    assert!(rows.iter().all(|row| !row.starts_with('#')));
+
+    // bigger than the whole canvas
+    assert_eq!(64, stamp(256, 4, 4).0);
+}
+
+#[test]
+fn brush_size_steps() {
+    let mut brush = Brush::default();
+    brush.shrink();
+    assert_eq!(Brush::MIN_SIZE, brush.size());
+
+    let mut sizes = vec![brush.size()];
+    while brush.size() < Brush::MAX_SIZE {
+        brush.grow();
+        sizes.push(brush.size());
+    }
+    assert_eq!(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], &sizes[..12]);
+    assert!(sizes.len() < 60, "{:?}", sizes);
+
+    brush.grow();
+    assert_eq!(Brush::MAX_SIZE, brush.size());
+    brush.shrink();
+    assert!(brush.size() < Brush::MAX_SIZE);
+}
+
+#[test]
+fn brush_erase_keeps_color() {
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(6, 6, red).unwrap();
+    let brush = Brush::new(3, Falloff::Hard);
+    assert_eq!(
+        4,
+        brush.erase(&mut canvas, 0, 5, &Selection::from(Rect::new(0, 0, 6, 6)))
+    );
+
+    assert_eq!(Pixel { a: 0.0, ..red }, canvas.pixel_at(0, 5));
+    assert_eq!(Pixel { a: 0.0, ..red }, canvas.pixel_at(1, 4));
+    assert_eq!(red, canvas.pixel_at(2, 5));
+    assert_eq!(red, canvas.pixel_at(0, 3));
+}
+
+#[test]
+fn brush_opacity_once_per_stroke() {
+    let everywhere = Selection::from(Rect::new(0, 0, 100, 100));
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut brush = Brush::new(3, Falloff::Hard);
+    brush.opacity = 0.3;
+    let mut canvas = Image::from_solid(8, 8, white).unwrap();
+
+    // stamps that overlap in one stroke only darken each pixel once
+    let mut coverage = Coverage::new(8, 8);
+    assert_eq!(
+        9,
+        brush.stamp(&mut canvas, 3, 3, black, &everywhere, &mut coverage)
+    );
+    assert_eq!(
+        3,
+        brush.stamp(&mut canvas, 4, 3, black, &everywhere, &mut coverage)
+    );
+    assert_eq!(
+        0,
+        brush.stamp(&mut canvas, 3, 3, black, &everywhere, &mut coverage)
+    );
+    assert!((canvas.pixel_at(3, 3).r - 0.7).abs() < 1e-6);
+    assert!((canvas.pixel_at(5, 3).r - 0.7).abs() < 1e-6);
+
+    // and the next stroke darkens them again, once what the last one covered is cleared
+    assert_eq!(Size::new(8, 8), coverage.size());
+    coverage.clear(Rect::new(2, 2, 3, 3));
+    assert_eq!(
+        9,
+        brush.stamp(&mut canvas, 3, 3, black, &everywhere, &mut coverage)
+    );
+    assert_eq!(
+        0,
+        brush.stamp(&mut canvas, 4, 3, black, &everywhere, &mut coverage)
+    );
+    assert!((canvas.pixel_at(3, 3).r - 0.49).abs() < 1e-6);
+    assert!((canvas.pixel_at(5, 3).r - 0.7).abs() < 1e-6);
+}
+
+#[test]
+fn soft_brush_kernel() {
+    let everywhere = Selection::from(Rect::new(0, 0, 100, 100));
+    let weights = |falloff| {
+        let brush = Brush::new(9, falloff);
+        let mut canvas = Image::from_solid(9, 9, Pixel::TRANSPARENT).unwrap();
+        let mut coverage = Coverage::new(9, 9);
+        let white = Pixel {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        };
+        brush.stamp(&mut canvas, 4, 4, white, &everywhere, &mut coverage);
+        (0..9).map(|x| canvas.pixel_at(x, 4).a).collect::<Vec<_>>()
+    };
+
+    // full in the middle, fading out to the edges the same way on both sides
+    for &falloff in &[Falloff::Linear, Falloff::Gaussian] {
+        let row = weights(falloff);
+        assert_eq!(1.0, row[4]);
+        for x in 0..4 {
+            assert_eq!(row[x], row[8 - x], "{:?}", falloff);
+            assert!(row[x] < row[x + 1], "{:?} {:?}", falloff, row);
+        }
+        assert!(row[0] < 0.2, "{:?} {:?}", falloff, row);
+    }
+    assert!(weights(Falloff::Gaussian)[2] < weights(Falloff::Linear)[2]);
+    assert!(weights(Falloff::Hard).iter().all(|&a| a == 1.0));
+
+    // remade with the size and falloff
+    let mut brush = Brush::new(9, Falloff::Hard);
+    brush.set_falloff(Falloff::Linear);
+    brush.grow();
+    assert_eq!(Brush::new(10, Falloff::Linear), brush);
+}
+
+#[test]
+fn soft_brush_clips_and_accumulates_once() {
+    let everywhere = Selection::from(Rect::new(0, 0, 100, 100));
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut brush = Brush::new(7, Falloff::Linear);
+    brush.opacity = 0.5;
+
+    // the part of a stamp that's on the canvas is the same as it would be anywhere else
+    let mut middle = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    brush.stamp(
+        &mut middle,
+        10,
+        10,
+        white,
+        &everywhere,
+        &mut Coverage::new(20, 20),
+    );
+    let mut corner = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    let painted = brush.stamp(
+        &mut corner,
+        1,
+        0,
+        white,
+        &everywhere,
+        &mut Coverage::new(20, 20),
+    );
+    // the bottom right corner of the square is outside of the circle
+    assert_eq!(5 * 4 - 3, painted);
+    for y in 0..4 {
+        for x in 0..5 {
+            assert_eq!(middle.pixel_at(x + 9, y + 10), corner.pixel_at(x, y));
+        }
+    }
+
+    // overlapping stamps in a stroke only get as strong as the strongest one, never past the
+    // opacity, while separate strokes build up
+    let mut canvas = Image::from_solid(20, 20, Pixel::TRANSPARENT).unwrap();
+    let mut coverage = Coverage::new(20, 20);
+    for x in 5..15 {
+        brush.stamp(&mut canvas, x, 10, white, &everywhere, &mut coverage);
+    }
+    for x in 5..15 {
+        assert!((canvas.pixel_at(x, 10).a - 0.5).abs() < 1e-6, "{}", x);
+    }
+    let edge = canvas.pixel_at(10, 12).a;
+    assert!(edge > 0.0 && edge < 0.5);
+    assert!((edge - middle.pixel_at(10, 12).a).abs() < 1e-6);
+
+    let mut coverage = Coverage::new(20, 20);
+    brush.stamp(&mut canvas, 10, 10, white, &everywhere, &mut coverage);
+    assert!((canvas.pixel_at(10, 10).a - 0.75).abs() < 1e-6);
+}
+
+#[test]
+fn symmetry_mirrors_across_center() {
+    let xs = |width: i32, size, x| {
+        Symmetry::Vertical
+            .mirror(Point::new(x, 0), Point::new(width, 0), size)
+            .iter()
+            .map(|point| point.x)
+            .collect::<Vec<_>>()
+    };
+
+    // an odd width has a center column, which is its own mirror
+    assert_eq!(vec![0, 4], xs(5, 1, 0));
+    assert_eq!(vec![1, 3], xs(5, 1, 1));
+    assert_eq!(vec![2], xs(5, 1, 2));
+    // an even width doesn't, so the middle two columns mirror each other
+    assert_eq!(vec![1, 2], xs(4, 1, 1));
+    assert_eq!(vec![2, 1], xs(4, 1, 2));
+    assert_eq!(vec![0, 3], xs(4, 1, 0));
+
+    // even brushes reach up and left of their pixel, so they mirror one further over to reach up
+    // and right instead. one straddling the axis covers both sides of it already
+    assert_eq!(vec![1, 3], xs(4, 2, 1));
+    assert_eq!(vec![2], xs(4, 2, 2));
+    assert_eq!(vec![0, 5], xs(5, 2, 0));
+
+    let mirrored = Symmetry::Both.mirror(Point::new(1, 2), Point::new(8, 6), 3);
+    assert_eq!(
+        vec![
+            Point::new(1, 2),
+            Point::new(6, 2),
+            Point::new(1, 3),
+            Point::new(6, 3)
+        ],
+        mirrored
+    );
+    assert_eq!(
+        vec![Point::new(1, 2), Point::new(1, 3)],
+        Symmetry::Horizontal.mirror(Point::new(1, 2), Point::new(8, 6), 3)
+    );
+    assert_eq!(
+        vec![Point::new(1, 2)],
+        Symmetry::Off.mirror(Point::new(1, 2), Point::new(8, 6), 3)
+    );
+
+    let mut symmetry = Symmetry::Off;
+    for _ in 0..4 {
+        symmetry = symmetry.next();
+    }
+    assert_eq!(Symmetry::Off, symmetry);
+}
+
+#[test]
+fn stabilizer_smooths_noisy_path() {
+    // a line to the right, jittering up and down
+    let raw = (0..=40)
+        .map(|i| (i as f32 * 2., if i % 2 == 0 { 3. } else { -3. }))
+        .collect::<Vec<_>>();
+    let radius = Smoothing::Low.radius();
+    let mut stabilizer = Stabilizer::default();
+    let mut smoothed = raw
+        .iter()
+        .map(|&point| stabilizer.pull(point, radius))
+        .collect::<Vec<_>>();
+
+    // never further behind than the string, and caught up at the end
+    for (&(x, y), &(sx, sy)) in raw.iter().zip(&smoothed) {
+        assert!((x - sx).hypot(y - sy) <= radius + 1e-3);
+    }
+    assert_eq!(raw[0], smoothed[0]);
+    let last = *raw.last().unwrap();
+    assert_eq!(Some(last), stabilizer.finish(last));
+    smoothed.push(last);
+    assert_eq!(None, stabilizer.finish(last));
+
+    // how much the path bends, all added up
+    let curvature = |path: &[(f32, f32)]| {
+        path.windows(3)
+            .map(|points| {
+                let (a, b, c) = (points[0], points[1], points[2]);
+                (a.0 - 2. * b.0 + c.0).hypot(a.1 - 2. * b.1 + c.1)
+            })
+            .sum::<f32>()
+    };
+    assert!(curvature(&smoothed) < curvature(&raw) / 4.);
+
+    // off, it's just the pointer
+    let mut off = Stabilizer::default();
+    for &point in &raw {
+        assert_eq!(point, off.pull(point, Smoothing::Off.radius()));
+    }
+    assert_eq!(None, off.finish(last));
+}
+
+#[test]
+fn smoothed_strokes_finish_at_the_pointer() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    let (mut color, mut selection) = (white, None);
+    let mut brush = Brush::new(1, Falloff::Hard);
+    brush.smoothing = Smoothing::High;
+    brush.pixel_perfect = true;
+    let view = View::default();
+    let mut history = History::default();
+    let mut ctx = ToolCtx {
+        canvas: &mut canvas,
+        history: &mut history,
+        color: &mut color,
+        selection: &mut selection,
+        brush: &brush,
+        symmetry: Symmetry::Off,
+        symmetry_axis: Point::new(100, 100),
+        view: &view,
+        window: PhysicalSize::new(100, 100),
+        pointer: (10.5, 50.5),
+        line_from: None,
+        modifiers: ModifiersState::empty(),
+        grid: None,
+    };
+
+    let mut tool = BrushTool::new(false);
+    assert!(tool.on_pointer_down(&mut ctx));
+    for x in 11..=80 {
+        ctx.pointer = (x as f32 + 0.5, 50.5);
+        tool.on_pointer_move(&mut ctx);
+    }
+    // the stroke lags behind until the button comes up
+    assert_eq!(white, ctx.canvas.pixel_at(50, 50));
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(80, 50));
+    assert!(tool.on_pointer_up(&mut ctx));
+    for x in 10..=80 {
+        assert_eq!(white, ctx.canvas.pixel_at(x, 50), "{}", x);
+    }
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(81, 50));
+}
+
+#[test]
+fn brush_tool_pointer_events() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    let mut color = white;
+    let mut selection = None;
+    let brush = Brush::new(3, Falloff::Hard);
+    let view = View::default();
+    // one window pixel to a canvas pixel
+    let mut history = History::default();
+    let mut ctx = ToolCtx {
+        canvas: &mut canvas,
+        history: &mut history,
+        color: &mut color,
+        selection: &mut selection,
+        brush: &brush,
+        symmetry: Symmetry::Off,
+        symmetry_axis: Point::new(100, 100),
+        view: &view,
+        window: PhysicalSize::new(100, 100),
+        pointer: (10.5, 10.5),
+        line_from: None,
+        modifiers: ModifiersState::empty(),
+        grid: None,
+    };
+
+    // a stroke in two straight segments, joined up between the pointer events
+    let mut tool = BrushTool::new(false);
+    assert!(tool.on_pointer_down(&mut ctx));
+    ctx.pointer = (50.5, 10.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    ctx.pointer = (50.5, 40.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    assert!(!tool.on_pointer_up(&mut ctx));
+    for x in 9..=51 {
+        assert_eq!(white, ctx.canvas.pixel_at(x, 10), "{}", x);
+    }
+    for y in 9..=41 {
+        assert_eq!(white, ctx.canvas.pixel_at(50, y), "{}", y);
+    }
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(30, 30));
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(52, 42));
+
+    // off the canvas altogether, nothing happens
+    ctx.pointer = (-20.5, 10.5);
+    assert!(!tool.on_pointer_down(&mut ctx));
+    tool.on_pointer_up(&mut ctx);
+
+    // a line from where the last stroke ended, clipped to the selection
+    *ctx.selection = Some(Selection::from(Rect::new(0, 0, 100, 60)));
+    ctx.line_from = Some(Point::new(50, 40));
+    ctx.pointer = (50.5, 80.5);
+    assert!(tool.on_pointer_down(&mut ctx));
+    tool.on_pointer_up(&mut ctx);
+    assert_eq!(white, ctx.canvas.pixel_at(50, 59));
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(50, 60));
+
+    // and the eraser takes it away again
+    ctx.line_from = None;
+    *ctx.selection = None;
+    let mut eraser = BrushTool::new(true);
+    ctx.pointer = (30.5, 10.5);
+    assert!(eraser.on_pointer_down(&mut ctx));
+    eraser.on_pointer_up(&mut ctx);
+    assert_eq!(0.0, ctx.canvas.pixel_at(30, 10).a);
+    assert_eq!(white, ctx.canvas.pixel_at(28, 10));
+}
+
+#[test]
+fn line_tool() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(100, 100, Pixel::TRANSPARENT).unwrap();
+    let mut color = white;
+    let mut selection = None;
+    let brush = Brush::new(1, Falloff::Hard);
+    let view = View::default();
+    let mut history = History::default();
+    let mut ctx = ToolCtx {
+        canvas: &mut canvas,
+        history: &mut history,
+        color: &mut color,
+        selection: &mut selection,
+        brush: &brush,
+        symmetry: Symmetry::Off,
+        symmetry_axis: Point::new(100, 100),
+        view: &view,
+        window: PhysicalSize::new(100, 100),
+        pointer: (10.5, 10.5),
+        line_from: None,
+        modifiers: ModifiersState::empty(),
+        grid: None,
+    };
+
+    // the line follows the pointer around, leaving nothing behind where it's been
+    let mut tool = BrushTool::line();
+    assert!(tool.on_pointer_down(&mut ctx));
+    ctx.pointer = (50.5, 30.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    ctx.pointer = (50.5, 10.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    tool.on_pointer_up(&mut ctx);
+    for x in 10..=50 {
+        assert_eq!(white, ctx.canvas.pixel_at(x, 10), "{}", x);
+    }
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(30, 20));
+    assert_eq!(Some(Point::new(50, 10)), tool.ended());
+
+    // shift snaps it to 45 degrees
+    ctx.pointer = (10.5, 50.5);
+    tool.on_pointer_down(&mut ctx);
+    ctx.modifiers = ModifiersState::SHIFT;
+    ctx.pointer = (30.5, 52.5);
+    tool.on_pointer_move(&mut ctx);
+    tool.on_pointer_up(&mut ctx);
+    assert_eq!(white, ctx.canvas.pixel_at(30, 50));
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(30, 52));
+}
+
+#[test]
+fn pixel_perfect_zig_zags() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let view = View::default();
+    // the pixels a stroke through some canvas pixels leaves white, as they're painted and at the
+    // end, on a canvas with a red pixel at (2, 1)
+    let draw = |path: &[(i32, i32)], pixel_perfect| {
+        let mut canvas = Image::from_solid(8, 8, Pixel::TRANSPARENT).unwrap();
+        canvas.set_pixel(2, 1, red);
+        let (mut color, mut selection) = (white, None);
+        let mut brush = Brush::new(1, Falloff::Hard);
+        brush.pixel_perfect = pixel_perfect;
+        let mut history = History::default();
+        let mut ctx = ToolCtx {
+            canvas: &mut canvas,
+            history: &mut history,
+            color: &mut color,
+            selection: &mut selection,
+            brush: &brush,
+            symmetry: Symmetry::Off,
+            symmetry_axis: Point::new(8, 8),
+            view: &view,
+            window: PhysicalSize::new(8, 8),
+            pointer: (0.0, 0.0),
+            line_from: None,
+            modifiers: ModifiersState::empty(),
+            grid: None,
+        };
+        let white_points = |canvas: &Image| {
+            canvas
+                .bounds()
+                .points()
+                .filter(|point| canvas.pixel_at(point.x as usize, point.y as usize) == white)
+                .map(|point| (point.x, point.y))
+                .collect::<Vec<_>>()
+        };
+
+        let mut tool = BrushTool::new(false);
+        let mut during = Vec::new();
+        for (i, &(x, y)) in path.iter().enumerate() {
+            ctx.pointer = (x as f32 + 0.5, y as f32 + 0.5);
+            if i == 0 {
+                tool.on_pointer_down(&mut ctx);
+            } else {
+                tool.on_pointer_move(&mut ctx);
+            }
+            during.push(white_points(ctx.canvas));
+        }
+        tool.on_pointer_up(&mut ctx);
+        (during, canvas)
+    };
+    let last = |(during, _): (Vec<Vec<(i32, i32)>>, Image)| during.last().unwrap().clone();
+
+    // a staircase becomes a diagonal, and the corners come out as it's drawn rather than at the end
+    let stairs = [(0, 0), (1, 0), (1, 1), (2, 1), (2, 2), (3, 2), (3, 3)];
+    let (during, _) = draw(&stairs, true);
+    assert_eq!(vec![(0, 0), (1, 0)], during[1]);
+    assert_eq!(vec![(0, 0), (1, 1)], during[2]);
+    assert_eq!(vec![(0, 0), (1, 1), (2, 2), (3, 3)], during[6]);
+    assert_eq!(7, last(draw(&stairs, false)).len());
+
+    // but what was there before the stroke is put back, not cleared
+    let (_, canvas) = draw(&stairs, true);
+    assert_eq!(red, canvas.pixel_at(2, 1));
+    assert_eq!(Pixel::TRANSPARENT, canvas.pixel_at(1, 0));
+
+    // a zig-zag loses all of its corners, but straight runs and diagonals are left alone
+    let zig_zag = [
+        (0, 4),
+        (1, 4),
+        (1, 3),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+        (3, 3),
+        (4, 3),
+        (5, 3),
+    ];
+    let mut painted = last(draw(&zig_zag, true));
+    painted.sort_unstable();
+    assert_eq!(
+        vec![(0, 4), (1, 3), (2, 4), (3, 3), (4, 3), (5, 3)],
+        painted
+    );
+
+    // a corner the stroke has already been through stays painted. around a square, back down
+    // through (2, 5) and out to the right again, taking out the corners on the way
+    let crossing = [(0, 5), (5, 5), (5, 2), (2, 2), (2, 5), (3, 5)];
+    let painted = last(draw(&crossing, true));
+    assert!(painted.contains(&(2, 5)), "{:?}", painted);
+    for corner in &[(5, 5), (5, 2), (2, 2)] {
+        assert!(!painted.contains(corner), "{:?}", corner);
+    }
+}
+
+#[test]
+fn dither_patterns() {
+    let everywhere = Selection::from(Rect::new(0, 0, 100, 100));
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    // how much of each tile is on
+    let density = |dither: Dither| {
+        (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| dither.on(x, y))
+            .count()
+    };
+    assert_eq!(16, density(Dither::Solid));
+    assert_eq!(8, density(Dither::Checkerboard));
+    assert_eq!(2, density(Dither::Bayer(2)));
+    assert_eq!(4, density(Dither::Bayer(4)));
+    assert_eq!(12, density(Dither::Bayer(12)));
+    // and the lighter levels are part of the heavier ones
+    for (x, y) in (0..4).flat_map(|y| (0..4).map(move |x| (x, y))) {
+        assert!(!Dither::Bayer(4).on(x, y) || Dither::Bayer(12).on(x, y));
+        assert_eq!(Dither::Bayer(4).on(x, y), Dither::Bayer(4).on(x + 4, y + 8));
+    }
+
+    let mut dither = Dither::Solid;
+    let mut seen = vec![dither];
+    loop {
+        dither = dither.next();
+        if dither == Dither::Solid {
+            break;
+        }
+        seen.push(dither);
+    }
+    assert_eq!(5, seen.len());
+
+    // two strokes that overlap paint exactly the pattern where they've been, lined up with the
+    // canvas rather than either stroke
+    let mut brush = Brush::new(5, Falloff::Hard);
+    brush.dither = Dither::Bayer(4);
+    let mut canvas = Image::from_solid(16, 16, Pixel::TRANSPARENT).unwrap();
+    let painted = brush.stamp(
+        &mut canvas,
+        5,
+        5,
+        white,
+        &everywhere,
+        &mut Coverage::new(16, 16),
+    );
+    brush.stamp(
+        &mut canvas,
+        7,
+        6,
+        white,
+        &everywhere,
+        &mut Coverage::new(16, 16),
+    );
+    assert!(painted < 21 && painted > 0);
+    let mut solid = Image::from_solid(16, 16, Pixel::TRANSPARENT).unwrap();
+    brush.dither = Dither::Solid;
+    brush.stamp(
+        &mut solid,
+        5,
+        5,
+        white,
+        &everywhere,
+        &mut Coverage::new(16, 16),
+    );
+    brush.stamp(
+        &mut solid,
+        7,
+        6,
+        white,
+        &everywhere,
+        &mut Coverage::new(16, 16),
+    );
+    for point in canvas.bounds().points() {
+        let (x, y) = (point.x as usize, point.y as usize);
+        let expected = solid.pixel_at(x, y) == white && Dither::Bayer(4).on(x, y);
+        assert_eq!(expected, canvas.pixel_at(x, y) == white, "{}, {}", x, y);
+    }
+
+    // and the eraser erases through it too
+    let mut canvas = Image::from_solid(4, 4, white).unwrap();
+    brush.dither = Dither::Checkerboard;
+    brush.set_size(9);
+    assert_eq!(8, brush.erase(&mut canvas, 2, 2, &everywhere));
+    assert_eq!(0.0, canvas.pixel_at(0, 0).a);
+    assert_eq!(1.0, canvas.pixel_at(1, 0).a);
+}
+
+#[test]
+fn blur_and_smudge() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let blue = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let view = View::default();
+    // drag a tool through some canvas pixels, with the window the same size as the canvas
+    let drag = |tool: &mut SmearTool, canvas: &mut Image, brush: &Brush, path: &[(i32, i32)]| {
+        let (mut color, mut selection) = (white, None);
+        let size = canvas.size();
+        let mut history = History::default();
+        let mut ctx = ToolCtx {
+            canvas,
+            history: &mut history,
+            color: &mut color,
+            selection: &mut selection,
+            brush,
+            symmetry: Symmetry::Off,
+            symmetry_axis: Point::new(0, 0),
+            view: &view,
+            window: PhysicalSize::new(size.width, size.height),
+            pointer: (path[0].0 as f32 + 0.5, path[0].1 as f32 + 0.5),
+            line_from: None,
+            modifiers: ModifiersState::empty(),
+            grid: None,
+        };
+        tool.on_pointer_down(&mut ctx);
+        for &(x, y) in &path[1..] {
+            ctx.pointer = (x as f32 + 0.5, y as f32 + 0.5);
+            tool.on_pointer_move(&mut ctx);
+        }
+        tool.on_pointer_up(&mut ctx);
+    };
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+
+    // every pixel under the brush is the average of the 3x3 around it from before, not from
+    // pixels the same stamp has already blurred
+    let brush = Brush::new(3, Falloff::Hard);
+    let mut canvas = Image::from_solid(9, 9, black).unwrap();
+    canvas.set_pixel(4, 4, white);
+    drag(
+        &mut SmearTool::new(Smear::Blur),
+        &mut canvas,
+        &brush,
+        &[(4, 4)],
+    );
+    for y in 3..=5 {
+        for x in 3..=5 {
+            assert!(close(1.0 / 9.0, canvas.pixel_at(x, y).r), "{}, {}", x, y);
+            assert_eq!(1.0, canvas.pixel_at(x, y).a);
+        }
+    }
+    assert_eq!(black, canvas.pixel_at(2, 4));
+
+    // at the edge only what's on the canvas counts
+    let mut canvas = Image::from_solid(9, 9, black).unwrap();
+    canvas.set_pixel(0, 0, white);
+    drag(
+        &mut SmearTool::new(Smear::Blur),
+        &mut canvas,
+        &brush,
+        &[(0, 0)],
+    );
+    assert!(close(0.25, canvas.pixel_at(0, 0).r));
+    assert!(close(1.0 / 6.0, canvas.pixel_at(1, 0).r));
+
+    // at half strength it's halfway there
+    let mut soft = Brush::new(3, Falloff::Hard);
+    soft.opacity = 0.5;
+    let mut canvas = Image::from_solid(9, 9, black).unwrap();
+    canvas.set_pixel(4, 4, white);
+    drag(
+        &mut SmearTool::new(Smear::Blur),
+        &mut canvas,
+        &soft,
+        &[(4, 4)],
+    );
+    assert!(close(0.5 + 0.5 / 9.0, canvas.pixel_at(4, 4).r));
+
+    // and going back over it in the same stroke doesn't blur it any more
+    let mut canvas = Image::from_solid(9, 9, black).unwrap();
+    canvas.set_pixel(4, 4, white);
+    drag(
+        &mut SmearTool::new(Smear::Blur),
+        &mut canvas,
+        &soft,
+        &[(4, 4), (5, 4), (4, 4)],
+    );
+    assert!(close(0.5 + 0.5 / 9.0, canvas.pixel_at(4, 4).r));
+
+    // smudging drags red out into the blue, fading the further it goes
+    let smudged = |brush: &Brush| {
+        let mut canvas = Image::from_solid(20, 5, blue).unwrap();
+        for point in Rect::new(0, 0, 10, 5).points() {
+            canvas.set_pixel(point.x as usize, point.y as usize, red);
+        }
+        drag(
+            &mut SmearTool::new(Smear::Smudge),
+            &mut canvas,
+            brush,
+            &[(5, 2), (15, 2)],
+        );
+        canvas
+    };
+    let canvas = smudged(&brush);
+    assert_eq!(red, canvas.pixel_at(12, 2));
+    assert_eq!(red, canvas.pixel_at(16, 2));
+    assert_eq!(blue, canvas.pixel_at(17, 2));
+    assert_eq!(blue, canvas.pixel_at(12, 0));
+
+    let canvas = smudged(&soft);
+    let (near, far) = (canvas.pixel_at(11, 2), canvas.pixel_at(15, 2));
+    assert!(near.r > far.r && far.r > 0.0, "{:?} {:?}", near, far);
+    assert!(close(1.0, near.r + near.b));
+
+    // and off the edge of the canvas there's nothing to drag along
+    let mut canvas = Image::from_solid(20, 5, blue).unwrap();
+    drag(
+        &mut SmearTool::new(Smear::Smudge),
+        &mut canvas,
+        &brush,
+        &[(-5, 2), (3, 2)],
+    );
+    assert_eq!(Image::from_solid(20, 5, blue).unwrap(), canvas);
+}
+
+#[test]
+fn shift_drags_straight_lines() {
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let red = Pixel {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    let mut canvas = Image::from_solid(50, 50, Pixel::TRANSPARENT).unwrap();
+    canvas.set_pixel(20, 10, red);
+    let (mut color, mut selection) = (white, None);
+    let mut brush = Brush::new(1, Falloff::Hard);
+    brush.opacity = 0.5;
+    let view = View::default();
+    let mut history = History::default();
+    let mut ctx = ToolCtx {
+        canvas: &mut canvas,
+        history: &mut history,
+        color: &mut color,
+        selection: &mut selection,
+        brush: &brush,
+        symmetry: Symmetry::Off,
+        symmetry_axis: Point::new(50, 50),
+        view: &view,
+        window: PhysicalSize::new(50, 50),
+        pointer: (10.5, 10.5),
+        line_from: None,
+        modifiers: ModifiersState::SHIFT,
+        grid: None,
+    };
+    // the pixels that have been painted, either color
+    let painted = |canvas: &Image| {
+        canvas
+            .bounds()
+            .points()
+            .filter(|point| canvas.pixel_at(point.x as usize, point.y as usize).a > 0.0)
+            .map(|point| (point.x, point.y))
+            .collect::<Vec<_>>()
+    };
+
+    let mut tool = BrushTool::new(false);
+    tool.on_pointer_down(&mut ctx);
+
+    // close to horizontal snaps to horizontal, painted like any other stroke
+    ctx.pointer = (30.5, 13.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    assert_eq!(21, painted(ctx.canvas).len());
+    assert_eq!(0.5, ctx.canvas.pixel_at(15, 10).a);
+    assert_eq!(0.5, ctx.canvas.pixel_at(20, 10).g);
+
+    // and moving on takes it back, putting back what was there, rather than adding to it
+    ctx.pointer = (24.5, 26.5);
+    assert!(tool.on_pointer_move(&mut ctx));
+    assert_eq!(red, ctx.canvas.pixel_at(20, 10));
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(30, 10));
+    let mut diagonal = (10..=25).map(|i| (i, i)).collect::<Vec<_>>();
+    diagonal.push((20, 10));
+    diagonal.sort_unstable_by_key(|&(x, y)| (y, x));
+    assert_eq!(diagonal, painted(ctx.canvas));
+    assert_eq!(0.5, ctx.canvas.pixel_at(25, 25).a);
+
+    // letting go of shift goes back to drawing freehand from the end of the line
+    ctx.modifiers = ModifiersState::empty();
+    ctx.pointer = (25.5, 40.5);
+    tool.on_pointer_move(&mut ctx);
+    tool.on_pointer_up(&mut ctx);
+    assert_eq!(0.5, ctx.canvas.pixel_at(25, 40).a);
+    assert_eq!(0.5, ctx.canvas.pixel_at(12, 12).a);
+
+    // shift-clicking starts from where the last stroke ended, and dragging snaps from there too
+    assert_eq!(Some(Point::new(25, 40)), tool.ended());
+    ctx.modifiers = ModifiersState::SHIFT;
+    ctx.line_from = tool.ended();
+    ctx.pointer = (40.5, 41.5);
+    tool.on_pointer_down(&mut ctx);
+    assert_eq!(0.5, ctx.canvas.pixel_at(40, 41).a);
+    tool.on_pointer_move(&mut ctx);
+    tool.on_pointer_up(&mut ctx);
+    assert_eq!(Pixel::TRANSPARENT, ctx.canvas.pixel_at(40, 41));
+    assert_eq!(0.5, ctx.canvas.pixel_at(40, 40).a);
+}
@@ -0,0 +1,709 @@
+use winit::dpi::PhysicalSize;
+
+use std::time::{Duration, Instant};
+
+/// How the canvas is displayed in the window. Never affects the image data itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    /// Horizontal zoom. The same as `zoom_y` unless the pixels are being stretched.
+    pub zoom_x: f32,
+    pub zoom_y: f32,
+    /// Counterclockwise rotation of the view in degrees, in `[0, 360)`.
+    pub rotation: f32,
+    /// How far the center of the canvas is moved from the center of the window, in window
+    /// pixels. Applied after zooming and rotating, so it follows the pointer.
+    pub pan_x: f32,
+    pub pan_y: f32,
+    /// Whether the zoom on screen is rounded down so each canvas pixel covers a whole number of
+    /// window pixels, or each window pixel a whole number of canvas pixels when zoomed out.
+    pub integer_zoom: bool,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            zoom_x: 1.0,
+            zoom_y: 1.0,
+            rotation: 0.0,
+            pan_x: 0.0,
+            pan_y: 0.0,
+            integer_zoom: false,
+        }
+    }
+}
+
+impl View {
+    pub const MIN_ZOOM: f32 = 0.05;
+    pub const MAX_ZOOM: f32 = 64.0;
+    /// How much one notch of the scroll wheel zooms by.
+    pub const ZOOM_STEP: f32 = 1.1;
+
+    /// Zoom in by some number of wheel notches, or out if `steps` is negative. Steps multiply the
+    /// zoom rather than adding to it, so each one feels the same at any zoom, and fractions of a
+    /// step are fine.
+    pub fn step_zoom(zoom: f32, steps: f32) -> f32 {
+        (zoom * View::ZOOM_STEP.powf(steps)).clamp(View::MIN_ZOOM, View::MAX_ZOOM)
+    }
+
+    /// The overall zoom. Stretched views report their horizontal zoom.
+    pub fn zoom(&self) -> f32 {
+        self.zoom_x
+    }
+
+    /// The zoom the canvas is actually shown at, which is only different from `zoom_x` and
+    /// `zoom_y` with `integer_zoom`. Depends on the window size, since that's what the canvas is
+    /// fit to at zoom 1.
+    pub fn display_zoom(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+    ) -> (f32, f32) {
+        if !self.integer_zoom {
+            return (self.zoom_x, self.zoom_y);
+        }
+
+        let fit = fit_pixels(canvas_width, canvas_height, window);
+
+        // with a little slack, so a zoom that's meant to be exact like from `actual_pixels` isn't
+        // rounded down a whole step
+        let snap = |zoom: f32| {
+            let pixels = fit * zoom;
+            let pixels = if pixels >= 1.0 - 1e-4 {
+                (pixels + 1e-4).floor()
+            } else {
+                1.0 / (1.0 / pixels - 1e-4).ceil()
+            };
+            pixels / fit
+        };
+        (snap(self.zoom_x), snap(self.zoom_y))
+    }
+
+    /// How many window pixels across one canvas pixel is on screen, horizontally. 1 is actual
+    /// size.
+    pub fn pixel_scale(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+    ) -> f32 {
+        let (zoom_x, _) = self.display_zoom(canvas_width, canvas_height, window);
+        fit_pixels(canvas_width, canvas_height, window) * zoom_x
+    }
+
+    /// Zoom both axes the same amount, undoing any stretch.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom_x = zoom;
+        self.zoom_y = zoom;
+    }
+
+    pub fn rotate_by(&mut self, degrees: f32) {
+        self.rotation = (self.rotation + degrees).rem_euclid(360.0);
+    }
+
+    /// Scale the zoom by `1 + delta`, the way pinch gestures report it. Both axes are scaled
+    /// together, so a stretched view stays stretched.
+    pub fn magnify(&mut self, delta: f32) {
+        let factor = (1.0 + delta).max(0.0);
+        self.zoom_x = (self.zoom_x * factor).clamp(View::MIN_ZOOM, View::MAX_ZOOM);
+        self.zoom_y = (self.zoom_y * factor).clamp(View::MIN_ZOOM, View::MAX_ZOOM);
+    }
+
+    /// Zoom and pan so the whole canvas fits in the window, as it's rotated now, with `margin`
+    /// window pixels to spare on every side.
+    pub fn fit(
+        &mut self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+        margin: f32,
+    ) {
+        // how big the canvas is in window pixels at zoom 1, which fits it before it's rotated
+        let (scale_x, scale_y) = fit_scale(canvas_width, canvas_height, window);
+        let width = scale_x * window.width.max(1) as f32;
+        let height = scale_y * window.height.max(1) as f32;
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (sin, cos) = (sin.abs(), cos.abs());
+        let bounds_width = width * cos + height * sin;
+        let bounds_height = width * sin + height * cos;
+
+        let room_x = (window.width as f32 - 2.0 * margin).max(1.0);
+        let room_y = (window.height as f32 - 2.0 * margin).max(1.0);
+        self.set_zoom(
+            (room_x / bounds_width)
+                .min(room_y / bounds_height)
+                .clamp(View::MIN_ZOOM, View::MAX_ZOOM),
+        );
+        self.pan_x = 0.0;
+        self.pan_y = 0.0;
+    }
+
+    /// Zoom so one canvas pixel covers exactly one window pixel, and center the canvas. The
+    /// window is measured in physical pixels, so this is one pixel on the screen even with
+    /// display scaling.
+    pub fn actual_pixels(
+        &mut self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+    ) {
+        let fit = fit_pixels(canvas_width, canvas_height, window);
+        self.set_zoom((1.0 / fit).clamp(View::MIN_ZOOM, View::MAX_ZOOM));
+
+        // centering an odd number of pixels in an even number of them, or the other way around,
+        // would put the edges of the canvas pixels halfway through the window pixels
+        let sideways = self.rotation.rem_euclid(180.0) == 90.0;
+        let (width, height) = if sideways {
+            (canvas_height, canvas_width)
+        } else {
+            (canvas_width, canvas_height)
+        };
+        let half_pixel = |window: u32, canvas: u32| ((window + canvas) % 2) as f32 / 2.0;
+        self.pan_x = half_pixel(window.width, width);
+        self.pan_y = half_pixel(window.height, height);
+    }
+
+    /// Move the canvas by some number of window pixels.
+    pub fn pan_by(&mut self, dx: f32, dy: f32) {
+        self.pan_x += dx;
+        self.pan_y += dy;
+    }
+
+    /// The pan in clip space, for the vertex shader.
+    pub fn pan_clip(&self, window: PhysicalSize<u32>) -> (f32, f32) {
+        (
+            2.0 * self.pan_x / window.width.max(1) as f32,
+            -2.0 * self.pan_y / window.height.max(1) as f32,
+        )
+    }
+
+    /// Map a position in the window to a position on the canvas, in canvas pixels.
+    ///
+    /// This is the inverse of what the vertex shader does to the canvas quad. The result may be
+    /// outside of the canvas.
+    pub fn screen_to_canvas(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+        x: f32,
+        y: f32,
+    ) -> (f32, f32) {
+        let (scale_x, scale_y) = fit_scale(canvas_width, canvas_height, window);
+        let (zoom_x, zoom_y) = self.display_zoom(canvas_width, canvas_height, window);
+        let aspect = aspect(window);
+
+        // window pixels to clip space, without the pan
+        let clip_x = 2.0 * (x - self.pan_x) / window.width.max(1) as f32 - 1.0;
+        let clip_y = 1.0 - 2.0 * (y - self.pan_y) / window.height.max(1) as f32;
+
+        // undo the rotation, with the same aspect correction the shader uses
+        let (sin, cos) = (-self.rotation.to_radians()).sin_cos();
+        let (rx, ry) = (clip_x * aspect, clip_y);
+        let (rx, ry) = (rx * cos - ry * sin, rx * sin + ry * cos);
+        let (clip_x, clip_y) = (rx / aspect, ry);
+
+        // clip space to the [-1, 1] quad
+        let quad_x = clip_x / (zoom_x * scale_x);
+        let quad_y = clip_y / (zoom_y * scale_y);
+
+        // and the quad to the image, which has its origin in the top left
+        (
+            (quad_x + 1.0) / 2.0 * canvas_width as f32,
+            (1.0 - quad_y) / 2.0 * canvas_height as f32,
+        )
+    }
+
+    /// Map a position on the canvas to where it is in the window, the same as the vertex shader.
+    /// The inverse of `screen_to_canvas`.
+    pub fn canvas_to_screen(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+        x: f32,
+        y: f32,
+    ) -> (f32, f32) {
+        let (scale_x, scale_y) = fit_scale(canvas_width, canvas_height, window);
+        let (zoom_x, zoom_y) = self.display_zoom(canvas_width, canvas_height, window);
+        let aspect = aspect(window);
+
+        let quad_x = 2.0 * x / canvas_width as f32 - 1.0;
+        let quad_y = 1.0 - 2.0 * y / canvas_height as f32;
+        let (clip_x, clip_y) = (quad_x * zoom_x * scale_x, quad_y * zoom_y * scale_y);
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (rx, ry) = (clip_x * aspect, clip_y);
+        let (rx, ry) = (rx * cos - ry * sin, rx * sin + ry * cos);
+        let (clip_x, clip_y) = (rx / aspect, ry);
+
+        (
+            (clip_x + 1.0) / 2.0 * window.width.max(1) as f32 + self.pan_x,
+            (1.0 - clip_y) / 2.0 * window.height.max(1) as f32 + self.pan_y,
+        )
+    }
+
+    /// Get the canvas pixel under a position in the window, if there is one.
+    #[allow(dead_code)] // only the tests need whole pixels so far
+    pub fn canvas_pixel(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+        window: PhysicalSize<u32>,
+        x: f32,
+        y: f32,
+    ) -> Option<(usize, usize)> {
+        let (x, y) = self.screen_to_canvas(canvas_width, canvas_height, window, x, y);
+        if x >= 0.0 && y >= 0.0 && x < canvas_width as f32 && y < canvas_height as f32 {
+            Some((x as usize, y as usize))
+        } else {
+            None
+        }
+    }
+}
+
+/// An eased transition from one zoom to another, and optionally from one pan to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomAnimation {
+    from: (f32, f32),
+    to: (f32, f32),
+    // left alone so panning some other way during the animation isn't undone, unless it's part of
+    // the animation
+    pan: Option<((f32, f32), (f32, f32))>,
+    start: Instant,
+    duration: Duration,
+}
+
+impl ZoomAnimation {
+    pub const DEFAULT_DURATION: Duration = Duration::from_millis(150);
+
+    /// Animate the zoom of `view` towards `to_x` and `to_y`, starting at `now`.
+    pub fn new(view: &View, to_x: f32, to_y: f32, now: Instant, duration: Duration) -> Self {
+        ZoomAnimation {
+            from: (view.zoom_x, view.zoom_y),
+            to: (to_x, to_y),
+            pan: None,
+            start: now,
+            duration,
+        }
+    }
+
+    /// Also pan `view` towards `to_x` and `to_y`, in step with the zoom.
+    pub fn with_pan(self, view: &View, to_x: f32, to_y: f32) -> Self {
+        ZoomAnimation {
+            pan: Some(((view.pan_x, view.pan_y), (to_x, to_y))),
+            ..self
+        }
+    }
+
+    /// The zoom the animation is heading towards.
+    pub fn target(&self) -> (f32, f32) {
+        self.to
+    }
+
+    /// The pan the animation is heading towards, if it pans.
+    pub fn target_pan(&self) -> Option<(f32, f32)> {
+        self.pan.map(|(_, to)| to)
+    }
+
+    /// The zoom at `now`. Exactly the target once the animation is over.
+    pub fn zoom_at(&self, now: Instant) -> (f32, f32) {
+        if self.is_finished(now) {
+            return self.to;
+        }
+
+        lerp(self.from, self.to, self.progress(now))
+    }
+
+    /// The pan at `now`, if the animation pans.
+    pub fn pan_at(&self, now: Instant) -> Option<(f32, f32)> {
+        let (from, to) = self.pan?;
+        if self.is_finished(now) {
+            return Some(to);
+        }
+
+        Some(lerp(from, to, self.progress(now)))
+    }
+
+    // how far along the animation is at `now`, eased
+    fn progress(&self, now: Instant) -> f32 {
+        ease_out_cubic(
+            now.saturating_duration_since(self.start).as_secs_f32() / self.duration.as_secs_f32(),
+        )
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.duration
+    }
+}
+
+fn lerp(from: (f32, f32), to: (f32, f32), t: f32) -> (f32, f32) {
+    (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+}
+
+/// Fast at first, slowing down towards the end. Maps `[0, 1]` onto `[0, 1]`.
+fn ease_out_cubic(t: f32) -> f32 {
+    1.0 - (1.0 - t.clamp(0.0, 1.0)).powi(3)
+}
+
+/// Width of the window divided by its height.
+pub fn aspect(window: PhysicalSize<u32>) -> f32 {
+    window.width.max(1) as f32 / window.height.max(1) as f32
+}
+
+// window pixels per canvas pixel at zoom 1, which is the same on both axes
+fn fit_pixels(canvas_width: u32, canvas_height: u32, window: PhysicalSize<u32>) -> f32 {
+    let (scale_x, _) = fit_scale(canvas_width, canvas_height, window);
+    scale_x * window.width.max(1) as f32 / canvas_width.max(1) as f32
+}
+
+/// Compute how much of clip space the canvas quad covers on each axis at zoom 1.
+///
+/// The canvas is scaled by a single factor so that it fits entirely inside the window, so its
+/// pixels stay square no matter the window's aspect ratio. The leftover space on the longer axis
+/// is split evenly on either side, since the quad is centered on the origin.
+pub fn fit_scale(canvas_width: u32, canvas_height: u32, window: PhysicalSize<u32>) -> (f32, f32) {
+    let (canvas_width, canvas_height) = (canvas_width as f32, canvas_height as f32);
+    let (window_width, window_height) = (window.width.max(1) as f32, window.height.max(1) as f32);
+
+    let fit = (window_width / canvas_width).min(window_height / canvas_height);
+
+    (
+        canvas_width * fit / window_width,
+        canvas_height * fit / window_height,
+    )
+}
+
+#[test]
+fn fit_scale_square_canvas_wide_window() {
+    let window = PhysicalSize::new(200, 100);
+    let (scale_x, scale_y) = fit_scale(100, 100, window);
+
+    // the quad spans [-scale, scale] in clip space, so this is its size in window pixels
+    let width_px = scale_x * window.width as f32;
+    let height_px = scale_y * window.height as f32;
+    assert_eq!(width_px, height_px);
+
+    // fills the window vertically, centered horizontally with a 50px bar on each side
+    assert_eq!(1.0, scale_y);
+    assert_eq!(0.5, scale_x);
+    let left_px = (1.0 - scale_x) / 2.0 * window.width as f32;
+    assert_eq!(50.0, left_px);
+}
+
+#[test]
+fn fit_scale_matching_aspect() {
+    assert_eq!((1.0, 1.0), fit_scale(400, 300, PhysicalSize::new(800, 600)));
+    assert_eq!((1.0, 0.5), fit_scale(400, 100, PhysicalSize::new(400, 200)));
+}
+
+#[test]
+fn screen_to_canvas_rotated() {
+    let window = PhysicalSize::new(100, 100);
+    let mut view = View::default();
+    assert_eq!(Some((5, 5)), view.canvas_pixel(100, 100, window, 5.5, 5.5));
+
+    // rotated counterclockwise a quarter turn, the top right of the canvas is in the top left of
+    // the window
+    view.rotate_by(90.0);
+    assert_eq!(Some((94, 5)), view.canvas_pixel(100, 100, window, 5.5, 5.5));
+    assert_eq!(Some((5, 5)), view.canvas_pixel(100, 100, window, 5.5, 94.5));
+
+    view.rotate_by(270.0);
+    assert_eq!(0.0, view.rotation);
+}
+
+#[test]
+fn screen_to_canvas_outside() {
+    let window = PhysicalSize::new(200, 100);
+    let view = View::default();
+
+    // letterboxed, so the left quarter of the window is empty
+    assert_eq!(None, view.canvas_pixel(100, 100, window, 25.0, 50.0));
+    assert_eq!(
+        Some((0, 50)),
+        view.canvas_pixel(100, 100, window, 50.0, 50.0)
+    );
+    assert_eq!(None, view.canvas_pixel(100, 100, window, 150.0, 50.0));
+}
+
+#[test]
+fn screen_to_canvas_stretched() {
+    let window = PhysicalSize::new(100, 100);
+    let mut view = View {
+        zoom_x: 2.0,
+        ..View::default()
+    };
+
+    assert_eq!(
+        (62.5, 25.0),
+        view.screen_to_canvas(100, 100, window, 75.0, 25.0)
+    );
+    assert_eq!(
+        (50.0, 50.0),
+        view.screen_to_canvas(100, 100, window, 50.0, 50.0)
+    );
+
+    // twice as wide, so half of the canvas is off the sides of the window
+    assert_eq!(Some((25, 0)), view.canvas_pixel(100, 100, window, 0.0, 0.0));
+    assert_eq!(None, view.canvas_pixel(100, 100, window, 50.0, 100.0));
+
+    view.zoom_y = 4.0;
+    assert_eq!(
+        (62.5, 43.75),
+        view.screen_to_canvas(100, 100, window, 75.0, 25.0)
+    );
+}
+
+#[test]
+fn screen_to_canvas_inverts_vertex_shader() {
+    // what shader.vert.glsl does to a point on the quad, in window pixels
+    let vertex_shader = |view: &View, canvas: (u32, u32), window, (x, y): (f32, f32)| {
+        let (scale_x, scale_y) = fit_scale(canvas.0, canvas.1, window);
+        let (xform_x, xform_y) = view.pan_clip(window);
+        let aspect = aspect(window);
+
+        let (x, y) = (
+            view.zoom_x * x * scale_x * aspect,
+            view.zoom_y * y * scale_y,
+        );
+        let (sin, cos) = view.rotation.to_radians().sin_cos();
+        let (x, y) = ((x * cos - y * sin) / aspect, x * sin + y * cos);
+        let (x, y) = (x + xform_x, y + xform_y);
+
+        (
+            (x + 1.0) / 2.0 * window.width as f32,
+            (1.0 - y) / 2.0 * window.height as f32,
+        )
+    };
+
+    let windows = [
+        PhysicalSize::new(800, 600),
+        PhysicalSize::new(300, 900),
+        PhysicalSize::new(1920, 1080),
+    ];
+    for &window in windows.iter() {
+        for &zoom in &[1.0, 2.5, 7.0] {
+            for &(rotation, pan_x, pan_y) in &[(0.0, 0.0, 0.0), (30.0, 12.0, -40.0)] {
+                let view = View {
+                    zoom_x: zoom,
+                    zoom_y: zoom,
+                    rotation,
+                    pan_x,
+                    pan_y,
+                    ..View::default()
+                };
+
+                // the corners and center of the quad land on the corners and center of the canvas
+                let points = [
+                    ((-1.0, 1.0), (0.0, 0.0)),
+                    ((1.0, 1.0), (64.0, 0.0)),
+                    ((1.0, -1.0), (64.0, 48.0)),
+                    ((0.0, 0.0), (32.0, 24.0)),
+                ];
+                for &(quad, expected) in points.iter() {
+                    let (x, y) = vertex_shader(&view, (64, 48), window, quad);
+                    let (cx, cy) = view.screen_to_canvas(64, 48, window, x, y);
+                    assert!(
+                        (cx - expected.0).abs() < 0.01 && (cy - expected.1).abs() < 0.01,
+                        "{:?} in {:?} at {:?}: {:?} instead of {:?}",
+                        view,
+                        window,
+                        quad,
+                        (cx, cy),
+                        expected
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn zoom_animation_eases_to_target() {
+    let view = View::default();
+    let start = Instant::now();
+    let duration = Duration::from_millis(100);
+    let animation = ZoomAnimation::new(&view, 3.0, 2.0, start, duration);
+
+    assert_eq!((1.0, 1.0), animation.zoom_at(start));
+    assert!(!animation.is_finished(start));
+
+    let mut last = (1.0, 1.0);
+    for ms in 1..100 {
+        let zoom = animation.zoom_at(start + Duration::from_millis(ms));
+        assert!(zoom.0 > last.0 && zoom.0 < 3.0);
+        assert!(zoom.1 > last.1 && zoom.1 < 2.0);
+        last = zoom;
+    }
+
+    assert!(animation.is_finished(start + duration));
+    assert_eq!((3.0, 2.0), animation.zoom_at(start + duration));
+    assert_eq!((3.0, 2.0), animation.zoom_at(start + duration * 2));
+    assert_eq!(None, animation.pan_at(start));
+
+    // the pan moves along with the zoom
+    let animation = animation.with_pan(&view, 10.0, -20.0);
+    assert_eq!(Some((0.0, 0.0)), animation.pan_at(start));
+    let halfway = start + duration / 2;
+    let (zoom, _) = animation.zoom_at(halfway);
+    let (pan, _) = animation.pan_at(halfway).unwrap();
+    assert!(((zoom - 1.0) / 2.0 - pan / 10.0).abs() < 1e-5);
+    assert_eq!(Some((10.0, -20.0)), animation.pan_at(start + duration));
+    assert_eq!(Some((10.0, -20.0)), animation.target_pan());
+}
+
+#[test]
+fn zoom_steps_multiply() {
+    assert_eq!(View::ZOOM_STEP, View::step_zoom(1.0, 1.0));
+    assert_eq!(1.0, View::step_zoom(View::ZOOM_STEP, -1.0));
+
+    // half a step twice is a whole step, give or take rounding
+    let half = View::step_zoom(View::step_zoom(2.0, 0.5), 0.5);
+    assert!((half - 2.0 * View::ZOOM_STEP).abs() < 1e-5);
+
+    // below 1 is allowed, down to a point
+    assert!(View::step_zoom(1.0, -5.0) < 1.0);
+    assert_eq!(View::MIN_ZOOM, View::step_zoom(1.0, -1000.0));
+    assert_eq!(View::MAX_ZOOM, View::step_zoom(1.0, 1000.0));
+}
+
+#[test]
+fn fit_and_actual_pixels() {
+    let window = PhysicalSize::new(200, 200);
+    let mut view = View {
+        pan_x: 30.0,
+        ..View::default()
+    };
+
+    // 200x100 at zoom 1, which has to shrink to fit the margins
+    view.fit(100, 50, window, 10.0);
+    assert_eq!((0.9, 0.9), (view.zoom_x, view.zoom_y));
+    assert_eq!((0.0, 0.0), (view.pan_x, view.pan_y));
+    assert_eq!(
+        Some((0, 25)),
+        view.canvas_pixel(100, 50, window, 10.5, 100.0)
+    );
+    assert_eq!(None, view.canvas_pixel(100, 50, window, 9.5, 100.0));
+
+    // turned on its side it's taller than it is wide, and still fits
+    view.rotation = 90.0;
+    view.fit(100, 50, window, 10.0);
+    assert!((view.zoom() - 0.9).abs() < 1e-5);
+    view.rotation = 45.0;
+    view.fit(100, 50, window, 10.0);
+    assert!(view.zoom() < 0.9);
+
+    // one canvas pixel to one window pixel, lined up with them even though the canvas is an odd
+    // number of pixels narrower than the window
+    let window = PhysicalSize::new(201, 200);
+    view.rotation = 0.0;
+    view.actual_pixels(100, 50, window);
+    assert_eq!((0.5, 0.0), (view.pan_x, view.pan_y));
+    assert_eq!(Some((0, 0)), view.canvas_pixel(100, 50, window, 51.5, 75.5));
+    assert_eq!(Some((1, 1)), view.canvas_pixel(100, 50, window, 52.5, 76.5));
+    assert_eq!(
+        Some((99, 49)),
+        view.canvas_pixel(100, 50, window, 150.5, 124.5)
+    );
+    assert_eq!(None, view.canvas_pixel(100, 50, window, 50.5, 75.5));
+    assert_eq!(None, view.canvas_pixel(100, 50, window, 51.5, 74.5));
+}
+
+#[test]
+fn canvas_to_screen_round_trip() {
+    let window = PhysicalSize::new(640, 360);
+    let view = View {
+        zoom_x: 3.0,
+        zoom_y: 2.0,
+        rotation: 30.0,
+        pan_x: -25.0,
+        pan_y: 40.0,
+        ..View::default()
+    };
+
+    for &(x, y) in &[(0.0, 0.0), (17.5, 3.25), (64.0, 48.0)] {
+        let (sx, sy) = view.canvas_to_screen(64, 48, window, x, y);
+        let (cx, cy) = view.screen_to_canvas(64, 48, window, sx, sy);
+        assert!(
+            (cx - x).abs() < 1e-3 && (cy - y).abs() < 1e-3,
+            "{:?}",
+            (x, y, cx, cy)
+        );
+    }
+}
+
+#[test]
+fn integer_zoom_rounds_down() {
+    // 2 window pixels per canvas pixel at zoom 1
+    let window = PhysicalSize::new(200, 100);
+    let mut view = View {
+        integer_zoom: true,
+        ..View::default()
+    };
+
+    view.set_zoom(1.7);
+    assert_eq!((1.5, 1.5), view.display_zoom(100, 50, window));
+    view.set_zoom(0.4);
+    assert_eq!((0.25, 0.25), view.display_zoom(100, 50, window));
+    view.zoom_y = 2.0;
+    assert_eq!((0.25, 2.0), view.display_zoom(100, 50, window));
+
+    // and painting follows what's on screen, 3 window pixels per canvas pixel
+    view.set_zoom(1.7);
+    assert_eq!(
+        Some((50, 25)),
+        view.canvas_pixel(100, 50, window, 100.5, 50.5)
+    );
+    assert_eq!(
+        Some((51, 25)),
+        view.canvas_pixel(100, 50, window, 103.5, 50.5)
+    );
+    assert_eq!(
+        Some((50, 25)),
+        view.canvas_pixel(100, 50, window, 102.5, 50.5)
+    );
+
+    // actual size stays actual size, whatever the window
+    for &window in &[
+        window,
+        PhysicalSize::new(777, 333),
+        PhysicalSize::new(1920, 1080),
+    ] {
+        view.actual_pixels(100, 50, window);
+        let (zoom, _) = view.display_zoom(100, 50, window);
+        assert!((view.zoom() - zoom).abs() < 1e-6);
+    }
+
+    view.set_zoom(1.7);
+    view.integer_zoom = false;
+    assert_eq!((1.7, 1.7), view.display_zoom(100, 50, window));
+}
+
+#[test]
+fn magnify_and_pan() {
+    let window = PhysicalSize::new(100, 100);
+    let mut view = View::default();
+
+    // a pinch opening by half again zooms in by half again
+    view.set_zoom(2.0);
+    view.magnify(0.5);
+    assert_eq!((3.0, 3.0), (view.zoom_x, view.zoom_y));
+    view.magnify(-0.5);
+    assert_eq!((1.5, 1.5), (view.zoom_x, view.zoom_y));
+    view.magnify(100.0);
+    assert_eq!(View::MAX_ZOOM, view.zoom());
+    view.magnify(-1.0);
+    assert_eq!(View::MIN_ZOOM, view.zoom());
+
+    // panning right and down moves the canvas with it
+    view.set_zoom(1.0);
+    view.pan_by(10.0, 20.0);
+    assert_eq!((0.2, -0.4), view.pan_clip(window));
+    assert_eq!(
+        Some((5, 5)),
+        view.canvas_pixel(100, 100, window, 15.5, 25.5)
+    );
+    assert_eq!(None, view.canvas_pixel(100, 100, window, 5.5, 5.5));
+}